@@ -1,7 +1,6 @@
 use hyperware_anthropic_sdk::types::tools::Tool;
 use hyperware_anthropic_sdk::{
-    AnthropicClient, CacheControl, Content, CreateMessageRequest, Message, Role, SystemPrompt,
-    SystemPromptBlock,
+    AnthropicClient, CacheControl, Content, CreateMessageRequest, Message, Role, SystemPromptBlock,
 };
 use serde_json::json;
 
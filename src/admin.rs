@@ -0,0 +1,404 @@
+use crate::client::AnthropicClient;
+use crate::error::AnthropicError;
+use hyperware_process_lib::http::Method;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// One page of a paginated Admin API listing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListPage<T> {
+    pub data: Vec<T>,
+    pub has_more: bool,
+    pub first_id: Option<String>,
+    pub last_id: Option<String>,
+}
+
+/// Pagination for Admin API listings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListParams {
+    pub limit: Option<u32>,
+    pub before_id: Option<String>,
+    pub after_id: Option<String>,
+}
+
+impl ListParams {
+    /// List from the start with the API's default page size
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of items per page
+    pub fn with_limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Continue after the last item of a previous page
+    pub fn after<T>(mut self, page: &ListPage<T>) -> Self {
+        self.after_id = page.last_id.clone();
+        self
+    }
+
+    fn append_to(&self, query: &mut Vec<(&'static str, String)>) {
+        if let Some(limit) = self.limit {
+            query.push(("limit", limit.to_string()));
+        }
+        if let Some(ref before_id) = self.before_id {
+            query.push(("before_id", before_id.clone()));
+        }
+        if let Some(ref after_id) = self.after_id {
+            query.push(("after_id", after_id.clone()));
+        }
+    }
+}
+
+/// A member of the organization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrganizationUser {
+    pub id: String,
+    pub email: String,
+    pub name: String,
+    /// One of `user`, `developer`, `billing`, `admin`, or `claude_code_user`
+    pub role: String,
+    pub added_at: String,
+}
+
+/// A pending invitation to the organization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invite {
+    pub id: String,
+    pub email: String,
+    pub role: String,
+    pub status: String,
+    pub invited_at: String,
+    pub expires_at: String,
+}
+
+/// A workspace within the organization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workspace {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+    pub archived_at: Option<String>,
+    #[serde(default)]
+    pub display_color: Option<String>,
+}
+
+/// A user's membership in a workspace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceMember {
+    pub user_id: String,
+    pub workspace_id: String,
+    /// One of `workspace_user`, `workspace_developer`, `workspace_admin`, or
+    /// `workspace_billing`
+    pub workspace_role: String,
+}
+
+/// An API key, identified by a partial hint rather than its secret value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub workspace_id: Option<String>,
+    pub created_at: String,
+    pub created_by: Value,
+    pub partial_key_hint: Option<String>,
+    /// One of `active`, `inactive`, or `archived`
+    pub status: String,
+}
+
+/// Client for the Admin API, authenticated with an organization admin key
+///
+/// Requests go through an [`AnthropicClient`], so they share its retries, timeout, and
+/// error mapping. The Admin API can update and deactivate API keys but not create them;
+/// new keys are still issued from the Console.
+#[derive(Clone)]
+pub struct AdminClient {
+    client: AnthropicClient,
+}
+
+impl AdminClient {
+    /// Create an Admin API client with an admin key (`sk-ant-admin...`)
+    pub fn new(admin_key: impl Into<String>) -> Self {
+        Self::from_client(AnthropicClient::new(admin_key))
+    }
+
+    /// Use an already configured client, e.g. one with a custom base URL or timeout
+    pub fn from_client(client: AnthropicClient) -> Self {
+        Self { client }
+    }
+
+    /// List the organization's members
+    pub async fn list_users(
+        &self,
+        params: &ListParams,
+    ) -> Result<ListPage<OrganizationUser>, AnthropicError> {
+        self.get("/v1/organizations/users", params, Vec::new())
+            .await
+    }
+
+    /// Get a member of the organization
+    pub async fn get_user(&self, user_id: &str) -> Result<OrganizationUser, AnthropicError> {
+        self.call(
+            Method::GET,
+            format!("/v1/organizations/users/{}", user_id),
+            Value::Null,
+        )
+        .await
+    }
+
+    /// Change a member's organization role
+    pub async fn update_user_role(
+        &self,
+        user_id: &str,
+        role: &str,
+    ) -> Result<OrganizationUser, AnthropicError> {
+        self.call(
+            Method::POST,
+            format!("/v1/organizations/users/{}", user_id),
+            json!({ "role": role }),
+        )
+        .await
+    }
+
+    /// Remove a member from the organization
+    pub async fn remove_user(&self, user_id: &str) -> Result<(), AnthropicError> {
+        self.call::<Value>(
+            Method::DELETE,
+            format!("/v1/organizations/users/{}", user_id),
+            Value::Null,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Invite someone to the organization with the given role
+    pub async fn create_invite(&self, email: &str, role: &str) -> Result<Invite, AnthropicError> {
+        self.call(
+            Method::POST,
+            "/v1/organizations/invites".to_string(),
+            json!({ "email": email, "role": role }),
+        )
+        .await
+    }
+
+    /// List pending invitations
+    pub async fn list_invites(
+        &self,
+        params: &ListParams,
+    ) -> Result<ListPage<Invite>, AnthropicError> {
+        self.get("/v1/organizations/invites", params, Vec::new())
+            .await
+    }
+
+    /// Withdraw an invitation
+    pub async fn delete_invite(&self, invite_id: &str) -> Result<(), AnthropicError> {
+        self.call::<Value>(
+            Method::DELETE,
+            format!("/v1/organizations/invites/{}", invite_id),
+            Value::Null,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// List workspaces, optionally including archived ones
+    pub async fn list_workspaces(
+        &self,
+        params: &ListParams,
+        include_archived: bool,
+    ) -> Result<ListPage<Workspace>, AnthropicError> {
+        let query = vec![("include_archived", include_archived.to_string())];
+        self.get("/v1/organizations/workspaces", params, query)
+            .await
+    }
+
+    /// Create a workspace
+    pub async fn create_workspace(&self, name: &str) -> Result<Workspace, AnthropicError> {
+        self.call(
+            Method::POST,
+            "/v1/organizations/workspaces".to_string(),
+            json!({ "name": name }),
+        )
+        .await
+    }
+
+    /// Get a workspace
+    pub async fn get_workspace(&self, workspace_id: &str) -> Result<Workspace, AnthropicError> {
+        self.call(
+            Method::GET,
+            format!("/v1/organizations/workspaces/{}", workspace_id),
+            Value::Null,
+        )
+        .await
+    }
+
+    /// Rename a workspace
+    pub async fn rename_workspace(
+        &self,
+        workspace_id: &str,
+        name: &str,
+    ) -> Result<Workspace, AnthropicError> {
+        self.call(
+            Method::POST,
+            format!("/v1/organizations/workspaces/{}", workspace_id),
+            json!({ "name": name }),
+        )
+        .await
+    }
+
+    /// Archive a workspace, deactivating its API keys
+    pub async fn archive_workspace(&self, workspace_id: &str) -> Result<Workspace, AnthropicError> {
+        self.call(
+            Method::POST,
+            format!("/v1/organizations/workspaces/{}/archive", workspace_id),
+            Value::Null,
+        )
+        .await
+    }
+
+    /// List a workspace's members
+    pub async fn list_workspace_members(
+        &self,
+        workspace_id: &str,
+        params: &ListParams,
+    ) -> Result<ListPage<WorkspaceMember>, AnthropicError> {
+        let path = format!("/v1/organizations/workspaces/{}/members", workspace_id);
+        self.get(&path, params, Vec::new()).await
+    }
+
+    /// Add an organization member to a workspace
+    pub async fn add_workspace_member(
+        &self,
+        workspace_id: &str,
+        user_id: &str,
+        workspace_role: &str,
+    ) -> Result<WorkspaceMember, AnthropicError> {
+        self.call(
+            Method::POST,
+            format!("/v1/organizations/workspaces/{}/members", workspace_id),
+            json!({ "user_id": user_id, "workspace_role": workspace_role }),
+        )
+        .await
+    }
+
+    /// Change a member's role in a workspace
+    pub async fn update_workspace_member(
+        &self,
+        workspace_id: &str,
+        user_id: &str,
+        workspace_role: &str,
+    ) -> Result<WorkspaceMember, AnthropicError> {
+        self.call(
+            Method::POST,
+            format!(
+                "/v1/organizations/workspaces/{}/members/{}",
+                workspace_id, user_id
+            ),
+            json!({ "workspace_role": workspace_role }),
+        )
+        .await
+    }
+
+    /// Remove a member from a workspace
+    pub async fn remove_workspace_member(
+        &self,
+        workspace_id: &str,
+        user_id: &str,
+    ) -> Result<(), AnthropicError> {
+        self.call::<Value>(
+            Method::DELETE,
+            format!(
+                "/v1/organizations/workspaces/{}/members/{}",
+                workspace_id, user_id
+            ),
+            Value::Null,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// List API keys, optionally only those in one workspace
+    pub async fn list_api_keys(
+        &self,
+        params: &ListParams,
+        workspace_id: Option<&str>,
+    ) -> Result<ListPage<ApiKey>, AnthropicError> {
+        let query = workspace_id
+            .map(|id| vec![("workspace_id", id.to_string())])
+            .unwrap_or_default();
+        self.get("/v1/organizations/api_keys", params, query).await
+    }
+
+    /// Get an API key
+    pub async fn get_api_key(&self, api_key_id: &str) -> Result<ApiKey, AnthropicError> {
+        self.call(
+            Method::GET,
+            format!("/v1/organizations/api_keys/{}", api_key_id),
+            Value::Null,
+        )
+        .await
+    }
+
+    /// Rename an API key or change its status (`active` or `inactive`)
+    pub async fn update_api_key(
+        &self,
+        api_key_id: &str,
+        name: Option<&str>,
+        status: Option<&str>,
+    ) -> Result<ApiKey, AnthropicError> {
+        let mut body = serde_json::Map::new();
+        if let Some(name) = name {
+            body.insert("name".to_string(), json!(name));
+        }
+        if let Some(status) = status {
+            body.insert("status".to_string(), json!(status));
+        }
+        self.call(
+            Method::POST,
+            format!("/v1/organizations/api_keys/{}", api_key_id),
+            Value::Object(body),
+        )
+        .await
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        params: &ListParams,
+        mut query: Vec<(&'static str, String)>,
+    ) -> Result<T, AnthropicError> {
+        params.append_to(&mut query);
+        self.call(Method::GET, with_query(path, &query), Value::Null)
+            .await
+    }
+
+    pub(crate) async fn call<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: String,
+        body: Value,
+    ) -> Result<T, AnthropicError> {
+        let response = self
+            .client
+            .send_raw(&path, method, body, HashMap::new())
+            .await?;
+        serde_json::from_value(response.body).map_err(|e| {
+            AnthropicError::Deserialization(format!("Failed to parse admin response: {}", e))
+        })
+    }
+}
+
+/// Append URL-encoded query parameters to a path
+pub(crate) fn with_query(path: &str, query: &[(&str, String)]) -> String {
+    if query.is_empty() {
+        return path.to_string();
+    }
+    let encoded = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(query)
+        .finish();
+    format!("{}?{}", path, encoded)
+}
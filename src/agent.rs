@@ -0,0 +1,471 @@
+use crate::client::AnthropicClient;
+use crate::conversation::{Conversation, ConversationUpdate, PendingToolUse, ToolResult};
+use crate::delegation::SharedBudget;
+use crate::error::AnthropicError;
+use crate::registry::ToolRegistry;
+use crate::storage::{save_delta, ConversationStore};
+use crate::types::messages::{Message, Usage};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_MAX_ITERATIONS: u32 = 10;
+
+type ApprovalFn = dyn Fn(&PendingToolUse) -> Result<(), String> + Send + Sync;
+
+/// Decides whether the agent may execute a tool use requested by Claude
+///
+/// Rejected tool uses are not executed; Claude receives an error result with the reason.
+#[derive(Clone)]
+pub enum ApprovalPolicy {
+    /// Execute every tool use
+    ApproveAll,
+    /// Execute a tool use only if the closure returns `Ok`
+    Custom(Arc<ApprovalFn>),
+}
+
+impl ApprovalPolicy {
+    /// Approve tool uses with a closure returning the rejection reason on failure
+    pub fn new<F>(check: F) -> Self
+    where
+        F: Fn(&PendingToolUse) -> Result<(), String> + Send + Sync + 'static,
+    {
+        ApprovalPolicy::Custom(Arc::new(check))
+    }
+
+    /// Only approve tools whose names are in the allow list
+    pub fn allow_only(names: Vec<String>) -> Self {
+        Self::new(move |tool_use| {
+            if names.contains(&tool_use.name) {
+                Ok(())
+            } else {
+                Err(format!("Tool `{}` is not allowed", tool_use.name))
+            }
+        })
+    }
+
+    /// Check a tool use against the policy
+    pub fn check(&self, tool_use: &PendingToolUse) -> Result<(), String> {
+        match self {
+            ApprovalPolicy::ApproveAll => Ok(()),
+            ApprovalPolicy::Custom(check) => check(tool_use),
+        }
+    }
+}
+
+impl fmt::Debug for ApprovalPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApprovalPolicy::ApproveAll => f.write_str("ApproveAll"),
+            ApprovalPolicy::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// Why an agent run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AgentStopReason {
+    /// Claude answered without requesting further tool use
+    Completed,
+    /// The loop hit the configured iteration limit
+    MaxIterations,
+    /// The cumulative token budget was used up
+    BudgetExhausted,
+    /// The run generated as many output tokens as its cap allows
+    Truncated,
+    /// The run's wall-clock time limit passed
+    TimedOut,
+}
+
+/// Result of a single [`Agent::run`]
+#[derive(Debug, Clone)]
+pub struct AgentRun {
+    /// Every response received during the run, in order
+    pub updates: Vec<ConversationUpdate>,
+    /// Why the run ended
+    pub stop_reason: AgentStopReason,
+}
+
+impl AgentRun {
+    /// Text of the last response in the run
+    pub fn final_text(&self) -> String {
+        self.updates
+            .last()
+            .map(ConversationUpdate::text)
+            .unwrap_or_default()
+    }
+}
+
+/// A step-by-step plan Claude proposes before acting, see [`Agent::plan`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Plan {
+    /// What the plan sets out to achieve
+    pub goal: String,
+    /// Steps in the order they should be carried out
+    pub steps: Vec<PlanStep>,
+}
+
+/// One step of a [`Plan`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct PlanStep {
+    /// What to do in this step
+    pub description: String,
+    /// Names of the tools the step expects to use
+    #[serde(default)]
+    pub tools: Vec<String>,
+}
+
+/// Result of [`Agent::execute_plan`]
+#[derive(Debug, Clone)]
+pub struct PlanRun {
+    /// One run per step that was started, in order
+    pub steps: Vec<AgentRun>,
+    /// Why the last step ended; anything but `Completed` stops the plan early
+    pub stop_reason: AgentStopReason,
+}
+
+impl PlanRun {
+    /// Whether every step ran to completion
+    pub fn is_complete(&self, plan: &Plan) -> bool {
+        self.stop_reason == AgentStopReason::Completed && self.steps.len() == plan.steps.len()
+    }
+}
+
+/// A conversation bundled with its client, tools, limits, approval policy, and storage
+///
+/// [`Agent::run`] adds the user's input, then sends, executes approved tools, and sends the
+/// results back until Claude stops requesting tools or a limit is reached. When a run stops
+/// early, outstanding tool uses are answered with error results so the history stays valid
+/// for the next run.
+pub struct Agent {
+    client: AnthropicClient,
+    conversation: Conversation,
+    registry: ToolRegistry,
+    max_iterations: u32,
+    token_budget: Option<u32>,
+    tokens_used: u32,
+    max_output_tokens: Option<u32>,
+    time_limit: Option<Duration>,
+    shared_budget: Option<SharedBudget>,
+    approval: ApprovalPolicy,
+    storage: Option<(Box<dyn ConversationStore>, String)>,
+    history_loaded: bool,
+    /// The history as last loaded or saved, so saves only append what's new
+    saved_history: Vec<Arc<Message>>,
+}
+
+impl Agent {
+    /// Create an agent using the given client and model
+    pub fn new(client: AnthropicClient, model: impl Into<String>, max_tokens: u32) -> Self {
+        Self::with_conversation(client, Conversation::new(model, max_tokens))
+    }
+
+    /// Create an agent around an existing, already configured conversation
+    pub fn with_conversation(client: AnthropicClient, conversation: Conversation) -> Self {
+        Self {
+            client,
+            conversation,
+            registry: ToolRegistry::new(),
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            token_budget: None,
+            tokens_used: 0,
+            max_output_tokens: None,
+            time_limit: None,
+            shared_budget: None,
+            approval: ApprovalPolicy::ApproveAll,
+            storage: None,
+            history_loaded: false,
+            saved_history: Vec::new(),
+        }
+    }
+
+    /// Set the system prompt
+    pub fn with_system(mut self, system: impl Into<String>) -> Self {
+        self.conversation = self.conversation.with_system(system);
+        self
+    }
+
+    /// Set the tools available to the agent
+    pub fn with_registry(mut self, registry: ToolRegistry) -> Self {
+        self.conversation = self.conversation.with_tools(registry.tools());
+        self.registry = registry;
+        self
+    }
+
+    /// Set the maximum number of tool round-trips per run
+    pub fn with_max_iterations(mut self, max_iterations: u32) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Stop running once this many input plus output tokens have been used across runs
+    pub fn with_token_budget(mut self, token_budget: u32) -> Self {
+        self.token_budget = Some(token_budget);
+        self
+    }
+
+    /// End each run once its responses have generated this many output tokens in total
+    ///
+    /// Unlike `max_tokens`, which limits one response, this caps a whole tool loop so a
+    /// verbose agent can't spiral through many expensive turns.
+    pub fn with_max_output_tokens(mut self, max_output_tokens: u32) -> Self {
+        self.max_output_tokens = Some(max_output_tokens);
+        self
+    }
+
+    /// End each run at the first safe point after it has taken this long, e.g. 90 seconds
+    ///
+    /// The limit is checked once each response arrives and once tools finish, so a run can
+    /// overshoot by one request or one round of tools, but interactive callers get control
+    /// back promptly. Tool uses left unexecuted are answered with error results.
+    pub fn with_time_limit(mut self, time_limit: Duration) -> Self {
+        self.time_limit = Some(time_limit);
+        self
+    }
+
+    /// Count usage against a budget shared with other agents, stopping when it runs out
+    pub fn with_shared_budget(mut self, budget: SharedBudget) -> Self {
+        self.shared_budget = Some(budget);
+        self
+    }
+
+    /// Set the policy that approves tool uses before they are executed
+    pub fn with_approval_policy(mut self, approval: ApprovalPolicy) -> Self {
+        self.approval = approval;
+        self
+    }
+
+    /// Persist the history under `id`, loading any stored history before the first run
+    pub fn with_storage(
+        mut self,
+        storage: impl ConversationStore + 'static,
+        id: impl Into<String>,
+    ) -> Self {
+        self.storage = Some((Box::new(storage), id.into()));
+        self.history_loaded = false;
+        self.saved_history.clear();
+        self
+    }
+
+    /// Handle one user input through the full tool loop
+    ///
+    /// On error the conversation is rolled back to how it was before the call, so the
+    /// input can simply be run again.
+    pub async fn run(&mut self, user_input: impl Into<String>) -> Result<AgentRun, AnthropicError> {
+        if self.budget_exhausted() {
+            return Ok(AgentRun {
+                updates: Vec::new(),
+                stop_reason: AgentStopReason::BudgetExhausted,
+            });
+        }
+
+        self.load_history()?;
+        let before = self.conversation.snapshot();
+        let result = self.run_loop(user_input.into()).await;
+        if result.is_err() {
+            self.conversation.restore(before);
+        }
+        result
+    }
+
+    async fn run_loop(&mut self, user_input: String) -> Result<AgentRun, AnthropicError> {
+        self.conversation.try_add_user_message(user_input)?;
+
+        let clock = self.client.clock();
+        let started = clock.now_ms();
+        let timed_out = |limit: Option<Duration>| {
+            limit.is_some_and(|l| clock.now_ms().saturating_sub(started) >= l.as_millis() as u64)
+        };
+        let mut updates = Vec::new();
+        let mut iterations = 0;
+        let mut output_tokens = 0;
+
+        let stop_reason = loop {
+            let update = self.conversation.send(&self.client).await?;
+            self.record_usage(&update.usage);
+            output_tokens += update.usage.output_tokens;
+            let has_tools = update.has_tool_uses();
+            let paused = update.is_paused();
+            updates.push(update);
+
+            if !has_tools && !paused {
+                break AgentStopReason::Completed;
+            }
+            if self.budget_exhausted() {
+                self.conversation
+                    .abandon_pending_tools("token budget exhausted")?;
+                break AgentStopReason::BudgetExhausted;
+            }
+            if self
+                .max_output_tokens
+                .is_some_and(|limit| output_tokens >= limit)
+            {
+                self.conversation
+                    .abandon_pending_tools("output token limit reached")?;
+                break AgentStopReason::Truncated;
+            }
+            if iterations >= self.max_iterations {
+                self.conversation
+                    .abandon_pending_tools("iteration limit reached")?;
+                break AgentStopReason::MaxIterations;
+            }
+            if timed_out(self.time_limit) {
+                self.conversation
+                    .abandon_pending_tools("time limit reached")?;
+                break AgentStopReason::TimedOut;
+            }
+
+            // A paused turn is continued by sending the conversation again
+            if has_tools {
+                self.execute_pending_tools().await?;
+            }
+            iterations += 1;
+            if timed_out(self.time_limit) {
+                break AgentStopReason::TimedOut;
+            }
+        };
+
+        self.save_history()?;
+        Ok(AgentRun {
+            updates,
+            stop_reason,
+        })
+    }
+
+    /// Ask Claude for a plan to carry out `task`, without executing any tools yet
+    ///
+    /// Show the plan for approval, edit or re-plan as needed, then pass it to
+    /// [`execute_plan`](Self::execute_plan). The plan stays in the history, so later
+    /// steps can refer back to it. On error the conversation is rolled back, as in
+    /// [`run`](Self::run).
+    pub async fn plan(&mut self, task: impl Into<String>) -> Result<Plan, AnthropicError> {
+        self.load_history()?;
+        let before = self.conversation.snapshot();
+        let result = self.plan_turn(task.into()).await;
+        if result.is_err() {
+            self.conversation.restore(before);
+        }
+        result
+    }
+
+    async fn plan_turn(&mut self, task: String) -> Result<Plan, AnthropicError> {
+        self.conversation.try_add_user_message(format!(
+            "{}\n\nBefore doing anything, plan how to accomplish this. Break it into \
+             steps and list the tools each step will use. Don't call any other tools yet.",
+            task
+        ))?;
+
+        let responses = self.conversation.turn_usage().len();
+        let plan = self
+            .conversation
+            .send_structured::<Plan>(&self.client)
+            .await;
+        let usage: Vec<Usage> = self.conversation.turn_usage()[responses..].into();
+        for usage in &usage {
+            self.record_usage(usage);
+        }
+        let plan = plan?;
+        self.save_history()?;
+        Ok(plan)
+    }
+
+    /// Carry out an approved plan, running the tool loop once per step
+    ///
+    /// Each step is its own [`run`](Self::run), so limits apply per step and the history
+    /// is saved after each one. Execution stops at the first step that doesn't complete.
+    pub async fn execute_plan(&mut self, plan: &Plan) -> Result<PlanRun, AnthropicError> {
+        let mut steps = Vec::new();
+        let mut stop_reason = AgentStopReason::Completed;
+        for (index, step) in plan.steps.iter().enumerate() {
+            let run = self
+                .run(format!(
+                    "Carry out step {} of {} of the plan: {}",
+                    index + 1,
+                    plan.steps.len(),
+                    step.description
+                ))
+                .await?;
+            stop_reason = run.stop_reason;
+            steps.push(run);
+            if stop_reason != AgentStopReason::Completed {
+                break;
+            }
+        }
+        Ok(PlanRun { steps, stop_reason })
+    }
+
+    /// The underlying conversation
+    pub fn conversation(&self) -> &Conversation {
+        &self.conversation
+    }
+
+    /// Mutable access to the underlying conversation (for advanced configuration)
+    pub fn conversation_mut(&mut self) -> &mut Conversation {
+        &mut self.conversation
+    }
+
+    /// Total input plus output tokens used by this agent so far
+    pub fn tokens_used(&self) -> u32 {
+        self.tokens_used
+    }
+
+    fn record_usage(&mut self, usage: &Usage) {
+        let tokens = usage.input_tokens + usage.output_tokens;
+        self.tokens_used += tokens;
+        if let Some(ref shared_budget) = self.shared_budget {
+            shared_budget.record(tokens);
+        }
+    }
+
+    fn budget_exhausted(&self) -> bool {
+        self.token_budget
+            .is_some_and(|budget| self.tokens_used >= budget)
+            || self
+                .shared_budget
+                .as_ref()
+                .is_some_and(SharedBudget::is_exhausted)
+    }
+
+    async fn execute_pending_tools(&mut self) -> Result<(), AnthropicError> {
+        let mut results = Vec::new();
+        for tool_use in self.conversation.pending_tool_uses().to_vec() {
+            let result = match self.approval.check(&tool_use) {
+                Ok(()) => self.registry.execute(tool_use).await?,
+                Err(reason) => ToolResult::error(
+                    tool_use.id,
+                    format!("Tool use was not approved: {}", reason),
+                ),
+            };
+            results.push(result);
+        }
+        self.conversation.add_tool_results(results)
+    }
+
+    fn load_history(&mut self) -> Result<(), AnthropicError> {
+        if self.history_loaded {
+            return Ok(());
+        }
+        if let Some((storage, id)) = &self.storage {
+            if let Some(messages) = storage.load(id)? {
+                self.saved_history = messages.clone();
+                *self.conversation.messages_mut() = messages;
+            }
+        }
+        self.history_loaded = true;
+        Ok(())
+    }
+
+    fn save_history(&mut self) -> Result<(), AnthropicError> {
+        match &self.storage {
+            Some((storage, id)) => save_delta(
+                storage.as_ref(),
+                id,
+                &mut self.saved_history,
+                self.conversation.messages(),
+            ),
+            None => Ok(()),
+        }
+    }
+}
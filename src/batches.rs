@@ -0,0 +1,187 @@
+use crate::client::AnthropicClient;
+use crate::error::{AnthropicError, ApiErrorResponse};
+use crate::types::messages::{CreateMessageRequest, MessageResponse};
+use hyperware_process_lib::http::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// One request in a Message Batch, identified by a caller-chosen `custom_id`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchRequestItem {
+    pub custom_id: String,
+    pub params: CreateMessageRequest,
+}
+
+impl BatchRequestItem {
+    /// Wrap a request for a batch; the `stream` flag is dropped since batches can't stream
+    pub fn new(custom_id: impl Into<String>, mut params: CreateMessageRequest) -> Self {
+        params.stream = None;
+        Self {
+            custom_id: custom_id.into(),
+            params,
+        }
+    }
+}
+
+/// How many of a batch's requests are in each state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchRequestCounts {
+    pub processing: u32,
+    pub succeeded: u32,
+    pub errored: u32,
+    pub canceled: u32,
+    pub expired: u32,
+}
+
+/// A Message Batch as reported by the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageBatch {
+    pub id: String,
+    /// One of `in_progress`, `canceling`, or `ended`
+    pub processing_status: String,
+    pub request_counts: BatchRequestCounts,
+    pub created_at: String,
+    pub expires_at: String,
+    #[serde(default)]
+    pub ended_at: Option<String>,
+    #[serde(default)]
+    pub results_url: Option<String>,
+}
+
+impl MessageBatch {
+    /// Whether processing has finished and results can be fetched
+    pub fn is_ended(&self) -> bool {
+        self.processing_status == "ended"
+    }
+}
+
+/// The outcome of one request in a batch
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BatchResult {
+    Succeeded { message: MessageResponse },
+    Errored { error: ApiErrorResponse },
+    Canceled,
+    Expired,
+}
+
+/// One line of a batch's results, matched to its request by `custom_id`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResultItem {
+    pub custom_id: String,
+    pub result: BatchResult,
+}
+
+impl BatchResultItem {
+    /// The response, or the error the request ended with
+    pub fn into_response(self) -> Result<MessageResponse, AnthropicError> {
+        match self.result {
+            BatchResult::Succeeded { message } => Ok(message),
+            BatchResult::Errored { error } => Err(AnthropicError::ApiError {
+                error_type: error.error.error_type,
+                message: error.error.message,
+            }),
+            BatchResult::Canceled => Err(AnthropicError::ApiError {
+                error_type: "canceled".to_string(),
+                message: format!("Batch request {} was canceled", self.custom_id),
+            }),
+            BatchResult::Expired => Err(AnthropicError::ApiError {
+                error_type: "expired".to_string(),
+                message: format!("Batch request {} expired before processing", self.custom_id),
+            }),
+        }
+    }
+}
+
+/// Parse the JSONL body of a batch's results
+pub fn parse_batch_results(jsonl: &str) -> Result<Vec<BatchResultItem>, AnthropicError> {
+    jsonl
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line).map_err(|e| {
+                AnthropicError::Deserialization(format!("Failed to parse batch result: {}", e))
+            })
+        })
+        .collect()
+}
+
+impl AnthropicClient {
+    /// Submit requests to the Message Batches API, at half the price of direct calls
+    ///
+    /// Each request is validated first, and betas the requests need are sent with the batch.
+    /// The submission is never retried, since a retried timeout could create the batch twice.
+    pub async fn create_message_batch(
+        &self,
+        requests: &[BatchRequestItem],
+    ) -> Result<MessageBatch, AnthropicError> {
+        let mut betas: Vec<&str> = Vec::new();
+        for item in requests {
+            item.params.validate()?;
+            for beta in &item.params.betas {
+                if !betas.contains(&beta.as_str()) {
+                    betas.push(beta);
+                }
+            }
+        }
+        let body = json!({ "requests": requests });
+        let response = self
+            .clone()
+            .with_max_retries(0)
+            .send_raw_with_betas(
+                "/v1/messages/batches",
+                Method::POST,
+                body,
+                &betas,
+                HashMap::new(),
+            )
+            .await?;
+        parse_batch(response.body)
+    }
+
+    /// Get a batch's processing status
+    pub async fn get_message_batch(&self, batch_id: &str) -> Result<MessageBatch, AnthropicError> {
+        let path = format!("/v1/messages/batches/{}", batch_id);
+        let response = self
+            .send_raw(&path, Method::GET, Value::Null, HashMap::new())
+            .await?;
+        parse_batch(response.body)
+    }
+
+    /// Cancel a batch; requests already processed still have results
+    pub async fn cancel_message_batch(
+        &self,
+        batch_id: &str,
+    ) -> Result<MessageBatch, AnthropicError> {
+        let path = format!("/v1/messages/batches/{}/cancel", batch_id);
+        let response = self
+            .send_raw(&path, Method::POST, Value::Null, HashMap::new())
+            .await?;
+        parse_batch(response.body)
+    }
+
+    /// Fetch the results of an ended batch
+    pub async fn message_batch_results(
+        &self,
+        batch_id: &str,
+    ) -> Result<Vec<BatchResultItem>, AnthropicError> {
+        let path = format!("/v1/messages/batches/{}/results", batch_id);
+        let response = self
+            .send_raw(&path, Method::GET, Value::Null, HashMap::new())
+            .await?;
+        match response.body {
+            Value::Null => Ok(Vec::new()),
+            Value::String(jsonl) => parse_batch_results(&jsonl),
+            // A single result line is itself valid JSON
+            line => parse_batch_results(&line.to_string()),
+        }
+    }
+}
+
+fn parse_batch(body: Value) -> Result<MessageBatch, AnthropicError> {
+    serde_json::from_value(body).map_err(|e| {
+        AnthropicError::Deserialization(format!("Failed to parse message batch: {}", e))
+    })
+}
@@ -0,0 +1,132 @@
+use crate::types::messages::{
+    CacheControl, Content, ContentBlock, CreateMessageRequest, SystemPrompt, ToolResultContent,
+};
+
+/// Beta for the one-hour prompt cache TTL
+pub const EXTENDED_CACHE_TTL_BETA: &str = "extended-cache-ttl-2025-04-11";
+
+/// Beta for computer use with the October 2024 tool versions
+pub const COMPUTER_USE_2024_10_22_BETA: &str = "computer-use-2024-10-22";
+
+/// Beta for computer use with the January 2025 tool versions
+pub const COMPUTER_USE_2025_01_24_BETA: &str = "computer-use-2025-01-24";
+
+/// Beta for the May 2025 code execution tool
+pub const CODE_EXECUTION_2025_05_22_BETA: &str = "code-execution-2025-05-22";
+
+/// Beta for the August 2025 code execution tool
+pub const CODE_EXECUTION_2025_08_25_BETA: &str = "code-execution-2025-08-25";
+
+/// Beta for the web fetch tool
+pub const WEB_FETCH_BETA: &str = "web-fetch-2025-09-10";
+
+/// Beta for thinking between tool calls, added by `with_interleaved_thinking`
+pub const INTERLEAVED_THINKING_BETA: &str = "interleaved-thinking-2025-05-14";
+
+/// Beta for streaming tool inputs without buffering them into complete JSON values
+pub const FINE_GRAINED_TOOL_STREAMING_BETA: &str = "fine-grained-tool-streaming-2025-05-14";
+
+/// Beta for writing tool calls in fewer output tokens, for Claude 3.7 Sonnet
+pub const TOKEN_EFFICIENT_TOOLS_BETA: &str = "token-efficient-tools-2025-02-19";
+
+/// Beta for referencing files uploaded through the Files API
+pub const FILES_API_BETA: &str = "files-api-2025-04-14";
+
+/// Beta for the memory tool and context management
+pub const CONTEXT_MANAGEMENT_BETA: &str = "context-management-2025-06-27";
+
+/// Beta for calling tools on remote MCP servers
+pub const MCP_CLIENT_BETA: &str = "mcp-client-2025-04-04";
+
+/// The `anthropic-beta` values a request needs for the features it uses, without duplicates
+pub fn required_betas(request: &CreateMessageRequest) -> Vec<&'static str> {
+    let mut betas = Vec::new();
+    let mut add = |beta: &'static str| {
+        if !betas.contains(&beta) {
+            betas.push(beta);
+        }
+    };
+
+    for tool in request.tools.iter().flatten() {
+        if let Some(beta) = tool.tool_type.as_deref().and_then(tool_beta) {
+            add(beta);
+        }
+    }
+
+    if request
+        .mcp_servers
+        .as_ref()
+        .is_some_and(|servers| !servers.is_empty())
+    {
+        add(MCP_CLIENT_BETA);
+    }
+
+    let mut cache_controls: Vec<&CacheControl> = request
+        .tools
+        .iter()
+        .flatten()
+        .filter_map(|tool| tool.cache_control.as_ref())
+        .collect();
+    if let Some(SystemPrompt::Blocks(blocks)) = &request.system {
+        cache_controls.extend(
+            blocks
+                .iter()
+                .filter_map(|block| block.cache_control.as_ref()),
+        );
+    }
+    for message in &request.messages {
+        if let Content::Blocks(blocks) = &message.content {
+            collect_block_cache_controls(blocks, &mut cache_controls);
+        }
+    }
+    if cache_controls
+        .iter()
+        .any(|cache_control| cache_control.ttl.as_deref() == Some("1h"))
+    {
+        add(EXTENDED_CACHE_TTL_BETA);
+    }
+    if request
+        .messages
+        .iter()
+        .any(|message| match &message.content {
+            Content::Blocks(blocks) => blocks.iter().any(ContentBlock::uses_file),
+            Content::Text(_) => false,
+        })
+    {
+        add(FILES_API_BETA);
+    }
+
+    betas
+}
+
+fn tool_beta(tool_type: &str) -> Option<&'static str> {
+    match tool_type {
+        "computer_20241022" | "bash_20241022" | "text_editor_20241022" => {
+            Some(COMPUTER_USE_2024_10_22_BETA)
+        }
+        "computer_20250124" | "bash_20250124" | "text_editor_20250124" => {
+            Some(COMPUTER_USE_2025_01_24_BETA)
+        }
+        "code_execution_20250522" => Some(CODE_EXECUTION_2025_05_22_BETA),
+        "code_execution_20250825" => Some(CODE_EXECUTION_2025_08_25_BETA),
+        "web_fetch_20250910" => Some(WEB_FETCH_BETA),
+        "memory_20250818" => Some(CONTEXT_MANAGEMENT_BETA),
+        _ => None,
+    }
+}
+
+fn collect_block_cache_controls<'a>(
+    blocks: &'a [ContentBlock],
+    cache_controls: &mut Vec<&'a CacheControl>,
+) {
+    for block in blocks {
+        if let ContentBlock::ToolResult {
+            content: Some(ToolResultContent::Blocks(nested)),
+            ..
+        } = block
+        {
+            collect_block_cache_controls(nested, cache_controls);
+        }
+        cache_controls.extend(block.cache_control());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::client::AnthropicClient;
+use crate::conversation::{Conversation, ConversationUpdate};
+use crate::error::{AnthropicError, HttpClientError, TransportErrorKind};
+use crate::streaming::MessageStream;
+use crate::types::messages::{CreateMessageRequest, MessageResponse};
+use futures::executor::block_on;
+use hyperware_process_lib::http::client::{
+    HttpClientAction, HttpClientResponse, OutgoingHttpRequest,
+};
+use hyperware_process_lib::http::{Method, StatusCode};
+use hyperware_process_lib::{get_blob, Message, Request, SendErrorKind};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The node's HTTP client process
+const HTTP_CLIENT: (&str, &str, &str, &str) = ("our", "http-client", "distro", "sys");
+
+/// Status, headers, and body of an HTTP response
+type HttpParts = (StatusCode, HashMap<String, String>, Vec<u8>);
+
+/// A synchronous wrapper around [`AnthropicClient`] for process bodies that don't use futures
+///
+/// Requests go to the node's HTTP client as blocking messages, and retry backoff and rate
+/// limit waits use a blocking timer, so nothing waits on the hyperapp runtime. Each call
+/// still runs the async client's retry logic, rate limiter, guardrails, and journaling.
+/// Requests passed to [`send_many`](Self::send_many) are sent one after another. Don't
+/// call it from inside an async handler; await the async client there instead.
+#[derive(Clone)]
+pub struct BlockingAnthropicClient {
+    client: AnthropicClient,
+}
+
+impl BlockingAnthropicClient {
+    /// Create a blocking client with the given API key
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self::from(AnthropicClient::new(api_key))
+    }
+
+    /// The underlying async client
+    pub fn client(&self) -> &AnthropicClient {
+        &self.client
+    }
+
+    /// Send a message to the Anthropic API with retry logic
+    pub fn send_message(
+        &self,
+        request: CreateMessageRequest,
+    ) -> Result<MessageResponse, AnthropicError> {
+        block_on(self.client.send_message(request))
+    }
+
+    /// Send many requests with at most `concurrency` in flight, returning results in input order
+    pub fn send_many(
+        &self,
+        requests: Vec<CreateMessageRequest>,
+        concurrency: usize,
+    ) -> Vec<Result<MessageResponse, AnthropicError>> {
+        block_on(self.client.send_many(requests, concurrency))
+    }
+
+    /// Send a message with `stream: true` and collect the server-sent events
+    pub fn stream_message(
+        &self,
+        request: CreateMessageRequest,
+    ) -> Result<MessageStream, AnthropicError> {
+        block_on(self.client.stream_message(request))
+    }
+
+    /// Send a simple text message and get the response text
+    pub fn send_simple_message(
+        &self,
+        model: impl Into<String>,
+        prompt: impl Into<String>,
+        max_tokens: u32,
+    ) -> Result<String, AnthropicError> {
+        block_on(self.client.send_simple_message(model, prompt, max_tokens))
+    }
+
+    /// Ask a question about a single image and get the response text
+    pub fn send_image_message(
+        &self,
+        model: impl Into<String>,
+        prompt: impl Into<String>,
+        image_bytes: impl Into<Arc<[u8]>>,
+        media_type: impl Into<String>,
+        max_tokens: u32,
+    ) -> Result<String, AnthropicError> {
+        block_on(
+            self.client
+                .send_image_message(model, prompt, image_bytes, media_type, max_tokens),
+        )
+    }
+
+    /// Send the current conversation to Claude and get a response
+    pub fn send_conversation(
+        &self,
+        conversation: &mut Conversation,
+    ) -> Result<ConversationUpdate, AnthropicError> {
+        block_on(conversation.send(&self.client))
+    }
+}
+
+impl From<AnthropicClient> for BlockingAnthropicClient {
+    fn from(client: AnthropicClient) -> Self {
+        Self {
+            client: client.with_blocking_transport(),
+        }
+    }
+}
+
+impl AnthropicClient {
+    /// Wrap this client for synchronous use
+    pub fn blocking(self) -> BlockingAnthropicClient {
+        BlockingAnthropicClient::from(self)
+    }
+}
+
+/// Send an HTTP request through the node's HTTP client, blocking until it replies
+pub(crate) fn send_http_blocking(
+    method: Method,
+    url: url::Url,
+    headers: HashMap<String, String>,
+    timeout: u64,
+    body: Vec<u8>,
+) -> Result<HttpParts, AnthropicError> {
+    let action = HttpClientAction::Http(OutgoingHttpRequest {
+        method: method.to_string(),
+        version: None,
+        url: url.to_string(),
+        headers,
+    });
+    let request = Request::to(HTTP_CLIENT)
+        .body(serde_json::to_vec(&action)?)
+        .blob_bytes(body);
+    let message = await_response(request, timeout).map_err(|kind| {
+        let message = match kind {
+            TransportErrorKind::Timeout => format!("No reply within {} seconds", timeout),
+            _ => "The HTTP client could not be reached".to_string(),
+        };
+        AnthropicError::HttpClient { kind, message }
+    })?;
+
+    let reply: Result<HttpClientResponse, HttpClientError> =
+        serde_json::from_slice(message.body())?;
+    let HttpClientResponse::Http(response) = reply? else {
+        return Err(AnthropicError::InvalidResponse(
+            "The HTTP client answered with a WebSocket reply".to_string(),
+        ));
+    };
+    let status = StatusCode::from_u16(response.status).map_err(|_| {
+        AnthropicError::InvalidResponse(format!("Invalid HTTP status {}", response.status))
+    })?;
+    let body = get_blob().map(|blob| blob.bytes).unwrap_or_default();
+    Ok((status, response.headers, body))
+}
+
+/// Send a request to a process and block until it responds, or fail with why it didn't
+pub(crate) fn await_response(
+    request: Request,
+    timeout: u64,
+) -> Result<Message, TransportErrorKind> {
+    match request.send_and_await_response(timeout) {
+        Ok(Ok(message)) => Ok(message),
+        Ok(Err(error)) => match error.kind() {
+            SendErrorKind::Timeout => Err(TransportErrorKind::Timeout),
+            _ => Err(TransportErrorKind::Other),
+        },
+        Err(_) => Err(TransportErrorKind::InvalidRequest),
+    }
+}
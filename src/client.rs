@@ -1,78 +1,235 @@
+use crate::betas::required_betas;
+use crate::blocking::send_http_blocking;
+use crate::clock::{BlockingClock, Clock, ClockHandle};
 use crate::error::{AnthropicError, ApiErrorResponse};
-use crate::types::messages::{Content, CreateMessageRequest, Message, MessageResponse, Role};
-use hyperware_process_lib::{
-    http::{client::send_request_await_response, Method},
-    hyperapp::sleep,
-    println,
+use crate::guardrails::Guardrails;
+use crate::journal::{outcome_known, JournalEntry, RequestJournal};
+use crate::models::MaxTokensPolicy;
+use crate::rate_limit::{request_tokens, SharedRateLimiter};
+use crate::stats::ClientStats;
+use crate::streaming::{with_prefill, MessageStream};
+use crate::transcript::{TranscriptRecorder, TranscriptReplay};
+use crate::types::messages::{
+    Content, ContentBlock, CreateMessageRequest, ImageSource, Message, MessageResponse, Role,
+    StopReason, Usage, MAX_REQUEST_BYTES,
 };
-use serde_json;
+use crate::versions::ApiVersion;
+use crate::warnings::{ClientWarning, DeprecationNotice};
+use futures::StreamExt;
+use hyperware_process_lib::http::{client::send_request_await_response, Method};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
 const ANTHROPIC_API_BASE_URL: &str = "https://api.anthropic.com";
-const ANTHROPIC_API_VERSION: &str = "2023-06-01";
 const ANTHROPIC_OAUTH_BETA: &str = "oauth-2025-04-20";
 const DEFAULT_TIMEOUT_SECONDS: u64 = 60;
 const MAX_RETRIES: u32 = 10;
 const INITIAL_RETRY_DELAY_MS: u64 = 1000;
 const MAX_RETRY_DELAY_MS: u64 = 60000;
 
+/// Headers the client sets for authentication and versioning, which custom headers may
+/// only override after [`AnthropicClient::allow_protected_headers`]
+pub const PROTECTED_HEADERS: &[&str] = &["x-api-key", "authorization", "anthropic-version"];
+
+/// Client for the Anthropic Messages API
+///
+/// Cloning is cheap: clones share the configuration and stats, and the recorder, rate
+/// limiter, and journal keep their state behind `Arc`s, so one client can live in shared
+/// process state and be used from any handler.
+#[derive(Clone)]
 pub struct AnthropicClient {
+    config: Arc<ClientConfig>,
+    stats: Arc<Mutex<ClientStats>>,
+}
+
+type WarningFn = dyn Fn(&ClientWarning) + Send + Sync;
+type UsageFn = dyn Fn(&Usage, &str, Duration) + Send + Sync;
+pub(crate) type RetryFn = dyn Fn(&RetryEvent) + Send + Sync;
+
+/// Configuration shared by clones of a client; builders copy it on write
+#[derive(Clone)]
+struct ClientConfig {
     api_key: String,
     base_url: String,
     api_version: String,
     timeout: u64,
     max_retries: u32,
     custom_headers: HashMap<String, String>,
+    allow_protected_headers: bool,
     use_oauth: bool,
+    guardrails: Option<Guardrails>,
+    recorder: Option<TranscriptRecorder>,
+    replay: Option<TranscriptReplay>,
+    rate_limiter: Option<SharedRateLimiter>,
+    /// Set by [`with_clock`](AnthropicClient::with_clock); the system clock otherwise
+    clock: Option<ClockHandle>,
+    /// Send requests and wait synchronously, for [`BlockingAnthropicClient`](crate::BlockingAnthropicClient)
+    blocking: bool,
+    journal: Option<Arc<dyn RequestJournal>>,
+    max_request_bytes: usize,
+    max_tokens_policy: Option<MaxTokensPolicy>,
+    check_context_window: bool,
+    auto_betas: bool,
+    capture_headers: bool,
+    stream_resumes: u32,
+    strict_deserialization: bool,
+    on_warning: Option<Arc<WarningFn>>,
+    on_usage: Option<Arc<UsageFn>>,
+    on_retry: Option<Arc<RetryFn>>,
+    #[cfg(feature = "vfs")]
+    debug_dump: Option<crate::vfs::VfsDebugDump>,
+    default_model: Option<String>,
+    spend_label: Option<String>,
 }
 
 impl AnthropicClient {
     /// Create a new Anthropic API client with the provided API key
     pub fn new(api_key: impl Into<String>) -> Self {
-        Self {
+        let config = ClientConfig {
             api_key: api_key.into(),
             base_url: ANTHROPIC_API_BASE_URL.to_string(),
-            api_version: ANTHROPIC_API_VERSION.to_string(),
+            api_version: ApiVersion::LATEST.into(),
             timeout: DEFAULT_TIMEOUT_SECONDS,
             max_retries: MAX_RETRIES,
             custom_headers: HashMap::new(),
+            allow_protected_headers: false,
             use_oauth: false,
+            guardrails: None,
+            recorder: None,
+            replay: None,
+            rate_limiter: None,
+            clock: None,
+            blocking: false,
+            journal: None,
+            max_request_bytes: MAX_REQUEST_BYTES,
+            max_tokens_policy: None,
+            check_context_window: false,
+            auto_betas: true,
+            capture_headers: false,
+            stream_resumes: 0,
+            strict_deserialization: false,
+            on_warning: None,
+            on_usage: None,
+            on_retry: None,
+            #[cfg(feature = "vfs")]
+            debug_dump: None,
+            default_model: None,
+            spend_label: None,
+        };
+        Self {
+            config: Arc::new(config),
+            stats: Arc::new(Mutex::new(ClientStats::default())),
         }
     }
 
+    fn config_mut(&mut self) -> &mut ClientConfig {
+        Arc::make_mut(&mut self.config)
+    }
+
     /// Create a new client with custom base URL (useful for testing or proxies)
     pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
-        self.base_url = base_url.into();
+        self.config_mut().base_url = base_url.into();
         self
     }
 
-    /// Set a custom API version
+    /// Set the `anthropic-version` header, e.g. [`ApiVersion::V2023_06_01`]
+    ///
+    /// Requests using features the version predates, and versions the SDK doesn't know,
+    /// are reported through the warning callback but still sent.
     pub fn with_api_version(mut self, api_version: impl Into<String>) -> Self {
-        self.api_version = api_version.into();
+        self.config_mut().api_version = api_version.into();
         self
     }
 
+    /// Set the model conversations built from this client use unless they pick one
+    pub fn with_default_model(mut self, model: impl Into<String>) -> Self {
+        self.config_mut().default_model = Some(model.into());
+        self
+    }
+
+    /// The model set with [`with_default_model`](Self::with_default_model), if any
+    pub fn default_model(&self) -> Option<&str> {
+        self.config.default_model.as_deref()
+    }
+
+    pub(crate) fn api_key(&self) -> &str {
+        &self.config.api_key
+    }
+
     /// Set custom timeout in seconds
     pub fn with_timeout(mut self, timeout: u64) -> Self {
-        self.timeout = timeout;
+        self.config_mut().timeout = timeout;
         self
     }
 
     /// Set maximum number of retries for transient errors
     pub fn with_max_retries(mut self, max_retries: u32) -> Self {
-        self.max_retries = max_retries;
+        self.config_mut().max_retries = max_retries;
+        self
+    }
+
+    /// Use `clock` for retry delays, rate limit waits, and agent time limits instead of the
+    /// system clock
+    ///
+    /// Pass a [`MockClock`](crate::MockClock) to test retries without waiting.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.config_mut().clock = Some(ClockHandle::new(clock));
+        self
+    }
+
+    /// The clock set with [`with_clock`](Self::with_clock), or the system clock
+    pub(crate) fn clock(&self) -> ClockHandle {
+        match self.config.clock {
+            Some(ref clock) => clock.clone(),
+            None if self.config.blocking => ClockHandle::new(BlockingClock),
+            None => ClockHandle::default(),
+        }
+    }
+
+    /// Send requests, and wait out retries, without yielding to the hyperapp runtime
+    pub(crate) fn with_blocking_transport(mut self) -> Self {
+        self.config_mut().blocking = true;
         self
     }
 
     /// Add a custom header to be sent with all requests
     pub fn with_header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
-        self.custom_headers.insert(key.into(), value.into());
+        self.config_mut()
+            .custom_headers
+            .insert(key.into(), value.into());
         self
     }
 
     /// Add multiple custom headers to be sent with all requests
     pub fn with_headers(mut self, headers: HashMap<String, String>) -> Self {
-        self.custom_headers.extend(headers);
+        self.config_mut().custom_headers.extend(headers);
+        self
+    }
+
+    /// Stop sending a custom header, matching its name case-insensitively
+    pub fn remove_header(mut self, key: &str) -> Self {
+        self.config_mut()
+            .custom_headers
+            .retain(|name, _| !name.eq_ignore_ascii_case(key));
+        self
+    }
+
+    /// Stop sending every custom header
+    pub fn clear_headers(mut self) -> Self {
+        self.config_mut().custom_headers.clear();
+        self
+    }
+
+    /// Let custom headers replace the auth and version headers the client sets itself
+    ///
+    /// Without this, requests fail with [`AnthropicError::InvalidRequest`] if a custom
+    /// header would override any of [`PROTECTED_HEADERS`].
+    pub fn allow_protected_headers(mut self) -> Self {
+        self.config_mut().allow_protected_headers = true;
         self
     }
 
@@ -80,31 +237,164 @@ impl AnthropicClient {
     /// When enabled, the API key will be sent as a Bearer token in the Authorization header
     /// instead of using the x-api-key header
     pub fn with_oauth(mut self) -> Self {
-        self.use_oauth = true;
+        self.config_mut().use_oauth = true;
         self
     }
 
-    /// Calculate retry delay with exponential backoff and jitter, in ms
-    fn calculate_retry_delay(attempt: u32) -> u64 {
-        let base_delay = INITIAL_RETRY_DELAY_MS * 2u64.pow(attempt);
-        let delay_with_jitter = base_delay + (rand::random::<u64>() % 1000);
-        let final_delay = delay_with_jitter.min(MAX_RETRY_DELAY_MS);
-        final_delay
+    /// Apply content filters to every request and response sent through this client
+    pub fn with_guardrails(mut self, guardrails: Guardrails) -> Self {
+        self.config_mut().guardrails = Some(guardrails);
+        self
     }
 
-    /// Check if an error is retryable
-    fn is_retryable_error(error: &AnthropicError) -> bool {
-        match error {
-            AnthropicError::ApiError { error_type, .. } => {
-                // Retry on overloaded errors
-                error_type == "overloaded_error" || error_type == "api_error"
-            }
-            AnthropicError::RateLimit => true,
-            AnthropicError::HttpClient(msg) => {
-                // Retry on connection errors or timeouts
-                msg.contains("timeout") || msg.contains("connection")
-            }
-            _ => false,
+    /// Record every request and response sent through this client
+    pub fn with_recorder(mut self, recorder: TranscriptRecorder) -> Self {
+        self.config_mut().recorder = Some(recorder);
+        self
+    }
+
+    /// Answer requests from a recorded transcript instead of calling the API
+    pub fn with_replay(mut self, replay: TranscriptReplay) -> Self {
+        self.config_mut().replay = Some(replay);
+        self
+    }
+
+    /// Wait for the rate limiter before each request, sharing limits with its other users
+    pub fn with_rate_limiter(mut self, rate_limiter: SharedRateLimiter) -> Self {
+        self.config_mut().rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Reject requests whose serialized body exceeds `max_request_bytes` before uploading them
+    ///
+    /// Values above the API's own limit are clamped to it.
+    pub fn with_max_request_bytes(mut self, max_request_bytes: usize) -> Self {
+        self.config_mut().max_request_bytes = max_request_bytes.min(MAX_REQUEST_BYTES);
+        self
+    }
+
+    /// Check each request's `max_tokens` against the model's output ceiling before sending
+    pub fn with_max_tokens_policy(mut self, policy: MaxTokensPolicy) -> Self {
+        self.config_mut().max_tokens_policy = Some(policy);
+        self
+    }
+
+    /// Estimate each request's size before sending and fail with
+    /// [`AnthropicError::ContextWindowExceeded`] if it can't fit the model's context window
+    pub fn with_context_window_check(mut self) -> Self {
+        self.config_mut().check_context_window = true;
+        self
+    }
+
+    /// Stop adding `anthropic-beta` values for the features each request uses
+    ///
+    /// By default the client adds the betas required by e.g. one-hour cache TTLs or
+    /// versioned computer use and code execution tools, alongside any set by hand.
+    pub fn without_auto_betas(mut self) -> Self {
+        self.config_mut().auto_betas = false;
+        self
+    }
+
+    /// Attach the full response header map to each `MessageResponse` and API error
+    ///
+    /// Useful behind proxies and gateways that report metadata in custom headers. Errors
+    /// are wrapped in [`AnthropicError::WithHeaders`]; use [`AnthropicError::inner`] to
+    /// match on the underlying error.
+    pub fn with_capture_headers(mut self, capture_headers: bool) -> Self {
+        self.config_mut().capture_headers = capture_headers;
+        self
+    }
+
+    /// Resume streams that break off partway up to `max_resumes` times per request
+    ///
+    /// The request is sent again with the text received so far as assistant prefill, and
+    /// the continuation is spliced onto the stream. Streams interrupted inside a tool use
+    /// or thinking block are restarted from scratch instead.
+    pub fn with_stream_resume(mut self, max_resumes: u32) -> Self {
+        self.config_mut().stream_resumes = max_resumes;
+        self
+    }
+
+    /// Fail on response fields the SDK doesn't know, instead of keeping them in `extra`
+    ///
+    /// Tolerant parsing is the default so new API fields don't break production; strict
+    /// parsing is meant for CI, where it surfaces protocol drift early.
+    pub fn with_strict_deserialization(mut self, strict: bool) -> Self {
+        self.config_mut().strict_deserialization = strict;
+        self
+    }
+
+    /// Call `callback` for non-fatal conditions, such as a deprecation notice for the model
+    ///
+    /// Warnings cover nearly exhausted rate limits, truncated responses, unknown response
+    /// fields, and API version mismatches; see [`ClientWarning`] for the full list.
+    pub fn with_warning_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&ClientWarning) + Send + Sync + 'static,
+    {
+        self.config_mut().on_warning = Some(Arc::new(callback));
+        self
+    }
+
+    /// Call `callback` with the usage, model, and latency of every successful request
+    ///
+    /// Latency covers the HTTP exchange including retries; replayed responses report
+    /// near-zero latency.
+    pub fn with_usage_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Usage, &str, Duration) + Send + Sync + 'static,
+    {
+        self.config_mut().on_usage = Some(Arc::new(callback));
+        self
+    }
+
+    /// Call `observer` before each retry of a failed request
+    ///
+    /// The client logs nothing itself, since library output on stdio is noise inside
+    /// Hyperware processes; forward events to the process's own logging here.
+    pub fn with_retry_observer<F>(mut self, observer: F) -> Self
+    where
+        F: Fn(&RetryEvent) + Send + Sync + 'static,
+    {
+        self.config_mut().on_retry = Some(Arc::new(observer));
+        self
+    }
+
+    /// Write every request and its response or error to the VFS for debugging
+    #[cfg(feature = "vfs")]
+    pub fn with_vfs_debug_dump(mut self, debug_dump: crate::vfs::VfsDebugDump) -> Self {
+        self.config_mut().debug_dump = Some(debug_dump);
+        self
+    }
+
+    /// Attribute this client's usage to `label` in [`ClientStats::spend`], e.g. a tenant
+    ///
+    /// Clones share stats, so one client cloned per tenant keeps every tenant's spend in
+    /// one place. Without a label, usage is attributed to a fingerprint of the API key.
+    pub fn with_spend_label(mut self, label: impl Into<String>) -> Self {
+        self.config_mut().spend_label = Some(label.into());
+        self
+    }
+
+    /// A snapshot of the request counts, spend, and deprecation notices seen by this client
+    pub fn stats(&self) -> ClientStats {
+        self.stats
+            .lock()
+            .map(|stats| stats.clone())
+            .unwrap_or_default()
+    }
+
+    /// Journal every request before sending it and mark it complete once its outcome is known
+    pub fn with_journal(mut self, journal: impl RequestJournal + 'static) -> Self {
+        self.config_mut().journal = Some(Arc::new(journal));
+        self
+    }
+
+    /// Requests journaled as in flight whose outcome is unknown, e.g. after a restart
+    pub fn in_flight_requests(&self) -> Result<Vec<JournalEntry>, AnthropicError> {
+        match self.config.journal {
+            Some(ref journal) => journal.in_flight(),
+            None => Ok(Vec::new()),
         }
     }
 
@@ -113,52 +403,493 @@ impl AnthropicClient {
         &self,
         request: CreateMessageRequest,
     ) -> Result<MessageResponse, AnthropicError> {
-        let mut last_error = None;
-
-        for attempt in 0..=self.max_retries {
-            match self.send_message_internal(request.clone()).await {
-                Ok(response) => return Ok(response),
-                Err(error) => {
-                    // Check if the error is retryable
-                    if Self::is_retryable_error(&error) && attempt < self.max_retries {
-                        let delay = Self::calculate_retry_delay(attempt);
-                        println!(
-                            "Retrying after error: {}. Attempt {} of {}. Waiting {:?}",
-                            error,
-                            attempt + 1,
-                            self.max_retries,
-                            delay
-                        );
-                        sleep(delay).await.unwrap();
-                        last_error = Some(error);
-                    } else {
-                        // Non-retryable error or max retries reached
-                        return Err(error);
-                    }
+        self.send_message_for(None, request).await
+    }
+
+    /// Send many requests with at most `concurrency` in flight, returning results in input order
+    ///
+    /// Every request goes through [`AnthropicClient::send_message`], so retries, the rate
+    /// limiter, guardrails, and journaling apply to each one.
+    pub async fn send_many(
+        &self,
+        requests: Vec<CreateMessageRequest>,
+        concurrency: usize,
+    ) -> Vec<Result<MessageResponse, AnthropicError>> {
+        futures::stream::iter(
+            requests
+                .into_iter()
+                .map(|request| self.send_message(request)),
+        )
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+    }
+
+    /// Send a message, recording `conversation_id` in the journal entry if journaling is on
+    pub async fn send_message_for(
+        &self,
+        conversation_id: Option<&str>,
+        request: CreateMessageRequest,
+    ) -> Result<MessageResponse, AnthropicError> {
+        let journal_entry = match self.config.journal {
+            Some(ref journal) => {
+                let entry = JournalEntry::new(&request, conversation_id.map(str::to_string))?;
+                journal.begin(&entry)?;
+                Some((journal, entry))
+            }
+            None => None,
+        };
+
+        let result = self.send_message_unjournaled(request).await;
+
+        if let Some((journal, entry)) = journal_entry {
+            let known = match result {
+                Ok(_) => true,
+                Err(ref error) => outcome_known(error),
+            };
+            if known {
+                if let Err(e) = journal.complete(&entry.id) {
+                    self.warn(ClientWarning::JournalNotCompleted {
+                        entry_id: entry.id.clone(),
+                        error: e.to_string(),
+                    });
                 }
             }
         }
-
-        // Should not reach here, but return last error if we do
-        Err(last_error
-            .unwrap_or_else(|| AnthropicError::InvalidResponse("Max retries reached".to_string())))
+        result
     }
 
-    /// Internal method to send a message without retry logic
-    async fn send_message_internal(
+    async fn send_message_unjournaled(
         &self,
         request: CreateMessageRequest,
     ) -> Result<MessageResponse, AnthropicError> {
-        // Ensure streaming is disabled
         let mut request = request;
+        if let Some(ref guardrails) = self.config.guardrails {
+            guardrails.apply_to_request(&mut request)?;
+        }
+        self.preflight(&mut request)?;
+        // Ensure streaming is disabled
         request.stream = Some(false);
 
-        // Serialize the request body
-        let body = serde_json::to_vec(&request)
+        let started = SystemTime::now();
+        let result = self.exchange(&request).await;
+        #[cfg(feature = "vfs")]
+        if let Some(ref debug_dump) = self.config.debug_dump {
+            let written = debug_dump.write(
+                &self.config.api_key,
+                &self.config.custom_headers,
+                &request,
+                result.as_ref(),
+                started.elapsed().unwrap_or_default(),
+            );
+            if let Err((path, error)) = written {
+                self.warn(ClientWarning::DebugDumpFailed { path, error });
+            }
+        }
+        let mut response = result?;
+        if let Some(ref recorder) = self.config.recorder {
+            recorder.record(&request, &response);
+        }
+        self.report_usage(&response, started);
+
+        if let Some(ref guardrails) = self.config.guardrails {
+            guardrails.apply_to_response(&mut response)?;
+        }
+        Ok(response)
+    }
+
+    /// Replay or POST a non-streaming request and parse the response
+    async fn exchange(
+        &self,
+        request: &CreateMessageRequest,
+    ) -> Result<MessageResponse, AnthropicError> {
+        let response = match self.config.replay {
+            Some(ref replay) => self.replay_with_retries(replay, request).await?,
+            None => {
+                let (body, headers) = self.post_with_retries(request).await?;
+                let mut response =
+                    serde_json::from_slice::<MessageResponse>(&body).map_err(|e| {
+                        AnthropicError::Deserialization(format!("Failed to parse response: {}", e))
+                    })?;
+                if self.config.capture_headers {
+                    response.headers = Some(headers);
+                }
+                response
+            }
+        };
+        self.check_strict(&response)?;
+        self.check_response(request, &response);
+        Ok(response)
+    }
+
+    /// Serve a request from the replay, retrying its injected failures as the API's would be
+    async fn replay_with_retries(
+        &self,
+        replay: &TranscriptReplay,
+        request: &CreateMessageRequest,
+    ) -> Result<MessageResponse, AnthropicError> {
+        let on_retry = self.config.on_retry.as_deref();
+        retry_with_backoff(self.config.max_retries, &*self.clock(), on_retry, || {
+            std::future::ready(replay.respond(request))
+        })
+        .await
+    }
+
+    /// Send a message with `stream: true` and collect the server-sent events
+    ///
+    /// The Hyperware HTTP client delivers the response body once it is complete, so the
+    /// events are available after the response finishes rather than as they arrive.
+    /// Replay, recording, strict parsing, and guardrails apply as in
+    /// [`send_message`](Self::send_message); a replayed or guardrail-filtered response is
+    /// served as the events the API would have sent for it.
+    pub async fn stream_message(
+        &self,
+        request: CreateMessageRequest,
+    ) -> Result<MessageStream, AnthropicError> {
+        let mut request = request;
+        if let Some(ref guardrails) = self.config.guardrails {
+            guardrails.apply_to_request(&mut request)?;
+        }
+        request.stream = Some(true);
+        self.preflight(&mut request)?;
+
+        let started = SystemTime::now();
+        let mut stream = match self.config.replay {
+            Some(ref replay) => {
+                MessageStream::from_response(&self.replay_with_retries(replay, &request).await?)
+            }
+            None => self.stream_with_resumes(&request).await?,
+        };
+        // Streams that ended in an error event are returned as they are
+        if let Ok(mut response) = stream.into_response() {
+            self.check_strict(&response)?;
+            self.check_response(&request, &response);
+            if let Some(ref recorder) = self.config.recorder {
+                recorder.record(&request, &response);
+            }
+            self.report_usage(&response, started);
+            if let Some(ref guardrails) = self.config.guardrails {
+                guardrails.apply_to_response(&mut response)?;
+                stream = MessageStream::from_response(&response);
+            }
+        }
+        Ok(stream)
+    }
+
+    /// POST a streaming request, resuming it from its prefill while it keeps breaking off
+    async fn stream_with_resumes(
+        &self,
+        request: &CreateMessageRequest,
+    ) -> Result<MessageStream, AnthropicError> {
+        let (body, _) = self.post_with_retries(request).await?;
+        let mut stream = MessageStream::from_sse(&String::from_utf8_lossy(&body));
+        for _ in 0..self.config.stream_resumes {
+            if !stream.is_interrupted() {
+                break;
+            }
+            stream = match stream.resume_prefill() {
+                Some(prefill) if !prefill.is_empty() => {
+                    let resumed = with_prefill(request, &prefill);
+                    let (body, _) = self.post_with_retries(&resumed).await?;
+                    stream.resume_with(&MessageStream::from_sse(&String::from_utf8_lossy(&body)))
+                }
+                // Nothing usable as prefill, so start over
+                _ => {
+                    let (body, _) = self.post_with_retries(request).await?;
+                    MessageStream::from_sse(&String::from_utf8_lossy(&body))
+                }
+            };
+        }
+        Ok(stream)
+    }
+
+    /// In strict mode, fail on response fields the SDK doesn't know
+    fn check_strict(&self, response: &MessageResponse) -> Result<(), AnthropicError> {
+        if !self.config.strict_deserialization {
+            return Ok(());
+        }
+        let unknown = response.unknown_fields();
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        Err(AnthropicError::Deserialization(format!(
+            "Unknown fields in response: {}",
+            unknown.join(", ")
+        )))
+    }
+
+    fn report_usage(&self, response: &MessageResponse, started: SystemTime) {
+        if let Ok(mut stats) = self.stats.lock() {
+            let label = match self.config.spend_label {
+                Some(ref label) => label.clone(),
+                None => key_fingerprint(&self.config.api_key),
+            };
+            stats.record_spend(&label, &response.model, &response.usage);
+        }
+        if let Some(ref on_usage) = self.config.on_usage {
+            let latency = started.elapsed().unwrap_or_default();
+            on_usage(&response.usage, &response.model, latency);
+        }
+    }
+
+    /// Apply the configured `max_tokens` policy and context window check
+    fn preflight(&self, request: &mut CreateMessageRequest) -> Result<(), AnthropicError> {
+        self.check_headers(&HashMap::new())?;
+        request.validate()?;
+        self.check_api_version(request);
+        if let Some(policy) = self.config.max_tokens_policy {
+            self.apply_max_tokens_policy(request, policy)?;
+        }
+        if self.config.check_context_window {
+            request.check_context_window()?;
+        }
+        Ok(())
+    }
+
+    /// Reject malformed custom headers and, unless allowed, ones that would clobber
+    /// the auth or version headers
+    fn check_headers(&self, extra_headers: &HashMap<String, String>) -> Result<(), AnthropicError> {
+        for (name, value) in self.config.custom_headers.iter().chain(extra_headers) {
+            let valid_name = !name.is_empty()
+                && name
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+            if !valid_name {
+                return Err(AnthropicError::InvalidRequest(format!(
+                    "Invalid header name: {:?}",
+                    name
+                )));
+            }
+            if value.chars().any(|c| c.is_control() && c != '\t') {
+                return Err(AnthropicError::InvalidRequest(format!(
+                    "Header {} has a value containing control characters",
+                    name
+                )));
+            }
+            if !self.config.allow_protected_headers
+                && PROTECTED_HEADERS
+                    .iter()
+                    .any(|protected| name.eq_ignore_ascii_case(protected))
+            {
+                return Err(AnthropicError::InvalidRequest(format!(
+                    "Header {} would override the one the client sets; call allow_protected_headers to send it",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_max_tokens_policy(
+        &self,
+        request: &mut CreateMessageRequest,
+        policy: MaxTokensPolicy,
+    ) -> Result<(), AnthropicError> {
+        match (request.check_max_tokens(), policy) {
+            (Ok(()), _) => Ok(()),
+            (
+                Err(AnthropicError::MaxTokensExceeded {
+                    model,
+                    requested,
+                    limit,
+                }),
+                MaxTokensPolicy::Warn,
+            ) => {
+                self.warn(ClientWarning::MaxTokensExceeded {
+                    model,
+                    requested,
+                    limit,
+                });
+                Ok(())
+            }
+            (Err(AnthropicError::MaxTokensExceeded { limit, .. }), MaxTokensPolicy::Clamp) => {
+                request.max_tokens = limit;
+                Ok(())
+            }
+            (Err(error), _) => Err(error),
+        }
+    }
+
+    /// POST a request with retries, serializing it (and estimating its tokens) only once
+    async fn post_with_retries(
+        &self,
+        request: &CreateMessageRequest,
+    ) -> Result<(Vec<u8>, HashMap<String, String>), AnthropicError> {
+        let body = serde_json::to_vec(request)
             .map_err(|e| AnthropicError::Serialization(e.to_string()))?;
+        request.check_serialized_size(body.len(), self.config.max_request_bytes)?;
+        let tokens = match self.config.rate_limiter {
+            Some(_) => request_tokens(request)?,
+            None => 0,
+        };
+        let mut betas = match self.config.auto_betas {
+            true => required_betas(request),
+            false => Vec::new(),
+        };
+        for beta in &request.betas {
+            if !betas.contains(&beta.as_str()) {
+                betas.push(beta);
+            }
+        }
+
+        let headers = request.options.headers();
+
+        let on_retry = self.config.on_retry.as_deref();
+        let clock = self.clock();
+        let clock = &*clock;
+        let result = retry_with_backoff(self.config.max_retries, clock, on_retry, || async {
+            if let Some(ref rate_limiter) = self.config.rate_limiter {
+                rate_limiter
+                    .acquire_on(tokens, clock, self.config.blocking)
+                    .await?;
+            }
+            self.post_messages(body.clone(), &betas, &headers).await
+        })
+        .await;
+
+        let notice = match result {
+            Ok((_, ref headers)) => DeprecationNotice::from_headers(&request.model, headers),
+            Err(_) => None,
+        };
+        if let Ok(mut stats) = self.stats.lock() {
+            match result {
+                Ok(_) => stats.requests += 1,
+                Err(_) => stats.failures += 1,
+            }
+            if let Some(ref notice) = notice {
+                stats.record_deprecation(notice.clone());
+            }
+        }
+        if let Some(notice) = notice {
+            self.warn(ClientWarning::Deprecation(notice));
+        }
+        if let (Some(_), Ok((_, ref headers))) = (&self.config.on_warning, &result) {
+            for warning in ClientWarning::rate_limits_from_headers(headers) {
+                self.warn(warning);
+            }
+        }
+        result
+    }
+
+    /// Warn about truncated responses and, when parsing tolerantly, unknown fields
+    fn check_response(&self, request: &CreateMessageRequest, response: &MessageResponse) {
+        if self.config.on_warning.is_none() {
+            return;
+        }
+        if response.stop_reason == Some(StopReason::MaxTokens) {
+            self.warn(ClientWarning::Truncated {
+                model: response.model.clone(),
+                max_tokens: request.max_tokens,
+            });
+        }
+        let unknown = response.unknown_fields();
+        if !unknown.is_empty() {
+            self.warn(ClientWarning::UnknownFields(unknown));
+        }
+    }
+
+    /// Warn about features the configured API version doesn't support
+    fn check_api_version(&self, request: &CreateMessageRequest) {
+        if self.config.on_warning.is_none() {
+            return;
+        }
+        let Some(version) = ApiVersion::parse(&self.config.api_version) else {
+            self.warn(ClientWarning::UnknownApiVersion(
+                self.config.api_version.clone(),
+            ));
+            return;
+        };
+        for feature in version.unsupported_features(request) {
+            self.warn(ClientWarning::UnsupportedFeature {
+                feature: feature.to_string(),
+                api_version: version.to_string(),
+            });
+        }
+    }
+
+    pub(crate) fn warn(&self, warning: ClientWarning) {
+        if let Some(ref on_warning) = self.config.on_warning {
+            on_warning(&warning);
+        }
+    }
+
+    /// POST a request to the messages endpoint, returning the body and headers of a
+    /// successful response
+    async fn post_messages(
+        &self,
+        body: Vec<u8>,
+        betas: &[&str],
+        headers: &HashMap<String, String>,
+    ) -> Result<(Vec<u8>, HashMap<String, String>), AnthropicError> {
+        let reply = self
+            .send_http(Method::POST, "/v1/messages", body, betas, headers)
+            .await?;
+        Ok((reply.body, reply.headers))
+    }
+
+    /// Call any API endpoint with the client's auth, retries, and error mapping
+    ///
+    /// An escape hatch for new or beta endpoints the SDK doesn't type yet. `path` is
+    /// relative to the base URL (e.g. `/v1/messages/count_tokens`), a `Null` body sends no
+    /// body, and `extra_headers` override the client's headers for this call. Response
+    /// bodies that aren't JSON are returned as a string.
+    pub async fn send_raw(
+        &self,
+        path: &str,
+        method: Method,
+        body: Value,
+        extra_headers: HashMap<String, String>,
+    ) -> Result<RawResponse, AnthropicError> {
+        self.send_raw_with_betas(path, method, body, &[], extra_headers)
+            .await
+    }
+
+    /// [`send_raw`](Self::send_raw), adding betas to the `anthropic-beta` header
+    pub(crate) async fn send_raw_with_betas(
+        &self,
+        path: &str,
+        method: Method,
+        body: Value,
+        betas: &[&str],
+        extra_headers: HashMap<String, String>,
+    ) -> Result<RawResponse, AnthropicError> {
+        self.check_headers(&extra_headers)?;
+        let body = match body {
+            Value::Null => Vec::new(),
+            body => serde_json::to_vec(&body)?,
+        };
+        let on_retry = self.config.on_retry.as_deref();
+        let clock = self.clock();
+        let reply = retry_with_backoff(self.config.max_retries, &*clock, on_retry, || {
+            self.send_http(method.clone(), path, body.clone(), betas, &extra_headers)
+        })
+        .await?;
+
+        let body = match reply.body.is_empty() {
+            true => Value::Null,
+            false => serde_json::from_slice(&reply.body).unwrap_or_else(|_| {
+                Value::String(String::from_utf8_lossy(&reply.body).into_owned())
+            }),
+        };
+        Ok(RawResponse {
+            status: reply.status,
+            headers: reply.headers,
+            body,
+        })
+    }
 
+    /// Send an HTTP request to the API, mapping unsuccessful statuses to errors
+    async fn send_http(
+        &self,
+        method: Method,
+        path: &str,
+        body: Vec<u8>,
+        betas: &[&str],
+        extra_headers: &HashMap<String, String>,
+    ) -> Result<HttpReply, AnthropicError> {
         // Build the URL
-        let url = format!("{}/v1/messages", self.base_url);
+        let separator = if path.starts_with('/') { "" } else { "/" };
+        let url = format!("{}{}{}", self.config.base_url, separator, path);
         let url = url::Url::parse(&url)
             .map_err(|_| AnthropicError::InvalidResponse(format!("Invalid URL: {}", url)))?;
 
@@ -166,13 +897,16 @@ impl AnthropicClient {
         let mut headers = HashMap::new();
 
         // Always include anthropic-version header
-        headers.insert("anthropic-version".to_string(), self.api_version.clone());
+        headers.insert(
+            "anthropic-version".to_string(),
+            self.config.api_version.clone(),
+        );
 
-        if self.use_oauth {
+        if self.config.use_oauth {
             // OAuth mode: use Bearer token and anthropic-beta header
             headers.insert(
                 "Authorization".to_string(),
-                format!("Bearer {}", self.api_key),
+                format!("Bearer {}", self.config.api_key),
             );
             headers.insert(
                 "anthropic-beta".to_string(),
@@ -180,44 +914,80 @@ impl AnthropicClient {
             );
         } else {
             // Standard mode: use x-api-key header
-            headers.insert("x-api-key".to_string(), self.api_key.clone());
+            headers.insert("x-api-key".to_string(), self.config.api_key.clone());
         }
 
         headers.insert("content-type".to_string(), "application/json".to_string());
 
-        // Add custom headers (these can override defaults if needed)
-        headers.extend(self.custom_headers.clone());
+        // Add custom headers, already checked against the protected defaults
+        headers.extend(self.config.custom_headers.clone());
+        headers.extend(extra_headers.clone());
+
+        // Add the betas this request needs to any already set
+        if !betas.is_empty() {
+            let existing = headers.remove("anthropic-beta").unwrap_or_default();
+            let mut values: Vec<&str> = existing
+                .split(',')
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .collect();
+            for beta in betas {
+                if !values.contains(beta) {
+                    values.push(beta);
+                }
+            }
+            headers.insert("anthropic-beta".to_string(), values.join(","));
+        }
 
         // Make the HTTP request using the Hyperware HTTP client
-        let response =
-            send_request_await_response(Method::POST, url, Some(headers), self.timeout, body)
-                .await
-                .map_err(|e| AnthropicError::HttpClient(e.to_string()))?;
+        let (status, headers, body) = if self.config.blocking {
+            send_http_blocking(method, url, headers, self.config.timeout, body)?
+        } else {
+            let response =
+                send_request_await_response(method, url, Some(headers), self.config.timeout, body)
+                    .await
+                    .map_err(AnthropicError::from)?;
+            let headers = response
+                .headers()
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.as_str().to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect();
+            (response.status(), headers, response.into_body())
+        };
 
         // Check response status
-        let status = response.status();
-        let body = response.into_body();
 
         if status.is_success() {
-            // Parse successful response
-            serde_json::from_slice::<MessageResponse>(&body).map_err(|e| {
-                AnthropicError::Deserialization(format!("Failed to parse response: {}", e))
+            Ok(HttpReply {
+                status: status.as_u16(),
+                headers,
+                body,
             })
         } else {
             // Try to parse error response
-            if let Ok(error_response) = serde_json::from_slice::<ApiErrorResponse>(&body) {
-                Err(AnthropicError::ApiError {
-                    error_type: error_response.error.error_type,
-                    message: error_response.error.message,
-                })
-            } else {
-                // Fallback to generic error
-                let error_text = String::from_utf8_lossy(&body);
-                Err(AnthropicError::InvalidResponse(format!(
-                    "API returned status {}: {}",
-                    status, error_text
-                )))
+            let error =
+                if let Ok(error_response) = serde_json::from_slice::<ApiErrorResponse>(&body) {
+                    AnthropicError::ApiError {
+                        error_type: error_response.error.error_type,
+                        message: error_response.error.message,
+                    }
+                } else {
+                    // Fallback to generic error
+                    let error_text = String::from_utf8_lossy(&body);
+                    AnthropicError::InvalidResponse(format!(
+                        "API returned status {}: {}",
+                        status, error_text
+                    ))
+                };
+            if self.config.capture_headers {
+                return Err(AnthropicError::WithHeaders {
+                    error: Box::new(error),
+                    headers,
+                });
             }
+            Err(error)
         }
     }
 
@@ -247,19 +1017,148 @@ impl AnthropicClient {
     ) -> Result<String, AnthropicError> {
         let request = self.create_simple_message(model, prompt, max_tokens);
         let response = self.send_message(request).await?;
+        first_text(&response)
+    }
 
-        // Extract text from the first content block
-        if let Some(first_block) = response.content.first() {
-            match first_block {
-                crate::types::messages::ResponseContentBlock::Text { text, .. } => Ok(text.clone()),
-                _ => Err(AnthropicError::InvalidResponse(
-                    "Expected text response".to_string(),
-                )),
+    /// Ask a question about a single image and get the response text
+    ///
+    /// The image is base64-encoded when the request is serialized.
+    pub async fn send_image_message(
+        &self,
+        model: impl Into<String>,
+        prompt: impl Into<String>,
+        image_bytes: impl Into<Arc<[u8]>>,
+        media_type: impl Into<String>,
+        max_tokens: u32,
+    ) -> Result<String, AnthropicError> {
+        let request = CreateMessageRequest::new(
+            model,
+            vec![Message {
+                role: Role::User,
+                content: Content::Blocks(image_question_blocks(prompt, image_bytes, media_type)),
+            }],
+            max_tokens,
+        );
+        let response = self.send_message(request).await?;
+        first_text(&response)
+    }
+}
+
+/// An image block followed by the question about it, the layout Anthropic recommends
+pub(crate) fn image_question_blocks(
+    prompt: impl Into<String>,
+    image_bytes: impl Into<Arc<[u8]>>,
+    media_type: impl Into<String>,
+) -> Vec<ContentBlock> {
+    vec![
+        ContentBlock::Image {
+            source: ImageSource::bytes(media_type, image_bytes),
+            cache_control: None,
+        },
+        ContentBlock::Text {
+            text: prompt.into(),
+            citations: None,
+            cache_control: None,
+        },
+    ]
+}
+
+/// Extract text from the first content block
+fn first_text(response: &MessageResponse) -> Result<String, AnthropicError> {
+    match response.content.first() {
+        Some(crate::types::messages::ResponseContentBlock::Text { text, .. }) => Ok(text.clone()),
+        Some(_) => Err(AnthropicError::InvalidResponse(
+            "Expected text response".to_string(),
+        )),
+        None => Err(AnthropicError::InvalidResponse(
+            "Empty response content".to_string(),
+        )),
+    }
+}
+
+/// A response from [`AnthropicClient::send_raw`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawResponse {
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+    pub body: Value,
+}
+
+/// A successful HTTP response before its body is parsed
+struct HttpReply {
+    status: u16,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// Calculate retry delay with exponential backoff and jitter, in ms
+fn calculate_retry_delay(attempt: u32) -> u64 {
+    let base_delay = INITIAL_RETRY_DELAY_MS * 2u64.pow(attempt);
+    let delay_with_jitter = base_delay + (rand::random::<u64>() % 1000);
+    delay_with_jitter.min(MAX_RETRY_DELAY_MS)
+}
+
+/// A retry about to happen, reported to
+/// [`AnthropicClient::with_retry_observer`](AnthropicClient::with_retry_observer)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryEvent {
+    /// The retry's number, starting at 1
+    pub attempt: u32,
+    pub max_retries: u32,
+    /// How long the client waits before retrying
+    pub delay_ms: u64,
+    /// [`AnthropicError::code`] of the error that triggered the retry
+    pub error_class: String,
+    pub error: String,
+}
+
+/// Run an operation, retrying retryable errors with exponential backoff
+pub(crate) async fn retry_with_backoff<T, F, Fut>(
+    max_retries: u32,
+    clock: &dyn Clock,
+    on_retry: Option<&RetryFn>,
+    mut operation: F,
+) -> Result<T, AnthropicError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, AnthropicError>>,
+{
+    let mut last_error = None;
+
+    for attempt in 0..=max_retries {
+        match operation().await {
+            Ok(response) => return Ok(response),
+            Err(error) => {
+                // Check if the error is retryable
+                if error.is_retryable() && attempt < max_retries {
+                    let delay = calculate_retry_delay(attempt);
+                    if let Some(on_retry) = on_retry {
+                        on_retry(&RetryEvent {
+                            attempt: attempt + 1,
+                            max_retries,
+                            delay_ms: delay,
+                            error_class: error.code().to_string(),
+                            error: error.to_string(),
+                        });
+                    }
+                    clock.sleep(delay).await;
+                    last_error = Some(error);
+                } else {
+                    // Non-retryable error or max retries reached
+                    return Err(error);
+                }
             }
-        } else {
-            Err(AnthropicError::InvalidResponse(
-                "Empty response content".to_string(),
-            ))
         }
     }
+
+    // Should not reach here, but return last error if we do
+    Err(last_error
+        .unwrap_or_else(|| AnthropicError::InvalidResponse("Max retries reached".to_string())))
+}
+
+/// Identify an API key in stats without revealing it
+fn key_fingerprint(api_key: &str) -> String {
+    let chars: Vec<char> = api_key.chars().collect();
+    let tail: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+    format!("key-...{}", tail)
 }
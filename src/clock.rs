@@ -0,0 +1,145 @@
+use hyperware_process_lib::{hyperapp, timer};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Future returned by [`Clock::sleep`]
+pub type SleepFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+/// The time source behind retry delays, rate limit waits, schedules, and store TTLs
+///
+/// [`SystemClock`] is used unless another is set; [`MockClock`] lets tests run backoff
+/// and schedules instantly.
+pub trait Clock: Send + Sync {
+    /// Milliseconds since the Unix epoch
+    fn now_ms(&self) -> u64;
+
+    /// Wait for `ms` milliseconds
+    fn sleep(&self, ms: u64) -> SleepFuture;
+}
+
+/// Wall-clock time and Hyperware's runtime sleep
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        now_ms()
+    }
+
+    fn sleep(&self, ms: u64) -> SleepFuture {
+        Box::pin(async move {
+            let _ = hyperapp::sleep(ms).await;
+        })
+    }
+}
+
+/// Wall-clock time and a timer that blocks the process, for the blocking client
+///
+/// Sleeping waits before returning the future, so it also works without an executor.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct BlockingClock;
+
+impl Clock for BlockingClock {
+    fn now_ms(&self) -> u64 {
+        now_ms()
+    }
+
+    fn sleep(&self, ms: u64) -> SleepFuture {
+        let _ = timer::set_and_await_timer(ms);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// A clock that only moves when told to, for tests
+///
+/// Sleeping returns at once and advances the time by the requested amount. Clones share
+/// the same time, so a clone kept by the test sees what the code under test did.
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    now_ms: u64,
+    sleeps: Vec<u64>,
+}
+
+impl MockClock {
+    /// Create a clock reading `now_ms` milliseconds since the Unix epoch
+    pub fn new(now_ms: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockState {
+                now_ms,
+                sleeps: Vec::new(),
+            })),
+        }
+    }
+
+    /// Move the time forward without recording a sleep
+    pub fn advance(&self, by: Duration) {
+        self.lock().now_ms += by.as_millis() as u64;
+    }
+
+    /// Every sleep requested so far, in milliseconds
+    pub fn sleeps(&self) -> Vec<u64> {
+        self.lock().sleeps.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Clock for MockClock {
+    fn now_ms(&self) -> u64 {
+        self.lock().now_ms
+    }
+
+    fn sleep(&self, ms: u64) -> SleepFuture {
+        let mut state = self.lock();
+        state.now_ms += ms;
+        state.sleeps.push(ms);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// A shared [`Clock`] that defaults to [`SystemClock`], for fields of `Debug`/`Default` types
+#[derive(Clone)]
+pub(crate) struct ClockHandle(Arc<dyn Clock>);
+
+impl ClockHandle {
+    pub(crate) fn new(clock: impl Clock + 'static) -> Self {
+        Self(Arc::new(clock))
+    }
+}
+
+impl Default for ClockHandle {
+    fn default() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+impl std::ops::Deref for ClockHandle {
+    type Target = dyn Clock;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+impl fmt::Debug for ClockHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ClockHandle")
+    }
+}
+
+/// Milliseconds since the Unix epoch by the system clock
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
@@ -0,0 +1,183 @@
+use crate::conversation::Conversation;
+use crate::tokens::estimate_text_tokens;
+use crate::types::messages::{CacheControl, ContentBlock};
+use std::cmp::Ordering;
+
+/// A retrieved piece of a document
+#[derive(Debug, Clone)]
+pub struct DocumentChunk {
+    /// Where the chunk came from (file path, URL, title, ...)
+    pub source: String,
+    pub text: String,
+    /// Retrieval relevance score, higher is more relevant
+    pub score: Option<f32>,
+}
+
+/// Order in which chunks are packed (and therefore which survive truncation)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkOrder {
+    /// Keep the order chunks were added in
+    Insertion,
+    /// Most relevant first; unscored chunks go last
+    ByScore,
+    /// Group chunks from the same source together, sources in first-seen order
+    BySource,
+}
+
+/// Builds source-annotated content blocks from retrieved chunks for RAG prompts
+///
+/// Chunks are ordered, wrapped in `<document>` tags with their source, and dropped once the
+/// estimated token budget is reached. The last document block carries `cache_control`, so the
+/// packed context forms a cacheable prefix in front of the question.
+#[derive(Debug, Clone)]
+pub struct ContextPack {
+    chunks: Vec<DocumentChunk>,
+    order: ChunkOrder,
+    token_budget: Option<u32>,
+    cache_control: Option<CacheControl>,
+}
+
+impl ContextPack {
+    /// Create an empty pack that caches its contents with the default ephemeral TTL
+    pub fn new() -> Self {
+        Self {
+            chunks: Vec::new(),
+            order: ChunkOrder::Insertion,
+            token_budget: None,
+            cache_control: Some(CacheControl::ephemeral()),
+        }
+    }
+
+    /// Add a chunk without a relevance score
+    pub fn with_chunk(mut self, source: impl Into<String>, text: impl Into<String>) -> Self {
+        self.chunks.push(DocumentChunk {
+            source: source.into(),
+            text: text.into(),
+            score: None,
+        });
+        self
+    }
+
+    /// Add a chunk with its retrieval relevance score
+    pub fn with_scored_chunk(
+        mut self,
+        source: impl Into<String>,
+        text: impl Into<String>,
+        score: f32,
+    ) -> Self {
+        self.chunks.push(DocumentChunk {
+            source: source.into(),
+            text: text.into(),
+            score: Some(score),
+        });
+        self
+    }
+
+    /// Add many chunks at once
+    pub fn with_chunks(mut self, chunks: impl IntoIterator<Item = DocumentChunk>) -> Self {
+        self.chunks.extend(chunks);
+        self
+    }
+
+    /// Set the packing order
+    pub fn with_order(mut self, order: ChunkOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Drop chunks once their estimated token total would exceed the budget
+    pub fn with_token_budget(mut self, token_budget: u32) -> Self {
+        self.token_budget = Some(token_budget);
+        self
+    }
+
+    /// Set the cache control placed on the last document block, or `None` to disable caching
+    pub fn with_cache_control(mut self, cache_control: Option<CacheControl>) -> Self {
+        self.cache_control = cache_control;
+        self
+    }
+
+    /// The chunks that make it into the pack, in packing order
+    pub fn selected(&self) -> Vec<&DocumentChunk> {
+        let mut ordered: Vec<&DocumentChunk> = self.chunks.iter().collect();
+        match self.order {
+            ChunkOrder::Insertion => {}
+            ChunkOrder::ByScore => ordered.sort_by(|a, b| match (a.score, b.score) {
+                (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }),
+            ChunkOrder::BySource => {
+                let mut sources: Vec<&str> = Vec::new();
+                for chunk in &self.chunks {
+                    if !sources.contains(&chunk.source.as_str()) {
+                        sources.push(&chunk.source);
+                    }
+                }
+                ordered.sort_by_key(|chunk| sources.iter().position(|s| *s == chunk.source));
+            }
+        }
+
+        let Some(budget) = self.token_budget else {
+            return ordered;
+        };
+        let mut used = 0;
+        ordered
+            .into_iter()
+            .take_while(|chunk| {
+                used += estimate_text_tokens(&Self::render(0, chunk));
+                used <= budget
+            })
+            .collect()
+    }
+
+    /// Build the annotated document blocks, ready to prepend to a user message
+    pub fn build(&self) -> Vec<ContentBlock> {
+        let mut blocks: Vec<ContentBlock> = self
+            .selected()
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk)| ContentBlock::Text {
+                text: Self::render(index + 1, chunk),
+                citations: None,
+                cache_control: None,
+            })
+            .collect();
+
+        if let Some(ContentBlock::Text { cache_control, .. }) = blocks.last_mut() {
+            *cache_control = self.cache_control.clone();
+        }
+        blocks
+    }
+
+    fn render(index: usize, chunk: &DocumentChunk) -> String {
+        format!(
+            "<document index=\"{}\">\n<source>{}</source>\n<document_content>\n{}\n</document_content>\n</document>",
+            index, chunk.source, chunk.text
+        )
+    }
+}
+
+impl Default for ContextPack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Conversation {
+    /// Add a user message made of the packed context followed by the question
+    pub fn add_user_message_with_context(
+        &mut self,
+        context: &ContextPack,
+        question: impl Into<String>,
+    ) -> &mut Self {
+        let mut blocks = context.build();
+        blocks.push(ContentBlock::Text {
+            text: question.into(),
+            citations: None,
+            cache_control: None,
+        });
+        self.add_user_blocks(blocks)
+    }
+}
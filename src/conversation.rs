@@ -1,30 +1,102 @@
-use crate::client::AnthropicClient;
+use crate::agent::{AgentRun, AgentStopReason};
+use crate::batches::{BatchRequestItem, BatchResultItem};
+use crate::betas::INTERLEAVED_THINKING_BETA;
+use crate::client::{image_question_blocks, AnthropicClient};
+use crate::clock::{Clock, ClockHandle};
 use crate::error::AnthropicError;
+use crate::guardrails::Guardrails;
+use crate::injection::InjectionScanner;
+use crate::models::{model_info, DEFAULT_MAX_TOKENS};
+use crate::presets::{GenerationPreset, SamplingParams};
+use crate::refusal::{RefusalAction, RefusalPolicy};
+use crate::registry::ToolRegistry;
+use crate::storage::{AttachedStore, ConversationStore};
 use crate::types::messages::{
-    Content, ContentBlock, CreateMessageRequest, Message, MessageResponse, ResponseContentBlock,
-    Role, ToolResultContent,
+    CacheTtl, Content, ContentBlock, CreateMessageRequest, McpServer, Message, MessageResponse,
+    ResponseContentBlock, Role, StopReason, ThinkingConfig, ToolResultContent, Usage,
 };
+use crate::validation::ResponseValidator;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+const DEFAULT_MAX_REPAIR_ATTEMPTS: u32 = 2;
+const MAX_REFUSAL_RETRIES: u32 = 1;
 
 /// Manages an ongoing conversation with Claude, handling message history and tool use loops
 #[derive(Debug, Clone)]
 pub struct Conversation {
-    /// The message history
-    messages: Vec<Message>,
+    /// The message history, shared with the requests built from it
+    messages: Vec<Arc<Message>>,
     /// The model to use for this conversation
     model: String,
     /// Default max tokens for responses
     max_tokens: u32,
     /// System prompt if any
     system: Option<String>,
+    /// Values substituted for `{name}` placeholders in the system prompt
+    variables: BTreeMap<String, String>,
     /// Available tools for this conversation
     tools: Option<Vec<crate::types::tools::Tool>>,
     /// Tool choice configuration
     tool_choice: Option<crate::types::tools::ToolChoice>,
+    /// Tool choice for the next request only, reverting once its response is processed
+    next_tool_choice: Option<crate::types::tools::ToolChoice>,
+    /// Whether Claude may use several tools per response, applied to the active tool choice
+    parallel_tool_use: Option<bool>,
     /// Temperature setting
     temperature: Option<f32>,
+    /// Nucleus sampling setting
+    top_p: Option<f32>,
+    /// Top-k sampling setting
+    top_k: Option<u32>,
+    /// Custom sequences that end a response
+    stop_sequences: Option<Vec<String>>,
+    /// Remote MCP servers Claude may call through the MCP connector
+    mcp_servers: Vec<McpServer>,
+    /// Extended thinking settings
+    thinking: Option<ThinkingConfig>,
+    /// Whether thinking may happen between tool calls (the interleaved-thinking beta)
+    interleaved_thinking: bool,
+    /// Whether requests ask for token-efficient tool calls where the model supports it
+    token_efficient_tools: bool,
+    /// TTL for the cache breakpoints placed on every request, if caching is enabled
+    cache_ttl: Option<CacheTtl>,
     /// Track pending tool uses that need responses
     pending_tool_uses: Vec<PendingToolUse>,
+    /// Usage reported for each response processed, oldest first
+    usage: Vec<Usage>,
+    /// Checks applied to text responses before they are returned from `send`
+    validators: Vec<ResponseValidator>,
+    /// How many corrective follow-ups to send when a validator rejects a response
+    max_repair_attempts: u32,
+    /// Content filters applied to user text sent and assistant text received
+    guardrails: Option<Guardrails>,
+    /// Scanner applied to tool results before they are added to the history
+    injection_scanner: Option<InjectionScanner>,
+    /// How many times `send` continues a paused turn before returning it
+    max_auto_resumes: u32,
+    /// Whether the last response paused its turn, so the next one continues it
+    paused: bool,
+    /// How `send` reacts to refusals; refusals are surfaced when unset
+    refusal_policy: Option<RefusalPolicy>,
+    /// Where the history is saved after each `send`
+    storage: Option<AttachedStore>,
+    /// What happens when the same user message is added twice in a row
+    duplicate_policy: Option<DuplicatePolicy>,
+    /// Whether a duplicate was dropped under [`DuplicatePolicy::Reject`]
+    duplicate_rejected: bool,
+    /// Clock for `{current_date}`; the sending client's clock when unset
+    clock: Option<ClockHandle>,
+}
+
+/// A conversation's state at some point, from [`Conversation::snapshot`]
+#[derive(Debug, Clone)]
+pub struct ConversationSnapshot {
+    state: Box<Conversation>,
 }
 
 #[derive(Debug, Clone)]
@@ -42,10 +114,46 @@ impl Conversation {
             model: model.into(),
             max_tokens,
             system: None,
+            variables: BTreeMap::new(),
             tools: None,
             tool_choice: None,
+            next_tool_choice: None,
+            parallel_tool_use: None,
             temperature: None,
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            mcp_servers: Vec::new(),
+            thinking: None,
+            interleaved_thinking: false,
+            token_efficient_tools: false,
+            cache_ttl: None,
             pending_tool_uses: Vec::new(),
+            usage: Vec::new(),
+            validators: Vec::new(),
+            max_repair_attempts: DEFAULT_MAX_REPAIR_ATTEMPTS,
+            guardrails: None,
+            injection_scanner: None,
+            max_auto_resumes: 0,
+            paused: false,
+            refusal_policy: None,
+            storage: None,
+            duplicate_policy: None,
+            duplicate_rejected: false,
+            clock: None,
+        }
+    }
+
+    /// Start building a conversation with defaults filled in from a client, preset, and store
+    pub fn builder() -> ConversationBuilder {
+        ConversationBuilder::new()
+    }
+
+    /// Pair the conversation with a client so sends don't need it passed in
+    pub fn bind(self, client: impl Into<Arc<AnthropicClient>>) -> BoundConversation {
+        BoundConversation {
+            conversation: self,
+            client: client.into(),
         }
     }
 
@@ -55,6 +163,52 @@ impl Conversation {
         self
     }
 
+    /// Set a value for `{name}` placeholders in the system prompt
+    ///
+    /// Placeholders are filled in each time a request is built, so values can change
+    /// between turns without rebuilding the prompt. `{current_date}` is filled with
+    /// today's UTC date by the conversation's clock (see [`with_clock`](Self::with_clock))
+    /// unless set here; unknown placeholders are left as written.
+    pub fn with_variable(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.set_variable(name, value);
+        self
+    }
+
+    /// Set or replace a system prompt variable between turns
+    pub fn set_variable(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.variables.insert(name.into(), value.into());
+        self
+    }
+
+    /// Read `{current_date}` from `clock` instead of the system clock
+    ///
+    /// When unset, requests sent through a client use the client's clock.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(ClockHandle::new(clock));
+        self
+    }
+
+    /// The system prompt with its variables filled in
+    pub fn resolved_system(&self) -> Option<String> {
+        self.resolved_system_at(&*self.clock.clone().unwrap_or_default())
+    }
+
+    fn resolved_system_at(&self, clock: &dyn Clock) -> Option<String> {
+        let system = self.system.as_ref()?;
+        static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+        let placeholder = PLACEHOLDER
+            .get_or_init(|| Regex::new(r"\{([A-Za-z_][A-Za-z0-9_]*)\}").expect("valid regex"));
+        let resolved = placeholder.replace_all(system, |captures: &Captures| {
+            let name = &captures[1];
+            match self.variables.get(name) {
+                Some(value) => value.clone(),
+                None if name == "current_date" => utc_date(clock.now_ms()),
+                None => captures[0].to_string(),
+            }
+        });
+        Some(resolved.into_owned())
+    }
+
     /// Set available tools
     pub fn with_tools(mut self, tools: Vec<crate::types::tools::Tool>) -> Self {
         self.tools = Some(tools);
@@ -67,50 +221,254 @@ impl Conversation {
         self
     }
 
+    /// Force Claude to call the named tool on subsequent requests
+    pub fn force_tool(&mut self, name: impl Into<String>) -> &mut Self {
+        self.tool_choice = Some(crate::types::tools::ToolChoice::tool(name));
+        self
+    }
+
+    /// Use `tool_choice` for the next request only, then revert to the conversation's own
+    ///
+    /// The override is dropped once a response is processed, so forcing a tool call
+    /// doesn't also force one when sending its result.
+    pub fn set_next_tool_choice(
+        &mut self,
+        tool_choice: crate::types::tools::ToolChoice,
+    ) -> &mut Self {
+        self.next_tool_choice = Some(tool_choice);
+        self
+    }
+
+    /// Force Claude to call the named tool in the next response only
+    pub fn force_tool_once(&mut self, name: impl Into<String>) -> &mut Self {
+        self.set_next_tool_choice(crate::types::tools::ToolChoice::tool(name))
+    }
+
+    /// Allow or forbid several tool uses in one response
+    ///
+    /// Applies to whichever tool choice is active, including a one-shot one and the
+    /// API's default of auto when none is set.
+    pub fn with_parallel_tool_use(mut self, parallel: bool) -> Self {
+        self.parallel_tool_use = Some(parallel);
+        self
+    }
+
     /// Set temperature
     pub fn with_temperature(mut self, temperature: f32) -> Self {
         self.temperature = Some(temperature);
         self
     }
 
+    /// Stop each response when Claude outputs any of these sequences
+    pub fn with_stop_sequences<S: Into<String>>(
+        mut self,
+        sequences: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.stop_sequences = Some(sequences.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Let Claude call tools on a remote MCP server (the MCP connector beta)
+    pub fn with_mcp_server(mut self, server: McpServer) -> Self {
+        self.mcp_servers.push(server);
+        self
+    }
+
+    /// Enable extended thinking with a token budget per response
+    ///
+    /// Thinking blocks are kept in the history and sent back unmodified, as the API
+    /// requires while a tool use loop is in progress.
+    pub fn with_thinking(mut self, budget_tokens: u32) -> Self {
+        self.thinking = Some(ThinkingConfig::Enabled { budget_tokens });
+        self
+    }
+
+    /// Enable thinking between tool calls as well as before the first one
+    ///
+    /// Adds the interleaved-thinking beta to every request. The budget covers the whole
+    /// assistant turn, so it may exceed `max_tokens`.
+    pub fn with_interleaved_thinking(mut self, budget_tokens: u32) -> Self {
+        self.interleaved_thinking = true;
+        self.with_thinking(budget_tokens)
+    }
+
+    /// Have Claude write tool calls in fewer output tokens where the model supports it
+    ///
+    /// See [`CreateMessageRequest::with_token_efficient_tools`] for when the beta applies.
+    pub fn with_token_efficient_tools(mut self) -> Self {
+        self.token_efficient_tools = true;
+        self
+    }
+
+    /// Cache each request's prefix with the given TTL
+    ///
+    /// See [`CreateMessageRequest::with_cache_ttl`] for where breakpoints are placed.
+    pub fn with_cache_ttl(mut self, ttl: CacheTtl) -> Self {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Set temperature, top_p, and top_k together, replacing any previous values
+    pub fn with_sampling(mut self, params: SamplingParams) -> Self {
+        self.temperature = params.temperature;
+        self.top_p = params.top_p;
+        self.top_k = params.top_k;
+        self
+    }
+
+    /// Add a validator that text responses must pass before `send` returns them
+    pub fn with_validator(mut self, validator: ResponseValidator) -> Self {
+        self.validators.push(validator);
+        self
+    }
+
+    /// Set how many corrective follow-ups are sent when a validator rejects a response
+    pub fn with_max_repair_attempts(mut self, max_repair_attempts: u32) -> Self {
+        self.max_repair_attempts = max_repair_attempts;
+        self
+    }
+
+    /// Let `send` continue turns paused by long-running server tools up to `max_resumes` times
+    ///
+    /// The returned update combines the text, tool uses, and usage of every continuation.
+    pub fn with_auto_resume(mut self, max_resumes: u32) -> Self {
+        self.max_auto_resumes = max_resumes;
+        self
+    }
+
+    /// Guard against the same user message being added twice in a row, e.g. by a
+    /// double-submitted form
+    pub fn with_duplicate_guard(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicate_policy = Some(policy);
+        self
+    }
+
+    /// The policy set with [`with_duplicate_guard`](Self::with_duplicate_guard)
+    pub fn duplicate_policy(&self) -> Option<DuplicatePolicy> {
+        self.duplicate_policy
+    }
+
+    /// Decide how `send` reacts when Claude refuses a request
+    pub fn with_refusal_policy(mut self, policy: RefusalPolicy) -> Self {
+        self.refusal_policy = Some(policy);
+        self
+    }
+
+    /// Apply content filters to user text sent and assistant text received by `send`
+    ///
+    /// A blocked response is not added to the history. A blocked user message stays in the
+    /// history, so callers should remove or replace it before sending again.
+    pub fn with_guardrails(mut self, guardrails: Guardrails) -> Self {
+        self.guardrails = Some(guardrails);
+        self
+    }
+
+    /// Scan tool results for suspected prompt injection before adding them to the history
+    pub fn with_injection_scanner(mut self, scanner: InjectionScanner) -> Self {
+        self.injection_scanner = Some(scanner);
+        self
+    }
+
     /// Add a user message to the conversation
     pub fn add_user_message(&mut self, content: impl Into<String>) -> &mut Self {
-        self.messages.push(Message {
-            role: Role::User,
-            content: Content::Text(content.into()),
-        });
-        self
+        self.push_user(Content::Text(content.into()))
     }
 
     /// Add a user message with content blocks (for images, etc.)
     pub fn add_user_blocks(&mut self, blocks: Vec<ContentBlock>) -> &mut Self {
-        self.messages.push(Message {
-            role: Role::User,
-            content: Content::Blocks(blocks),
-        });
+        self.push_user(Content::Blocks(blocks))
+    }
+
+    /// Add a user message, failing with [`AnthropicError::DuplicateMessage`] if the
+    /// duplicate guard rejects it
+    pub fn try_add_user_message(
+        &mut self,
+        content: impl Into<String>,
+    ) -> Result<&mut Self, AnthropicError> {
+        self.try_push_user(Content::Text(content.into()))
+    }
+
+    /// Add a user message with content blocks, failing with
+    /// [`AnthropicError::DuplicateMessage`] if the duplicate guard rejects it
+    pub fn try_add_user_blocks(
+        &mut self,
+        blocks: Vec<ContentBlock>,
+    ) -> Result<&mut Self, AnthropicError> {
+        self.try_push_user(Content::Blocks(blocks))
+    }
+
+    /// Append a user message, remembering a rejected duplicate for the next send
+    fn push_user(&mut self, content: Content) -> &mut Self {
+        if self.try_push_user(content).is_err() {
+            self.duplicate_rejected = true;
+        }
         self
     }
 
+    /// Append a user message, applying the duplicate guard
+    fn try_push_user(&mut self, content: Content) -> Result<&mut Self, AnthropicError> {
+        if let Some(policy) = self.duplicate_policy {
+            let duplicate = self.messages.last().is_some_and(|last| {
+                last.role == Role::User
+                    && serde_json::to_value(&last.content).ok()
+                        == serde_json::to_value(&content).ok()
+            });
+            match (duplicate, policy) {
+                (true, DuplicatePolicy::Skip) => return Ok(self),
+                (true, DuplicatePolicy::Reject) => return Err(AnthropicError::DuplicateMessage),
+                (false, _) => {}
+            }
+        }
+        // A new message supersedes an earlier rejected repeat
+        self.duplicate_rejected = false;
+        self.messages.push(Arc::new(Message {
+            role: Role::User,
+            content,
+        }));
+        Ok(self)
+    }
+
+    /// Fail with [`AnthropicError::DuplicateMessage`] if the last message added was a
+    /// rejected repeat, clearing the rejection
+    pub(crate) fn take_duplicate_rejection(&mut self) -> Result<(), AnthropicError> {
+        match std::mem::take(&mut self.duplicate_rejected) {
+            true => Err(AnthropicError::DuplicateMessage),
+            false => Ok(()),
+        }
+    }
+
+    /// Add a user message asking a question about a single image
+    pub fn add_user_image_message(
+        &mut self,
+        prompt: impl Into<String>,
+        image_bytes: impl Into<Arc<[u8]>>,
+        media_type: impl Into<String>,
+    ) -> &mut Self {
+        self.add_user_blocks(image_question_blocks(prompt, image_bytes, media_type))
+    }
+
     /// Add an assistant message (useful for providing examples or continuing conversations)
     pub fn add_assistant_message(&mut self, content: impl Into<String>) -> &mut Self {
-        self.messages.push(Message {
+        self.messages.push(Arc::new(Message {
             role: Role::Assistant,
             content: Content::Text(content.into()),
-        });
+        }));
         self
     }
 
     /// Add an assistant message with content blocks
     pub fn add_assistant_blocks(&mut self, blocks: Vec<ContentBlock>) -> &mut Self {
-        self.messages.push(Message {
+        self.messages.push(Arc::new(Message {
             role: Role::Assistant,
             content: Content::Blocks(blocks),
-        });
+        }));
         self
     }
 
     /// Process a response from Claude and update the conversation state
     pub fn process_response(&mut self, response: &MessageResponse) -> ConversationUpdate {
+        // Refused output is reported but kept out of the history
+        let refused = response.stop_reason == Some(StopReason::Refusal);
         let mut tool_uses = Vec::new();
         let mut text_responses = Vec::new();
         let mut blocks = Vec::new();
@@ -118,13 +476,7 @@ impl Conversation {
         // Process each content block in the response
         for block in &response.content {
             match block {
-                ResponseContentBlock::Text { text, .. } => {
-                    text_responses.push(text.clone());
-                    blocks.push(ContentBlock::Text {
-                        text: text.clone(),
-                        cache_control: None,
-                    });
-                }
+                ResponseContentBlock::Text { text, .. } => text_responses.push(text.clone()),
                 ResponseContentBlock::ToolUse { id, name, input } => {
                     let pending = PendingToolUse {
                         id: id.clone(),
@@ -132,27 +484,44 @@ impl Conversation {
                         input: input.clone(),
                     };
                     tool_uses.push(pending.clone());
-                    self.pending_tool_uses.push(pending);
-
-                    blocks.push(ContentBlock::ToolUse {
-                        id: id.clone(),
-                        name: name.clone(),
-                        input: input.clone(),
-                        cache_control: None,
-                    });
+                    if !refused {
+                        self.pending_tool_uses.push(pending);
+                    }
                 }
+                _ => {}
             }
+            // Thinking must go back unmodified for tool use to continue, so nothing is dropped
+            blocks.push(ContentBlock::from_response(block));
         }
 
-        // Add the assistant's response to the conversation
-        if !blocks.is_empty() {
-            self.add_assistant_blocks(blocks);
+        // Add the assistant's response to the conversation, continuing a paused turn in place
+        if !blocks.is_empty() && !refused {
+            let continued = match self.messages.last_mut() {
+                Some(last) if self.paused && matches!(last.role, Role::Assistant) => {
+                    match &mut Arc::make_mut(last).content {
+                        Content::Blocks(existing) => {
+                            existing.append(&mut blocks);
+                            true
+                        }
+                        Content::Text(_) => false,
+                    }
+                }
+                _ => false,
+            };
+            if !continued {
+                self.add_assistant_blocks(blocks);
+            }
         }
+        self.paused = response.stop_reason == Some(StopReason::PauseTurn);
+        self.usage.push(response.usage.clone());
+        self.next_tool_choice = None;
 
         ConversationUpdate {
             tool_uses,
             text_responses,
             stop_reason: response.stop_reason.clone(),
+            stop_sequence: response.stop_sequence.clone(),
+            usage: response.usage.clone(),
         }
     }
 
@@ -164,7 +533,11 @@ impl Conversation {
 
         let mut blocks = Vec::new();
 
-        for result in results {
+        for mut result in results {
+            if let Some(ref scanner) = self.injection_scanner {
+                scanner.apply(&mut result);
+            }
+
             // Find and remove the pending tool use
             let pending_index = self
                 .pending_tool_uses
@@ -210,6 +583,10 @@ impl Conversation {
         }])
     }
 
+    pub(crate) fn pending_tool_uses_mut(&mut self) -> &mut Vec<PendingToolUse> {
+        &mut self.pending_tool_uses
+    }
+
     /// Check if there are pending tool uses that need responses
     pub fn has_pending_tool_uses(&self) -> bool {
         !self.pending_tool_uses.is_empty()
@@ -222,45 +599,334 @@ impl Conversation {
 
     /// Build a request from the current conversation state
     pub fn build_request(&self) -> CreateMessageRequest {
+        self.build_request_at(&*self.clock.clone().unwrap_or_default())
+    }
+
+    /// Build a request to send through `client`, dated by its clock unless the
+    /// conversation has its own
+    pub(crate) fn build_request_for(&self, client: &AnthropicClient) -> CreateMessageRequest {
+        let clock = self.clock.clone().unwrap_or_else(|| client.clock());
+        self.build_request_at(&*clock)
+    }
+
+    fn build_request_at(&self, clock: &dyn Clock) -> CreateMessageRequest {
         let mut request =
-            CreateMessageRequest::new(self.model.clone(), self.messages.clone(), self.max_tokens);
+            CreateMessageRequest::new(self.model.clone(), Vec::new(), self.max_tokens);
+        request.messages = self.messages.clone();
 
-        if let Some(ref system) = self.system {
-            request = request.with_system(system.clone());
+        if let Some(system) = self.resolved_system_at(clock) {
+            request = request.with_system(system);
         }
 
         if let Some(ref tools) = self.tools {
             request = request.with_tools(tools.clone());
         }
 
-        if let Some(ref tool_choice) = self.tool_choice {
-            request = request.with_tool_choice(tool_choice.clone());
+        // A one-shot choice takes precedence over the conversation's own
+        let mut tool_choice = self
+            .next_tool_choice
+            .as_ref()
+            .or(self.tool_choice.as_ref())
+            .cloned();
+        if let Some(parallel) = self.parallel_tool_use.filter(|_| self.tools.is_some()) {
+            tool_choice = Some(
+                tool_choice
+                    .unwrap_or_default()
+                    .with_parallel_tool_use(parallel),
+            );
+        }
+        if let Some(tool_choice) = tool_choice {
+            request = request.with_tool_choice(tool_choice);
         }
 
         if let Some(temperature) = self.temperature {
             request = request.with_temperature(temperature);
         }
 
+        request.top_p = self.top_p;
+        request.top_k = self.top_k;
+        request.stop_sequences = self.stop_sequences.clone();
+        if !self.mcp_servers.is_empty() {
+            request.mcp_servers = Some(self.mcp_servers.clone());
+        }
+        request.thinking = self.thinking;
+        if self.interleaved_thinking {
+            request = request.with_beta(INTERLEAVED_THINKING_BETA);
+        }
+        if self.token_efficient_tools {
+            request = request.with_token_efficient_tools();
+        }
+        if let Some(ttl) = self.cache_ttl {
+            request = request.with_cache_ttl(ttl);
+        }
+
         request
     }
 
+    /// Build a Message Batches request for the next turn
+    ///
+    /// Submit it with [`AnthropicClient::create_message_batch`] and, once the batch has
+    /// ended, hand the matching result to [`apply_batch_result`](Self::apply_batch_result).
+    pub fn build_batch_item(&self, custom_id: impl Into<String>) -> BatchRequestItem {
+        BatchRequestItem::new(custom_id, self.build_request())
+    }
+
+    /// Apply a batch result for this conversation's next turn, as if it had been sent
+    ///
+    /// A successful result is processed like a direct response and saved to any attached
+    /// store. Errored, canceled, and expired results leave the history untouched.
+    pub fn apply_batch_result(
+        &mut self,
+        item: BatchResultItem,
+    ) -> Result<ConversationUpdate, AnthropicError> {
+        let response = item.into_response()?;
+        let update = self.process_response(&response);
+        self.save()?;
+        Ok(update)
+    }
+
     /// Send the current conversation to Claude and get a response
+    ///
+    /// If validators are configured and a text-only response fails one of them, a corrective
+    /// user message describing the problem is appended and the request is re-sent, up to
+    /// `max_repair_attempts` times. Responses that request tool use are not validated.
+    /// With storage attached, the history is saved once the response is accepted.
+    ///
+    /// On error, including a failed save, the conversation is rolled back to how it was
+    /// before the call. Messages added beforehand stay in the history, unanswered, so
+    /// calling `send` again retries them without duplicating anything.
     pub async fn send(
         &mut self,
         client: &AnthropicClient,
     ) -> Result<ConversationUpdate, AnthropicError> {
-        let request = self.build_request();
-        let response = client.send_message(request).await?;
+        self.take_duplicate_rejection()?;
+        let before = self.snapshot();
+        let result = self.send_checked(client).await;
+        self.commit(before, result)
+    }
+
+    /// Save after a successful call, rolling back to `before` if the call or the save failed
+    pub(crate) fn commit<T>(
+        &mut self,
+        before: ConversationSnapshot,
+        result: Result<T, AnthropicError>,
+    ) -> Result<T, AnthropicError> {
+        let result = result.and_then(|value| self.save().map(|()| value));
+        if result.is_err() {
+            self.restore(before);
+        }
+        result
+    }
+
+    /// Whether the history ends with a user message that hasn't been answered, e.g. after
+    /// a failed [`send`](Self::send)
+    pub fn has_unanswered_message(&self) -> bool {
+        self.messages
+            .last()
+            .is_some_and(|message| message.role == Role::User)
+    }
+
+    /// Save the history to the attached store, if any
+    pub fn save(&self) -> Result<(), AnthropicError> {
+        match self.storage {
+            Some(ref storage) => storage.save(&self.messages),
+            None => Ok(()),
+        }
+    }
+
+    /// Send, applying auto-resume, the refusal policy, and validators
+    async fn send_checked(
+        &mut self,
+        client: &AnthropicClient,
+    ) -> Result<ConversationUpdate, AnthropicError> {
+        let mut repair_attempts = 0;
+        let mut refusal_retries = 0;
+
+        loop {
+            let mut update = self.send_once(client).await?;
+            let mut resumes = 0;
+            while update.is_paused() && resumes < self.max_auto_resumes {
+                let continuation = self.send_once(client).await?;
+                update.absorb(continuation);
+                resumes += 1;
+            }
+
+            if update.is_refusal() {
+                let action = match self.refusal_policy {
+                    Some(ref policy) => policy.decide(&update),
+                    None => RefusalAction::Surface,
+                };
+                match action {
+                    RefusalAction::Retry(prompt) if refusal_retries < MAX_REFUSAL_RETRIES => {
+                        refusal_retries += 1;
+                        self.replace_last_user_message(prompt)?;
+                        continue;
+                    }
+                    RefusalAction::Abort => return Err(AnthropicError::Refused(update.text())),
+                    _ => return Ok(update),
+                }
+            }
+
+            let Err(reason) = self.validate_update(&update) else {
+                return Ok(update);
+            };
+
+            if repair_attempts >= self.max_repair_attempts {
+                return Err(AnthropicError::ResponseValidation(reason));
+            }
+            repair_attempts += 1;
+
+            self.add_user_message(format!(
+                "Your previous response was rejected: {}. Please respond again and fix this.",
+                reason
+            ));
+        }
+    }
+
+    /// Swap the text of the most recent user message, e.g. to rephrase a refused prompt
+    ///
+    /// Only plain-text messages are rewritten; replacing one that carries tool results,
+    /// images, or documents would drop them, so that is an error.
+    fn replace_last_user_message(&mut self, text: String) -> Result<(), AnthropicError> {
+        let Some(message) = self
+            .messages
+            .iter_mut()
+            .rev()
+            .find(|message| matches!(message.role, Role::User))
+        else {
+            self.add_user_message(text);
+            return Ok(());
+        };
+        let content = match message.content {
+            Content::Text(_) => Content::Text(text),
+            Content::Blocks(ref blocks) => {
+                if let Some(block) = blocks
+                    .iter()
+                    .find(|block| !matches!(block, ContentBlock::Text { .. }))
+                {
+                    return Err(AnthropicError::InvalidRequest(format!(
+                        "Can't rephrase a user message holding a {}",
+                        block.kind()
+                    )));
+                }
+                // Keep the message's cache breakpoint on the new text
+                let cache_control = blocks
+                    .last()
+                    .and_then(|block| block.cache_control().cloned());
+                Content::Blocks(vec![ContentBlock::Text {
+                    text,
+                    citations: None,
+                    cache_control,
+                }])
+            }
+        };
+        Arc::make_mut(message).content = content;
+        Ok(())
+    }
+
+    /// Continue a turn paused by a long-running server tool
+    ///
+    /// The paused response is already in the history, so this sends it back as-is and the
+    /// continuation is appended to the same assistant message. Like [`send`](Self::send),
+    /// it saves on success and rolls back on error.
+    pub async fn resume(
+        &mut self,
+        client: &AnthropicClient,
+    ) -> Result<ConversationUpdate, AnthropicError> {
+        let before = self.snapshot();
+        let result = self.send_once(client).await;
+        self.commit(before, result)
+    }
+
+    /// Whether the last response paused its turn and should be resumed
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Send the conversation once, applying guardrails, and record the response
+    async fn send_once(
+        &mut self,
+        client: &AnthropicClient,
+    ) -> Result<ConversationUpdate, AnthropicError> {
+        self.take_duplicate_rejection()?;
+        let mut request = self.build_request_for(client);
+        if let Some(ref guardrails) = self.guardrails {
+            guardrails.apply_to_request(&mut request)?;
+        }
+
+        let mut response = client.send_message(request).await?;
+        if let Some(ref guardrails) = self.guardrails {
+            guardrails.apply_to_response(&mut response)?;
+        }
         Ok(self.process_response(&response))
     }
 
+    /// Run all validators against a response, returning the first rejection reason
+    fn validate_update(&self, update: &ConversationUpdate) -> Result<(), String> {
+        if update.has_tool_uses() || update.is_paused() {
+            return Ok(());
+        }
+
+        for validator in &self.validators {
+            validator
+                .validate(update)
+                .map_err(|reason| format!("{} ({})", reason, validator.name()))?;
+        }
+        Ok(())
+    }
+
     /// Add a user message and immediately send to Claude
+    ///
+    /// If sending fails, the message is removed again, so the call can simply be retried.
     pub async fn send_user_message(
         &mut self,
         client: &AnthropicClient,
         message: impl Into<String>,
     ) -> Result<ConversationUpdate, AnthropicError> {
+        let before = self.snapshot();
         self.add_user_message(message);
+        let result = self.send(client).await;
+        if result.is_err() {
+            self.restore(before);
+        }
+        result
+    }
+
+    /// Send and return the response text, failing if the response isn't plain text
+    ///
+    /// Tool requests, truncated and paused responses fail with
+    /// [`AnthropicError::NotText`], and refusals with [`AnthropicError::Refused`], rather
+    /// than being silently reduced to whatever text they contain. The response is still
+    /// added to the history either way.
+    pub async fn send_expect_text(
+        &mut self,
+        client: &AnthropicClient,
+    ) -> Result<String, AnthropicError> {
+        let update = self.send(client).await?;
+        let problem = match update.outcome() {
+            UpdateOutcome::Completed | UpdateOutcome::HitStopSequence(_) => {
+                return Ok(update.text())
+            }
+            UpdateOutcome::Refused => return Err(AnthropicError::Refused(update.text())),
+            UpdateOutcome::NeedsToolResults => {
+                let names: Vec<&str> = update.tool_uses.iter().map(|t| t.name.as_str()).collect();
+                format!("Claude requested tools: {}", names.join(", "))
+            }
+            UpdateOutcome::Truncated => "the response hit max_tokens and was cut off".to_string(),
+            UpdateOutcome::Paused => "a server tool paused the turn".to_string(),
+        };
+        Err(AnthropicError::NotText(problem))
+    }
+
+    /// Add a question about a single image and immediately send to Claude
+    pub async fn send_image_message(
+        &mut self,
+        client: &AnthropicClient,
+        prompt: impl Into<String>,
+        image_bytes: impl Into<Arc<[u8]>>,
+        media_type: impl Into<String>,
+    ) -> Result<ConversationUpdate, AnthropicError> {
+        self.add_user_image_message(prompt, image_bytes, media_type);
         self.send(client).await
     }
 
@@ -268,21 +934,67 @@ impl Conversation {
     pub async fn complete_tool_loop<F, Fut>(
         &mut self,
         client: &AnthropicClient,
-        mut tool_executor: F,
+        tool_executor: F,
     ) -> Result<Vec<ConversationUpdate>, AnthropicError>
     where
         F: FnMut(PendingToolUse) -> Fut,
         Fut: std::future::Future<Output = Result<ToolResult, AnthropicError>>,
     {
+        self.run_tool_loop(client, None, tool_executor)
+            .await
+            .map(|run| run.updates)
+    }
+
+    /// Run the tool loop like [`complete_tool_loop`](Self::complete_tool_loop), stopping at
+    /// the first safe point after `time_limit`
+    ///
+    /// The limit is checked once each response arrives and once tools finish. A run that
+    /// stops early has [`AgentStopReason::TimedOut`], and any tool uses it didn't execute
+    /// are answered with error results so the history stays valid.
+    pub async fn complete_tool_loop_within<F, Fut>(
+        &mut self,
+        client: &AnthropicClient,
+        time_limit: Duration,
+        tool_executor: F,
+    ) -> Result<AgentRun, AnthropicError>
+    where
+        F: FnMut(PendingToolUse) -> Fut,
+        Fut: std::future::Future<Output = Result<ToolResult, AnthropicError>>,
+    {
+        self.run_tool_loop(client, Some(time_limit), tool_executor)
+            .await
+    }
+
+    async fn run_tool_loop<F, Fut>(
+        &mut self,
+        client: &AnthropicClient,
+        time_limit: Option<Duration>,
+        mut tool_executor: F,
+    ) -> Result<AgentRun, AnthropicError>
+    where
+        F: FnMut(PendingToolUse) -> Fut,
+        Fut: std::future::Future<Output = Result<ToolResult, AnthropicError>>,
+    {
+        let clock = client.clock();
+        let started = clock.now_ms();
+        let timed_out = || {
+            time_limit.is_some_and(|limit| {
+                clock.now_ms().saturating_sub(started) >= limit.as_millis() as u64
+            })
+        };
         let mut updates = Vec::new();
 
-        loop {
+        let stop_reason = loop {
             let update = self.send(client).await?;
             let has_tools = !update.tool_uses.is_empty();
             updates.push(update);
 
             if !has_tools {
-                break; // No more tool uses, we're done
+                break AgentStopReason::Completed; // No more tool uses, we're done
+            }
+            if timed_out() {
+                self.abandon_pending_tools("time limit reached")?;
+                break AgentStopReason::TimedOut;
             }
 
             // Execute all pending tools
@@ -294,18 +1006,56 @@ impl Conversation {
 
             // Add the results back to the conversation
             self.add_tool_results(results)?;
-        }
+            if timed_out() {
+                break AgentStopReason::TimedOut;
+            }
+        };
+
+        Ok(AgentRun {
+            updates,
+            stop_reason,
+        })
+    }
+
+    /// Answer every pending tool use with an error result explaining why it wasn't run
+    pub fn abandon_pending_tools(&mut self, reason: &str) -> Result<(), AnthropicError> {
+        let results = self
+            .pending_tool_uses
+            .iter()
+            .map(|tool_use| {
+                ToolResult::error(tool_use.id.clone(), format!("Tool was not run: {}", reason))
+            })
+            .collect();
+        self.add_tool_results(results)
+    }
+
+    /// Get the model used for this conversation
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Get the default max tokens for responses
+    pub fn max_tokens(&self) -> u32 {
+        self.max_tokens
+    }
 
-        Ok(updates)
+    /// Get the system prompt, if any
+    pub fn system(&self) -> Option<&str> {
+        self.system.as_deref()
     }
 
     /// Get the current message history
-    pub fn messages(&self) -> &[Message] {
+    pub fn messages(&self) -> &[Arc<Message>] {
         &self.messages
     }
 
+    /// Usage reported for each response processed so far, oldest first
+    pub fn turn_usage(&self) -> &[Usage] {
+        &self.usage
+    }
+
     /// Get a mutable reference to the message history (for advanced use cases)
-    pub fn messages_mut(&mut self) -> &mut Vec<Message> {
+    pub fn messages_mut(&mut self) -> &mut Vec<Arc<Message>> {
         &mut self.messages
     }
 
@@ -313,6 +1063,10 @@ impl Conversation {
     pub fn clear(&mut self) {
         self.messages.clear();
         self.pending_tool_uses.clear();
+        self.usage.clear();
+        self.next_tool_choice = None;
+        self.paused = false;
+        self.duplicate_rejected = false;
     }
 
     /// Create a new conversation with the same settings but empty history
@@ -322,14 +1076,133 @@ impl Conversation {
             model: self.model.clone(),
             max_tokens: self.max_tokens,
             system: self.system.clone(),
+            variables: self.variables.clone(),
             tools: self.tools.clone(),
             tool_choice: self.tool_choice.clone(),
+            next_tool_choice: None,
+            parallel_tool_use: self.parallel_tool_use,
             temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: self.top_k,
+            stop_sequences: self.stop_sequences.clone(),
+            mcp_servers: self.mcp_servers.clone(),
+            thinking: self.thinking,
+            interleaved_thinking: self.interleaved_thinking,
+            token_efficient_tools: self.token_efficient_tools,
+            cache_ttl: self.cache_ttl,
             pending_tool_uses: Vec::new(),
+            usage: Vec::new(),
+            validators: self.validators.clone(),
+            max_repair_attempts: self.max_repair_attempts,
+            guardrails: self.guardrails.clone(),
+            injection_scanner: self.injection_scanner.clone(),
+            max_auto_resumes: self.max_auto_resumes,
+            paused: false,
+            refusal_policy: self.refusal_policy.clone(),
+            storage: None,
+            duplicate_policy: self.duplicate_policy,
+            duplicate_rejected: false,
+            clock: self.clock.clone(),
+        }
+    }
+
+    /// Capture the conversation's state so it can be rolled back with
+    /// [`restore`](Self::restore)
+    ///
+    /// Snapshots live in memory only; the history is shared, so taking one is cheap.
+    pub fn snapshot(&self) -> ConversationSnapshot {
+        ConversationSnapshot {
+            state: Box::new(self.clone()),
+        }
+    }
+
+    /// Roll back to a snapshot, discarding everything since, without saving to storage
+    ///
+    /// The attached storage is kept as it is now, so the next `send` saves the restored
+    /// history over anything saved in between.
+    pub fn restore(&mut self, snapshot: ConversationSnapshot) {
+        let storage = self.storage.take();
+        *self = *snapshot.state;
+        self.storage = storage;
+    }
+
+    /// Adopt a branch's history from the point where it diverges from this one
+    ///
+    /// The branch is usually a clone of this conversation that was edited or regenerated.
+    /// Messages after the divergence point are replaced by the branch's, and the branch's
+    /// pending tool uses carry over. Fails with [`AnthropicError::MergeConflict`] if a tool
+    /// use pending here would be dropped without the branch answering or keeping it.
+    /// Returns the number of messages taken from the branch.
+    pub fn merge(&mut self, branch: &Conversation) -> Result<usize, AnthropicError> {
+        let Some(diff) = self.diff(branch) else {
+            return Ok(0);
+        };
+        let suffix = &branch.messages[diff.index..];
+
+        let answered: Vec<&str> = blocks_of(suffix)
+            .filter_map(|block| match block {
+                ContentBlock::ToolResult { tool_use_id, .. } => Some(tool_use_id.as_str()),
+                _ => None,
+            })
+            .collect();
+        // A stale pending entry whose tool use the branch removed doesn't count as kept
+        let requested: Vec<&str> = blocks_of(&branch.messages)
+            .filter_map(|block| match block {
+                ContentBlock::ToolUse { id, .. } => Some(id.as_str()),
+                _ => None,
+            })
+            .collect();
+        let dropped: Vec<String> = self
+            .pending_tool_uses
+            .iter()
+            .filter(|pending| {
+                let kept = requested.contains(&pending.id.as_str())
+                    && branch.pending_tool_uses.iter().any(|p| p.id == pending.id);
+                !answered.contains(&pending.id.as_str()) && !kept
+            })
+            .map(|pending| pending.id.clone())
+            .collect();
+        if !dropped.is_empty() {
+            return Err(AnthropicError::MergeConflict {
+                tool_use_ids: dropped,
+            });
         }
+
+        self.messages.truncate(diff.index);
+        self.messages.extend(suffix.iter().cloned());
+        self.pending_tool_uses = branch.pending_tool_uses.clone();
+        self.paused = branch.paused;
+        Ok(suffix.len())
     }
 }
 
+/// Format milliseconds since the Unix epoch as a `YYYY-MM-DD` date in UTC
+fn utc_date(now_ms: u64) -> String {
+    let days = (now_ms / 86_400_000) as i64;
+    // Civil-from-days conversion for the proleptic Gregorian calendar
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Every content block in a run of messages
+fn blocks_of(messages: &[Arc<Message>]) -> impl Iterator<Item = &ContentBlock> {
+    messages
+        .iter()
+        .filter_map(|message| match message.content {
+            Content::Blocks(ref blocks) => Some(blocks),
+            Content::Text(_) => None,
+        })
+        .flatten()
+}
+
 /// Result of processing a Claude response
 #[derive(Debug, Clone)]
 pub struct ConversationUpdate {
@@ -339,9 +1212,63 @@ pub struct ConversationUpdate {
     pub text_responses: Vec<String>,
     /// The stop reason for this response
     pub stop_reason: Option<crate::types::messages::StopReason>,
+    /// The custom stop sequence that ended the response, if any
+    pub stop_sequence: Option<String>,
+    /// Token usage reported for this response
+    pub usage: crate::types::messages::Usage,
+}
+
+/// What a conversation does when the same user message is added twice in a row
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DuplicatePolicy {
+    /// Drop the repeat silently
+    Skip,
+    /// Drop the repeat and fail with [`AnthropicError::DuplicateMessage`]: at once from the
+    /// `try_add_*` methods, or from the next send after `add_user_message`
+    Reject,
+}
+
+/// How a response ended, combining the stop reason with any pending tool uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    /// Claude finished its turn
+    Completed,
+    /// Claude is waiting for the results of the requested tool uses
+    NeedsToolResults,
+    /// The response hit `max_tokens` and was cut off
+    Truncated,
+    /// The response ended at this custom stop sequence
+    HitStopSequence(String),
+    /// Claude declined to respond
+    Refused,
+    /// A long-running server tool paused the turn; resume to continue
+    Paused,
 }
 
 impl ConversationUpdate {
+    /// Classify how the response ended
+    ///
+    /// Tool uses take precedence over the stop reason, so a response cut off while
+    /// requesting tools still reports `NeedsToolResults`. A missing or unrecognized stop
+    /// reason is treated as `Completed`.
+    pub fn outcome(&self) -> UpdateOutcome {
+        if self.has_tool_uses() && !self.is_refusal() {
+            return UpdateOutcome::NeedsToolResults;
+        }
+        match self.stop_reason {
+            Some(StopReason::MaxTokens) => UpdateOutcome::Truncated,
+            Some(StopReason::StopSequence) => {
+                UpdateOutcome::HitStopSequence(self.stop_sequence.clone().unwrap_or_default())
+            }
+            Some(StopReason::Refusal) => UpdateOutcome::Refused,
+            Some(StopReason::PauseTurn) => UpdateOutcome::Paused,
+            Some(StopReason::EndTurn)
+            | Some(StopReason::ToolUse)
+            | Some(StopReason::Other(_))
+            | None => UpdateOutcome::Completed,
+        }
+    }
+
     /// Check if this update contains tool use requests
     pub fn has_tool_uses(&self) -> bool {
         !self.tool_uses.is_empty()
@@ -351,6 +1278,60 @@ impl ConversationUpdate {
     pub fn text(&self) -> String {
         self.text_responses.join("\n")
     }
+
+    /// The custom stop sequence that ended the response, if one did
+    pub fn matched_stop_sequence(&self) -> Option<&str> {
+        match self.stop_reason {
+            Some(StopReason::StopSequence) => self.stop_sequence.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Check if the turn was paused by a long-running server tool
+    pub fn is_paused(&self) -> bool {
+        self.stop_reason == Some(StopReason::PauseTurn)
+    }
+
+    /// Check if Claude refused to respond
+    pub fn is_refusal(&self) -> bool {
+        self.stop_reason == Some(StopReason::Refusal)
+    }
+
+    /// Fold a continuation of this turn into the update
+    fn absorb(&mut self, continuation: ConversationUpdate) {
+        self.tool_uses.extend(continuation.tool_uses);
+        self.text_responses.extend(continuation.text_responses);
+        self.stop_reason = continuation.stop_reason;
+        self.stop_sequence = continuation.stop_sequence;
+        let (usage, more) = (&mut self.usage, continuation.usage);
+        usage.input_tokens += more.input_tokens;
+        usage.output_tokens += more.output_tokens;
+        usage.cache_creation_input_tokens = sum_counts(
+            usage.cache_creation_input_tokens,
+            more.cache_creation_input_tokens,
+        );
+        usage.cache_read_input_tokens =
+            sum_counts(usage.cache_read_input_tokens, more.cache_read_input_tokens);
+        if let Some(more) = more.cache_creation {
+            let split = usage.cache_creation.get_or_insert_with(Default::default);
+            split.ephemeral_1h_input_tokens += more.ephemeral_1h_input_tokens;
+            split.ephemeral_5m_input_tokens += more.ephemeral_5m_input_tokens;
+        }
+        if let Some(more) = more.server_tool_use {
+            let requests = usage.server_tool_use.get_or_insert_with(Default::default);
+            requests.web_search_requests += more.web_search_requests;
+            requests.web_fetch_requests =
+                sum_counts(requests.web_fetch_requests, more.web_fetch_requests);
+        }
+    }
+}
+
+/// Add two optional usage counts, staying `None` only if neither was reported
+fn sum_counts(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+    match (a, b) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+    }
 }
 
 /// A tool execution result to be sent back to Claude
@@ -367,6 +1348,24 @@ pub enum ToolResultData {
     Blocks(Vec<ContentBlock>),
 }
 
+impl From<String> for ToolResultData {
+    fn from(text: String) -> Self {
+        ToolResultData::Text(text)
+    }
+}
+
+impl From<&str> for ToolResultData {
+    fn from(text: &str) -> Self {
+        ToolResultData::Text(text.to_string())
+    }
+}
+
+impl From<Vec<ContentBlock>> for ToolResultData {
+    fn from(blocks: Vec<ContentBlock>) -> Self {
+        ToolResultData::Blocks(blocks)
+    }
+}
+
 impl ToolResult {
     /// Create a successful tool result with text content
     pub fn success(tool_use_id: impl Into<String>, content: impl Into<String>) -> Self {
@@ -386,3 +1385,170 @@ impl ToolResult {
         }
     }
 }
+
+/// Fluent setup for a [`Conversation`], filling in defaults from a client, preset, and store
+///
+/// The model comes from [`with_model`](Self::with_model), falling back to the client's
+/// [default model](AnthropicClient::with_default_model). `max_tokens` defaults to
+/// [`DEFAULT_MAX_TOKENS`], capped at the model's output limit.
+#[derive(Debug, Clone, Default)]
+pub struct ConversationBuilder {
+    model: Option<String>,
+    client_model: Option<String>,
+    max_tokens: Option<u32>,
+    system: Option<String>,
+    preset: Option<GenerationPreset>,
+    tools: Vec<crate::types::tools::Tool>,
+    tool_choice: Option<crate::types::tools::ToolChoice>,
+    storage: Option<AttachedStore>,
+}
+
+impl ConversationBuilder {
+    /// Start with no settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take defaults, such as the model, from a client's configuration
+    pub fn with_client(mut self, client: &AnthropicClient) -> Self {
+        self.client_model = client.default_model().map(str::to_string);
+        self
+    }
+
+    /// Set the model, overriding the client's default
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Set max tokens per response
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Set the system prompt
+    pub fn with_system(mut self, system: impl Into<String>) -> Self {
+        self.system = Some(system.into());
+        self
+    }
+
+    /// Apply a generation preset's sampling parameters
+    pub fn with_preset(mut self, preset: GenerationPreset) -> Self {
+        self.preset = Some(preset);
+        self
+    }
+
+    /// Add a tool
+    pub fn with_tool(mut self, tool: crate::types::tools::Tool) -> Self {
+        self.tools.push(tool);
+        self
+    }
+
+    /// Add every tool in a registry
+    pub fn with_registry(mut self, registry: &ToolRegistry) -> Self {
+        self.tools.extend(registry.tools());
+        self
+    }
+
+    /// Set tool choice
+    pub fn with_tool_choice(mut self, tool_choice: crate::types::tools::ToolChoice) -> Self {
+        self.tool_choice = Some(tool_choice);
+        self
+    }
+
+    /// Resume the history stored under `id` and save to it after each `send`
+    pub fn with_storage(
+        mut self,
+        store: impl ConversationStore + Send + Sync + 'static,
+        id: impl Into<String>,
+    ) -> Self {
+        self.storage = Some(AttachedStore::new(store, id));
+        self
+    }
+
+    /// Build the conversation, loading any stored history
+    pub fn build(self) -> Result<Conversation, AnthropicError> {
+        let model = self.model.or(self.client_model).ok_or_else(|| {
+            AnthropicError::InvalidRequest(
+                "no model set and the client has no default model".to_string(),
+            )
+        })?;
+        let max_tokens = self.max_tokens.unwrap_or_else(|| match model_info(&model) {
+            Some(info) => DEFAULT_MAX_TOKENS.min(info.max_output_tokens),
+            None => DEFAULT_MAX_TOKENS,
+        });
+
+        let mut conversation = Conversation::new(model, max_tokens);
+        conversation.system = self.system;
+        if !self.tools.is_empty() {
+            conversation.tools = Some(self.tools);
+        }
+        conversation.tool_choice = self.tool_choice;
+        if let Some(preset) = self.preset {
+            conversation = conversation.with_preset(preset);
+        }
+        if let Some(storage) = self.storage {
+            if let Some(history) = storage.load()? {
+                conversation.messages = history;
+            }
+            conversation.storage = Some(storage);
+        }
+        Ok(conversation)
+    }
+}
+
+/// A [`Conversation`] bound to the client it sends with, created by [`Conversation::bind`]
+///
+/// Dereferences to the conversation, so its other methods stay available.
+#[derive(Clone)]
+pub struct BoundConversation {
+    conversation: Conversation,
+    client: Arc<AnthropicClient>,
+}
+
+impl BoundConversation {
+    /// Send the current conversation, as [`Conversation::send`]
+    pub async fn send(&mut self) -> Result<ConversationUpdate, AnthropicError> {
+        self.conversation.send(&self.client).await
+    }
+
+    /// Add a user message and send it
+    pub async fn say(
+        &mut self,
+        message: impl Into<String>,
+    ) -> Result<ConversationUpdate, AnthropicError> {
+        self.conversation
+            .send_user_message(&self.client, message)
+            .await
+    }
+
+    /// Continue a turn paused by a long-running server tool
+    pub async fn resume(&mut self) -> Result<ConversationUpdate, AnthropicError> {
+        self.conversation.resume(&self.client).await
+    }
+
+    /// The bound client
+    pub fn client(&self) -> &AnthropicClient {
+        &self.client
+    }
+
+    /// Unbind, returning the conversation
+    pub fn into_inner(self) -> Conversation {
+        self.conversation
+    }
+}
+
+impl std::ops::Deref for BoundConversation {
+    type Target = Conversation;
+
+    fn deref(&self) -> &Conversation {
+        &self.conversation
+    }
+}
+
+impl std::ops::DerefMut for BoundConversation {
+    fn deref_mut(&mut self) -> &mut Conversation {
+        &mut self.conversation
+    }
+}
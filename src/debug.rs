@@ -0,0 +1,337 @@
+use crate::conversation::Conversation;
+use crate::error::AnthropicError;
+use crate::tokens::{estimate_message_tokens, estimate_text_tokens};
+use crate::types::messages::{
+    CacheControl, Content, ContentBlock, DocumentSource, ImageSourceData, Message, Role,
+    SystemPrompt, ToolResultContent,
+};
+use serde_json::{json, Value};
+use std::fmt::{self, Write};
+use std::sync::Arc;
+
+/// Longest preview shown for a block's text before it is cut off
+const PREVIEW_CHARS: usize = 80;
+
+const RESET: &str = "\x1b[0m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+
+impl Conversation {
+    /// Render the history as compact text for the terminal while developing agents
+    ///
+    /// Each message shows its role, estimated input tokens, and a one-line preview per
+    /// block; cache breakpoints are marked with `[cache]`. Pass `color` to add ANSI colors.
+    pub fn debug_dump(&self, color: bool) -> String {
+        let paint = |code: &str, text: &str| {
+            if color {
+                format!("{}{}{}", code, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+
+        let request = self.build_request();
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}",
+            paint(
+                DIM,
+                &format!(
+                    "{} · {} messages · ~{} tokens",
+                    self.model(),
+                    self.messages().len(),
+                    crate::tokens::estimate_request_tokens(&request)
+                )
+            )
+        );
+
+        if let Some(system) = self.system() {
+            let _ = writeln!(
+                out,
+                "{} {} {}",
+                paint(YELLOW, "system"),
+                paint(DIM, &format!("~{}", estimate_text_tokens(system))),
+                preview(system)
+            );
+        }
+
+        for (index, message) in self.messages().iter().enumerate() {
+            let (role, role_color) = match message.role {
+                Role::User => ("user", CYAN),
+                Role::Assistant => ("assistant", GREEN),
+            };
+            let _ = writeln!(
+                out,
+                "[{}] {} {}",
+                index,
+                paint(role_color, role),
+                paint(DIM, &format!("~{}", estimate_message_tokens(message)))
+            );
+            match &message.content {
+                Content::Text(text) => {
+                    let _ = writeln!(out, "    {}", preview(text));
+                }
+                Content::Blocks(blocks) => {
+                    for block in blocks {
+                        let (line, is_error) = describe_block(block);
+                        let line = if is_error { paint(RED, &line) } else { line };
+                        let _ = writeln!(out, "    {}{}", line, cache_marker(block));
+                    }
+                }
+            }
+        }
+
+        for tool_use in self.pending_tool_uses() {
+            let _ = writeln!(
+                out,
+                "{} {} {}",
+                paint(YELLOW, "pending"),
+                tool_use.name,
+                preview(&tool_use.input.to_string())
+            );
+        }
+        out
+    }
+}
+
+/// Where two conversations' histories first differ, from [`Conversation::diff`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversationDiff {
+    /// Index of the first message that differs
+    pub index: usize,
+    /// The message in `self`, or `None` if its history ends first
+    pub left: Option<MessageSummary>,
+    /// The message in `other`, or `None` if its history ends first
+    pub right: Option<MessageSummary>,
+}
+
+/// A message's role and a one-line preview of each of its blocks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSummary {
+    pub role: Role,
+    pub summary: String,
+}
+
+impl MessageSummary {
+    fn new(message: &Message) -> Self {
+        let summary = match &message.content {
+            Content::Text(text) => preview(text),
+            Content::Blocks(blocks) => blocks
+                .iter()
+                .map(|block| describe_block(block).0)
+                .collect::<Vec<_>>()
+                .join(" | "),
+        };
+        Self {
+            role: message.role,
+            summary,
+        }
+    }
+}
+
+impl fmt::Display for ConversationDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "conversations diverge at message [{}]", self.index)?;
+        for (sign, side) in [("-", &self.left), ("+", &self.right)] {
+            match side {
+                Some(message) => {
+                    let role = match message.role {
+                        Role::User => "user",
+                        Role::Assistant => "assistant",
+                    };
+                    writeln!(f, "{} {} {}", sign, role, message.summary)?;
+                }
+                None => writeln!(f, "{} (no message)", sign)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Conversation {
+    /// Find the first message where this history and `other`'s differ
+    ///
+    /// Returns `None` when the histories are identical. The diff's `Display` output is a
+    /// short two-line report, handy in golden-transcript assertions.
+    pub fn diff(&self, other: &Conversation) -> Option<ConversationDiff> {
+        let (left, right) = (self.messages(), other.messages());
+        let index = (0..left.len().max(right.len())).find(|&index| {
+            match (left.get(index), right.get(index)) {
+                (Some(a), Some(b)) => !Arc::ptr_eq(a, b) && !same_message(a, b),
+                _ => true,
+            }
+        })?;
+        Some(ConversationDiff {
+            index,
+            left: left.get(index).map(|message| MessageSummary::new(message)),
+            right: right.get(index).map(|message| MessageSummary::new(message)),
+        })
+    }
+}
+
+impl Conversation {
+    /// Export the request the next send would make as JSON for the Anthropic Workbench
+    ///
+    /// The output has `system` as plain text, `messages`, and the sampling and tool
+    /// settings under `params`, so a problematic conversation can be pasted into the
+    /// Console. Cache breakpoints are dropped since the Workbench doesn't use them.
+    pub fn to_workbench_json(&self) -> Result<String, AnthropicError> {
+        let request = self.build_request();
+        let system = match request.system {
+            Some(SystemPrompt::Text(ref text)) => text.clone(),
+            Some(SystemPrompt::Blocks(ref blocks)) => blocks
+                .iter()
+                .map(|block| block.text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            None => String::new(),
+        };
+        let mut params = serde_json::to_value(&request)?;
+        let mut messages = Value::Null;
+        if let Value::Object(ref mut fields) = params {
+            messages = fields.remove("messages").unwrap_or_default();
+            for field in ["system", "stream", "metadata"] {
+                fields.remove(field);
+            }
+        }
+        strip_cache_control(&mut messages);
+        strip_cache_control(&mut params);
+        Ok(serde_json::to_string_pretty(&json!({
+            "system": system,
+            "messages": messages,
+            "params": params,
+        }))?)
+    }
+}
+
+fn strip_cache_control(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            fields.remove("cache_control");
+            fields.values_mut().for_each(strip_cache_control);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_cache_control),
+        _ => {}
+    }
+}
+
+fn same_message(a: &Message, b: &Message) -> bool {
+    a.role == b.role
+        && serde_json::to_value(&a.content).ok() == serde_json::to_value(&b.content).ok()
+}
+
+/// One-line summary of a block and whether it reports an error
+fn describe_block(block: &ContentBlock) -> (String, bool) {
+    match block {
+        ContentBlock::Text { text, .. } => (format!("text {}", preview(text)), false),
+        ContentBlock::Image { source, .. } => {
+            let detail = match &source.data {
+                ImageSourceData::Base64 { media_type, data } => {
+                    format!("{} {} bytes base64", media_type, data.len())
+                }
+                ImageSourceData::Bytes { media_type, data } => {
+                    format!("{} {} bytes", media_type, data.as_bytes().len())
+                }
+                ImageSourceData::Url { url } => url.clone(),
+                ImageSourceData::File { file_id } => file_id.clone(),
+            };
+            (format!("image {}", detail), false)
+        }
+        ContentBlock::Document { source, .. } => {
+            let detail = match source {
+                DocumentSource::Base64 { media_type, data } => {
+                    format!("{} {} bytes base64", media_type, data.len())
+                }
+                DocumentSource::Text { data, .. } => preview(data),
+                DocumentSource::Url { url } => url.clone(),
+                DocumentSource::File { file_id } => file_id.clone(),
+            };
+            (format!("document {}", detail), false)
+        }
+        ContentBlock::ToolUse {
+            id, name, input, ..
+        }
+        | ContentBlock::ServerToolUse {
+            id, name, input, ..
+        }
+        | ContentBlock::McpToolUse {
+            id, name, input, ..
+        } => (
+            format!("tool_use {} {} {}", name, id, preview(&input.to_string())),
+            false,
+        ),
+        ContentBlock::ToolResult {
+            tool_use_id,
+            content,
+            is_error,
+            ..
+        } => {
+            let body = match content {
+                Some(ToolResultContent::Text(text)) => preview(text),
+                Some(ToolResultContent::Blocks(blocks)) => format!("{} blocks", blocks.len()),
+                None => String::new(),
+            };
+            let failed = is_error.unwrap_or(false);
+            let status = if failed { "error" } else { "ok" };
+            (
+                format!("tool_result {} {} {}", tool_use_id, status, body),
+                failed,
+            )
+        }
+        ContentBlock::WebSearchToolResult { tool_use_id, .. } => {
+            (format!("web_search_result {}", tool_use_id), false)
+        }
+        ContentBlock::WebFetchToolResult { tool_use_id, .. } => {
+            (format!("web_fetch_result {}", tool_use_id), false)
+        }
+        ContentBlock::CodeExecutionToolResult { tool_use_id, .. }
+        | ContentBlock::BashCodeExecutionToolResult { tool_use_id, .. }
+        | ContentBlock::TextEditorCodeExecutionToolResult { tool_use_id, .. } => {
+            (format!("code_execution_result {}", tool_use_id), false)
+        }
+        ContentBlock::McpToolResult {
+            tool_use_id,
+            is_error,
+            ..
+        } => {
+            let failed = is_error.unwrap_or(false);
+            let status = if failed { "error" } else { "ok" };
+            (
+                format!("mcp_tool_result {} {}", tool_use_id, status),
+                failed,
+            )
+        }
+        ContentBlock::Thinking { thinking, .. } => {
+            (format!("thinking {}", preview(thinking)), false)
+        }
+        ContentBlock::RedactedThinking { .. } => ("redacted_thinking".to_string(), false),
+        ContentBlock::Unknown(raw) => {
+            let kind = raw.get("type").and_then(Value::as_str).unwrap_or("?");
+            (format!("unknown {}", kind), false)
+        }
+    }
+}
+
+fn cache_marker(block: &ContentBlock) -> String {
+    match block.cache_control() {
+        Some(CacheControl { ttl: Some(ttl), .. }) => format!(" [cache {}]", ttl),
+        Some(_) => " [cache]".to_string(),
+        None => String::new(),
+    }
+}
+
+/// Quote text on one line, cut to [`PREVIEW_CHARS`]
+fn preview(text: &str) -> String {
+    let flat: String = text
+        .chars()
+        .map(|c| if c == '\n' { '⏎' } else { c })
+        .collect();
+    match flat.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{:?}…", &flat[..end]),
+        None => format!("{:?}", flat),
+    }
+}
@@ -0,0 +1,146 @@
+use crate::agent::{Agent, AgentStopReason};
+use crate::registry::ToolRegistry;
+use crate::types::tools::Tool;
+use serde_json::json;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Token budget shared between a supervisor agent and every agent it delegates to
+#[derive(Debug, Clone)]
+pub struct SharedBudget {
+    limit: u32,
+    used: Arc<AtomicU32>,
+}
+
+impl SharedBudget {
+    /// Create a budget of `limit` input plus output tokens
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            used: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    /// Record tokens spent by any participating agent
+    pub fn record(&self, tokens: u32) {
+        self.used.fetch_add(tokens, Ordering::Relaxed);
+    }
+
+    /// Tokens spent so far across all participating agents
+    pub fn used(&self) -> u32 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Tokens left before the budget is exhausted
+    pub fn remaining(&self) -> u32 {
+        self.limit.saturating_sub(self.used())
+    }
+
+    /// Check whether the budget has been used up
+    pub fn is_exhausted(&self) -> bool {
+        self.used() >= self.limit
+    }
+}
+
+/// Where a delegated agent sits in a supervisor/worker hierarchy
+#[derive(Debug, Clone)]
+pub struct DelegationContext {
+    /// Depth of the agent receiving this context (the top-level supervisor is 0)
+    pub depth: u32,
+    /// Deepest level at which agents may still be delegated to
+    pub max_depth: u32,
+    /// Budget shared by the whole hierarchy, if any
+    pub budget: Option<SharedBudget>,
+}
+
+impl DelegationContext {
+    /// Context for a top-level supervisor allowing delegation down to `max_depth`
+    pub fn new(max_depth: u32) -> Self {
+        Self {
+            depth: 0,
+            max_depth,
+            budget: None,
+        }
+    }
+
+    /// Share a token budget across the hierarchy
+    pub fn with_budget(mut self, budget: SharedBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Context handed to an agent created one level below this one
+    pub fn child(&self) -> Self {
+        Self {
+            depth: self.depth + 1,
+            ..self.clone()
+        }
+    }
+}
+
+impl ToolRegistry {
+    /// Register a tool that delegates a sub-task to a freshly built worker agent
+    ///
+    /// Claude calls the tool with a `task` description; `factory` builds the worker from the
+    /// child [`DelegationContext`] (so workers can register delegates of their own), the
+    /// worker runs the task to completion, and its final answer becomes the tool result.
+    /// Calls beyond `context.max_depth` or after the shared budget is exhausted fail with an
+    /// error result instead of spawning a worker.
+    pub fn register_delegate<F>(
+        &mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        context: DelegationContext,
+        factory: F,
+    ) -> &mut Self
+    where
+        F: Fn(DelegationContext) -> Agent + 'static,
+    {
+        let tool = Tool::new(
+            name,
+            description,
+            json!({
+                "task": {
+                    "type": "string",
+                    "description": "A complete, self-contained description of the sub-task"
+                }
+            }),
+            vec!["task".to_string()],
+            None,
+        );
+
+        self.register(tool, move |input| {
+            let child = context.child();
+            let worker = if child.depth > child.max_depth {
+                Err("Delegation depth limit reached".to_string())
+            } else if child
+                .budget
+                .as_ref()
+                .is_some_and(SharedBudget::is_exhausted)
+            {
+                Err("Shared token budget exhausted".to_string())
+            } else {
+                let budget = child.budget.clone();
+                let worker = factory(child);
+                Ok(match budget {
+                    Some(budget) => worker.with_shared_budget(budget),
+                    None => worker,
+                })
+            };
+            let task = input["task"].as_str().unwrap_or_default().to_string();
+
+            async move {
+                let mut worker = worker?;
+                let run = worker.run(task).await.map_err(|e| e.to_string())?;
+                let summary = run.final_text();
+                Ok::<_, String>(match run.stop_reason {
+                    AgentStopReason::Completed => summary,
+                    stop_reason => format!(
+                        "{}\n\n[Sub-agent stopped early: {:?}]",
+                        summary, stop_reason
+                    ),
+                })
+            }
+        })
+    }
+}
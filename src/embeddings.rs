@@ -0,0 +1,193 @@
+use crate::client::retry_with_backoff;
+use crate::clock::SystemClock;
+use crate::error::AnthropicError;
+use hyperware_process_lib::http::{client::send_request_await_response, Method};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const VOYAGE_API_BASE_URL: &str = "https://api.voyageai.com";
+const DEFAULT_TIMEOUT_SECONDS: u64 = 60;
+const MAX_RETRIES: u32 = 10;
+
+/// Whether the texts being embedded are search queries or documents to be searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbeddingInputType {
+    Query,
+    Document,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingRequest {
+    pub input: Vec<String>,
+    pub model: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_type: Option<EmbeddingInputType>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncation: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_dimension: Option<u32>,
+}
+
+impl EmbeddingRequest {
+    pub fn new(model: impl Into<String>, input: Vec<String>) -> Self {
+        Self {
+            input,
+            model: model.into(),
+            input_type: None,
+            truncation: None,
+            output_dimension: None,
+        }
+    }
+
+    pub fn with_input_type(mut self, input_type: EmbeddingInputType) -> Self {
+        self.input_type = Some(input_type);
+        self
+    }
+
+    pub fn with_output_dimension(mut self, output_dimension: u32) -> Self {
+        self.output_dimension = Some(output_dimension);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingResponse {
+    pub object: String,
+    pub data: Vec<Embedding>,
+    pub model: String,
+    pub usage: EmbeddingUsage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Embedding {
+    pub object: String,
+    pub embedding: Vec<f32>,
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingUsage {
+    pub total_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct VoyageErrorResponse {
+    detail: String,
+}
+
+/// Client for Voyage AI embeddings, using the same Hyperware HTTP transport and retry
+/// behavior as [`AnthropicClient`](crate::AnthropicClient)
+pub struct VoyageClient {
+    api_key: String,
+    base_url: String,
+    timeout: u64,
+    max_retries: u32,
+}
+
+impl VoyageClient {
+    /// Create a new Voyage AI client with the provided API key
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            base_url: VOYAGE_API_BASE_URL.to_string(),
+            timeout: DEFAULT_TIMEOUT_SECONDS,
+            max_retries: MAX_RETRIES,
+        }
+    }
+
+    /// Use a custom base URL (useful for testing or proxies)
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Set custom timeout in seconds
+    pub fn with_timeout(mut self, timeout: u64) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set maximum number of retries for transient errors
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Create embeddings with retry logic
+    pub async fn embed(
+        &self,
+        request: EmbeddingRequest,
+    ) -> Result<EmbeddingResponse, AnthropicError> {
+        let body = serde_json::to_vec(&request)
+            .map_err(|e| AnthropicError::Serialization(e.to_string()))?;
+        retry_with_backoff(self.max_retries, &SystemClock, None, || {
+            self.embed_internal(body.clone())
+        })
+        .await
+    }
+
+    /// Embed texts and return the vectors in input order
+    pub async fn embed_texts(
+        &self,
+        model: impl Into<String>,
+        texts: Vec<String>,
+        input_type: EmbeddingInputType,
+    ) -> Result<Vec<Vec<f32>>, AnthropicError> {
+        let request = EmbeddingRequest::new(model, texts).with_input_type(input_type);
+        let mut response = self.embed(request).await?;
+        response.data.sort_by_key(|embedding| embedding.index);
+        Ok(response
+            .data
+            .into_iter()
+            .map(|embedding| embedding.embedding)
+            .collect())
+    }
+
+    async fn embed_internal(&self, body: Vec<u8>) -> Result<EmbeddingResponse, AnthropicError> {
+        let url = format!("{}/v1/embeddings", self.base_url);
+        let url = url::Url::parse(&url)
+            .map_err(|_| AnthropicError::InvalidResponse(format!("Invalid URL: {}", url)))?;
+
+        let mut headers = HashMap::new();
+        headers.insert(
+            "Authorization".to_string(),
+            format!("Bearer {}", self.api_key),
+        );
+        headers.insert("content-type".to_string(), "application/json".to_string());
+
+        let response =
+            send_request_await_response(Method::POST, url, Some(headers), self.timeout, body)
+                .await
+                .map_err(AnthropicError::from)?;
+
+        let status = response.status();
+        let body = response.into_body();
+
+        if status.is_success() {
+            return serde_json::from_slice::<EmbeddingResponse>(&body).map_err(|e| {
+                AnthropicError::Deserialization(format!("Failed to parse response: {}", e))
+            });
+        }
+
+        let message = serde_json::from_slice::<VoyageErrorResponse>(&body)
+            .map(|error| error.detail)
+            .unwrap_or_else(|_| String::from_utf8_lossy(&body).to_string());
+
+        Err(match status.as_u16() {
+            401 => AnthropicError::Authentication,
+            429 => AnthropicError::RateLimit,
+            500..=599 => AnthropicError::ApiError {
+                error_type: "api_error".to_string(),
+                message,
+            },
+            _ => AnthropicError::InvalidResponse(format!(
+                "API returned status {}: {}",
+                status, message
+            )),
+        })
+    }
+}
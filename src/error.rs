@@ -1,13 +1,123 @@
+use hyperware_process_lib::http::{server::send_response, StatusCode};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
 use thiserror::Error;
 
 // Re-export HttpClientError from hyperware_process_lib for convenience
 pub use hyperware_process_lib::http::client::HttpClientError;
 
+/// What kind of transport failure an [`AnthropicError::HttpClient`] was
+///
+/// Hyperware's HTTP client reports failures as text, so the kind is classified from the
+/// message; anything unrecognized is [`Other`](Self::Other).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportErrorKind {
+    /// No response arrived in time
+    Timeout,
+    /// The API's host name could not be resolved, usually because the node is offline
+    Dns,
+    /// The node has no route to the API, usually because it is offline
+    Unreachable,
+    /// Nothing accepted the connection, e.g. a misconfigured proxy
+    ConnectionRefused,
+    /// The connection dropped mid-request
+    ConnectionReset,
+    /// The TLS handshake or certificate check failed
+    Tls,
+    /// The request couldn't be built or sent, e.g. a bad URL; it never left the node
+    InvalidRequest,
+    /// Any other failure
+    Other,
+}
+
+impl TransportErrorKind {
+    /// Classify an HTTP client error message
+    pub fn classify(message: &str) -> Self {
+        let message = message.to_ascii_lowercase();
+        let mentions = |fragments: &[&str]| fragments.iter().any(|f| message.contains(f));
+        if mentions(&["timed out", "timeout"]) {
+            TransportErrorKind::Timeout
+        } else if mentions(&[
+            "dns",
+            "failed to lookup",
+            "name or service not known",
+            "nodename nor servname",
+            "no such host",
+        ]) {
+            TransportErrorKind::Dns
+        } else if mentions(&["network is unreachable", "no route to host"]) {
+            TransportErrorKind::Unreachable
+        } else if mentions(&["connection refused"]) {
+            TransportErrorKind::ConnectionRefused
+        } else if mentions(&["certificate", "tls", "ssl", "handshake"]) {
+            TransportErrorKind::Tls
+        } else if mentions(&[
+            "connection reset",
+            "connection closed",
+            "connection aborted",
+            "broken pipe",
+            "unexpected eof",
+        ]) {
+            TransportErrorKind::ConnectionReset
+        } else {
+            TransportErrorKind::Other
+        }
+    }
+
+    /// Whether the failure points at the node's own network rather than at the API
+    pub fn is_local(&self) -> bool {
+        matches!(
+            self,
+            TransportErrorKind::Dns | TransportErrorKind::Unreachable
+        )
+    }
+
+    /// Whether the API may have received the request before the failure
+    pub fn may_have_reached_api(&self) -> bool {
+        matches!(
+            self,
+            TransportErrorKind::Timeout
+                | TransportErrorKind::ConnectionReset
+                | TransportErrorKind::Other
+        )
+    }
+
+    /// Whether sending again may succeed
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            TransportErrorKind::Tls
+                | TransportErrorKind::InvalidRequest
+                | TransportErrorKind::Other
+        )
+    }
+}
+
+impl fmt::Display for TransportErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TransportErrorKind::Timeout => "timeout",
+            TransportErrorKind::Dns => "DNS failure",
+            TransportErrorKind::Unreachable => "network unreachable",
+            TransportErrorKind::ConnectionRefused => "connection refused",
+            TransportErrorKind::ConnectionReset => "connection reset",
+            TransportErrorKind::Tls => "TLS failure",
+            TransportErrorKind::InvalidRequest => "invalid request",
+            TransportErrorKind::Other => "transport failure",
+        };
+        f.write_str(name)
+    }
+}
+
 #[derive(Clone, Debug, Error, Serialize, Deserialize)]
 pub enum AnthropicError {
-    #[error("HTTP client error: {0}")]
-    HttpClient(String),
+    #[error("HTTP client error ({kind}): {message}")]
+    HttpClient {
+        kind: TransportErrorKind,
+        message: String,
+    },
 
     #[error("API key not provided")]
     MissingApiKey,
@@ -29,6 +139,270 @@ pub enum AnthropicError {
 
     #[error("Deserialization error: {0}")]
     Deserialization(String),
+
+    #[error("Response validation failed: {0}")]
+    ResponseValidation(String),
+
+    #[error("Storage error: {0}")]
+    Storage(String),
+
+    #[error("Content blocked by guardrail {filter}: {reason}")]
+    PolicyViolation { filter: String, reason: String },
+
+    #[error("Claude refused the request: {0}")]
+    Refused(String),
+
+    #[error("Transcript mismatch: {0}")]
+    TranscriptMismatch(String),
+
+    #[error("Gateway error: {0}")]
+    Gateway(String),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Invalid image: {0}")]
+    InvalidImage(String),
+
+    #[error("max_tokens {requested} is over the {limit} token output limit of {model}")]
+    MaxTokensExceeded {
+        model: String,
+        requested: u32,
+        limit: u32,
+    },
+
+    #[error("Request needs about {needed} tokens but the context window holds {available}")]
+    ContextWindowExceeded { needed: u32, available: u32 },
+
+    /// An API error with the response headers attached, returned when the client
+    /// captures headers
+    #[error("{error}")]
+    WithHeaders {
+        error: Box<AnthropicError>,
+        headers: HashMap<String, String>,
+    },
+
+    #[error("Request body is {size} bytes, over the {limit} byte limit ({hint})")]
+    RequestTooLarge {
+        size: usize,
+        limit: usize,
+        hint: String,
+    },
+
+    #[error("Merging would drop pending tool uses {tool_use_ids:?}")]
+    MergeConflict { tool_use_ids: Vec<String> },
+
+    #[error("The same user message was added twice in a row")]
+    DuplicateMessage,
+
+    #[error("Expected a plain text response, but {0}")]
+    NotText(String),
+}
+
+impl AnthropicError {
+    /// The underlying error, looking through any attached headers
+    pub fn inner(&self) -> &AnthropicError {
+        match self {
+            AnthropicError::WithHeaders { error, .. } => error.inner(),
+            other => other,
+        }
+    }
+
+    /// Response headers attached to the error, if the client captured them
+    pub fn headers(&self) -> Option<&HashMap<String, String>> {
+        match self {
+            AnthropicError::WithHeaders { headers, .. } => Some(headers),
+            _ => None,
+        }
+    }
+}
+
+impl AnthropicError {
+    /// Whether sending the same request again may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self.inner() {
+            AnthropicError::ApiError { error_type, .. } => {
+                // Retry on overloaded errors
+                error_type == "overloaded_error" || error_type == "api_error"
+            }
+            AnthropicError::RateLimit => true,
+            AnthropicError::HttpClient { kind, .. } => kind.is_retryable(),
+            _ => false,
+        }
+    }
+
+    /// A stable, machine-readable name for the kind of error
+    pub fn code(&self) -> &str {
+        match self.inner() {
+            AnthropicError::HttpClient { .. } => "network_error",
+            AnthropicError::MissingApiKey | AnthropicError::Authentication => {
+                "authentication_error"
+            }
+            AnthropicError::InvalidResponse(_) | AnthropicError::Deserialization(_) => {
+                "invalid_response"
+            }
+            AnthropicError::ApiError { error_type, .. } => error_type,
+            AnthropicError::RateLimit => "rate_limit_error",
+            AnthropicError::Serialization(_) => "serialization_error",
+            AnthropicError::ResponseValidation(_) => "response_validation_error",
+            AnthropicError::Storage(_) => "storage_error",
+            AnthropicError::PolicyViolation { .. } => "policy_violation",
+            AnthropicError::Refused(_) => "refusal",
+            AnthropicError::TranscriptMismatch(_) => "transcript_mismatch",
+            AnthropicError::Gateway(_) => "gateway_error",
+            AnthropicError::InvalidRequest(_)
+            | AnthropicError::InvalidImage(_)
+            | AnthropicError::MaxTokensExceeded { .. }
+            | AnthropicError::ContextWindowExceeded { .. } => "invalid_request_error",
+            AnthropicError::RequestTooLarge { .. } => "request_too_large",
+            AnthropicError::MergeConflict { .. } => "merge_conflict",
+            AnthropicError::DuplicateMessage => "duplicate_message",
+            AnthropicError::NotText(_) => "unexpected_response",
+            AnthropicError::WithHeaders { .. } => unreachable!("inner() looks through headers"),
+        }
+    }
+
+    /// The HTTP status an app should answer with when passing the error on to its users
+    pub fn http_status(&self) -> u16 {
+        match self.inner() {
+            AnthropicError::MissingApiKey | AnthropicError::Authentication => 502,
+            AnthropicError::ApiError { error_type, .. } => match error_type.as_str() {
+                "invalid_request_error" => 400,
+                "not_found_error" => 404,
+                "request_too_large" => 413,
+                "rate_limit_error" => 429,
+                "overloaded_error" => 529,
+                _ => 502,
+            },
+            AnthropicError::RateLimit => 429,
+            AnthropicError::PolicyViolation { .. } | AnthropicError::Refused(_) => 422,
+            AnthropicError::InvalidRequest(_)
+            | AnthropicError::InvalidImage(_)
+            | AnthropicError::MaxTokensExceeded { .. }
+            | AnthropicError::ContextWindowExceeded { .. } => 400,
+            AnthropicError::RequestTooLarge { .. } => 413,
+            AnthropicError::DuplicateMessage => 409,
+            AnthropicError::NotText(_) => 502,
+            AnthropicError::HttpClient {
+                kind: TransportErrorKind::Timeout,
+                ..
+            } => 504,
+            AnthropicError::HttpClient { .. } => 502,
+            _ => 500,
+        }
+    }
+
+    /// A payload that is safe to show users, e.g. in an IPC or HTTP response
+    pub fn to_payload(&self) -> ErrorPayload {
+        ErrorPayload::from(self)
+    }
+}
+
+/// An error as reported to an app's own users
+///
+/// Internal details such as raw response bodies, storage paths, and anything resembling
+/// an API key are left out, so the payload can be returned over Hyperware IPC or HTTP
+/// without each app writing its own mapping.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorPayload {
+    /// See [`AnthropicError::code`]
+    pub code: String,
+    pub message: String,
+    /// Whether the user may try again later
+    pub retryable: bool,
+}
+
+impl ErrorPayload {
+    /// Answer the current HTTP server request with the payload as a JSON body
+    pub fn send_http_response(&self, status: u16) {
+        let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let headers = HashMap::from([("Content-Type".to_string(), "application/json".to_string())]);
+        let body = serde_json::to_vec(self).unwrap_or_default();
+        send_response(status, Some(headers), body);
+    }
+}
+
+impl From<&AnthropicError> for ErrorPayload {
+    fn from(error: &AnthropicError) -> Self {
+        let message = match error.inner() {
+            AnthropicError::HttpClient { kind, .. } if kind.is_local() => {
+                "The AI service could not be reached from this node. Check its network \
+                 connection."
+                    .to_string()
+            }
+            AnthropicError::HttpClient {
+                kind: TransportErrorKind::InvalidRequest,
+                ..
+            } => "The request to the AI service could not be sent.".to_string(),
+            AnthropicError::HttpClient { .. } => {
+                "The AI service could not be reached. Please try again.".to_string()
+            }
+            AnthropicError::MissingApiKey | AnthropicError::Authentication => {
+                "The AI service is not configured correctly.".to_string()
+            }
+            AnthropicError::InvalidResponse(_)
+            | AnthropicError::Deserialization(_)
+            | AnthropicError::Serialization(_)
+            | AnthropicError::TranscriptMismatch(_)
+            | AnthropicError::Gateway(_) => {
+                "The AI service returned an unexpected response.".to_string()
+            }
+            AnthropicError::ApiError { message, .. } => redact_keys(message),
+            AnthropicError::RateLimit => {
+                "Too many requests right now. Please try again shortly.".to_string()
+            }
+            AnthropicError::ResponseValidation(_) | AnthropicError::NotText(_) => {
+                "The response did not have the expected format.".to_string()
+            }
+            AnthropicError::Storage(_) => "The conversation could not be saved.".to_string(),
+            AnthropicError::PolicyViolation { .. } => {
+                "The content was blocked by a content policy.".to_string()
+            }
+            AnthropicError::Refused(_) => "Claude declined to respond to this request.".to_string(),
+            other => redact_keys(&other.to_string()),
+        };
+        Self {
+            code: error.code().to_string(),
+            message,
+            retryable: error.is_retryable(),
+        }
+    }
+}
+
+impl From<AnthropicError> for ErrorPayload {
+    fn from(error: AnthropicError) -> Self {
+        Self::from(&error)
+    }
+}
+
+/// Replace anything shaped like an Anthropic key (`sk-ant-...`) with `[redacted]`
+fn redact_keys(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("sk-ant-") {
+        redacted.push_str(&rest[..start]);
+        redacted.push_str("[redacted]");
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(rest.len());
+        rest = &rest[end..];
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+impl From<HttpClientError> for AnthropicError {
+    fn from(error: HttpClientError) -> Self {
+        let kind = match &error {
+            HttpClientError::ExecuteRequestFailed(message) => TransportErrorKind::classify(message),
+            _ => TransportErrorKind::InvalidRequest,
+        };
+        AnthropicError::HttpClient {
+            kind,
+            message: error.to_string(),
+        }
+    }
 }
 
 impl From<serde_json::Error> for AnthropicError {
@@ -38,14 +412,14 @@ impl From<serde_json::Error> for AnthropicError {
 }
 
 // API Error response structure from Anthropic
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiErrorResponse {
     pub error: ApiErrorDetail,
     #[serde(rename = "type")]
     pub error_type: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiErrorDetail {
     #[serde(rename = "type")]
     pub error_type: String,
@@ -0,0 +1,407 @@
+use crate::client::AnthropicClient;
+use crate::conversation::{Conversation, ToolResult};
+use crate::error::AnthropicError;
+use crate::validation::strip_code_fence;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+const GRADER_MAX_TOKENS: u32 = 1024;
+const GRADER_SYSTEM_PROMPT: &str = "You grade responses from an AI assistant against a rubric. \
+Judge only whether the response satisfies the rubric, and explain your decision in one sentence.";
+
+/// A check applied to the final response text of an eval case
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Assertion {
+    /// The response must contain the given substring
+    Contains { value: String },
+    /// The response must not contain the given substring
+    NotContains { value: String },
+    /// The response must match the given regular expression
+    Regex { pattern: String },
+    /// The response must be JSON matching the schema (`type`, `enum`, `required`,
+    /// `properties`, and `items` are checked)
+    JsonSchema { schema: Value },
+    /// A grader model must judge that the response satisfies the rubric
+    Graded { rubric: String },
+}
+
+impl Assertion {
+    /// Require the response to contain a substring
+    pub fn contains(value: impl Into<String>) -> Self {
+        Assertion::Contains {
+            value: value.into(),
+        }
+    }
+
+    /// Require the response not to contain a substring
+    pub fn not_contains(value: impl Into<String>) -> Self {
+        Assertion::NotContains {
+            value: value.into(),
+        }
+    }
+
+    /// Require the response to match a regular expression
+    pub fn regex(pattern: impl Into<String>) -> Self {
+        Assertion::Regex {
+            pattern: pattern.into(),
+        }
+    }
+
+    /// Require the response to be JSON matching a schema
+    pub fn json_schema(schema: Value) -> Self {
+        Assertion::JsonSchema { schema }
+    }
+
+    /// Have a grader model judge the response against a rubric
+    pub fn graded(rubric: impl Into<String>) -> Self {
+        Assertion::Graded {
+            rubric: rubric.into(),
+        }
+    }
+
+    /// Check the response without calling a model, or `None` for LLM-graded assertions
+    pub fn check_text(&self, response: &str) -> Option<Result<(), String>> {
+        let result = match self {
+            Assertion::Contains { value } => {
+                if response.contains(value.as_str()) {
+                    Ok(())
+                } else {
+                    Err(format!("expected the response to contain \"{}\"", value))
+                }
+            }
+            Assertion::NotContains { value } => {
+                if response.contains(value.as_str()) {
+                    Err(format!(
+                        "expected the response not to contain \"{}\"",
+                        value
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            Assertion::Regex { pattern } => match Regex::new(pattern) {
+                Ok(regex) if regex.is_match(response) => Ok(()),
+                Ok(_) => Err(format!("expected the response to match /{}/", pattern)),
+                Err(e) => Err(format!("invalid regex /{}/: {}", pattern, e)),
+            },
+            Assertion::JsonSchema { schema } => {
+                serde_json::from_str::<Value>(strip_code_fence(response))
+                    .map_err(|e| format!("expected a JSON response: {}", e))
+                    .and_then(|value| check_schema(&value, schema, "$"))
+            }
+            Assertion::Graded { .. } => return None,
+        };
+        Some(result)
+    }
+
+    async fn check(
+        &self,
+        client: &AnthropicClient,
+        grader_model: &str,
+        response: &str,
+    ) -> Result<(), String> {
+        if let Some(result) = self.check_text(response) {
+            return result;
+        }
+        let Assertion::Graded { rubric } = self else {
+            return Ok(());
+        };
+
+        let mut grader = Conversation::new(grader_model, GRADER_MAX_TOKENS)
+            .with_system(GRADER_SYSTEM_PROMPT)
+            .with_temperature(0.0);
+        grader.add_user_message(format!(
+            "<rubric>\n{}\n</rubric>\n<response>\n{}\n</response>",
+            rubric, response
+        ));
+        match grader.send_structured::<Grade>(client).await {
+            Ok(grade) if grade.pass => Ok(()),
+            Ok(grade) => Err(format!("grader rejected the response: {}", grade.reason)),
+            Err(e) => Err(format!("grader failed: {}", e)),
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct Grade {
+    /// Whether the response satisfies the rubric
+    pass: bool,
+    /// One-sentence justification
+    reason: String,
+}
+
+fn check_schema(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| matches_type(value, t)) {
+            return Err(format!("{}: expected type {}", path, expected));
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            return Err(format!("{}: {} is not one of {:?}", path, value, allowed));
+        }
+    }
+
+    if let Value::Object(object) = value {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(key) {
+                    return Err(format!("{}: missing required property `{}`", path, key));
+                }
+            }
+        }
+        if let Some(Value::Object(properties)) = schema.get("properties") {
+            for (key, property_schema) in properties {
+                if let Some(property) = object.get(key) {
+                    check_schema(property, property_schema, &format!("{}.{}", path, key))?;
+                }
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check_schema(item, item_schema, &format!("{}[{}]", path, i))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn matches_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// A prompt to run, optionally over several turns with scripted tool results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalCase {
+    pub name: String,
+    /// User messages sent in order; assertions apply to the response to the last one
+    pub turns: Vec<String>,
+    /// Canned results returned when Claude calls a tool, keyed by tool name
+    #[serde(default)]
+    pub tool_results: HashMap<String, String>,
+    pub assertions: Vec<Assertion>,
+}
+
+impl EvalCase {
+    /// Create a single-turn case
+    pub fn new(name: impl Into<String>, prompt: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            turns: vec![prompt.into()],
+            tool_results: HashMap::new(),
+            assertions: Vec::new(),
+        }
+    }
+
+    /// Add a follow-up user turn
+    pub fn then(mut self, prompt: impl Into<String>) -> Self {
+        self.turns.push(prompt.into());
+        self
+    }
+
+    /// Return a fixed result whenever Claude calls the named tool
+    pub fn with_tool_result(mut self, tool: impl Into<String>, result: impl Into<String>) -> Self {
+        self.tool_results.insert(tool.into(), result.into());
+        self
+    }
+
+    /// Add an assertion on the final response
+    pub fn with_assertion(mut self, assertion: Assertion) -> Self {
+        self.assertions.push(assertion);
+        self
+    }
+}
+
+/// Outcome of a single eval case
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalCaseResult {
+    pub name: String,
+    /// Final response text the assertions were checked against
+    pub response: String,
+    /// Messages from assertions that failed
+    pub failures: Vec<String>,
+    /// Set when the case could not be run to completion
+    pub error: Option<String>,
+}
+
+impl EvalCaseResult {
+    /// Whether the case ran and every assertion held
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.failures.is_empty()
+    }
+}
+
+/// Results of running an [`EvalSuite`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalReport {
+    pub results: Vec<EvalCaseResult>,
+}
+
+impl EvalReport {
+    /// Number of passing cases
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed()).count()
+    }
+
+    /// Number of failing cases
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+
+    /// Fraction of cases that passed (1.0 for an empty report)
+    pub fn pass_rate(&self) -> f32 {
+        if self.results.is_empty() {
+            1.0
+        } else {
+            self.passed() as f32 / self.results.len() as f32
+        }
+    }
+
+    /// Human-readable summary listing each failing case and why it failed
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{}/{} cases passed ({:.0}%)",
+            self.passed(),
+            self.results.len(),
+            self.pass_rate() * 100.0
+        );
+        for result in self.results.iter().filter(|r| !r.passed()) {
+            summary.push_str(&format!("\n✗ {}", result.name));
+            if let Some(ref error) = result.error {
+                summary.push_str(&format!("\n    error: {}", error));
+            }
+            for failure in &result.failures {
+                summary.push_str(&format!("\n    {}", failure));
+            }
+        }
+        summary
+    }
+}
+
+/// Runs eval cases against a conversation template
+///
+/// Each case starts from a fork of the template, so the system prompt, tools, sampling
+/// settings, and validators under test are shared by every case.
+#[derive(Debug, Clone)]
+pub struct EvalSuite {
+    template: Conversation,
+    grader_model: Option<String>,
+    cases: Vec<EvalCase>,
+}
+
+impl EvalSuite {
+    /// Create a suite whose cases run against forks of `template`
+    pub fn new(template: Conversation) -> Self {
+        Self {
+            template,
+            grader_model: None,
+            cases: Vec::new(),
+        }
+    }
+
+    /// Use a different model for LLM-graded assertions (defaults to the model under test)
+    pub fn with_grader_model(mut self, model: impl Into<String>) -> Self {
+        self.grader_model = Some(model.into());
+        self
+    }
+
+    /// Add a case
+    pub fn with_case(mut self, case: EvalCase) -> Self {
+        self.cases.push(case);
+        self
+    }
+
+    /// Add several cases, e.g. loaded from a fixture file
+    pub fn with_cases(mut self, cases: impl IntoIterator<Item = EvalCase>) -> Self {
+        self.cases.extend(cases);
+        self
+    }
+
+    /// Run every case in order and collect the results
+    pub async fn run(&self, client: &AnthropicClient) -> EvalReport {
+        let mut results = Vec::new();
+        for case in &self.cases {
+            results.push(self.run_case(client, case).await);
+        }
+        EvalReport { results }
+    }
+
+    async fn run_case(&self, client: &AnthropicClient, case: &EvalCase) -> EvalCaseResult {
+        let mut result = EvalCaseResult {
+            name: case.name.clone(),
+            response: String::new(),
+            failures: Vec::new(),
+            error: None,
+        };
+
+        match self.run_turns(client, case).await {
+            Ok(response) => result.response = response,
+            Err(e) => {
+                result.error = Some(e.to_string());
+                return result;
+            }
+        }
+
+        let grader_model = self
+            .grader_model
+            .clone()
+            .unwrap_or_else(|| self.template.model().to_string());
+        for assertion in &case.assertions {
+            if let Err(failure) = assertion
+                .check(client, &grader_model, &result.response)
+                .await
+            {
+                result.failures.push(failure);
+            }
+        }
+        result
+    }
+
+    async fn run_turns(
+        &self,
+        client: &AnthropicClient,
+        case: &EvalCase,
+    ) -> Result<String, AnthropicError> {
+        let mut conversation = self.template.fork();
+        let mut response = String::new();
+
+        for turn in &case.turns {
+            conversation.add_user_message(turn.clone());
+            let updates = conversation
+                .complete_tool_loop(client, |tool_use| async move {
+                    Ok(match case.tool_results.get(&tool_use.name) {
+                        Some(content) => ToolResult::success(tool_use.id, content.clone()),
+                        None => ToolResult::error(
+                            tool_use.id,
+                            format!("No scripted result for tool `{}`", tool_use.name),
+                        ),
+                    })
+                })
+                .await?;
+            response = updates.last().map(|u| u.text()).unwrap_or_default();
+        }
+
+        Ok(response)
+    }
+}
@@ -0,0 +1,139 @@
+use crate::conversation::Conversation;
+use crate::tokens::estimate_text_tokens;
+use crate::types::messages::{Content, Message, Role};
+use std::sync::Arc;
+
+const DEFAULT_FEW_SHOT_HEADER: &str = "Here are some examples of inputs and ideal outputs:";
+
+/// A single input paired with the ideal output Claude should produce for it
+#[derive(Debug, Clone)]
+pub struct FewShotExample {
+    pub input: String,
+    pub output: String,
+}
+
+/// How few-shot examples are injected into a conversation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FewShotMode {
+    /// As alternating user/assistant messages at the current end of the history
+    Messages,
+    /// As a formatted block appended to the system prompt
+    System,
+}
+
+/// Builder for few-shot examples with optional token-budget-aware selection
+#[derive(Debug, Clone)]
+pub struct FewShot {
+    examples: Vec<FewShotExample>,
+    token_budget: Option<u32>,
+    header: String,
+}
+
+impl FewShot {
+    /// Create an empty set of examples
+    pub fn new() -> Self {
+        Self {
+            examples: Vec::new(),
+            token_budget: None,
+            header: DEFAULT_FEW_SHOT_HEADER.to_string(),
+        }
+    }
+
+    /// Add an example pair; examples are kept in insertion order, most important first
+    pub fn with_example(mut self, input: impl Into<String>, output: impl Into<String>) -> Self {
+        self.examples.push(FewShotExample {
+            input: input.into(),
+            output: output.into(),
+        });
+        self
+    }
+
+    /// Only include as many leading examples as fit within the estimated token budget
+    pub fn with_token_budget(mut self, token_budget: u32) -> Self {
+        self.token_budget = Some(token_budget);
+        self
+    }
+
+    /// Set the introduction line used in system prompt mode
+    pub fn with_header(mut self, header: impl Into<String>) -> Self {
+        self.header = header.into();
+        self
+    }
+
+    /// The examples that fit within the token budget
+    pub fn selected(&self) -> &[FewShotExample] {
+        let Some(budget) = self.token_budget else {
+            return &self.examples;
+        };
+
+        let mut used = 0;
+        let count = self
+            .examples
+            .iter()
+            .take_while(|example| {
+                used +=
+                    estimate_text_tokens(&example.input) + estimate_text_tokens(&example.output);
+                used <= budget
+            })
+            .count();
+        &self.examples[..count]
+    }
+
+    /// Render the selected examples as alternating user/assistant messages
+    pub fn to_messages(&self) -> Vec<Message> {
+        self.selected()
+            .iter()
+            .flat_map(|example| {
+                [
+                    Message {
+                        role: Role::User,
+                        content: Content::Text(example.input.clone()),
+                    },
+                    Message {
+                        role: Role::Assistant,
+                        content: Content::Text(example.output.clone()),
+                    },
+                ]
+            })
+            .collect()
+    }
+
+    /// Render the selected examples as a block of text for the system prompt
+    pub fn to_system_text(&self) -> String {
+        let mut text = self.header.clone();
+        for example in self.selected() {
+            text.push_str(&format!(
+                "\n\n<example>\n<input>\n{}\n</input>\n<output>\n{}\n</output>\n</example>",
+                example.input, example.output
+            ));
+        }
+        text
+    }
+}
+
+impl Default for FewShot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Conversation {
+    /// Inject few-shot examples, either as messages or appended to the system prompt
+    pub fn with_few_shot(mut self, few_shot: &FewShot, mode: FewShotMode) -> Self {
+        match mode {
+            FewShotMode::Messages => {
+                self.messages_mut()
+                    .extend(few_shot.to_messages().into_iter().map(Arc::new));
+                self
+            }
+            FewShotMode::System => {
+                let examples = few_shot.to_system_text();
+                let system = match self.system() {
+                    Some(system) => format!("{}\n\n{}", system, examples),
+                    None => examples,
+                };
+                self.with_system(system)
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::client::AnthropicClient;
+use crate::error::AnthropicError;
+use crate::types::messages::{CreateMessageRequest, MessageResponse};
+use hyperware_process_lib::{hyperapp, our, Address, Request};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_GATEWAY_TIMEOUT_SECONDS: u64 = 300;
+
+/// Request sent over IPC to an Anthropic gateway process
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GatewayRequest {
+    SendMessage(CreateMessageRequest),
+}
+
+/// Response returned by an Anthropic gateway process
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GatewayResponse {
+    SendMessage(Result<MessageResponse, AnthropicError>),
+}
+
+/// Process-side half of the gateway: owns the API key and forwards requests from other processes
+///
+/// Put one in a hyperapp's state and call [`AnthropicGateway::handle`] from a remote or local
+/// endpoint (or [`AnthropicGateway::handle_bytes`] for raw request bodies). Every process on
+/// the node then shares one key, one client configuration, and one retry policy. Only
+/// processes on the gateway's own node are served unless remote nodes are allowed.
+pub struct AnthropicGateway {
+    client: AnthropicClient,
+    allowed_processes: Option<Vec<String>>,
+    allow_remote_nodes: bool,
+}
+
+impl AnthropicGateway {
+    /// Create a gateway that sends requests with the given client
+    pub fn new(client: AnthropicClient) -> Self {
+        Self {
+            client,
+            allowed_processes: None,
+            allow_remote_nodes: false,
+        }
+    }
+
+    /// Only serve the listed process IDs (e.g. `chat:chat:sys`); all local processes by default
+    pub fn with_allowed_process(mut self, process: impl Into<String>) -> Self {
+        self.allowed_processes
+            .get_or_insert_with(Vec::new)
+            .push(process.into());
+        self
+    }
+
+    /// Also serve processes on other nodes
+    pub fn allow_remote_nodes(mut self) -> Self {
+        self.allow_remote_nodes = true;
+        self
+    }
+
+    /// Whether a request from `source` would be served
+    pub fn is_allowed(&self, source: &Address) -> bool {
+        if !self.allow_remote_nodes && source.node() != our().node() {
+            return false;
+        }
+        match self.allowed_processes {
+            Some(ref allowed) => allowed.contains(&source.process.to_string()),
+            None => true,
+        }
+    }
+
+    /// Serve a typed request from `source`
+    pub async fn handle(&self, source: &Address, request: GatewayRequest) -> GatewayResponse {
+        match request {
+            GatewayRequest::SendMessage(request) => {
+                if !self.is_allowed(source) {
+                    return GatewayResponse::SendMessage(Err(AnthropicError::Gateway(format!(
+                        "{} is not allowed to use this gateway",
+                        source
+                    ))));
+                }
+                GatewayResponse::SendMessage(self.client.send_message(request).await)
+            }
+        }
+    }
+
+    /// Serve a serialized [`GatewayRequest`], returning the serialized [`GatewayResponse`]
+    pub async fn handle_bytes(&self, source: &Address, body: &[u8]) -> Vec<u8> {
+        let response = match serde_json::from_slice::<GatewayRequest>(body) {
+            Ok(request) => self.handle(source, request).await,
+            Err(e) => GatewayResponse::SendMessage(Err(AnthropicError::Deserialization(format!(
+                "Invalid gateway request: {}",
+                e
+            )))),
+        };
+        serde_json::to_vec(&response).unwrap_or_default()
+    }
+}
+
+/// Caller-side half of the gateway: sends requests to a gateway process instead of the API
+#[derive(Debug, Clone)]
+pub struct GatewayClient {
+    gateway: Address,
+    timeout: u64,
+}
+
+impl GatewayClient {
+    /// Create a client for the gateway process at `gateway`
+    pub fn new(gateway: Address) -> Self {
+        Self {
+            gateway,
+            timeout: DEFAULT_GATEWAY_TIMEOUT_SECONDS,
+        }
+    }
+
+    /// Set how long to wait for the gateway's response, in seconds
+    pub fn with_timeout(mut self, timeout: u64) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send a message through the gateway
+    pub async fn send_message(
+        &self,
+        request: CreateMessageRequest,
+    ) -> Result<MessageResponse, AnthropicError> {
+        let body = serde_json::to_vec(&GatewayRequest::SendMessage(request))?;
+        let response: GatewayResponse = hyperapp::send(
+            Request::to(self.gateway.clone())
+                .body(body)
+                .expects_response(self.timeout),
+        )
+        .await
+        .map_err(|e| AnthropicError::Gateway(format!("{:?}", e)))?;
+
+        match response {
+            GatewayResponse::SendMessage(result) => result,
+        }
+    }
+}
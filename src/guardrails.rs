@@ -0,0 +1,165 @@
+use crate::error::AnthropicError;
+use crate::types::messages::{
+    Content, ContentBlock, CreateMessageRequest, MessageResponse, ResponseContentBlock, Role,
+};
+use std::fmt;
+use std::sync::Arc;
+
+type FilterFn = dyn Fn(&str) -> GuardrailAction + Send + Sync;
+
+/// What a content filter decided about a piece of text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GuardrailAction {
+    /// Let the text through unchanged
+    Allow,
+    /// Substitute the text (e.g. with redacted content)
+    Replace(String),
+    /// Reject the text, failing the request with the given reason
+    Block(String),
+}
+
+#[derive(Clone)]
+struct ContentFilter {
+    name: String,
+    filter: Arc<FilterFn>,
+}
+
+/// Content filters applied to outgoing user text and incoming assistant text
+///
+/// Filters run in registration order, each seeing the output of the previous one. Outgoing
+/// filters are applied to every user text block of each request (tool results are left
+/// alone), so replacements are applied consistently on every turn without altering the
+/// stored history. Incoming filters are applied to the text blocks of each response before
+/// it is returned.
+#[derive(Clone, Default)]
+pub struct Guardrails {
+    outgoing: Vec<ContentFilter>,
+    incoming: Vec<ContentFilter>,
+}
+
+impl Guardrails {
+    /// Create an empty set of guardrails
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a filter for user text sent to Claude
+    pub fn with_outgoing_filter<F>(mut self, name: impl Into<String>, filter: F) -> Self
+    where
+        F: Fn(&str) -> GuardrailAction + Send + Sync + 'static,
+    {
+        self.outgoing.push(ContentFilter {
+            name: name.into(),
+            filter: Arc::new(filter),
+        });
+        self
+    }
+
+    /// Add a filter for assistant text received from Claude
+    pub fn with_incoming_filter<F>(mut self, name: impl Into<String>, filter: F) -> Self
+    where
+        F: Fn(&str) -> GuardrailAction + Send + Sync + 'static,
+    {
+        self.incoming.push(ContentFilter {
+            name: name.into(),
+            filter: Arc::new(filter),
+        });
+        self
+    }
+
+    /// Run the outgoing filters over a piece of text
+    pub fn check_outgoing(&self, text: &str) -> Result<String, AnthropicError> {
+        Self::run(&self.outgoing, text)
+    }
+
+    /// Run the incoming filters over a piece of text
+    pub fn check_incoming(&self, text: &str) -> Result<String, AnthropicError> {
+        Self::run(&self.incoming, text)
+    }
+
+    /// Filter every user text block in a request
+    pub fn apply_to_request(
+        &self,
+        request: &mut CreateMessageRequest,
+    ) -> Result<(), AnthropicError> {
+        if self.outgoing.is_empty() {
+            return Ok(());
+        }
+
+        for message in request.messages.iter_mut() {
+            if !matches!(message.role, Role::User) {
+                continue;
+            }
+            let texts: Vec<&String> = match &message.content {
+                Content::Text(text) => vec![text],
+                Content::Blocks(blocks) => blocks
+                    .iter()
+                    .filter_map(|block| match block {
+                        ContentBlock::Text { text, .. } => Some(text),
+                        _ => None,
+                    })
+                    .collect(),
+            };
+            let filtered = texts
+                .iter()
+                .map(|text| self.check_outgoing(text))
+                .collect::<Result<Vec<String>, _>>()?;
+            if texts.iter().zip(&filtered).all(|(a, b)| *a == b) {
+                continue;
+            }
+
+            // Only copy messages the filters changed; the rest stay shared with the history
+            let mut filtered = filtered.into_iter();
+            match &mut Arc::make_mut(message).content {
+                Content::Text(text) => *text = filtered.next().unwrap_or_default(),
+                Content::Blocks(blocks) => {
+                    for block in blocks.iter_mut() {
+                        if let ContentBlock::Text { text, .. } = block {
+                            *text = filtered.next().unwrap_or_default();
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Filter every text block in a response
+    pub fn apply_to_response(&self, response: &mut MessageResponse) -> Result<(), AnthropicError> {
+        for block in response.content.iter_mut() {
+            if let ResponseContentBlock::Text { text, .. } = block {
+                *text = self.check_incoming(text)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn run(filters: &[ContentFilter], text: &str) -> Result<String, AnthropicError> {
+        let mut current = text.to_string();
+        for filter in filters {
+            match (filter.filter)(&current) {
+                GuardrailAction::Allow => {}
+                GuardrailAction::Replace(replacement) => current = replacement,
+                GuardrailAction::Block(reason) => {
+                    return Err(AnthropicError::PolicyViolation {
+                        filter: filter.name.clone(),
+                        reason,
+                    })
+                }
+            }
+        }
+        Ok(current)
+    }
+}
+
+impl fmt::Debug for Guardrails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = |filters: &[ContentFilter]| -> Vec<String> {
+            filters.iter().map(|filter| filter.name.clone()).collect()
+        };
+        f.debug_struct("Guardrails")
+            .field("outgoing", &names(&self.outgoing))
+            .field("incoming", &names(&self.incoming))
+            .finish()
+    }
+}
@@ -0,0 +1,116 @@
+use crate::error::AnthropicError;
+use crate::types::messages::{sniff_image_type, ImageSource, MAX_IMAGE_BYTES};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+
+/// Longest edge Anthropic recommends; larger images are downscaled by the API anyway
+pub const DEFAULT_MAX_DIMENSION: u32 = 1568;
+
+/// Lowest JPEG quality tried when shrinking an image to fit `max_bytes`
+const MIN_JPEG_QUALITY: u8 = 40;
+
+/// Downscales and re-encodes images before upload
+///
+/// Images already within both limits are passed through untouched. Otherwise the image
+/// is resized to fit `max_dimension` and re-encoded as JPEG at `jpeg_quality`, lowering
+/// the quality and then the dimensions until it fits in `max_bytes`.
+#[derive(Debug, Clone)]
+pub struct ImagePreprocessor {
+    max_dimension: u32,
+    max_bytes: usize,
+    jpeg_quality: u8,
+}
+
+impl Default for ImagePreprocessor {
+    fn default() -> Self {
+        Self {
+            max_dimension: DEFAULT_MAX_DIMENSION,
+            max_bytes: MAX_IMAGE_BYTES,
+            jpeg_quality: 85,
+        }
+    }
+}
+
+impl ImagePreprocessor {
+    /// Create a preprocessor with the recommended limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the longest allowed edge in pixels
+    pub fn with_max_dimension(mut self, max_dimension: u32) -> Self {
+        self.max_dimension = max_dimension.max(1);
+        self
+    }
+
+    /// Set the largest allowed encoded size (capped at the API limit)
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes.min(MAX_IMAGE_BYTES);
+        self
+    }
+
+    /// Set the JPEG quality (1-100) used when re-encoding
+    pub fn with_jpeg_quality(mut self, quality: u8) -> Self {
+        self.jpeg_quality = quality.clamp(1, 100);
+        self
+    }
+
+    /// Shrink an image to fit the limits, returning the bytes and their media type
+    pub fn process(&self, bytes: &[u8]) -> Result<(Vec<u8>, &'static str), AnthropicError> {
+        let image = image::load_from_memory(bytes)
+            .map_err(|e| AnthropicError::InvalidImage(format!("failed to decode: {}", e)))?;
+        let (width, height) = image.dimensions();
+
+        if width.max(height) <= self.max_dimension && bytes.len() <= self.max_bytes {
+            if let Some(media_type) = sniff_image_type(bytes) {
+                return Ok((bytes.to_vec(), media_type));
+            }
+        }
+
+        let mut image = self.fit(image, self.max_dimension);
+        loop {
+            let mut quality = self.jpeg_quality;
+            loop {
+                let encoded = encode_jpeg(&image, quality)?;
+                if encoded.len() <= self.max_bytes {
+                    return Ok((encoded, "image/jpeg"));
+                }
+                if quality <= MIN_JPEG_QUALITY {
+                    break;
+                }
+                quality = quality.saturating_sub(15).max(MIN_JPEG_QUALITY);
+            }
+
+            let longest = image.width().max(image.height());
+            if longest <= 1 {
+                return Err(AnthropicError::InvalidImage(format!(
+                    "could not shrink image below {} bytes",
+                    self.max_bytes
+                )));
+            }
+            image = self.fit(image, longest * 3 / 4);
+        }
+    }
+
+    /// Shrink an image to fit the limits and wrap it as an image source
+    pub fn source(&self, bytes: &[u8]) -> Result<ImageSource, AnthropicError> {
+        let (bytes, media_type) = self.process(bytes)?;
+        Ok(ImageSource::bytes(media_type, bytes))
+    }
+
+    fn fit(&self, image: DynamicImage, max_dimension: u32) -> DynamicImage {
+        if image.width().max(image.height()) <= max_dimension {
+            return image;
+        }
+        image.resize(max_dimension, max_dimension, FilterType::Triangle)
+    }
+}
+
+fn encode_jpeg(image: &DynamicImage, quality: u8) -> Result<Vec<u8>, AnthropicError> {
+    let mut encoded = Vec::new();
+    JpegEncoder::new_with_quality(&mut encoded, quality)
+        .encode_image(&image.to_rgb8())
+        .map_err(|e| AnthropicError::InvalidImage(format!("failed to encode: {}", e)))?;
+    Ok(encoded)
+}
@@ -0,0 +1,111 @@
+use crate::error::AnthropicError;
+use crate::types::messages::CreateMessageRequest;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A request that was sent but whose outcome has not been recorded yet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: String,
+    /// Stable hash of the serialized request, for matching it against application state
+    pub request_hash: String,
+    pub conversation_id: Option<String>,
+    /// When the request was started, in milliseconds since the Unix epoch
+    pub started_at: u64,
+    /// The request's idempotency key, for resending it safely after a restart
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+impl JournalEntry {
+    /// Create an entry for a request about to be sent
+    pub fn new(
+        request: &CreateMessageRequest,
+        conversation_id: Option<String>,
+    ) -> Result<Self, AnthropicError> {
+        let request_hash = request.canonical_hash()?;
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        Ok(Self {
+            id: format!(
+                "{}-{}-{:08x}",
+                request_hash,
+                started_at,
+                rand::random::<u32>()
+            ),
+            request_hash,
+            conversation_id,
+            started_at,
+            idempotency_key: request.options.idempotency_key.clone(),
+        })
+    }
+}
+
+/// Durable record of in-flight requests, used to detect requests interrupted by a crash
+///
+/// Implementations must persist `begin` before returning, since the request is sent right
+/// after. Entries still present after a restart had unknown outcomes: the API may or may
+/// not have processed (and billed) them.
+pub trait RequestJournal: Send + Sync {
+    /// Record a request before it is sent
+    fn begin(&self, entry: &JournalEntry) -> Result<(), AnthropicError>;
+
+    /// Record that the outcome of a request is known
+    fn complete(&self, id: &str) -> Result<(), AnthropicError>;
+
+    /// Requests that were begun but never completed
+    fn in_flight(&self) -> Result<Vec<JournalEntry>, AnthropicError>;
+}
+
+/// Process-local journal, useful for tests; it does not survive restarts
+#[derive(Debug, Default)]
+pub struct InMemoryJournal {
+    entries: Mutex<HashMap<String, JournalEntry>>,
+}
+
+impl InMemoryJournal {
+    /// Create an empty journal
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(
+        &self,
+    ) -> Result<std::sync::MutexGuard<'_, HashMap<String, JournalEntry>>, AnthropicError> {
+        self.entries
+            .lock()
+            .map_err(|_| AnthropicError::Storage("In-memory journal lock poisoned".to_string()))
+    }
+}
+
+impl RequestJournal for InMemoryJournal {
+    fn begin(&self, entry: &JournalEntry) -> Result<(), AnthropicError> {
+        self.lock()?.insert(entry.id.clone(), entry.clone());
+        Ok(())
+    }
+
+    fn complete(&self, id: &str) -> Result<(), AnthropicError> {
+        self.lock()?.remove(id);
+        Ok(())
+    }
+
+    fn in_flight(&self) -> Result<Vec<JournalEntry>, AnthropicError> {
+        let mut entries: Vec<JournalEntry> = self.lock()?.values().cloned().collect();
+        entries.sort_by_key(|entry| entry.started_at);
+        Ok(entries)
+    }
+}
+
+/// Whether an error means the API definitely did not complete the request
+///
+/// Transport failures that may have reached the API (timeouts, dropped connections)
+/// leave the outcome unknown.
+pub(crate) fn outcome_known(error: &AnthropicError) -> bool {
+    !matches!(
+        error.inner(),
+        AnthropicError::HttpClient { kind, .. } if kind.may_have_reached_api()
+    )
+}
@@ -1,17 +1,123 @@
 // Hyperware Anthropic SDK
 // A Rust library for Hyperware processes to access the Anthropic API
 
+pub mod admin;
+pub mod agent;
+pub mod batches;
+pub mod betas;
+pub mod blocking;
 pub mod client;
+pub mod clock;
+pub mod context_pack;
 pub mod conversation;
+pub mod debug;
+pub mod delegation;
+#[cfg(feature = "embeddings")]
+pub mod embeddings;
 pub mod error;
+pub mod eval;
+pub mod few_shot;
+pub mod gateway;
+pub mod guardrails;
+#[cfg(feature = "image-processing")]
+pub mod imaging;
+pub mod injection;
+pub mod journal;
+pub mod macros;
+pub mod models;
+pub mod notify;
+pub mod partial_json;
+pub mod presets;
+pub mod rate_limit;
+pub mod refusal;
+pub mod registry;
+pub mod reports;
+pub mod router;
+pub mod sanitize;
+pub mod scheduler;
+pub mod secrets;
+pub mod stats;
+pub mod storage;
+pub mod streaming;
+pub mod structured;
+pub mod tokens;
+pub mod transcript;
 pub mod types;
+pub mod validation;
+pub mod versions;
+#[cfg(feature = "vfs")]
+pub mod vfs;
+pub mod warnings;
+pub mod waste;
+pub mod websocket;
+pub mod worker;
 
-pub use client::AnthropicClient;
+pub use admin::{
+    AdminClient, ApiKey, Invite, ListPage, ListParams, OrganizationUser, Workspace, WorkspaceMember,
+};
+pub use agent::{Agent, AgentRun, AgentStopReason, ApprovalPolicy, Plan, PlanRun, PlanStep};
+pub use batches::{
+    BatchRequestCounts, BatchRequestItem, BatchResult, BatchResultItem, MessageBatch,
+};
+pub use blocking::BlockingAnthropicClient;
+pub use client::{AnthropicClient, RawResponse, RetryEvent, PROTECTED_HEADERS};
+pub use clock::{Clock, MockClock, SystemClock};
+pub use context_pack::{ChunkOrder, ContextPack, DocumentChunk};
 pub use conversation::{
-    Conversation, ConversationUpdate, PendingToolUse, ToolResult, ToolResultData,
+    BoundConversation, Conversation, ConversationBuilder, ConversationSnapshot, ConversationUpdate,
+    DuplicatePolicy, PendingToolUse, ToolResult, ToolResultData, UpdateOutcome,
+};
+pub use debug::{ConversationDiff, MessageSummary};
+pub use delegation::{DelegationContext, SharedBudget};
+pub use error::{AnthropicError, ErrorPayload, TransportErrorKind};
+pub use eval::{Assertion, EvalCase, EvalCaseResult, EvalReport, EvalSuite};
+pub use few_shot::{FewShot, FewShotExample, FewShotMode};
+pub use gateway::{AnthropicGateway, GatewayClient, GatewayRequest, GatewayResponse};
+pub use guardrails::{GuardrailAction, Guardrails};
+#[cfg(feature = "image-processing")]
+pub use imaging::ImagePreprocessor;
+pub use injection::{InjectionAction, InjectionFinding, InjectionScanner};
+pub use journal::{InMemoryJournal, JournalEntry, RequestJournal};
+pub use macros::ToolParam;
+pub use models::{model_info, MaxTokensPolicy, ModelInfo};
+pub use notify::CompletionNotification;
+pub use partial_json::PartialJson;
+pub use presets::{GenerationPreset, PromptFragment, SamplingParams, SystemPromptBuilder};
+pub use rate_limit::{
+    RateLimitCoordinator, RateLimitRequest, RateLimitResponse, RateLimits, SharedRateLimiter,
+};
+pub use refusal::{RefusalAction, RefusalPolicy};
+pub use registry::{ToolError, ToolRegistry, MAX_STORED_RESULTS, READ_RESULT_TOOL};
+pub use reports::{
+    BucketWidth, CostGroupBy, CostReportParams, CostResult, Report, ReportBucket, UsageGroupBy,
+    UsageReportParams, UsageResult,
+};
+pub use router::{Route, Router};
+pub use sanitize::HistoryRepair;
+pub use scheduler::{
+    InMemoryScheduleStore, KvScheduleStore, ScheduleStore, ScheduledPrompt, Scheduler,
+    DEFAULT_RETRY_DELAY, SCHEDULER_DB,
+};
+pub use secrets::{read_secret, store_secret, CredentialStatus};
+pub use stats::{ClientStats, ConversationStats, SpendTotals};
+pub use storage::{
+    ConversationStore, ExpiringStore, ExpiryPolicy, ExpiryReport, InMemoryStore,
+    DEFAULT_EXPIRY_CHECK_INTERVAL,
+};
+pub use streaming::{
+    parse_sse, send_sse_error, send_sse_response, ContentDelta, MessageStream, PartialToolUse,
+    SseEvent, StreamEvent, ToolInputAccumulator,
 };
-pub use error::AnthropicError;
+pub use transcript::{Transcript, TranscriptExchange, TranscriptRecorder, TranscriptReplay};
 pub use types::*;
+pub use validation::ResponseValidator;
+pub use versions::ApiVersion;
+#[cfg(feature = "vfs")]
+pub use vfs::{VfsDebugDump, VfsStore};
+pub use warnings::{ClientWarning, DeprecationNotice, RATE_LIMIT_WARNING_FRACTION};
+pub use waste::{Waste, WasteReport};
+pub use websocket::{WsForwarder, WsFrame};
+pub use worker::{run_worker_job, ToolLoopState, WorkerJob, WorkerOutput, WorkerResult};
 
 // Re-export commonly used types
 pub use types::messages::{ContentBlock, CreateMessageRequest, Message, MessageResponse, Role};
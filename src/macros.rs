@@ -0,0 +1,171 @@
+use crate::conversation::ToolResultData;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// A type that can be used as a parameter in [`define_tool!`](crate::define_tool)
+pub trait ToolParam: DeserializeOwned {
+    /// Whether the parameter must be present in the tool input
+    const REQUIRED: bool = true;
+
+    /// JSON schema describing this parameter
+    fn json_schema() -> Value;
+}
+
+macro_rules! impl_tool_param {
+    ($schema_type:literal: $($ty:ty),*) => {
+        $(
+            impl ToolParam for $ty {
+                fn json_schema() -> Value {
+                    json!({ "type": $schema_type })
+                }
+            }
+        )*
+    };
+}
+
+impl_tool_param!("string": String);
+impl_tool_param!("boolean": bool);
+impl_tool_param!("integer": i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+impl_tool_param!("number": f32, f64);
+
+impl<T: ToolParam> ToolParam for Option<T> {
+    const REQUIRED: bool = false;
+
+    fn json_schema() -> Value {
+        T::json_schema()
+    }
+}
+
+impl<T: ToolParam> ToolParam for Vec<T> {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+impl<T: ToolParam> ToolParam for HashMap<String, T> {
+    fn json_schema() -> Value {
+        json!({ "type": "object", "additionalProperties": T::json_schema() })
+    }
+}
+
+impl ToolParam for Value {
+    fn json_schema() -> Value {
+        json!({})
+    }
+}
+
+#[doc(hidden)]
+pub fn __param_schema<T: ToolParam>(docs: &[&str]) -> Value {
+    let mut schema = T::json_schema();
+    let description = docs
+        .iter()
+        .map(|line| line.trim())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if let (Value::Object(ref mut fields), false) = (&mut schema, description.is_empty()) {
+        fields.insert("description".to_string(), Value::String(description));
+    }
+    schema
+}
+
+#[doc(hidden)]
+pub fn __properties(properties: Vec<(&str, Value)>) -> Value {
+    Value::Object(
+        properties
+            .into_iter()
+            .map(|(name, schema)| (name.to_string(), schema))
+            .collect::<Map<String, Value>>(),
+    )
+}
+
+#[doc(hidden)]
+pub fn __extract_param<T: ToolParam>(input: &Value, name: &str) -> Result<T, String> {
+    let value = input.get(name).cloned().unwrap_or(Value::Null);
+    serde_json::from_value(value).map_err(|e| format!("Invalid parameter `{}`: {}", name, e))
+}
+
+#[doc(hidden)]
+pub fn __into_output<T, E>(result: Result<T, E>) -> Result<ToolResultData, String>
+where
+    T: Into<ToolResultData>,
+    E: Display,
+{
+    result.map(Into::into).map_err(|e| e.to_string())
+}
+
+/// Define a tool and register it with a [`ToolRegistry`](crate::ToolRegistry) in one place
+///
+/// Parameters are typed; their doc comments become the parameter descriptions in the
+/// generated input schema, and `Option<T>` parameters are left out of `required`. The
+/// handler body is run as an async block and must evaluate to a `Result` whose `Ok` value
+/// converts into [`ToolResultData`] and whose error implements `Display`.
+///
+/// ```ignore
+/// define_tool!(registry, get_weather {
+///     description: "Get the current weather for a location",
+///     params: {
+///         /// City and state, e.g. San Francisco, CA
+///         location: String,
+///         /// Either "celsius" or "fahrenheit"
+///         unit: Option<String>,
+///     },
+///     handler: |params| {
+///         let report = fetch_weather(&params.location).await?;
+///         Ok::<_, String>(report)
+///     }
+/// });
+/// ```
+#[macro_export]
+macro_rules! define_tool {
+    (
+        $registry:expr,
+        $name:ident {
+            description: $description:expr,
+            params: {
+                $( $(#[doc = $doc:expr])* $field:ident : $ty:ty ),* $(,)?
+            },
+            handler: |$params:ident| $body:block $(,)?
+        }
+    ) => {{
+        #[allow(dead_code)]
+        struct Params {
+            $( $field: $ty, )*
+        }
+
+        let tool = $crate::types::tools::Tool::new(
+            stringify!($name),
+            $description,
+            $crate::macros::__properties(vec![
+                $( (stringify!($field), $crate::macros::__param_schema::<$ty>(&[$($doc),*])), )*
+            ]),
+            {
+                #[allow(unused_mut)]
+                let mut required: Vec<String> = Vec::new();
+                $(
+                    if <$ty as $crate::macros::ToolParam>::REQUIRED {
+                        required.push(stringify!($field).to_string());
+                    }
+                )*
+                required
+            },
+            None,
+        );
+
+        $registry.register(tool, move |input| {
+            #[allow(unused_variables)]
+            let parsed = (|| -> Result<Params, String> {
+                Ok(Params {
+                    $( $field: $crate::macros::__extract_param::<$ty>(&input, stringify!($field))?, )*
+                })
+            })();
+            async move {
+                #[allow(unused_variables)]
+                let $params = parsed?;
+                let output = async move $body.await;
+                $crate::macros::__into_output(output)
+            }
+        })
+    }};
+}
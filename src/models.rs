@@ -0,0 +1,158 @@
+use crate::types::messages::Usage;
+use serde::{Deserialize, Serialize};
+
+/// Limits of a Claude model family
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    /// Model id prefix, matching both aliases and dated snapshots
+    pub id: &'static str,
+    /// Input plus output tokens the model can attend to
+    pub context_window: u32,
+    /// Largest `max_tokens` the model accepts
+    pub max_output_tokens: u32,
+    /// Whether the model rejects requests that set both `temperature` and `top_p`
+    pub exclusive_sampling: bool,
+    /// Base input price in US cents per million tokens
+    pub input_cents_per_mtok: u32,
+    /// Output price in US cents per million tokens
+    pub output_cents_per_mtok: u32,
+}
+
+impl ModelInfo {
+    /// Estimated price of a response in US dollars at standard (non-batch) rates
+    ///
+    /// Cache writes are billed at 1.25x the input price and cache reads at 0.1x.
+    pub fn cost_usd(&self, usage: &Usage) -> f64 {
+        let input = self.input_cents_per_mtok as f64;
+        let cents_per_mtok = usage.input_tokens as f64 * input
+            + usage.cache_creation_input_tokens.unwrap_or(0) as f64 * input * 1.25
+            + usage.cache_read_input_tokens.unwrap_or(0) as f64 * input * 0.1
+            + usage.output_tokens as f64 * self.output_cents_per_mtok as f64;
+        cents_per_mtok / 100_000_000.0
+    }
+
+    /// What caching saved on a response in US dollars, net of the cache write premium
+    pub fn cache_savings_usd(&self, usage: &Usage) -> f64 {
+        let input = self.input_cents_per_mtok as f64;
+        let saved = usage.cache_read_input_tokens.unwrap_or(0) as f64 * input * 0.9
+            - usage.cache_creation_input_tokens.unwrap_or(0) as f64 * input * 0.25;
+        saved / 100_000_000.0
+    }
+}
+
+/// `max_tokens` used by [`CreateMessageRequest::with_default_max_tokens`](crate::CreateMessageRequest::with_default_max_tokens),
+/// capped at the model's ceiling
+pub const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+/// Known models, most specific prefix first
+const MODELS: &[ModelInfo] = &[
+    ModelInfo {
+        id: "claude-opus-4-5",
+        context_window: 200_000,
+        max_output_tokens: 64_000,
+        exclusive_sampling: true,
+        input_cents_per_mtok: 500,
+        output_cents_per_mtok: 2_500,
+    },
+    ModelInfo {
+        id: "claude-opus-4-1",
+        context_window: 200_000,
+        max_output_tokens: 32_000,
+        exclusive_sampling: true,
+        input_cents_per_mtok: 1_500,
+        output_cents_per_mtok: 7_500,
+    },
+    ModelInfo {
+        id: "claude-opus-4",
+        context_window: 200_000,
+        max_output_tokens: 32_000,
+        exclusive_sampling: false,
+        input_cents_per_mtok: 1_500,
+        output_cents_per_mtok: 7_500,
+    },
+    ModelInfo {
+        id: "claude-sonnet-4-5",
+        context_window: 200_000,
+        max_output_tokens: 64_000,
+        exclusive_sampling: true,
+        input_cents_per_mtok: 300,
+        output_cents_per_mtok: 1_500,
+    },
+    ModelInfo {
+        id: "claude-sonnet-4",
+        context_window: 200_000,
+        max_output_tokens: 64_000,
+        exclusive_sampling: false,
+        input_cents_per_mtok: 300,
+        output_cents_per_mtok: 1_500,
+    },
+    ModelInfo {
+        id: "claude-haiku-4-5",
+        context_window: 200_000,
+        max_output_tokens: 64_000,
+        exclusive_sampling: true,
+        input_cents_per_mtok: 100,
+        output_cents_per_mtok: 500,
+    },
+    ModelInfo {
+        id: "claude-3-7-sonnet",
+        context_window: 200_000,
+        max_output_tokens: 64_000,
+        exclusive_sampling: false,
+        input_cents_per_mtok: 300,
+        output_cents_per_mtok: 1_500,
+    },
+    ModelInfo {
+        id: "claude-3-5-sonnet",
+        context_window: 200_000,
+        max_output_tokens: 8_192,
+        exclusive_sampling: false,
+        input_cents_per_mtok: 300,
+        output_cents_per_mtok: 1_500,
+    },
+    ModelInfo {
+        id: "claude-3-5-haiku",
+        context_window: 200_000,
+        max_output_tokens: 8_192,
+        exclusive_sampling: false,
+        input_cents_per_mtok: 80,
+        output_cents_per_mtok: 400,
+    },
+    ModelInfo {
+        id: "claude-3-opus",
+        context_window: 200_000,
+        max_output_tokens: 4_096,
+        exclusive_sampling: false,
+        input_cents_per_mtok: 1_500,
+        output_cents_per_mtok: 7_500,
+    },
+    ModelInfo {
+        id: "claude-3-haiku",
+        context_window: 200_000,
+        max_output_tokens: 4_096,
+        exclusive_sampling: false,
+        input_cents_per_mtok: 25,
+        output_cents_per_mtok: 125,
+    },
+];
+
+/// Look up a model's limits by id, e.g. `claude-sonnet-4-20250514` or `claude-sonnet-4-5`
+pub fn model_info(model: &str) -> Option<&'static ModelInfo> {
+    MODELS.iter().find(|info| {
+        model
+            .strip_prefix(info.id)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('-') || rest.starts_with('@'))
+    })
+}
+
+/// What the client does when a request's `max_tokens` is over the model's ceiling
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MaxTokensPolicy {
+    /// Report [`ClientWarning::MaxTokensExceeded`](crate::ClientWarning::MaxTokensExceeded)
+    /// and send the request unchanged
+    Warn,
+    /// Lower `max_tokens` to the ceiling
+    Clamp,
+    /// Fail with [`AnthropicError::MaxTokensExceeded`](crate::AnthropicError::MaxTokensExceeded)
+    Reject,
+}
@@ -0,0 +1,53 @@
+use crate::client::AnthropicClient;
+use crate::error::AnthropicError;
+use crate::types::messages::{CreateMessageRequest, MessageResponse};
+use crate::warnings::ClientWarning;
+use hyperware_process_lib::{hyperapp, Address, Request};
+use serde::{Deserialize, Serialize};
+
+/// Message delivered to the target process by [`AnthropicClient::send_message_notify`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionNotification {
+    /// The ID passed to `send_message_notify`, to match the result to its request
+    pub correlation_id: String,
+    pub result: Result<MessageResponse, AnthropicError>,
+}
+
+impl AnthropicClient {
+    /// Send a message in the background and deliver the outcome to `target` as a
+    /// [`CompletionNotification`]
+    ///
+    /// Returns immediately; the call (with retries) runs on the hyperapp executor, so the
+    /// calling handler doesn't hold a long-lived future. `target` can be the calling process
+    /// itself.
+    pub fn send_message_notify(
+        &self,
+        request: CreateMessageRequest,
+        target: Address,
+        correlation_id: impl Into<String>,
+    ) {
+        let client = self.clone();
+        let correlation_id = correlation_id.into();
+
+        hyperapp::spawn(async move {
+            let notification = CompletionNotification {
+                result: client.send_message(request).await,
+                correlation_id,
+            };
+            let delivered = serde_json::to_vec(&notification)
+                .map_err(|e| e.to_string())
+                .and_then(|body| {
+                    Request::to(target)
+                        .body(body)
+                        .send()
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(error) = delivered {
+                client.warn(ClientWarning::NotificationFailed {
+                    correlation_id: notification.correlation_id,
+                    error,
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,394 @@
+use serde_json::{Map, Value};
+
+/// What a container expects next while scanning partial JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frame {
+    ObjectKey,
+    ObjectColon,
+    ObjectValue,
+    ObjectNext,
+    ArrayValue,
+    ArrayNext,
+}
+
+/// Where an open container sits in its parent
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Root,
+    Key(String),
+    Index(usize),
+}
+
+/// An object or array that hasn't closed yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Container {
+    frame: Frame,
+    at: Segment,
+    /// The key of the member being read, once it is complete
+    key: Option<String>,
+}
+
+/// Accumulates streamed JSON fragments, such as `input_json_delta`s, and parses what has
+/// arrived so far
+///
+/// Each fragment is scanned once and applied to the value read so far, so pushing costs
+/// only the fragment's length; [`value`](Self::value) returns the longest valid reading of
+/// the text, closing open strings and containers. An unterminated string value is kept and
+/// closed, while a dangling key, colon, comma, or incomplete literal is dropped.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PartialJson {
+    raw: String,
+    value: Value,
+    scanner: Scanner,
+}
+
+impl PartialJson {
+    /// Start with no text
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a fragment
+    pub fn push(&mut self, fragment: &str) {
+        let start = self.raw.len();
+        self.raw.push_str(fragment);
+        self.scanner.scan(&self.raw, start, &mut self.value);
+    }
+
+    /// The text received so far
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// Whether the text so far is a complete JSON value
+    pub fn is_complete(&self) -> bool {
+        self.scanner.is_complete()
+    }
+
+    /// Best-effort parse of the text so far, or `None` if no value has started
+    pub fn value(&self) -> Option<Value> {
+        self.scanner.present.then(|| self.value.clone())
+    }
+}
+
+/// Parse truncated JSON in one go; see [`PartialJson`]
+pub fn parse_partial(text: &str) -> Option<Value> {
+    let mut partial = PartialJson::new();
+    partial.push(text);
+    partial.value()
+}
+
+/// The scanning state behind [`PartialJson`], applying each fragment to a value owned
+/// by the caller
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Scanner {
+    stack: Vec<Container>,
+    /// Whether the value being read has been placed in its parent yet
+    placed: bool,
+    /// Whether the root value has started and reads as valid JSON
+    present: bool,
+    /// Whether the root value has been read in full
+    done: bool,
+    in_string: bool,
+    string_is_key: bool,
+    key: String,
+    escape: Option<String>,
+    high_surrogate: Option<u32>,
+    token_start: Option<usize>,
+    /// Set once the text can't be valid JSON; later fragments are kept but not scanned
+    invalid: bool,
+}
+
+impl Scanner {
+    /// Scan `text` from byte `from`, updating `root` with what was read
+    ///
+    /// `root` is only replaced once the root value starts.
+    pub(crate) fn scan(&mut self, text: &str, from: usize, root: &mut Value) {
+        if self.invalid {
+            return;
+        }
+        let mut decoded = String::new();
+        for (i, c) in text[from..].char_indices() {
+            let i = from + i;
+            if self.in_string {
+                if self.read_string_char(c, &mut decoded, root).is_err() {
+                    return self.fail(root);
+                }
+                continue;
+            }
+            if let Some(start) = self.token_start {
+                if !(matches!(c, ',' | '}' | ']' | ':') || c.is_ascii_whitespace()) {
+                    continue;
+                }
+                self.token_start = None;
+                match serde_json::from_str::<Value>(&text[start..i]) {
+                    Ok(value) => {
+                        self.place(root, value);
+                        self.value_done();
+                    }
+                    Err(_) => return self.fail(root),
+                }
+            }
+            if c.is_ascii_whitespace() {
+                continue;
+            }
+            if self.done {
+                return self.fail(root);
+            }
+            let frame = self.stack.last().map(|container| container.frame);
+            let expects_value =
+                matches!(frame, None | Some(Frame::ObjectValue | Frame::ArrayValue));
+            match (c, frame) {
+                ('"', Some(Frame::ObjectKey)) => {
+                    self.in_string = true;
+                    self.string_is_key = true;
+                }
+                ('"', _) if expects_value => {
+                    self.in_string = true;
+                    self.string_is_key = false;
+                    self.place(root, Value::String(String::new()));
+                }
+                ('{' | '[', _) if expects_value => {
+                    let (value, frame) = match c {
+                        '{' => (Value::Object(Map::new()), Frame::ObjectKey),
+                        _ => (Value::Array(Vec::new()), Frame::ArrayValue),
+                    };
+                    self.place(root, value);
+                    let at = self.segment(root);
+                    self.stack.push(Container {
+                        frame,
+                        at,
+                        key: None,
+                    });
+                    self.placed = false;
+                }
+                ('}', Some(Frame::ObjectKey | Frame::ObjectNext))
+                | (']', Some(Frame::ArrayValue | Frame::ArrayNext)) => {
+                    self.stack.pop();
+                    self.value_done();
+                }
+                (':', Some(Frame::ObjectColon)) => self.set_frame(Frame::ObjectValue),
+                (',', Some(Frame::ObjectNext)) => self.set_frame(Frame::ObjectKey),
+                (',', Some(Frame::ArrayNext)) => self.set_frame(Frame::ArrayValue),
+                _ if expects_value && !matches!(c, '}' | ']' | ':' | ',') => {
+                    self.token_start = Some(i);
+                }
+                _ => return self.fail(root),
+            }
+        }
+
+        if self.in_string && !self.string_is_key {
+            append(self.current_mut(root), &decoded);
+        }
+        // A literal still being written counts while it parses on its own
+        if let Some(start) = self.token_start {
+            match serde_json::from_str::<Value>(&text[start..]) {
+                Ok(value) => self.place(root, value),
+                Err(_) => self.unplace(root),
+            }
+        }
+    }
+
+    pub(crate) fn is_complete(&self) -> bool {
+        !self.invalid && !self.in_string && self.stack.is_empty() && self.present
+    }
+
+    /// Read one character inside a string, decoding escapes
+    fn read_string_char(
+        &mut self,
+        c: char,
+        decoded: &mut String,
+        root: &mut Value,
+    ) -> Result<(), ()> {
+        if let Some(ref mut escape) = self.escape {
+            escape.push(c);
+            let ch = match escape.as_str() {
+                "\"" => '"',
+                "\\" => '\\',
+                "/" => '/',
+                "b" => '\u{8}',
+                "f" => '\u{c}',
+                "n" => '\n',
+                "r" => '\r',
+                "t" => '\t',
+                unicode if unicode.starts_with('u') => {
+                    if unicode.len() > 1 && !c.is_ascii_hexdigit() {
+                        return Err(());
+                    }
+                    if unicode.len() < 5 {
+                        return Ok(());
+                    }
+                    let code = u32::from_str_radix(&unicode[1..], 16).map_err(|_| ())?;
+                    match (self.high_surrogate.take(), code) {
+                        (None, 0xD800..=0xDBFF) => {
+                            self.high_surrogate = Some(code);
+                            self.escape = None;
+                            return Ok(());
+                        }
+                        (Some(high), 0xDC00..=0xDFFF) => {
+                            char::from_u32(0x10000 + ((high - 0xD800) << 10) + (code - 0xDC00))
+                                .ok_or(())?
+                        }
+                        (None, _) => char::from_u32(code).ok_or(())?,
+                        (Some(_), _) => return Err(()),
+                    }
+                }
+                _ => return Err(()),
+            };
+            self.escape = None;
+            match self.string_is_key {
+                true => self.key.push(ch),
+                false => decoded.push(ch),
+            }
+            return Ok(());
+        }
+        // A high surrogate escape must be followed by a low one
+        if self.high_surrogate.is_some() && c != '\\' {
+            return Err(());
+        }
+        match c {
+            '\\' => self.escape = Some(String::new()),
+            '"' if self.string_is_key => {
+                self.in_string = false;
+                let key = std::mem::take(&mut self.key);
+                if let Some(container) = self.stack.last_mut() {
+                    container.key = Some(key);
+                    container.frame = Frame::ObjectColon;
+                }
+            }
+            '"' => {
+                self.in_string = false;
+                append(self.current_mut(root), decoded);
+                decoded.clear();
+                self.value_done();
+            }
+            c if self.string_is_key => self.key.push(c),
+            c => decoded.push(c),
+        }
+        Ok(())
+    }
+
+    /// Stop scanning, keeping the value as of the last complete element
+    fn fail(&mut self, root: &mut Value) {
+        // Only a string or literal still being read is placed
+        self.unplace(root);
+        self.invalid = true;
+    }
+
+    fn set_frame(&mut self, frame: Frame) {
+        if let Some(container) = self.stack.last_mut() {
+            container.frame = frame;
+            if frame == Frame::ObjectKey {
+                container.key = None;
+            }
+        }
+    }
+
+    /// Mark the value being read as complete
+    fn value_done(&mut self) {
+        self.placed = false;
+        match self.stack.last().map(|container| container.frame) {
+            Some(Frame::ObjectValue) => self.set_frame(Frame::ObjectNext),
+            Some(Frame::ArrayValue) => self.set_frame(Frame::ArrayNext),
+            None => self.done = true,
+            _ => {}
+        }
+    }
+
+    /// The innermost open container
+    fn parent_mut<'a>(&self, root: &'a mut Value) -> Option<&'a mut Value> {
+        let mut value = root;
+        for container in self.stack.iter().skip(1) {
+            value = match (&container.at, value) {
+                (Segment::Key(key), Value::Object(map)) => map.get_mut(key)?,
+                (Segment::Index(index), Value::Array(items)) => items.get_mut(*index)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Put the value being read in its parent, replacing what was placed before
+    fn place(&mut self, root: &mut Value, value: Value) {
+        let placed = self.placed;
+        self.placed = true;
+        let key = match self.stack.last() {
+            None => {
+                *root = value;
+                self.present = true;
+                return;
+            }
+            Some(container) => container.key.clone(),
+        };
+        match (self.parent_mut(root), key) {
+            (Some(Value::Object(map)), Some(key)) => {
+                map.insert(key, value);
+            }
+            (Some(Value::Array(items)), _) => match placed {
+                true => {
+                    if let Some(last) = items.last_mut() {
+                        *last = value;
+                    }
+                }
+                false => items.push(value),
+            },
+            _ => {}
+        }
+    }
+
+    /// Take the value being read back out of its parent
+    fn unplace(&mut self, root: &mut Value) {
+        if !std::mem::take(&mut self.placed) {
+            return;
+        }
+        let key = match self.stack.last() {
+            None => {
+                self.present = false;
+                return;
+            }
+            Some(container) => container.key.clone(),
+        };
+        match (self.parent_mut(root), key) {
+            (Some(Value::Object(map)), Some(key)) => {
+                map.remove(&key);
+            }
+            (Some(Value::Array(items)), _) => {
+                items.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// Where the value just placed sits in its parent
+    fn segment(&self, root: &mut Value) -> Segment {
+        match self.stack.last() {
+            None => Segment::Root,
+            Some(Container { key: Some(key), .. }) => Segment::Key(key.clone()),
+            Some(_) => match self.parent_mut(root) {
+                Some(Value::Array(items)) => Segment::Index(items.len().saturating_sub(1)),
+                _ => Segment::Root,
+            },
+        }
+    }
+
+    /// The value being read, if it has been placed
+    fn current_mut<'a>(&self, root: &'a mut Value) -> Option<&'a mut Value> {
+        if !self.placed {
+            return None;
+        }
+        match self.stack.last() {
+            None => Some(root),
+            Some(container) => match self.parent_mut(root)? {
+                Value::Object(map) => map.get_mut(container.key.as_ref()?),
+                Value::Array(items) => items.last_mut(),
+                _ => None,
+            },
+        }
+    }
+}
+
+fn append(value: Option<&mut Value>, text: &str) {
+    if let Some(Value::String(string)) = value {
+        string.push_str(text);
+    }
+}
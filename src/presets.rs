@@ -0,0 +1,222 @@
+use crate::conversation::Conversation;
+use crate::error::AnthropicError;
+use crate::types::messages::{CacheControl, CreateMessageRequest, SystemPrompt, SystemPromptBlock};
+
+/// Sampling parameters bundled by a [`GenerationPreset`]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SamplingParams {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
+}
+
+impl SamplingParams {
+    /// Fail if `temperature` or `top_p` is outside `0.0..=1.0`, or `top_k` is 0
+    pub fn validate(&self) -> Result<(), AnthropicError> {
+        if self.top_k == Some(0) {
+            return Err(AnthropicError::InvalidRequest(
+                "top_k must be at least 1".to_string(),
+            ));
+        }
+        for (name, value) in [("temperature", self.temperature), ("top_p", self.top_p)] {
+            if let Some(value) = value {
+                if !(0.0..=1.0).contains(&value) {
+                    return Err(AnthropicError::InvalidRequest(format!(
+                        "{} must be between 0 and 1, got {}",
+                        name, value
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Named sampling configurations so teams can standardize settings instead of magic floats
+///
+/// The built-in presets only combine `temperature` with `top_k`, since newer models reject
+/// requests that set both `temperature` and `top_p`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GenerationPreset {
+    /// Greedy, repeatable output for extraction, classification, and tool use
+    Deterministic,
+    /// A middle ground suitable for most assistant replies
+    Balanced,
+    /// Maximum variety for brainstorming and creative writing
+    Creative,
+    /// A team-defined bundle of sampling parameters
+    Custom(SamplingParams),
+}
+
+impl GenerationPreset {
+    /// The sampling parameters this preset applies
+    pub fn params(&self) -> SamplingParams {
+        match self {
+            GenerationPreset::Deterministic => SamplingParams {
+                temperature: Some(0.0),
+                top_p: None,
+                top_k: Some(1),
+            },
+            GenerationPreset::Balanced => SamplingParams {
+                temperature: Some(0.7),
+                top_p: None,
+                top_k: None,
+            },
+            GenerationPreset::Creative => SamplingParams {
+                temperature: Some(1.0),
+                top_p: None,
+                top_k: None,
+            },
+            GenerationPreset::Custom(params) => *params,
+        }
+    }
+}
+
+/// A reusable piece of a system prompt, combined with others by [`SystemPromptBuilder`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PromptFragment {
+    /// Keep answers short and skip preamble
+    Concise,
+    /// Reply with a single JSON value and nothing else
+    JsonOnly,
+    /// Back claims with the provided sources and say when they don't cover a question
+    CiteSources,
+    /// Answer as a named character with a role, e.g. "a support agent for Acme"
+    Persona { name: String, role: String },
+    /// Any other instructions
+    Custom(String),
+}
+
+impl PromptFragment {
+    /// The fragment's instructions
+    pub fn text(&self) -> String {
+        match self {
+            PromptFragment::Concise => {
+                "Answer concisely. Skip preamble and restating the question, and stop once the \
+                 question is answered."
+                    .to_string()
+            }
+            PromptFragment::JsonOnly => {
+                "Respond with a single valid JSON value and nothing else: no prose, no markdown \
+                 code fences."
+                    .to_string()
+            }
+            PromptFragment::CiteSources => {
+                "Base your answer on the provided sources and cite the source for each claim. If \
+                 the sources don't answer the question, say so instead of guessing."
+                    .to_string()
+            }
+            PromptFragment::Persona { name, role } => format!("You are {}, {}.", name, role),
+            PromptFragment::Custom(text) => text.clone(),
+        }
+    }
+}
+
+/// Composes [`PromptFragment`]s into a system prompt with a cache breakpoint after the
+/// parts that stay the same between requests
+///
+/// Fragments added with [`with`](Self::with) come first and end with the breakpoint;
+/// ones added with [`with_dynamic`](Self::with_dynamic), such as per-user context, follow
+/// it so changing them doesn't invalidate the cached prefix.
+#[derive(Debug, Clone)]
+pub struct SystemPromptBuilder {
+    stable: Vec<PromptFragment>,
+    dynamic: Vec<PromptFragment>,
+    cache_control: Option<CacheControl>,
+}
+
+impl Default for SystemPromptBuilder {
+    fn default() -> Self {
+        Self {
+            stable: Vec::new(),
+            dynamic: Vec::new(),
+            cache_control: Some(CacheControl::ephemeral()),
+        }
+    }
+}
+
+impl SystemPromptBuilder {
+    /// Start an empty prompt, caching its stable part with the default TTL
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a fragment that stays the same between requests
+    pub fn with(mut self, fragment: PromptFragment) -> Self {
+        self.stable.push(fragment);
+        self
+    }
+
+    /// Add a fragment that changes between requests, after the cache breakpoint
+    pub fn with_dynamic(mut self, fragment: PromptFragment) -> Self {
+        self.dynamic.push(fragment);
+        self
+    }
+
+    /// Set the cache breakpoint's TTL, e.g. [`CacheControl::ephemeral_1h`]
+    pub fn with_cache_control(mut self, cache_control: CacheControl) -> Self {
+        self.cache_control = Some(cache_control);
+        self
+    }
+
+    /// Don't place a cache breakpoint
+    pub fn without_cache(mut self) -> Self {
+        self.cache_control = None;
+        self
+    }
+
+    /// One block per fragment, with the breakpoint on the last stable one
+    pub fn build(&self) -> SystemPrompt {
+        let mut blocks: Vec<SystemPromptBlock> = self
+            .stable
+            .iter()
+            .map(|fragment| SystemPromptBlock::text(fragment.text()))
+            .collect();
+        if let (Some(last), Some(ref cache_control)) = (blocks.last_mut(), &self.cache_control) {
+            last.cache_control = Some(cache_control.clone());
+        }
+        blocks.extend(
+            self.dynamic
+                .iter()
+                .map(|fragment| SystemPromptBlock::text(fragment.text())),
+        );
+        SystemPrompt::Blocks(blocks)
+    }
+
+    /// The fragments joined as plain text, for [`Conversation::with_system`]
+    pub fn build_text(&self) -> String {
+        self.stable
+            .iter()
+            .chain(&self.dynamic)
+            .map(PromptFragment::text)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+impl CreateMessageRequest {
+    /// The request's temperature, top_p, and top_k
+    pub fn sampling(&self) -> SamplingParams {
+        SamplingParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: self.top_k,
+        }
+    }
+
+    /// Apply a generation preset, replacing any temperature, top_p, and top_k settings
+    pub fn with_preset(mut self, preset: GenerationPreset) -> Self {
+        let params = preset.params();
+        self.temperature = params.temperature;
+        self.top_p = params.top_p;
+        self.top_k = params.top_k;
+        self
+    }
+}
+
+impl Conversation {
+    /// Apply a generation preset, replacing any temperature, top_p, and top_k settings
+    pub fn with_preset(self, preset: GenerationPreset) -> Self {
+        self.with_sampling(preset.params())
+    }
+}
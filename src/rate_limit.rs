@@ -0,0 +1,213 @@
+use crate::blocking::await_response;
+use crate::clock::{now_ms, Clock, SystemClock};
+use crate::error::AnthropicError;
+use crate::tokens::estimate_text_tokens;
+use crate::types::messages::CreateMessageRequest;
+use hyperware_process_lib::{hyperapp, Address, Request};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+const WINDOW_MS: u64 = 60_000;
+const DEFAULT_COORDINATOR_TIMEOUT_SECONDS: u64 = 30;
+
+/// Per-minute limits enforced by a [`RateLimitCoordinator`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimits {
+    pub requests_per_minute: Option<u32>,
+    /// Limit on estimated input tokens
+    pub tokens_per_minute: Option<u32>,
+}
+
+impl RateLimits {
+    /// Check that every set limit lets at least one request through
+    pub fn validate(&self) -> Result<(), AnthropicError> {
+        if self.requests_per_minute == Some(0) {
+            return Err(AnthropicError::InvalidRequest(
+                "requests_per_minute must be at least 1".to_string(),
+            ));
+        }
+        if self.tokens_per_minute == Some(0) {
+            return Err(AnthropicError::InvalidRequest(
+                "tokens_per_minute must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// IPC request sent to a rate limit coordinator process
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RateLimitRequest {
+    /// Ask to send one request using about this many input tokens
+    Acquire { tokens: u32 },
+}
+
+/// Response from a rate limit coordinator process
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RateLimitResponse {
+    Granted,
+    /// The limit is reached; ask again after this many milliseconds
+    RetryAfter {
+        ms: u64,
+    },
+}
+
+/// Sliding-window limiter state shared by every process using the same API key
+///
+/// Run one in a coordinator process and answer [`RateLimitRequest`]s with
+/// [`RateLimitCoordinator::handle`], or share one inside a single process through
+/// [`SharedRateLimiter::local`].
+#[derive(Debug, Clone)]
+pub struct RateLimitCoordinator {
+    limits: RateLimits,
+    /// (timestamp in ms, tokens) for every grant in the current window
+    grants: VecDeque<(u64, u32)>,
+}
+
+impl RateLimitCoordinator {
+    /// Create a coordinator enforcing `limits`, which must not be zero
+    pub fn new(limits: RateLimits) -> Result<Self, AnthropicError> {
+        limits.validate()?;
+        Ok(Self {
+            limits,
+            grants: VecDeque::new(),
+        })
+    }
+
+    /// Answer an IPC request using the current time
+    pub fn handle(&mut self, request: RateLimitRequest) -> RateLimitResponse {
+        match request {
+            RateLimitRequest::Acquire { tokens } => match self.try_acquire(tokens, now_ms()) {
+                Ok(()) => RateLimitResponse::Granted,
+                Err(ms) => RateLimitResponse::RetryAfter { ms },
+            },
+        }
+    }
+
+    /// Grant a request at `now` (ms since the epoch), or return how long to wait
+    ///
+    /// A single request larger than the token limit is granted once the window is empty,
+    /// so oversized requests are slowed down rather than blocked forever.
+    pub fn try_acquire(&mut self, tokens: u32, now: u64) -> Result<(), u64> {
+        while self
+            .grants
+            .front()
+            .is_some_and(|(granted_at, _)| granted_at + WINDOW_MS <= now)
+        {
+            self.grants.pop_front();
+        }
+
+        let over_requests = self
+            .limits
+            .requests_per_minute
+            .is_some_and(|limit| self.grants.len() as u32 >= limit);
+        let window_tokens: u64 = self.grants.iter().map(|(_, t)| u64::from(*t)).sum();
+        let over_tokens = self.limits.tokens_per_minute.is_some_and(|limit| {
+            !self.grants.is_empty() && window_tokens + u64::from(tokens) > u64::from(limit)
+        });
+
+        if over_requests || over_tokens {
+            let oldest = self
+                .grants
+                .front()
+                .map_or(now, |(granted_at, _)| *granted_at);
+            return Err((oldest + WINDOW_MS).saturating_sub(now).max(1));
+        }
+
+        self.grants.push_back((now, tokens));
+        Ok(())
+    }
+}
+
+/// A rate limiter handle that can be shared by clients, locally or across processes
+#[derive(Debug, Clone)]
+pub enum SharedRateLimiter {
+    /// Limits shared by every client holding a clone, within one process
+    Local(Arc<Mutex<RateLimitCoordinator>>),
+    /// Limits enforced by a coordinator process at this address
+    Remote { coordinator: Address, timeout: u64 },
+}
+
+impl SharedRateLimiter {
+    /// Limit clients within this process; the limits must not be zero
+    pub fn local(limits: RateLimits) -> Result<Self, AnthropicError> {
+        let coordinator = RateLimitCoordinator::new(limits)?;
+        Ok(SharedRateLimiter::Local(Arc::new(Mutex::new(coordinator))))
+    }
+
+    /// Limit clients through a coordinator process, shared with every other process using it
+    pub fn remote(coordinator: Address) -> Self {
+        SharedRateLimiter::Remote {
+            coordinator,
+            timeout: DEFAULT_COORDINATOR_TIMEOUT_SECONDS,
+        }
+    }
+
+    /// Wait until a request of about `tokens` input tokens may be sent
+    pub async fn acquire(&self, tokens: u32) -> Result<(), AnthropicError> {
+        self.acquire_with(tokens, &SystemClock).await
+    }
+
+    /// Like [`acquire`](Self::acquire), reading and waiting on `clock`
+    ///
+    /// A local coordinator's window follows `clock` too; a remote one keeps its own time.
+    pub async fn acquire_with(&self, tokens: u32, clock: &dyn Clock) -> Result<(), AnthropicError> {
+        self.acquire_on(tokens, clock, false).await
+    }
+
+    /// Like [`acquire_with`](Self::acquire_with), asking a remote coordinator with a
+    /// blocking message if `blocking` is set
+    pub(crate) async fn acquire_on(
+        &self,
+        tokens: u32,
+        clock: &dyn Clock,
+        blocking: bool,
+    ) -> Result<(), AnthropicError> {
+        loop {
+            let response = match self {
+                SharedRateLimiter::Local(coordinator) => match coordinator
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .try_acquire(tokens, clock.now_ms())
+                {
+                    Ok(()) => RateLimitResponse::Granted,
+                    Err(ms) => RateLimitResponse::RetryAfter { ms },
+                },
+                SharedRateLimiter::Remote {
+                    coordinator,
+                    timeout,
+                } => {
+                    let body = serde_json::to_vec(&RateLimitRequest::Acquire { tokens })?;
+                    let request = Request::to(coordinator.clone()).body(body);
+                    if blocking {
+                        let message = await_response(request, *timeout)
+                            .map_err(|e| AnthropicError::Gateway(e.to_string()))?;
+                        serde_json::from_slice(message.body())?
+                    } else {
+                        hyperapp::send(request.expects_response(*timeout))
+                            .await
+                            .map_err(|e| AnthropicError::Gateway(format!("{:?}", e)))?
+                    }
+                }
+            };
+
+            match response {
+                RateLimitResponse::Granted => return Ok(()),
+                RateLimitResponse::RetryAfter { ms } => clock.sleep(ms).await,
+            }
+        }
+    }
+
+    /// Wait until `request` may be sent, charging its estimated input tokens
+    pub async fn acquire_for(&self, request: &CreateMessageRequest) -> Result<(), AnthropicError> {
+        self.acquire(request_tokens(request)?).await
+    }
+}
+
+/// Estimated input tokens charged against the limiter for a request
+pub(crate) fn request_tokens(request: &CreateMessageRequest) -> Result<u32, AnthropicError> {
+    Ok(estimate_text_tokens(&serde_json::to_string(
+        &request.messages,
+    )?))
+}
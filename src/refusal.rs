@@ -0,0 +1,69 @@
+use crate::conversation::ConversationUpdate;
+use std::fmt;
+use std::sync::Arc;
+
+type RefusalFn = dyn Fn(&ConversationUpdate) -> RefusalAction + Send + Sync;
+
+/// What [`Conversation::send`](crate::Conversation::send) does after Claude refuses a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefusalAction {
+    /// Return the refused update so the app can show it to the user
+    Surface,
+    /// Replace the last user message with this text and send again
+    ///
+    /// Only a plain-text message can be replaced; if the last user message holds tool
+    /// results or attachments, the send fails with
+    /// [`AnthropicError::InvalidRequest`](crate::AnthropicError::InvalidRequest).
+    Retry(String),
+    /// Fail with [`AnthropicError::Refused`](crate::AnthropicError::Refused)
+    Abort,
+}
+
+/// A hook deciding how a conversation reacts to a `refusal` stop reason
+///
+/// Refused output is never added to the history. The hook can log the refusal before
+/// returning its decision. At most one retry is made per `send`, so a hook that always
+/// retries can't loop.
+#[derive(Clone)]
+pub struct RefusalPolicy {
+    decide: Arc<RefusalFn>,
+}
+
+impl RefusalPolicy {
+    /// Create a policy from a closure that inspects the refused update
+    pub fn new<F>(decide: F) -> Self
+    where
+        F: Fn(&ConversationUpdate) -> RefusalAction + Send + Sync + 'static,
+    {
+        Self {
+            decide: Arc::new(decide),
+        }
+    }
+
+    /// Return refusals to the caller as-is
+    pub fn surface() -> Self {
+        Self::new(|_| RefusalAction::Surface)
+    }
+
+    /// Turn refusals into errors
+    pub fn abort() -> Self {
+        Self::new(|_| RefusalAction::Abort)
+    }
+
+    /// Retry once with the last user message replaced by `prompt`
+    pub fn retry_with(prompt: impl Into<String>) -> Self {
+        let prompt = prompt.into();
+        Self::new(move |_| RefusalAction::Retry(prompt.clone()))
+    }
+
+    /// Decide what to do about a refused update
+    pub fn decide(&self, update: &ConversationUpdate) -> RefusalAction {
+        (self.decide)(update)
+    }
+}
+
+impl fmt::Debug for RefusalPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RefusalPolicy").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,414 @@
+use crate::conversation::{PendingToolUse, ToolResult, ToolResultData};
+use crate::error::AnthropicError;
+use crate::tokens::{estimate_text_tokens, CHARS_PER_TOKEN};
+use crate::types::tools::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_NAMESPACE_SEPARATOR: &str = "__";
+
+/// Name of the tool added by [`ToolRegistry::with_result_chunking`] to read later parts
+pub const READ_RESULT_TOOL: &str = "read_tool_result";
+
+/// Most chunked results kept at once; storing another drops the oldest
+pub const MAX_STORED_RESULTS: usize = 32;
+
+/// Parts of chunked results by tool use ID, oldest first
+type StoredResults = Arc<Mutex<VecDeque<(String, Vec<String>)>>>;
+
+const DEFAULT_ERROR_CODE: &str = "tool_error";
+
+/// A tool failure reported to Claude in a consistent, parseable shape
+///
+/// Handlers can return this as their error type; any other error is converted with the
+/// code `tool_error`. The registry sends it as an error result whose text is
+/// `{"error": {"code": ..., "message": ..., "retryable": ..., "data": ...}}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolError {
+    pub message: String,
+    /// Machine-readable code, e.g. `not_found` or `rate_limited`
+    pub code: String,
+    /// Whether calling the tool again with the same input may succeed
+    pub retryable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl ToolError {
+    /// Create a non-retryable error with the default `tool_error` code
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            code: DEFAULT_ERROR_CODE.to_string(),
+            retryable: false,
+            data: None,
+        }
+    }
+
+    /// Set the machine-readable code
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = code.into();
+        self
+    }
+
+    /// Mark the error as transient, so Claude may retry the call
+    pub fn retryable(mut self) -> Self {
+        self.retryable = true;
+        self
+    }
+
+    /// Attach structured details, e.g. the invalid fields
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    /// Convert any handler error, keeping a `ToolError` as-is
+    fn from_handler<E: Display + 'static>(error: E) -> Self {
+        match (&error as &dyn Any).downcast_ref::<ToolError>() {
+            Some(tool_error) => tool_error.clone(),
+            None => Self::new(error.to_string()),
+        }
+    }
+
+    /// The error result sent back to Claude for a tool use
+    pub fn into_result(self, tool_use_id: impl Into<String>) -> ToolResult {
+        ToolResult::error(tool_use_id, json!({ "error": self }).to_string())
+    }
+}
+
+impl Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for ToolError {}
+
+/// Boxed future returned by registered tool handlers
+pub type ToolFuture = Pin<Box<dyn Future<Output = Result<ToolResultData, ToolError>>>>;
+
+/// Type-erased handler invoked with the tool input sent by Claude
+pub type ToolHandler = Box<dyn Fn(Value) -> ToolFuture>;
+
+struct RegisteredTool {
+    /// Tool definition as exposed to Claude (name includes any namespace prefix)
+    tool: Tool,
+    /// Namespace this tool was registered under, if any
+    namespace: Option<String>,
+    /// Name of the tool without its namespace prefix
+    local_name: String,
+    handler: ToolHandler,
+}
+
+/// Collection of tools and the handlers that execute them
+///
+/// Tools can be registered under a namespace, in which case their name is exposed to Claude
+/// as `{namespace}{separator}{name}` (e.g. `vfs__read_file`). The prefix is stripped again
+/// when dispatching, so handlers never need to know which namespace they were mounted under.
+pub struct ToolRegistry {
+    tools: Vec<RegisteredTool>,
+    separator: String,
+    /// Token threshold above which text results are split into parts
+    chunk_tokens: Option<u32>,
+    stored_results: StoredResults,
+}
+
+impl ToolRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self {
+            tools: Vec::new(),
+            separator: DEFAULT_NAMESPACE_SEPARATOR.to_string(),
+            chunk_tokens: None,
+            stored_results: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// Split text results over about `max_tokens` into parts Claude reads one at a time
+    ///
+    /// Only the first part is returned as the tool result, followed by a note on how to
+    /// fetch the rest with the [`READ_RESULT_TOOL`] tool, which this registers. Big outputs
+    /// like logs or CSVs then enter the context only as far as Claude needs them.
+    ///
+    /// A result is dropped once its last part is read, and at most [`MAX_STORED_RESULTS`]
+    /// are kept, the oldest going first.
+    pub fn with_result_chunking(mut self, max_tokens: u32) -> Self {
+        self.chunk_tokens = Some(max_tokens.max(1));
+        let stored_results = self.stored_results.clone();
+        let tool = Tool::new(
+            READ_RESULT_TOOL,
+            "Read another part of a tool result that was too large to return at once",
+            json!({
+                "tool_use_id": {
+                    "type": "string",
+                    "description": "ID of the tool use whose result was split"
+                },
+                "part": {
+                    "type": "integer",
+                    "description": "1-based number of the part to read"
+                }
+            }),
+            vec!["tool_use_id".to_string(), "part".to_string()],
+            None,
+        );
+        self.register(tool, move |input: Value| {
+            let stored_results = stored_results.clone();
+            async move { read_stored_part(&stored_results, &input) }
+        });
+        self
+    }
+
+    /// Drop the stored parts of chunked results, e.g. once a run has finished
+    pub fn clear_stored_results(&self) {
+        self.stored_results.lock().unwrap().clear();
+    }
+
+    /// Set the separator placed between a namespace and a tool name (default `__`)
+    pub fn with_namespace_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Register a tool and its handler, replacing any tool with the same name
+    pub fn register<F, Fut, T, E>(&mut self, tool: Tool, handler: F) -> &mut Self
+    where
+        F: Fn(Value) -> Fut + 'static,
+        Fut: Future<Output = Result<T, E>> + 'static,
+        T: Into<ToolResultData>,
+        E: Display + 'static,
+    {
+        self.insert(None, tool, Self::erase(handler));
+        self
+    }
+
+    /// Register a tool under a namespace, exposing it as `{namespace}{separator}{name}`
+    pub fn register_namespaced<F, Fut, T, E>(
+        &mut self,
+        namespace: impl Into<String>,
+        tool: Tool,
+        handler: F,
+    ) -> &mut Self
+    where
+        F: Fn(Value) -> Fut + 'static,
+        Fut: Future<Output = Result<T, E>> + 'static,
+        T: Into<ToolResultData>,
+        E: Display + 'static,
+    {
+        self.insert(Some(namespace.into()), tool, Self::erase(handler));
+        self
+    }
+
+    /// Move every tool from another registry into this one under the given namespace
+    ///
+    /// Tools that were already namespaced in `other` keep their local name only, so
+    /// nesting does not produce stacked prefixes.
+    pub fn merge_namespaced(
+        &mut self,
+        namespace: impl Into<String>,
+        other: ToolRegistry,
+    ) -> &mut Self {
+        let namespace = namespace.into();
+        for mut registered in other.tools {
+            registered.tool.name = registered.local_name;
+            self.insert(Some(namespace.clone()), registered.tool, registered.handler);
+        }
+        self
+    }
+
+    /// Move every tool from another registry into this one, keeping their exposed names
+    pub fn merge(&mut self, other: ToolRegistry) -> &mut Self {
+        for registered in other.tools {
+            self.remove_exposed(&registered.tool.name);
+            self.tools.push(registered);
+        }
+        self
+    }
+
+    /// Tool definitions to send to Claude, in registration order
+    pub fn tools(&self) -> Vec<Tool> {
+        self.tools.iter().map(|r| r.tool.clone()).collect()
+    }
+
+    /// Check whether a tool with the given exposed name is registered
+    pub fn contains(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    /// Number of registered tools
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    /// Check whether the registry has no tools
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Split an exposed tool name into its namespace and local name
+    pub fn resolve(&self, name: &str) -> Option<(Option<&str>, &str)> {
+        self.find(name)
+            .map(|r| (r.namespace.as_deref(), r.local_name.as_str()))
+    }
+
+    /// Execute a tool use requested by Claude
+    ///
+    /// Handler failures and unknown tools are reported back to Claude as [`ToolError`] results,
+    /// so this never fails; the `Result` lets it plug straight into
+    /// [`Conversation::complete_tool_loop`](crate::Conversation::complete_tool_loop).
+    pub async fn execute(&self, tool_use: PendingToolUse) -> Result<ToolResult, AnthropicError> {
+        let Some(registered) = self.find(&tool_use.name) else {
+            return Ok(ToolError::new(format!("Unknown tool: {}", tool_use.name))
+                .with_code("unknown_tool")
+                .into_result(tool_use.id));
+        };
+
+        match (registered.handler)(tool_use.input).await {
+            Ok(ToolResultData::Text(text)) if registered.tool.name != READ_RESULT_TOOL => {
+                let content = self.chunk_result(&tool_use.id, text);
+                Ok(ToolResult::success(tool_use.id, content))
+            }
+            Ok(content) => Ok(ToolResult {
+                tool_use_id: tool_use.id,
+                content,
+                is_error: false,
+            }),
+            Err(error) => Ok(error.into_result(tool_use.id)),
+        }
+    }
+
+    /// Return the first part of an oversized result and store the whole of it
+    fn chunk_result(&self, tool_use_id: &str, text: String) -> String {
+        let Some(max_tokens) = self.chunk_tokens else {
+            return text;
+        };
+        if estimate_text_tokens(&text) <= max_tokens {
+            return text;
+        }
+        let parts = split_parts(&text, max_tokens as usize * CHARS_PER_TOKEN);
+        let first = part_with_note(tool_use_id, &parts, 1);
+        let mut stored_results = self.stored_results.lock().unwrap();
+        stored_results.retain(|(id, _)| id != tool_use_id);
+        if stored_results.len() >= MAX_STORED_RESULTS {
+            stored_results.pop_front();
+        }
+        stored_results.push_back((tool_use_id.to_string(), parts));
+        first
+    }
+
+    fn erase<F, Fut, T, E>(handler: F) -> ToolHandler
+    where
+        F: Fn(Value) -> Fut + 'static,
+        Fut: Future<Output = Result<T, E>> + 'static,
+        T: Into<ToolResultData>,
+        E: Display + 'static,
+    {
+        Box::new(move |input| {
+            let fut = handler(input);
+            Box::pin(async move { fut.await.map(Into::into).map_err(ToolError::from_handler) })
+        })
+    }
+
+    fn insert(&mut self, namespace: Option<String>, mut tool: Tool, handler: ToolHandler) {
+        let local_name = tool.name.clone();
+        if let Some(ref namespace) = namespace {
+            tool.name = format!("{}{}{}", namespace, self.separator, local_name);
+        }
+
+        self.remove_exposed(&tool.name);
+        self.tools.push(RegisteredTool {
+            tool,
+            namespace,
+            local_name,
+            handler,
+        });
+    }
+
+    fn remove_exposed(&mut self, name: &str) {
+        self.tools.retain(|r| r.tool.name != name);
+    }
+
+    fn find(&self, name: &str) -> Option<&RegisteredTool> {
+        self.tools.iter().find(|r| r.tool.name == name)
+    }
+}
+
+/// Split text into pieces of at most `max_chars` characters, preferring line breaks
+fn split_parts(text: &str, max_chars: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let end = match rest.char_indices().nth(max_chars) {
+            Some((limit, _)) => match rest[..limit].rfind('\n') {
+                Some(newline) if newline >= limit / 2 => newline + 1,
+                _ => limit,
+            },
+            None => rest.len(),
+        };
+        parts.push(rest[..end].to_string());
+        rest = &rest[end..];
+    }
+    parts
+}
+
+/// A part of a chunked result followed by a note on reading the next one
+fn part_with_note(tool_use_id: &str, parts: &[String], part: usize) -> String {
+    let mut text = parts[part - 1].clone();
+    if part < parts.len() {
+        let total_tokens: u32 = parts.iter().map(|p| estimate_text_tokens(p)).sum();
+        text.push_str(&format!(
+            "\n\n[Part {} of {} of a result of about {} tokens. Call `{}` with \
+             {{\"tool_use_id\": \"{}\", \"part\": {}}} to read more.]",
+            part,
+            parts.len(),
+            total_tokens,
+            READ_RESULT_TOOL,
+            tool_use_id,
+            part + 1
+        ));
+    } else {
+        text.push_str(&format!(
+            "\n\n[Part {} of {}, the last.]",
+            part,
+            parts.len()
+        ));
+    }
+    text
+}
+
+fn read_stored_part(stored_results: &StoredResults, input: &Value) -> Result<String, ToolError> {
+    let tool_use_id = input["tool_use_id"].as_str().unwrap_or_default();
+    let mut stored_results = stored_results.lock().unwrap();
+    let index = stored_results
+        .iter()
+        .position(|(id, _)| id == tool_use_id)
+        .ok_or_else(|| {
+            ToolError::new(format!("No stored result for tool use `{}`", tool_use_id))
+                .with_code("not_found")
+        })?;
+    let parts = &stored_results[index].1;
+    let part = input["part"].as_u64().unwrap_or(0) as usize;
+    if part == 0 || part > parts.len() {
+        return Err(
+            ToolError::new(format!("Part must be between 1 and {}", parts.len()))
+                .with_code("invalid_part"),
+        );
+    }
+    let text = part_with_note(tool_use_id, parts, part);
+    if part == parts.len() {
+        stored_results.remove(index);
+    }
+    Ok(text)
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,338 @@
+use crate::admin::{with_query, AdminClient};
+use crate::error::AnthropicError;
+pub use crate::types::messages::CacheCreationUsage;
+use hyperware_process_lib::http::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Width of the time buckets in a usage report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BucketWidth {
+    #[serde(rename = "1m")]
+    Minute,
+    #[serde(rename = "1h")]
+    Hour,
+    #[serde(rename = "1d")]
+    Day,
+}
+
+impl BucketWidth {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BucketWidth::Minute => "1m",
+            BucketWidth::Hour => "1h",
+            BucketWidth::Day => "1d",
+        }
+    }
+}
+
+/// Dimensions a usage report can be split by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageGroupBy {
+    Model,
+    WorkspaceId,
+    ApiKeyId,
+    ServiceTier,
+    ContextWindow,
+}
+
+/// Dimensions a cost report can be split by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CostGroupBy {
+    WorkspaceId,
+    Description,
+}
+
+fn group_name<T: Serialize>(group: &T) -> String {
+    serde_json::to_value(group)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Filters and bucketing for [`AdminClient::usage_report`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReportParams {
+    /// RFC 3339 start time, snapped to the bucket width
+    pub starting_at: String,
+    pub ending_at: Option<String>,
+    pub bucket_width: Option<BucketWidth>,
+    pub group_by: Vec<UsageGroupBy>,
+    pub models: Vec<String>,
+    pub workspace_ids: Vec<String>,
+    pub api_key_ids: Vec<String>,
+    pub limit: Option<u32>,
+    /// Cursor from a previous report's `next_page`
+    pub page: Option<String>,
+}
+
+impl UsageReportParams {
+    /// Report usage from `starting_at` (RFC 3339) to now in the API's default buckets
+    pub fn new(starting_at: impl Into<String>) -> Self {
+        Self {
+            starting_at: starting_at.into(),
+            ending_at: None,
+            bucket_width: None,
+            group_by: Vec::new(),
+            models: Vec::new(),
+            workspace_ids: Vec::new(),
+            api_key_ids: Vec::new(),
+            limit: None,
+            page: None,
+        }
+    }
+
+    /// End the report at `ending_at` (RFC 3339) instead of now
+    pub fn with_ending_at(mut self, ending_at: impl Into<String>) -> Self {
+        self.ending_at = Some(ending_at.into());
+        self
+    }
+
+    /// Set the width of each time bucket
+    pub fn with_bucket_width(mut self, bucket_width: BucketWidth) -> Self {
+        self.bucket_width = Some(bucket_width);
+        self
+    }
+
+    /// Split each bucket by `group_by`; call again to add more dimensions
+    pub fn with_group_by(mut self, group_by: UsageGroupBy) -> Self {
+        self.group_by.push(group_by);
+        self
+    }
+
+    /// Only count requests to `model`; call again to include more models
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.models.push(model.into());
+        self
+    }
+
+    /// Only count requests from a workspace; call again to include more workspaces
+    pub fn with_workspace(mut self, workspace_id: impl Into<String>) -> Self {
+        self.workspace_ids.push(workspace_id.into());
+        self
+    }
+
+    /// Only count requests made with an API key; call again to include more keys
+    pub fn with_api_key(mut self, api_key_id: impl Into<String>) -> Self {
+        self.api_key_ids.push(api_key_id.into());
+        self
+    }
+
+    /// Set the number of buckets per page
+    pub fn with_limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Continue from a previous report's `next_page` cursor
+    pub fn with_page(mut self, page: impl Into<String>) -> Self {
+        self.page = Some(page.into());
+        self
+    }
+
+    /// Continue with the page after `report`
+    pub fn after<T>(mut self, report: &Report<T>) -> Self {
+        self.page = report.next_page.clone();
+        self
+    }
+
+    /// The query parameters sent for these filters
+    pub fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = vec![("starting_at", self.starting_at.clone())];
+        query.extend(self.ending_at.clone().map(|value| ("ending_at", value)));
+        query.extend(
+            self.bucket_width
+                .map(|width| ("bucket_width", width.as_str().to_string())),
+        );
+        query.extend(
+            self.group_by
+                .iter()
+                .map(|group| ("group_by[]", group_name(group))),
+        );
+        query.extend(self.models.iter().map(|model| ("models[]", model.clone())));
+        query.extend(
+            self.workspace_ids
+                .iter()
+                .map(|id| ("workspace_ids[]", id.clone())),
+        );
+        query.extend(
+            self.api_key_ids
+                .iter()
+                .map(|id| ("api_key_ids[]", id.clone())),
+        );
+        query.extend(self.limit.map(|limit| ("limit", limit.to_string())));
+        query.extend(self.page.clone().map(|page| ("page", page)));
+        query
+    }
+}
+
+/// Filters for [`AdminClient::cost_report`]; costs are always bucketed by day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CostReportParams {
+    /// RFC 3339 start time
+    pub starting_at: String,
+    pub ending_at: Option<String>,
+    pub group_by: Vec<CostGroupBy>,
+    pub limit: Option<u32>,
+    /// Cursor from a previous report's `next_page`
+    pub page: Option<String>,
+}
+
+impl CostReportParams {
+    /// Report costs from `starting_at` (RFC 3339) to now
+    pub fn new(starting_at: impl Into<String>) -> Self {
+        Self {
+            starting_at: starting_at.into(),
+            ending_at: None,
+            group_by: Vec::new(),
+            limit: None,
+            page: None,
+        }
+    }
+
+    /// End the report at `ending_at` (RFC 3339) instead of now
+    pub fn with_ending_at(mut self, ending_at: impl Into<String>) -> Self {
+        self.ending_at = Some(ending_at.into());
+        self
+    }
+
+    /// Split each bucket by `group_by`; call again to add more dimensions
+    pub fn with_group_by(mut self, group_by: CostGroupBy) -> Self {
+        self.group_by.push(group_by);
+        self
+    }
+
+    /// Set the number of buckets per page
+    pub fn with_limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Continue from a previous report's `next_page` cursor
+    pub fn with_page(mut self, page: impl Into<String>) -> Self {
+        self.page = Some(page.into());
+        self
+    }
+
+    /// Continue with the page after `report`
+    pub fn after<T>(mut self, report: &Report<T>) -> Self {
+        self.page = report.next_page.clone();
+        self
+    }
+
+    /// The query parameters sent for these filters
+    pub fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = vec![("starting_at", self.starting_at.clone())];
+        query.extend(self.ending_at.clone().map(|value| ("ending_at", value)));
+        query.extend(
+            self.group_by
+                .iter()
+                .map(|group| ("group_by[]", group_name(group))),
+        );
+        query.extend(self.limit.map(|limit| ("limit", limit.to_string())));
+        query.extend(self.page.clone().map(|page| ("page", page)));
+        query
+    }
+}
+
+/// A page of time buckets from a usage or cost report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report<T> {
+    pub data: Vec<ReportBucket<T>>,
+    pub has_more: bool,
+    /// Cursor for the next page, when `has_more` is set
+    pub next_page: Option<String>,
+}
+
+/// One time bucket of a report, with a result per group
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportBucket<T> {
+    pub starting_at: String,
+    pub ending_at: String,
+    pub results: Vec<T>,
+}
+
+/// Token counts for one group within a usage bucket
+///
+/// The grouping fields are set only for the dimensions the report was grouped by.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageResult {
+    pub uncached_input_tokens: u64,
+    #[serde(default)]
+    pub cache_creation: CacheCreationUsage,
+    pub cache_read_input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub server_tool_use: Option<Value>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub workspace_id: Option<String>,
+    #[serde(default)]
+    pub api_key_id: Option<String>,
+    #[serde(default)]
+    pub service_tier: Option<String>,
+    #[serde(default)]
+    pub context_window: Option<String>,
+}
+
+impl UsageResult {
+    /// Input tokens of every kind: uncached, cache writes, and cache reads
+    pub fn total_input_tokens(&self) -> u64 {
+        self.uncached_input_tokens
+            + self.cache_creation.ephemeral_1h_input_tokens
+            + self.cache_creation.ephemeral_5m_input_tokens
+            + self.cache_read_input_tokens
+    }
+}
+
+/// Spend for one group within a cost bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CostResult {
+    pub currency: String,
+    /// Decimal amount in the lowest currency unit (cents for USD)
+    pub amount: String,
+    #[serde(default)]
+    pub workspace_id: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub cost_type: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub token_type: Option<String>,
+    #[serde(default)]
+    pub service_tier: Option<String>,
+    #[serde(default)]
+    pub context_window: Option<String>,
+}
+
+impl CostResult {
+    /// The amount in the lowest currency unit, if it parses
+    pub fn amount_cents(&self) -> Option<f64> {
+        self.amount.parse().ok()
+    }
+}
+
+impl AdminClient {
+    /// Fetch token usage for Messages API requests, bucketed by time
+    pub async fn usage_report(
+        &self,
+        params: &UsageReportParams,
+    ) -> Result<Report<UsageResult>, AnthropicError> {
+        let path = with_query("/v1/organizations/usage_report/messages", &params.query());
+        self.call(Method::GET, path, Value::Null).await
+    }
+
+    /// Fetch daily spend, optionally grouped by workspace or line-item description
+    pub async fn cost_report(
+        &self,
+        params: &CostReportParams,
+    ) -> Result<Report<CostResult>, AnthropicError> {
+        let path = with_query("/v1/organizations/cost_report", &params.query());
+        self.call(Method::GET, path, Value::Null).await
+    }
+}
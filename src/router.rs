@@ -0,0 +1,172 @@
+use crate::client::AnthropicClient;
+use crate::conversation::{Conversation, ConversationUpdate};
+use crate::error::AnthropicError;
+use crate::types::messages::{Content, CreateMessageRequest, Message, ResponseContentBlock, Role};
+use crate::types::tools::{Tool, ToolChoice};
+use serde_json::json;
+
+const ROUTE_TOOL: &str = "select_route";
+const ROUTER_MAX_TOKENS: u32 = 256;
+const DEFAULT_ROUTER_INSTRUCTIONS: &str =
+    "Classify the user's message and select the route that should handle it.";
+
+/// A named destination the router can dispatch to
+#[derive(Debug, Clone)]
+pub struct Route<T> {
+    pub name: String,
+    pub description: String,
+    pub target: T,
+}
+
+/// Classifies input with a cheap constrained call, then dispatches to one of several targets
+///
+/// Targets are generic, so a router can hold conversations, agents, or plain handler values.
+/// The classification forces a tool call whose only parameter is an enum of route names.
+#[derive(Debug, Clone)]
+pub struct Router<T> {
+    model: String,
+    instructions: String,
+    routes: Vec<Route<T>>,
+    fallback: Option<String>,
+}
+
+impl<T> Router<T> {
+    /// Create a router that classifies with the given (typically small, fast) model
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            instructions: DEFAULT_ROUTER_INSTRUCTIONS.to_string(),
+            routes: Vec::new(),
+            fallback: None,
+        }
+    }
+
+    /// Replace the system prompt used for classification
+    pub fn with_instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.instructions = instructions.into();
+        self
+    }
+
+    /// Add a route; the description tells the classifier when to pick it
+    pub fn route(
+        mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        target: T,
+    ) -> Self {
+        self.routes.push(Route {
+            name: name.into(),
+            description: description.into(),
+            target,
+        });
+        self
+    }
+
+    /// Use this route when the classifier's answer doesn't match any route
+    pub fn with_fallback(mut self, name: impl Into<String>) -> Self {
+        self.fallback = Some(name.into());
+        self
+    }
+
+    /// All configured routes
+    pub fn routes(&self) -> &[Route<T>] {
+        &self.routes
+    }
+
+    /// Build the classification request for an input
+    pub fn classification_request(&self, input: impl Into<String>) -> CreateMessageRequest {
+        let route_list = self
+            .routes
+            .iter()
+            .map(|route| format!("- {}: {}", route.name, route.description))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let route_names: Vec<&str> = self.routes.iter().map(|r| r.name.as_str()).collect();
+
+        let tool = Tool::new(
+            ROUTE_TOOL,
+            format!("Select the route for the message. Routes:\n{}", route_list),
+            json!({
+                "route": {
+                    "type": "string",
+                    "enum": route_names,
+                    "description": "Name of the route that should handle the message"
+                }
+            }),
+            vec!["route".to_string()],
+            None,
+        );
+
+        CreateMessageRequest::new(
+            self.model.clone(),
+            vec![Message {
+                role: Role::User,
+                content: Content::Text(input.into()),
+            }],
+            ROUTER_MAX_TOKENS,
+        )
+        .with_system(self.instructions.clone())
+        .with_tools(vec![tool])
+        .with_tool_choice(ToolChoice::tool(ROUTE_TOOL))
+        .with_temperature(0.0)
+    }
+
+    /// Classify an input and return the index of the selected route
+    pub async fn classify(
+        &self,
+        client: &AnthropicClient,
+        input: impl Into<String>,
+    ) -> Result<usize, AnthropicError> {
+        let response = client
+            .send_message(self.classification_request(input))
+            .await?;
+
+        let selected = response.content.iter().find_map(|block| match block {
+            ResponseContentBlock::ToolUse { name, input, .. } if name == ROUTE_TOOL => {
+                input["route"].as_str().map(str::to_string)
+            }
+            _ => None,
+        });
+
+        selected
+            .and_then(|name| self.position(&name))
+            .or_else(|| {
+                self.fallback
+                    .as_deref()
+                    .and_then(|name| self.position(name))
+            })
+            .ok_or_else(|| {
+                AnthropicError::InvalidResponse("Router did not select a known route".to_string())
+            })
+    }
+
+    /// Classify an input and return the selected route
+    pub async fn select(
+        &mut self,
+        client: &AnthropicClient,
+        input: impl Into<String>,
+    ) -> Result<&mut Route<T>, AnthropicError> {
+        let index = self.classify(client, input).await?;
+        Ok(&mut self.routes[index])
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.routes.iter().position(|route| route.name == name)
+    }
+}
+
+impl Router<Conversation> {
+    /// Classify a user message, then send it to the selected conversation
+    ///
+    /// Returns the name of the route that handled the message along with its response.
+    pub async fn send(
+        &mut self,
+        client: &AnthropicClient,
+        input: impl Into<String>,
+    ) -> Result<(String, ConversationUpdate), AnthropicError> {
+        let input = input.into();
+        let route = self.select(client, input.clone()).await?;
+        let update = route.target.send_user_message(client, input).await?;
+        Ok((route.name.clone(), update))
+    }
+}
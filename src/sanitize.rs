@@ -0,0 +1,216 @@
+use crate::conversation::{Conversation, PendingToolUse};
+use crate::types::messages::{Content, ContentBlock, Message, Role, ToolResultContent};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Error text given to tool uses whose results are missing from imported history
+const MISSING_RESULT: &str = "The result of this tool use was lost.";
+
+/// A change made to imported history by [`Conversation::import_messages`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistoryRepair {
+    /// A message with no content was dropped
+    DroppedEmpty { role: Role },
+    /// A message was merged into the one before it, which had the same role
+    MergedConsecutive { role: Role },
+    /// A tool result that answered no tool use in the message before it was dropped
+    DroppedOrphanedResult { tool_use_id: String },
+    /// A tool use that was never answered got an error result
+    AnsweredOrphanedUse { tool_use_id: String },
+}
+
+impl Conversation {
+    /// Create a conversation from history stored outside this crate, repairing it as needed
+    ///
+    /// See [`import_messages`](Self::import_messages) for the repairs made.
+    pub fn from_messages(
+        model: impl Into<String>,
+        max_tokens: u32,
+        messages: Vec<Message>,
+    ) -> Self {
+        let mut conversation = Conversation::new(model, max_tokens);
+        conversation.import_messages(messages);
+        conversation
+    }
+
+    /// Replace the history with `messages`, returning the repairs made to keep it valid
+    ///
+    /// Empty messages are dropped, consecutive messages with the same role are merged,
+    /// tool results are paired with the tool uses just before them, and unanswered tool
+    /// uses get error results. Tool uses in a final assistant message are left pending,
+    /// so the conversation continues like one built here.
+    pub fn import_messages(&mut self, messages: Vec<Message>) -> Vec<HistoryRepair> {
+        let mut repairs = Vec::new();
+        let messages = merge_consecutive(drop_empty(messages, &mut repairs), &mut repairs);
+        let messages = pair_tool_results(messages, &mut repairs);
+        // Dropping orphaned results can leave empty messages, and their neighbors adjacent
+        let messages = merge_consecutive(drop_empty(messages, &mut repairs), &mut repairs);
+
+        let pending = match messages.last() {
+            Some(last) if last.role == Role::Assistant => tool_uses(last),
+            _ => Vec::new(),
+        };
+
+        self.clear();
+        *self.messages_mut() = messages.into_iter().map(Arc::new).collect();
+        *self.pending_tool_uses_mut() = pending;
+        repairs
+    }
+}
+
+/// Drop empty text blocks, then messages left with no content
+fn drop_empty(messages: Vec<Message>, repairs: &mut Vec<HistoryRepair>) -> Vec<Message> {
+    messages
+        .into_iter()
+        .filter_map(|mut message| {
+            let empty = match &mut message.content {
+                Content::Text(text) => text.trim().is_empty(),
+                Content::Blocks(blocks) => {
+                    blocks.retain(|block| {
+                        !matches!(block, ContentBlock::Text { text, .. } if text.trim().is_empty())
+                    });
+                    blocks.is_empty()
+                }
+            };
+            if empty {
+                repairs.push(HistoryRepair::DroppedEmpty { role: message.role });
+                None
+            } else {
+                Some(message)
+            }
+        })
+        .collect()
+}
+
+/// Merge each message into the previous one when both have the same role
+fn merge_consecutive(messages: Vec<Message>, repairs: &mut Vec<HistoryRepair>) -> Vec<Message> {
+    let mut merged: Vec<Message> = Vec::with_capacity(messages.len());
+    for message in messages {
+        match merged.last_mut() {
+            Some(previous) if previous.role == message.role => {
+                let mut blocks = into_blocks(std::mem::replace(
+                    &mut previous.content,
+                    Content::Blocks(Vec::new()),
+                ));
+                blocks.extend(into_blocks(message.content));
+                // Tool results have to lead a user message
+                blocks.sort_by_key(|block| !matches!(block, ContentBlock::ToolResult { .. }));
+                previous.content = Content::Blocks(blocks);
+                repairs.push(HistoryRepair::MergedConsecutive { role: message.role });
+            }
+            _ => merged.push(message),
+        }
+    }
+    merged
+}
+
+/// Keep only tool results that answer the message before them, answering the rest
+fn pair_tool_results(messages: Vec<Message>, repairs: &mut Vec<HistoryRepair>) -> Vec<Message> {
+    let mut paired: Vec<Message> = Vec::with_capacity(messages.len());
+    let mut messages = messages.into_iter().peekable();
+    while let Some(mut message) = messages.next() {
+        if message.role == Role::User {
+            let expected: HashSet<String> = match paired.last() {
+                Some(previous) => tool_uses(previous).into_iter().map(|t| t.id).collect(),
+                None => HashSet::new(),
+            };
+            if let Content::Blocks(blocks) = &mut message.content {
+                blocks.retain(|block| match block {
+                    ContentBlock::ToolResult { tool_use_id, .. }
+                        if !expected.contains(tool_use_id) =>
+                    {
+                        repairs.push(HistoryRepair::DroppedOrphanedResult {
+                            tool_use_id: tool_use_id.clone(),
+                        });
+                        false
+                    }
+                    _ => true,
+                });
+            }
+            paired.push(message);
+            continue;
+        }
+
+        let uses = tool_uses(&message);
+        paired.push(message);
+        if uses.is_empty() {
+            continue;
+        }
+        // Tool uses at the very end are pending rather than orphaned
+        let Some(next) = messages.peek_mut() else {
+            continue;
+        };
+        let answered: HashSet<String> = match &next.content {
+            Content::Blocks(blocks) => blocks
+                .iter()
+                .filter_map(|block| match block {
+                    ContentBlock::ToolResult { tool_use_id, .. } => Some(tool_use_id.clone()),
+                    _ => None,
+                })
+                .collect(),
+            Content::Text(_) => HashSet::new(),
+        };
+        let missing: Vec<ContentBlock> = uses
+            .into_iter()
+            .filter(|tool_use| !answered.contains(&tool_use.id))
+            .map(|tool_use| {
+                repairs.push(HistoryRepair::AnsweredOrphanedUse {
+                    tool_use_id: tool_use.id.clone(),
+                });
+                ContentBlock::ToolResult {
+                    tool_use_id: tool_use.id,
+                    content: Some(ToolResultContent::Text(MISSING_RESULT.to_string())),
+                    is_error: Some(true),
+                    cache_control: None,
+                }
+            })
+            .collect();
+        if missing.is_empty() {
+            continue;
+        }
+        if next.role == Role::User {
+            let mut blocks = missing;
+            blocks.extend(into_blocks(std::mem::replace(
+                &mut next.content,
+                Content::Blocks(Vec::new()),
+            )));
+            next.content = Content::Blocks(blocks);
+        } else {
+            paired.push(Message {
+                role: Role::User,
+                content: Content::Blocks(missing),
+            });
+        }
+    }
+    paired
+}
+
+fn tool_uses(message: &Message) -> Vec<PendingToolUse> {
+    match &message.content {
+        Content::Blocks(blocks) => blocks
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::ToolUse {
+                    id, name, input, ..
+                } => Some(PendingToolUse {
+                    id: id.clone(),
+                    name: name.clone(),
+                    input: input.clone(),
+                }),
+                _ => None,
+            })
+            .collect(),
+        Content::Text(_) => Vec::new(),
+    }
+}
+
+fn into_blocks(content: Content) -> Vec<ContentBlock> {
+    match content {
+        Content::Text(text) => vec![ContentBlock::Text {
+            text,
+            cache_control: None,
+            citations: None,
+        }],
+        Content::Blocks(blocks) => blocks,
+    }
+}
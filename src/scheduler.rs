@@ -0,0 +1,336 @@
+use crate::client::AnthropicClient;
+use crate::clock::{Clock, ClockHandle};
+use crate::conversation::{Conversation, ConversationUpdate};
+use crate::error::AnthropicError;
+use crate::storage::ConversationStore;
+use hyperware_process_lib::timer::set_timer;
+use hyperware_process_lib::{kv, our};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Prefix of the timer context set for scheduled prompts
+const TIMER_CONTEXT_PREFIX: &str = "anthropic-scheduler:";
+
+/// Name of the package-scoped key-value database used by [`KvScheduleStore`]
+pub const SCHEDULER_DB: &str = "scheduler";
+
+/// Delay before a prompt whose send failed with a retryable error is tried again
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// A prompt waiting to be sent to a conversation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduledPrompt {
+    pub id: String,
+    pub conversation_id: String,
+    pub prompt: String,
+    /// When to send, in milliseconds since the Unix epoch
+    pub fire_at: u64,
+}
+
+/// Durable copy of a scheduler's pending prompts
+///
+/// A scheduler with a store saves itself after every change: scheduling, cancelling, and
+/// each prompt it sends.
+pub trait ScheduleStore: Send + Sync {
+    /// Replace the saved copy with `scheduler`'s pending prompts
+    fn save(&self, scheduler: &Scheduler) -> Result<(), AnthropicError>;
+
+    /// The scheduler saved last, if any
+    fn load(&self) -> Result<Option<Scheduler>, AnthropicError>;
+}
+
+impl<S: ScheduleStore + ?Sized> ScheduleStore for Arc<S> {
+    fn save(&self, scheduler: &Scheduler) -> Result<(), AnthropicError> {
+        (**self).save(scheduler)
+    }
+
+    fn load(&self) -> Result<Option<Scheduler>, AnthropicError> {
+        (**self).load()
+    }
+}
+
+/// Process-local schedule store, useful for tests; it does not survive restarts
+#[derive(Debug, Default)]
+pub struct InMemoryScheduleStore {
+    saved: Mutex<Option<String>>,
+}
+
+impl InMemoryScheduleStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ScheduleStore for InMemoryScheduleStore {
+    fn save(&self, scheduler: &Scheduler) -> Result<(), AnthropicError> {
+        let json = serde_json::to_string(scheduler)?;
+        *self.saved.lock().map_err(|_| {
+            AnthropicError::Storage("In-memory schedule lock poisoned".to_string())
+        })? = Some(json);
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Option<Scheduler>, AnthropicError> {
+        let saved = self
+            .saved
+            .lock()
+            .map_err(|_| AnthropicError::Storage("In-memory schedule lock poisoned".to_string()))?;
+        match saved.as_deref() {
+            Some(json) => Ok(Some(serde_json::from_str(json)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Saves pending prompts under one key of this package's [`SCHEDULER_DB`] database
+#[derive(Debug, Clone)]
+pub struct KvScheduleStore {
+    key: String,
+}
+
+impl KvScheduleStore {
+    /// Save under `key`, so several schedulers can share the database
+    pub fn new(key: impl Into<String>) -> Self {
+        Self { key: key.into() }
+    }
+
+    fn open(&self) -> Result<kv::Kv<String, String>, AnthropicError> {
+        kv::open::<String, String>(our().package_id(), SCHEDULER_DB, None)
+            .map_err(|e| AnthropicError::Storage(format!("Failed to open schedule: {}", e)))
+    }
+}
+
+impl ScheduleStore for KvScheduleStore {
+    fn save(&self, scheduler: &Scheduler) -> Result<(), AnthropicError> {
+        let json = serde_json::to_string(scheduler)?;
+        self.open()?.set(&self.key, &json, None).map_err(|e| {
+            AnthropicError::Storage(format!("Failed to save schedule `{}`: {}", self.key, e))
+        })
+    }
+
+    /// A key that was never written reads as nothing saved, since the database reports
+    /// missing keys as errors
+    fn load(&self) -> Result<Option<Scheduler>, AnthropicError> {
+        match self.open()?.get(&self.key) {
+            Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+/// Sends prompts to stored conversations at a later time using Hyperware timers
+///
+/// Pending prompts survive restarts when the scheduler has a [`ScheduleStore`] (see
+/// [`Scheduler::load`]) or is kept in the process state; call [`Scheduler::resume`] on
+/// startup to re-arm their timers. When a timer fires, pass its context to
+/// [`Scheduler::is_scheduler_timer`] and then call [`Scheduler::run_due`].
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Scheduler {
+    jobs: Vec<ScheduledPrompt>,
+    next_id: u64,
+    #[serde(skip)]
+    clock: ClockHandle,
+    #[serde(skip)]
+    store: Option<Arc<dyn ScheduleStore>>,
+    #[serde(skip)]
+    retry_delay: Option<Duration>,
+}
+
+impl fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("jobs", &self.jobs)
+            .field("next_id", &self.next_id)
+            .field("retry_delay", &self.retry_delay)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Scheduler {
+    /// Create a scheduler with no pending prompts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restore the scheduler saved in `store`, or start an empty one, and save every change
+    /// back to it
+    pub fn load(store: impl ScheduleStore + 'static) -> Result<Self, AnthropicError> {
+        let scheduler = store.load()?.unwrap_or_default();
+        Ok(scheduler.with_store(store))
+    }
+
+    /// Save every change to `store`
+    pub fn with_store(mut self, store: impl ScheduleStore + 'static) -> Self {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    /// Wait this long before retrying a prompt whose send failed with a retryable error
+    /// (default [`DEFAULT_RETRY_DELAY`])
+    pub fn with_retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = Some(delay);
+        self
+    }
+
+    /// Read the time from `clock` instead of the system clock
+    ///
+    /// The clock isn't serialized; set it again after restoring a scheduler.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = ClockHandle::new(clock);
+        self
+    }
+
+    /// Schedule `prompt` to be sent to `conversation_id` at `fire_at` (ms since the epoch)
+    ///
+    /// The prompt is saved before its timer is armed; if saving fails it isn't scheduled.
+    pub fn schedule(
+        &mut self,
+        conversation_id: impl Into<String>,
+        prompt: impl Into<String>,
+        fire_at: u64,
+    ) -> Result<String, AnthropicError> {
+        let id = format!("job-{}", self.next_id);
+        self.next_id += 1;
+        let job = ScheduledPrompt {
+            id: id.clone(),
+            conversation_id: conversation_id.into(),
+            prompt: prompt.into(),
+            fire_at,
+        };
+        self.jobs.push(job.clone());
+        if let Err(error) = self.save() {
+            self.jobs.pop();
+            return Err(error);
+        }
+        arm_timer(&job, self.clock.now_ms());
+        Ok(id)
+    }
+
+    /// Cancel a pending prompt, returning whether it existed
+    ///
+    /// Its timer still fires but finds nothing due.
+    pub fn cancel(&mut self, id: &str) -> Result<bool, AnthropicError> {
+        let before = self.jobs.len();
+        self.jobs.retain(|job| job.id != id);
+        if self.jobs.len() == before {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Prompts that haven't been sent yet
+    pub fn pending(&self) -> &[ScheduledPrompt] {
+        &self.jobs
+    }
+
+    /// Re-arm timers for every pending prompt, e.g. after the process restarts
+    pub fn resume(&self) {
+        let now = self.clock.now_ms();
+        for job in &self.jobs {
+            arm_timer(job, now);
+        }
+    }
+
+    /// Whether a fired timer's context belongs to this scheduler
+    pub fn is_scheduler_timer(context: &[u8]) -> bool {
+        context.starts_with(TIMER_CONTEXT_PREFIX.as_bytes())
+    }
+
+    /// Remove and return every prompt due at `now` (ms since the epoch)
+    ///
+    /// The prompts are removed from the saved copy too, so a caller sending them itself
+    /// should schedule again any whose send fails.
+    pub fn take_due(&mut self, now: u64) -> Result<Vec<ScheduledPrompt>, AnthropicError> {
+        let (due, pending): (Vec<_>, Vec<_>) =
+            self.jobs.drain(..).partition(|job| job.fire_at <= now);
+        self.jobs = pending;
+        if let Err(error) = self.save() {
+            self.jobs.extend(due);
+            return Err(error);
+        }
+        Ok(due)
+    }
+
+    /// Send every due prompt to its stored conversation and save the updated history
+    ///
+    /// Each conversation starts from a fork of `template` (model, system prompt, tools) with
+    /// the stored history loaded into it. A prompt stays pending until its send finishes,
+    /// so one interrupted by a restart is sent again. Failed sends are reported in the
+    /// results; those that failed with a retryable error are also scheduled again after the
+    /// retry delay. An error is returned only if the schedule can't be saved, leaving the
+    /// prompts not yet sent pending.
+    pub async fn run_due(
+        &mut self,
+        client: &AnthropicClient,
+        store: &dyn ConversationStore,
+        template: &Conversation,
+    ) -> Result<Vec<(ScheduledPrompt, Result<ConversationUpdate, AnthropicError>)>, AnthropicError>
+    {
+        let now = self.clock.now_ms();
+        let due: Vec<ScheduledPrompt> = self
+            .jobs
+            .iter()
+            .filter(|job| job.fire_at <= now)
+            .cloned()
+            .collect();
+        let mut results = Vec::new();
+        for job in due {
+            let result = send_job(&job, client, store, template).await;
+            let position = self.jobs.iter().position(|pending| pending.id == job.id);
+            match (&result, position) {
+                (Err(error), Some(position)) if error.is_retryable() => {
+                    let retry_at = self.clock.now_ms() + self.retry_delay_ms();
+                    self.jobs[position].fire_at = retry_at;
+                    self.save()?;
+                    arm_timer(&self.jobs[position], self.clock.now_ms());
+                }
+                (_, Some(position)) => {
+                    self.jobs.remove(position);
+                    self.save()?;
+                }
+                // Cancelled while it was being sent
+                (_, None) => {}
+            }
+            results.push((job, result));
+        }
+        Ok(results)
+    }
+
+    fn retry_delay_ms(&self) -> u64 {
+        self.retry_delay.unwrap_or(DEFAULT_RETRY_DELAY).as_millis() as u64
+    }
+
+    fn save(&self) -> Result<(), AnthropicError> {
+        match self.store {
+            Some(ref store) => store.save(self),
+            None => Ok(()),
+        }
+    }
+}
+
+async fn send_job(
+    job: &ScheduledPrompt,
+    client: &AnthropicClient,
+    store: &dyn ConversationStore,
+    template: &Conversation,
+) -> Result<ConversationUpdate, AnthropicError> {
+    let mut conversation = template.fork();
+    if let Some(messages) = store.load(&job.conversation_id)? {
+        *conversation.messages_mut() = messages;
+    }
+    let update = conversation
+        .send_user_message(client, job.prompt.clone())
+        .await?;
+    store.save(&job.conversation_id, conversation.messages())?;
+    Ok(update)
+}
+
+fn arm_timer(job: &ScheduledPrompt, now: u64) {
+    let delay = job.fire_at.saturating_sub(now);
+    let context = format!("{}{}", TIMER_CONTEXT_PREFIX, job.id);
+    set_timer(delay, Some(context.into_bytes()));
+}
@@ -0,0 +1,111 @@
+use crate::client::AnthropicClient;
+use crate::error::AnthropicError;
+use hyperware_process_lib::{http::Method, kv, our};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Name of the package-scoped key-value database holding secrets
+pub const SECRETS_DB: &str = "secrets";
+
+/// Read a secret stored under `name` in this package's secrets database
+pub fn read_secret(name: &str) -> Result<String, AnthropicError> {
+    let db = kv::open::<String, String>(our().package_id(), SECRETS_DB, None)
+        .map_err(|e| AnthropicError::Storage(format!("Failed to open secrets: {}", e)))?;
+    db.get(&name.to_string())
+        .map_err(|e| AnthropicError::Storage(format!("Failed to read secret `{}`: {}", name, e)))
+}
+
+/// Store a secret under `name`, e.g. from a settings page, for later use with
+/// [`AnthropicClient::from_secret`]
+pub fn store_secret(name: &str, value: &str) -> Result<(), AnthropicError> {
+    let db = kv::open::<String, String>(our().package_id(), SECRETS_DB, None)
+        .map_err(|e| AnthropicError::Storage(format!("Failed to open secrets: {}", e)))?;
+    db.set(&name.to_string(), &value.to_string(), None)
+        .map_err(|e| AnthropicError::Storage(format!("Failed to store secret `{}`: {}", name, e)))
+}
+
+/// Whether an API key works, as reported by [`AnthropicClient::verify_credentials`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "detail", rename_all = "snake_case")]
+pub enum CredentialStatus {
+    /// The key authenticated, even if the call was then rate limited
+    Valid,
+    /// The key is missing, malformed, or revoked
+    InvalidKey,
+    /// The key authenticated but may not use the endpoint
+    InsufficientPermissions(String),
+    /// The API couldn't be reached, so the key is untested
+    NetworkFailure(String),
+}
+
+impl CredentialStatus {
+    /// Classify the error a credential check ended with, or `None` if it says nothing
+    /// about the key
+    pub fn from_error(error: &AnthropicError) -> Option<Self> {
+        match error.inner() {
+            AnthropicError::MissingApiKey | AnthropicError::Authentication => {
+                Some(CredentialStatus::InvalidKey)
+            }
+            AnthropicError::RateLimit => Some(CredentialStatus::Valid),
+            AnthropicError::HttpClient { message, .. } => {
+                Some(CredentialStatus::NetworkFailure(message.clone()))
+            }
+            AnthropicError::ApiError {
+                error_type,
+                message,
+            } => match error_type.as_str() {
+                "authentication_error" => Some(CredentialStatus::InvalidKey),
+                "permission_error" => {
+                    Some(CredentialStatus::InsufficientPermissions(message.clone()))
+                }
+                "rate_limit_error" | "overloaded_error" => Some(CredentialStatus::Valid),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether the key can be used
+    pub fn is_valid(&self) -> bool {
+        matches!(self, CredentialStatus::Valid)
+    }
+}
+
+impl AnthropicClient {
+    /// Create a client whose API key is read from this package's secrets database
+    ///
+    /// Only the process that stored the key and processes in the same package can read it,
+    /// so the key doesn't need to be passed around or embedded in configuration.
+    pub fn from_secret(name: &str) -> Result<Self, AnthropicError> {
+        let api_key = read_secret(name)?;
+        if api_key.is_empty() {
+            return Err(AnthropicError::MissingApiKey);
+        }
+        Ok(Self::new(api_key))
+    }
+
+    /// Check the API key by listing a single model, which costs no tokens
+    ///
+    /// Meant for settings screens that validate a key on entry, so the check isn't
+    /// retried. Errors that say nothing about the key, such as a server error, are
+    /// returned as-is.
+    pub async fn verify_credentials(&self) -> Result<CredentialStatus, AnthropicError> {
+        if self.api_key().is_empty() {
+            return Ok(CredentialStatus::InvalidKey);
+        }
+        let client = self.clone().with_max_retries(0);
+        match client
+            .send_raw(
+                "/v1/models?limit=1",
+                Method::GET,
+                Value::Null,
+                HashMap::new(),
+            )
+            .await
+        {
+            Ok(_) => Ok(CredentialStatus::Valid),
+            Err(error) => CredentialStatus::from_error(&error).ok_or(error),
+        }
+    }
+}
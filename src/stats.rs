@@ -0,0 +1,140 @@
+use crate::conversation::Conversation;
+use crate::models::model_info;
+use crate::types::messages::{Content, ContentBlock, Role, Usage};
+use crate::warnings::DeprecationNotice;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Running totals for a client, shared by all of its clones
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClientStats {
+    /// Requests sent to the API that returned a response
+    pub requests: u64,
+    /// Requests sent to the API that failed after retries
+    pub failures: u64,
+    /// The latest deprecation notice seen for each model
+    pub deprecations: Vec<DeprecationNotice>,
+    /// Usage by spend label: the label set with
+    /// [`AnthropicClient::with_spend_label`](crate::AnthropicClient::with_spend_label), or
+    /// a fingerprint of the API key
+    #[serde(default)]
+    pub spend: BTreeMap<String, SpendTotals>,
+}
+
+/// Tokens and estimated cost attributed to one key or tenant
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpendTotals {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+    /// Estimated spend in dollars; responses from models without known pricing add nothing
+    pub estimated_cost_usd: f64,
+}
+
+impl ClientStats {
+    /// Add a response's usage to the totals for `label`
+    pub(crate) fn record_spend(&mut self, label: &str, model: &str, usage: &Usage) {
+        let totals = self.spend.entry(label.to_string()).or_default();
+        totals.requests += 1;
+        totals.input_tokens += usage.input_tokens as u64;
+        totals.output_tokens += usage.output_tokens as u64;
+        totals.cache_creation_input_tokens += usage.cache_creation_input_tokens.unwrap_or(0) as u64;
+        totals.cache_read_input_tokens += usage.cache_read_input_tokens.unwrap_or(0) as u64;
+        if let Some(info) = model_info(model) {
+            totals.estimated_cost_usd += info.cost_usd(usage);
+        }
+    }
+
+    /// Remember a deprecation notice, replacing any earlier one for the same model
+    pub(crate) fn record_deprecation(&mut self, notice: DeprecationNotice) {
+        self.deprecations
+            .retain(|existing| existing.model != notice.model);
+        self.deprecations.push(notice);
+    }
+}
+
+/// Usage and activity totals for one conversation, from [`Conversation::stats`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConversationStats {
+    /// Responses processed, counting each continuation of a paused turn
+    pub turns: usize,
+    pub user_messages: usize,
+    pub assistant_messages: usize,
+    /// Tool calls in the history by tool name
+    pub tool_calls: BTreeMap<String, usize>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+    /// Net dollars saved by cache reads, or `None` for models without known pricing
+    pub cache_savings_usd: Option<f64>,
+    /// Estimated spend in dollars, or `None` for models without known pricing
+    pub estimated_cost_usd: Option<f64>,
+}
+
+impl ConversationStats {
+    /// Input, cache, and output tokens across all turns
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens
+            + self.output_tokens
+            + self.cache_creation_input_tokens
+            + self.cache_read_input_tokens
+    }
+
+    /// Average tokens per turn, or zero before the first response
+    pub fn average_tokens_per_turn(&self) -> f64 {
+        match self.turns {
+            0 => 0.0,
+            turns => self.total_tokens() as f64 / turns as f64,
+        }
+    }
+}
+
+impl Conversation {
+    /// Summarize the turns, messages, tool calls, tokens, and estimated cost so far
+    ///
+    /// Token counts come from the usage of each processed response, so history loaded
+    /// from storage or added by hand contributes messages and tool calls but no tokens.
+    pub fn stats(&self) -> ConversationStats {
+        let mut stats = ConversationStats {
+            turns: self.turn_usage().len(),
+            ..ConversationStats::default()
+        };
+
+        for message in self.messages() {
+            match message.role {
+                Role::User => stats.user_messages += 1,
+                Role::Assistant => stats.assistant_messages += 1,
+            }
+            if let Content::Blocks(ref blocks) = message.content {
+                for block in blocks {
+                    if let ContentBlock::ToolUse { ref name, .. } = block {
+                        *stats.tool_calls.entry(name.clone()).or_default() += 1;
+                    }
+                }
+            }
+        }
+
+        let pricing = model_info(self.model());
+        let mut cost = 0.0;
+        let mut savings = 0.0;
+        for usage in self.turn_usage() {
+            stats.input_tokens += usage.input_tokens as u64;
+            stats.output_tokens += usage.output_tokens as u64;
+            stats.cache_creation_input_tokens +=
+                usage.cache_creation_input_tokens.unwrap_or(0) as u64;
+            stats.cache_read_input_tokens += usage.cache_read_input_tokens.unwrap_or(0) as u64;
+            if let Some(info) = pricing {
+                cost += info.cost_usd(usage);
+                savings += info.cache_savings_usd(usage);
+            }
+        }
+        if pricing.is_some() {
+            stats.estimated_cost_usd = Some(cost);
+            stats.cache_savings_usd = Some(savings);
+        }
+        stats
+    }
+}
@@ -0,0 +1,444 @@
+use crate::clock::{Clock, ClockHandle};
+use crate::error::AnthropicError;
+use crate::types::messages::Message;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+type History = Vec<Arc<Message>>;
+type BeforeDeleteFn = dyn Fn(&str, &[Arc<Message>]) -> bool + Send + Sync;
+
+/// Persistence backend for conversation histories, keyed by conversation id
+pub trait ConversationStore {
+    /// Load a stored history, returning `None` if the id is unknown
+    fn load(&self, id: &str) -> Result<Option<Vec<Arc<Message>>>, AnthropicError>;
+
+    /// Replace the stored history for an id
+    fn save(&self, id: &str, messages: &[Arc<Message>]) -> Result<(), AnthropicError>;
+
+    /// Add messages to the end of a stored history, returning `false` without writing
+    /// anything if the id is unknown
+    ///
+    /// Conversations call this instead of [`save`](Self::save) when only new messages were
+    /// added, falling back to a full save when it returns `false` (e.g. because the history
+    /// was archived in the meantime). Stores with an append-only format override it to
+    /// write just the new records; the default rewrites the whole history.
+    fn append(&self, id: &str, messages: &[Arc<Message>]) -> Result<bool, AnthropicError> {
+        let Some(mut history) = self.load(id)? else {
+            return Ok(false);
+        };
+        history.extend_from_slice(messages);
+        self.save(id, &history)?;
+        Ok(true)
+    }
+
+    /// Remove a stored history; deleting an unknown id is not an error
+    fn delete(&self, id: &str) -> Result<(), AnthropicError>;
+
+    /// List the ids of all stored histories
+    fn list(&self) -> Result<Vec<String>, AnthropicError>;
+
+    /// When a history was last saved, in milliseconds since the Unix epoch
+    ///
+    /// Stores that don't track this return `None`, and their histories never expire.
+    fn last_saved(&self, _id: &str) -> Result<Option<u64>, AnthropicError> {
+        Ok(None)
+    }
+}
+
+/// Process-local store, useful for tests and for processes that don't need durability
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    conversations: Mutex<HashMap<String, (History, u64)>>,
+    clock: ClockHandle,
+}
+
+impl InMemoryStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time saves with `clock` instead of the system clock, e.g. to test expiry
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = ClockHandle::new(clock);
+        self
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn lock(
+        &self,
+    ) -> Result<std::sync::MutexGuard<'_, HashMap<String, (History, u64)>>, AnthropicError> {
+        self.conversations
+            .lock()
+            .map_err(|_| AnthropicError::Storage("In-memory store lock poisoned".to_string()))
+    }
+}
+
+impl ConversationStore for InMemoryStore {
+    fn load(&self, id: &str) -> Result<Option<Vec<Arc<Message>>>, AnthropicError> {
+        Ok(self.lock()?.get(id).map(|(messages, _)| messages.clone()))
+    }
+
+    fn save(&self, id: &str, messages: &[Arc<Message>]) -> Result<(), AnthropicError> {
+        self.lock()?
+            .insert(id.to_string(), (messages.to_vec(), self.clock.now_ms()));
+        Ok(())
+    }
+
+    fn append(&self, id: &str, messages: &[Arc<Message>]) -> Result<bool, AnthropicError> {
+        let mut conversations = self.lock()?;
+        let Some((history, saved_at)) = conversations.get_mut(id) else {
+            return Ok(false);
+        };
+        history.extend_from_slice(messages);
+        *saved_at = self.clock.now_ms();
+        Ok(true)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), AnthropicError> {
+        self.lock()?.remove(id);
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, AnthropicError> {
+        Ok(self.lock()?.keys().cloned().collect())
+    }
+
+    fn last_saved(&self, id: &str) -> Result<Option<u64>, AnthropicError> {
+        Ok(self.lock()?.get(id).map(|(_, saved_at)| *saved_at))
+    }
+}
+
+/// Lets a store be shared, e.g. between a conversation and the code that lists its ids
+impl<S: ConversationStore + ?Sized> ConversationStore for Arc<S> {
+    fn load(&self, id: &str) -> Result<Option<Vec<Arc<Message>>>, AnthropicError> {
+        (**self).load(id)
+    }
+
+    fn save(&self, id: &str, messages: &[Arc<Message>]) -> Result<(), AnthropicError> {
+        (**self).save(id, messages)
+    }
+
+    fn append(&self, id: &str, messages: &[Arc<Message>]) -> Result<bool, AnthropicError> {
+        (**self).append(id, messages)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), AnthropicError> {
+        (**self).delete(id)
+    }
+
+    fn list(&self) -> Result<Vec<String>, AnthropicError> {
+        (**self).list()
+    }
+
+    fn last_saved(&self, id: &str) -> Result<Option<u64>, AnthropicError> {
+        (**self).last_saved(id)
+    }
+}
+
+/// Archives conversations that have gone quiet, then deletes them once they've stayed
+/// archived long enough, so long-running processes don't keep unbounded history
+///
+/// Wrap the stores in an [`ExpiringStore`] to have it enforced as conversations are saved,
+/// or call [`ExpiryPolicy::enforce`] yourself, e.g. from a timer. Activity is judged by
+/// [`ConversationStore::last_saved`], so histories in stores that don't track it are kept.
+#[derive(Clone)]
+pub struct ExpiryPolicy {
+    archive_after: Duration,
+    delete_after: Option<Duration>,
+    before_delete: Option<Arc<BeforeDeleteFn>>,
+}
+
+/// The conversations moved by one [`ExpiryPolicy::enforce`] pass
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpiryReport {
+    pub archived: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+impl ExpiryPolicy {
+    /// Archive conversations not saved for `archive_after`; archived ones are kept forever
+    pub fn new(archive_after: Duration) -> Self {
+        Self {
+            archive_after,
+            delete_after: None,
+            before_delete: None,
+        }
+    }
+
+    /// Delete archived conversations once they've been archived for `delete_after`
+    pub fn with_delete_after(mut self, delete_after: Duration) -> Self {
+        self.delete_after = Some(delete_after);
+        self
+    }
+
+    /// Call `hook` with each conversation's id and history before deleting it
+    ///
+    /// Return `false` to keep the conversation archived, e.g. if exporting it failed; it
+    /// is offered again on the next pass.
+    pub fn with_before_delete<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str, &[Arc<Message>]) -> bool + Send + Sync + 'static,
+    {
+        self.before_delete = Some(Arc::new(hook));
+        self
+    }
+
+    /// Move inactive conversations from `active` to `archive`, and delete expired ones
+    /// from `archive`, as of `now` (ms since the epoch)
+    pub fn enforce(
+        &self,
+        active: &impl ConversationStore,
+        archive: &impl ConversationStore,
+        now: u64,
+    ) -> Result<ExpiryReport, AnthropicError> {
+        let mut report = ExpiryReport::default();
+        for id in active.list()? {
+            if !expired(active.last_saved(&id)?, self.archive_after, now) {
+                continue;
+            }
+            if let Some(messages) = active.load(&id)? {
+                archive.save(&id, &messages)?;
+            }
+            active.delete(&id)?;
+            report.archived.push(id);
+        }
+
+        let Some(delete_after) = self.delete_after else {
+            return Ok(report);
+        };
+        for id in archive.list()? {
+            if !expired(archive.last_saved(&id)?, delete_after, now) {
+                continue;
+            }
+            let messages = archive.load(&id)?.unwrap_or_default();
+            let keep = match self.before_delete {
+                Some(ref hook) => !hook(&id, &messages),
+                None => false,
+            };
+            if !keep {
+                archive.delete(&id)?;
+                report.deleted.push(id);
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl fmt::Debug for ExpiryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExpiryPolicy")
+            .field("archive_after", &self.archive_after)
+            .field("delete_after", &self.delete_after)
+            .finish_non_exhaustive()
+    }
+}
+
+/// How often an [`ExpiringStore`] enforces its policy unless told otherwise
+pub const DEFAULT_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// An active store and its archive, kept bounded by an [`ExpiryPolicy`]
+///
+/// Saves go to the active store, and the policy is enforced on a save once the check
+/// interval has passed since the last pass. Loading an archived conversation moves it back
+/// to the active store, so it picks up where it left off.
+#[derive(Debug)]
+pub struct ExpiringStore<A, R> {
+    active: A,
+    archive: R,
+    policy: ExpiryPolicy,
+    check_interval: Duration,
+    /// When the policy was last enforced, in ms since the epoch
+    last_check: Mutex<Option<u64>>,
+    clock: ClockHandle,
+}
+
+impl<A: ConversationStore, R: ConversationStore> ExpiringStore<A, R> {
+    /// Keep conversations in `active`, moving them to `archive` as `policy` says
+    pub fn new(active: A, archive: R, policy: ExpiryPolicy) -> Self {
+        Self {
+            active,
+            archive,
+            policy,
+            check_interval: DEFAULT_EXPIRY_CHECK_INTERVAL,
+            last_check: Mutex::new(None),
+            clock: ClockHandle::default(),
+        }
+    }
+
+    /// Enforce the policy at most once per `interval` (default one hour)
+    pub fn with_check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval;
+        self
+    }
+
+    /// Judge expiry by `clock` instead of the system clock
+    ///
+    /// The wrapped stores time their saves with their own clocks.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = ClockHandle::new(clock);
+        self
+    }
+
+    /// The store conversations are saved to
+    pub fn active(&self) -> &A {
+        &self.active
+    }
+
+    /// The store quiet conversations are moved to
+    pub fn archive(&self) -> &R {
+        &self.archive
+    }
+
+    /// Enforce the policy now, whenever it last ran
+    pub fn enforce(&self) -> Result<ExpiryReport, AnthropicError> {
+        let now = self.clock.now_ms();
+        *self.lock_last_check()? = Some(now);
+        self.policy.enforce(&self.active, &self.archive, now)
+    }
+
+    /// Enforce the policy if the check interval has passed since it last ran
+    fn enforce_if_due(&self) -> Result<(), AnthropicError> {
+        let now = self.clock.now_ms();
+        let due = match *self.lock_last_check()? {
+            Some(last) => now.saturating_sub(last) >= self.check_interval.as_millis() as u64,
+            None => true,
+        };
+        if due {
+            self.enforce()?;
+        }
+        Ok(())
+    }
+
+    fn lock_last_check(&self) -> Result<std::sync::MutexGuard<'_, Option<u64>>, AnthropicError> {
+        self.last_check
+            .lock()
+            .map_err(|_| AnthropicError::Storage("Expiry check lock poisoned".to_string()))
+    }
+}
+
+impl<A: ConversationStore, R: ConversationStore> ConversationStore for ExpiringStore<A, R> {
+    fn load(&self, id: &str) -> Result<Option<Vec<Arc<Message>>>, AnthropicError> {
+        if let Some(messages) = self.active.load(id)? {
+            return Ok(Some(messages));
+        }
+        let Some(messages) = self.archive.load(id)? else {
+            return Ok(None);
+        };
+        self.active.save(id, &messages)?;
+        self.archive.delete(id)?;
+        Ok(Some(messages))
+    }
+
+    fn save(&self, id: &str, messages: &[Arc<Message>]) -> Result<(), AnthropicError> {
+        self.active.save(id, messages)?;
+        // A full save may follow a failed append because the history was archived
+        self.archive.delete(id)?;
+        self.enforce_if_due()
+    }
+
+    fn append(&self, id: &str, messages: &[Arc<Message>]) -> Result<bool, AnthropicError> {
+        if !self.active.append(id, messages)? {
+            return Ok(false);
+        }
+        self.enforce_if_due()?;
+        Ok(true)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), AnthropicError> {
+        self.active.delete(id)?;
+        self.archive.delete(id)
+    }
+
+    fn list(&self) -> Result<Vec<String>, AnthropicError> {
+        let mut ids = self.active.list()?;
+        for id in self.archive.list()? {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    fn last_saved(&self, id: &str) -> Result<Option<u64>, AnthropicError> {
+        match self.active.last_saved(id)? {
+            Some(saved_at) => Ok(Some(saved_at)),
+            None => self.archive.last_saved(id),
+        }
+    }
+}
+
+fn expired(last_saved: Option<u64>, after: Duration, now: u64) -> bool {
+    last_saved.is_some_and(|saved_at| now.saturating_sub(saved_at) >= after.as_millis() as u64)
+}
+
+/// Write `messages` to the store, appending only what was added since `saved`
+///
+/// Messages are compared by pointer, so an edited message (which `Arc::make_mut` copies)
+/// or a shorter history falls back to a full save. `saved` is updated on success.
+pub(crate) fn save_delta(
+    store: &(impl ConversationStore + ?Sized),
+    id: &str,
+    saved: &mut History,
+    messages: &[Arc<Message>],
+) -> Result<(), AnthropicError> {
+    let extends_saved = !saved.is_empty()
+        && saved.len() <= messages.len()
+        && saved.iter().zip(messages).all(|(a, b)| Arc::ptr_eq(a, b));
+    if extends_saved && saved.len() == messages.len() {
+        return Ok(());
+    }
+    if !(extends_saved && store.append(id, &messages[saved.len()..])?) {
+        store.save(id, messages)?;
+    }
+    *saved = messages.to_vec();
+    Ok(())
+}
+
+/// A store and the id a conversation's history is saved under
+#[derive(Clone)]
+pub(crate) struct AttachedStore {
+    store: Arc<dyn ConversationStore + Send + Sync>,
+    id: String,
+    /// The history as last loaded or saved, shared by copies of the conversation
+    saved: Arc<Mutex<History>>,
+}
+
+impl AttachedStore {
+    pub(crate) fn new(
+        store: impl ConversationStore + Send + Sync + 'static,
+        id: impl Into<String>,
+    ) -> Self {
+        Self {
+            store: Arc::new(store),
+            id: id.into(),
+            saved: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub(crate) fn load(&self) -> Result<Option<Vec<Arc<Message>>>, AnthropicError> {
+        let history = self.store.load(&self.id)?;
+        *self.lock_saved()? = history.clone().unwrap_or_default();
+        Ok(history)
+    }
+
+    pub(crate) fn save(&self, messages: &[Arc<Message>]) -> Result<(), AnthropicError> {
+        save_delta(&*self.store, &self.id, &mut *self.lock_saved()?, messages)
+    }
+
+    fn lock_saved(&self) -> Result<std::sync::MutexGuard<'_, History>, AnthropicError> {
+        self.saved
+            .lock()
+            .map_err(|_| AnthropicError::Storage("Saved history lock poisoned".to_string()))
+    }
+}
+
+impl fmt::Debug for AttachedStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AttachedStore")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,635 @@
+use crate::error::{AnthropicError, ApiErrorDetail};
+use crate::partial_json::Scanner;
+use crate::types::messages::{
+    Content, ContentBlock, CreateMessageRequest, Message, MessageResponse, ResponseContentBlock,
+    Role, StopReason,
+};
+use hyperware_process_lib::http::{server::send_response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// A raw server-sent event, kept verbatim so it can be relayed with its boundaries intact
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+impl SseEvent {
+    /// Encode the event in `text/event-stream` format, including the terminating blank line
+    pub fn to_sse(&self) -> String {
+        let mut encoded = String::new();
+        if let Some(ref event) = self.event {
+            encoded.push_str(&format!("event: {}\n", event));
+        }
+        for line in self.data.split('\n') {
+            encoded.push_str(&format!("data: {}\n", line));
+        }
+        encoded.push('\n');
+        encoded
+    }
+
+    /// An `error` event in the shape of Anthropic's stream errors
+    ///
+    /// The message comes from [`AnthropicError::to_payload`], so it is safe to show users.
+    pub fn error(error: &AnthropicError) -> Self {
+        let error_type = match error.inner() {
+            AnthropicError::ApiError { error_type, .. } => error_type.as_str(),
+            _ => "api_error",
+        };
+        SseEvent {
+            event: Some("error".to_string()),
+            data: serde_json::json!({
+                "type": "error",
+                "error": {"type": error_type, "message": error.to_payload().message}
+            })
+            .to_string(),
+        }
+    }
+}
+
+/// Split a `text/event-stream` body into events
+pub fn parse_sse(body: &str) -> Vec<SseEvent> {
+    let mut events = Vec::new();
+    let mut event = None;
+    let mut data: Vec<&str> = Vec::new();
+
+    for line in body.lines() {
+        if line.is_empty() {
+            if event.is_some() || !data.is_empty() {
+                events.push(SseEvent {
+                    event: event.take(),
+                    data: data.join("\n"),
+                });
+                data.clear();
+            }
+            continue;
+        }
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => event = Some(value.to_string()),
+            "data" => data.push(value),
+            // Comments and unsupported fields (`id`, `retry`) are ignored
+            _ => {}
+        }
+    }
+    if event.is_some() || !data.is_empty() {
+        events.push(SseEvent {
+            event,
+            data: data.join("\n"),
+        });
+    }
+    events
+}
+
+/// A typed event from the streaming Messages API
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    MessageStart {
+        message: MessageResponse,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ResponseContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: ContentDelta,
+    },
+    ContentBlockStop {
+        index: usize,
+    },
+    MessageDelta {
+        delta: MessageDelta,
+        usage: DeltaUsage,
+    },
+    MessageStop,
+    Ping,
+    Error {
+        error: ApiErrorDetail,
+    },
+    /// An event type this SDK doesn't know about yet
+    #[serde(other)]
+    Unknown,
+}
+
+/// Incremental content for a content block
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentDelta {
+    TextDelta { text: String },
+    InputJsonDelta { partial_json: String },
+    ThinkingDelta { thinking: String },
+    SignatureDelta { signature: String },
+}
+
+/// Top-level message fields that change at the end of a stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageDelta {
+    pub stop_reason: Option<StopReason>,
+    pub stop_sequence: Option<String>,
+}
+
+/// Cumulative usage reported in a `message_delta` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeltaUsage {
+    pub output_tokens: u32,
+}
+
+/// The events of a streamed response
+#[derive(Debug, Clone)]
+pub struct MessageStream {
+    events: Vec<SseEvent>,
+}
+
+impl MessageStream {
+    /// Parse a complete `text/event-stream` body
+    pub fn from_sse(body: &str) -> Self {
+        Self {
+            events: parse_sse(body),
+        }
+    }
+
+    /// The events the API would stream for `response`, one delta per content block
+    ///
+    /// Used to serve replayed responses and guardrail-filtered text as a stream.
+    pub fn from_response(response: &MessageResponse) -> Self {
+        let mut start = response.clone();
+        start.content = Vec::new();
+        start.stop_reason = None;
+        start.stop_sequence = None;
+        let mut events = vec![StreamEvent::MessageStart { message: start }];
+        for (index, block) in response.content.iter().enumerate() {
+            let (content_block, deltas) = match block {
+                ResponseContentBlock::Text { text, citations } => (
+                    ResponseContentBlock::Text {
+                        text: String::new(),
+                        citations: citations.clone(),
+                    },
+                    vec![ContentDelta::TextDelta { text: text.clone() }],
+                ),
+                ResponseContentBlock::ToolUse { id, name, input } => (
+                    ResponseContentBlock::ToolUse {
+                        id: id.clone(),
+                        name: name.clone(),
+                        input: Value::Object(Default::default()),
+                    },
+                    vec![ContentDelta::InputJsonDelta {
+                        partial_json: input.to_string(),
+                    }],
+                ),
+                ResponseContentBlock::Thinking {
+                    thinking,
+                    signature,
+                } => (
+                    ResponseContentBlock::Thinking {
+                        thinking: String::new(),
+                        signature: String::new(),
+                    },
+                    vec![
+                        ContentDelta::ThinkingDelta {
+                            thinking: thinking.clone(),
+                        },
+                        ContentDelta::SignatureDelta {
+                            signature: signature.clone(),
+                        },
+                    ],
+                ),
+                // Server tool blocks and redacted thinking arrive whole
+                block => (block.clone(), Vec::new()),
+            };
+            events.push(StreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            });
+            events.extend(
+                deltas
+                    .into_iter()
+                    .map(|delta| StreamEvent::ContentBlockDelta { index, delta }),
+            );
+            events.push(StreamEvent::ContentBlockStop { index });
+        }
+        events.push(StreamEvent::MessageDelta {
+            delta: MessageDelta {
+                stop_reason: response.stop_reason.clone(),
+                stop_sequence: response.stop_sequence.clone(),
+            },
+            usage: DeltaUsage {
+                output_tokens: response.usage.output_tokens,
+            },
+        });
+        events.push(StreamEvent::MessageStop);
+        Self {
+            events: events.iter().filter_map(encode_event).collect(),
+        }
+    }
+
+    /// The raw events, in order
+    pub fn raw_events(&self) -> &[SseEvent] {
+        &self.events
+    }
+
+    /// The events parsed into [`StreamEvent`]s
+    pub fn events(&self) -> Result<Vec<StreamEvent>, AnthropicError> {
+        self.events
+            .iter()
+            .map(|event| {
+                serde_json::from_str(&event.data).map_err(|e| {
+                    AnthropicError::Deserialization(format!("Invalid stream event: {}", e))
+                })
+            })
+            .collect()
+    }
+
+    /// Accumulate the events into the response the non-streaming API would have returned
+    pub fn into_response(&self) -> Result<MessageResponse, AnthropicError> {
+        let mut message: Option<MessageResponse> = None;
+        let mut partial_json: HashMap<usize, String> = HashMap::new();
+
+        for event in self.events()? {
+            match event {
+                StreamEvent::MessageStart { message: start } => message = Some(start),
+                StreamEvent::ContentBlockStart {
+                    index,
+                    content_block,
+                } => {
+                    let content = &mut started(&mut message)?.content;
+                    if index >= content.len() {
+                        content.resize(index + 1, content_block.clone());
+                    }
+                    content[index] = content_block;
+                }
+                StreamEvent::ContentBlockDelta { index, delta } => match delta {
+                    ContentDelta::TextDelta { text } => {
+                        if let Some(ResponseContentBlock::Text { text: existing, .. }) =
+                            started(&mut message)?.content.get_mut(index)
+                        {
+                            existing.push_str(&text);
+                        }
+                    }
+                    ContentDelta::InputJsonDelta { partial_json: json } => {
+                        partial_json.entry(index).or_default().push_str(&json);
+                    }
+                    ContentDelta::ThinkingDelta { thinking } => {
+                        if let Some(ResponseContentBlock::Thinking {
+                            thinking: existing, ..
+                        }) = started(&mut message)?.content.get_mut(index)
+                        {
+                            existing.push_str(&thinking);
+                        }
+                    }
+                    ContentDelta::SignatureDelta { signature } => {
+                        if let Some(ResponseContentBlock::Thinking {
+                            signature: existing,
+                            ..
+                        }) = started(&mut message)?.content.get_mut(index)
+                        {
+                            existing.push_str(&signature);
+                        }
+                    }
+                },
+                StreamEvent::ContentBlockStop { index } => {
+                    let Some(json) = partial_json.remove(&index) else {
+                        continue;
+                    };
+                    if let Some(
+                        ResponseContentBlock::ToolUse { input, .. }
+                        | ResponseContentBlock::ServerToolUse { input, .. },
+                    ) = started(&mut message)?.content.get_mut(index)
+                    {
+                        if !json.is_empty() {
+                            *input = serde_json::from_str(&json).map_err(|e| {
+                                AnthropicError::Deserialization(format!(
+                                    "Invalid tool input in stream: {}",
+                                    e
+                                ))
+                            })?;
+                        }
+                    }
+                }
+                StreamEvent::MessageDelta { delta, usage } => {
+                    let message = started(&mut message)?;
+                    message.stop_reason = delta.stop_reason;
+                    message.stop_sequence = delta.stop_sequence;
+                    message.usage.output_tokens = usage.output_tokens;
+                }
+                StreamEvent::Error { error } => {
+                    return Err(AnthropicError::ApiError {
+                        error_type: error.error_type,
+                        message: error.message,
+                    })
+                }
+                StreamEvent::MessageStop | StreamEvent::Ping | StreamEvent::Unknown => {}
+            }
+        }
+
+        message.ok_or_else(|| {
+            AnthropicError::InvalidResponse("Stream ended without a message_start event".into())
+        })
+    }
+
+    /// Whether the stream broke off, or ended in a transient error, before `message_stop`
+    ///
+    /// Streams that end in any other error event aren't worth sending again.
+    pub fn is_interrupted(&self) -> bool {
+        let (events, error) = self.received();
+        match error {
+            Some(error) => AnthropicError::ApiError {
+                error_type: error.error_type,
+                message: error.message,
+            }
+            .is_retryable(),
+            None => !events
+                .iter()
+                .any(|event| matches!(event, StreamEvent::MessageStop)),
+        }
+    }
+
+    /// The text received before the stream broke off, as assistant prefill for resuming it
+    ///
+    /// Trailing whitespace is dropped since the API rejects prefill ending in it. `None`
+    /// if the stream got past its first message event with anything but text, which
+    /// can't be prefilled.
+    pub fn resume_prefill(&self) -> Option<String> {
+        let mut blocks: BTreeMap<usize, String> = BTreeMap::new();
+        for event in self.received().0 {
+            match event {
+                StreamEvent::ContentBlockStart {
+                    index,
+                    content_block: ResponseContentBlock::Text { text, .. },
+                } => {
+                    blocks.insert(index, text);
+                }
+                StreamEvent::ContentBlockStart { .. } => return None,
+                StreamEvent::ContentBlockDelta {
+                    index,
+                    delta: ContentDelta::TextDelta { text },
+                } => blocks.entry(index).or_default().push_str(&text),
+                _ => {}
+            }
+        }
+        Some(
+            blocks
+                .into_values()
+                .collect::<String>()
+                .trim_end()
+                .to_string(),
+        )
+    }
+
+    /// Continue this interrupted stream with the stream of the request resumed from its
+    /// [`resume_prefill`](Self::resume_prefill)
+    ///
+    /// The continuation's text is appended to the last text block received, so the
+    /// combined stream reads as one response. Usage reflects the resumed request.
+    pub fn resume_with(&self, continuation: &MessageStream) -> MessageStream {
+        let (mut events, _) = self.received();
+        // Keep only the content received; the continuation supplies the ending
+        events.retain(|event| {
+            matches!(
+                event,
+                StreamEvent::MessageStart { .. }
+                    | StreamEvent::ContentBlockStart { .. }
+                    | StreamEvent::ContentBlockDelta { .. }
+            )
+        });
+        let last = events
+            .iter()
+            .filter_map(|event| match event {
+                StreamEvent::ContentBlockStart { index, .. } => Some(*index),
+                _ => None,
+            })
+            .max();
+        // The prefill dropped trailing whitespace, which the continuation repeats
+        let mut trailing = true;
+        for event in events.iter_mut().rev() {
+            if !trailing {
+                break;
+            }
+            match event {
+                StreamEvent::ContentBlockDelta {
+                    delta: ContentDelta::TextDelta { text },
+                    ..
+                }
+                | StreamEvent::ContentBlockStart {
+                    content_block: ResponseContentBlock::Text { text, .. },
+                    ..
+                } => {
+                    text.truncate(text.trim_end().len());
+                    trailing = text.is_empty();
+                }
+                _ => {}
+            }
+        }
+
+        let offset = last.unwrap_or(0);
+        let has_start = events
+            .iter()
+            .any(|event| matches!(event, StreamEvent::MessageStart { .. }));
+        for event in continuation.received().0 {
+            let event = match event {
+                StreamEvent::MessageStart { .. } if has_start => continue,
+                StreamEvent::ContentBlockStart { index: 0, .. } if last.is_some() => continue,
+                StreamEvent::ContentBlockStart {
+                    index,
+                    content_block,
+                } => StreamEvent::ContentBlockStart {
+                    index: index + offset,
+                    content_block,
+                },
+                StreamEvent::ContentBlockDelta { index, delta } => StreamEvent::ContentBlockDelta {
+                    index: index + offset,
+                    delta,
+                },
+                StreamEvent::ContentBlockStop { index } => StreamEvent::ContentBlockStop {
+                    index: index + offset,
+                },
+                StreamEvent::Ping | StreamEvent::Unknown => continue,
+                event => event,
+            };
+            events.push(event);
+        }
+        if let Some(error) = continuation.received().1 {
+            events.push(StreamEvent::Error { error });
+        }
+
+        MessageStream {
+            events: events.iter().filter_map(encode_event).collect(),
+        }
+    }
+
+    /// The events that parsed before the stream broke off, and the error it ended with
+    fn received(&self) -> (Vec<StreamEvent>, Option<ApiErrorDetail>) {
+        let mut events = Vec::new();
+        for event in &self.events {
+            match serde_json::from_str(&event.data) {
+                Ok(StreamEvent::Error { error }) => return (events, Some(error)),
+                Ok(event) => events.push(event),
+                // A connection dropped mid-event leaves it truncated
+                Err(_) => break,
+            }
+        }
+        (events, None)
+    }
+
+    /// Encode the events back into a `text/event-stream` body, preserving event boundaries
+    pub fn to_sse_body(&self) -> Vec<u8> {
+        self.events
+            .iter()
+            .map(SseEvent::to_sse)
+            .collect::<String>()
+            .into_bytes()
+    }
+}
+
+fn encode_event(event: &StreamEvent) -> Option<SseEvent> {
+    let data = serde_json::to_value(event).ok()?;
+    Some(SseEvent {
+        event: data["type"].as_str().map(str::to_string),
+        data: data.to_string(),
+    })
+}
+
+/// `request` with `prefill` appended to its assistant prefill, or added as one
+pub(crate) fn with_prefill(request: &CreateMessageRequest, prefill: &str) -> CreateMessageRequest {
+    let mut request = request.clone();
+    let last = request
+        .messages
+        .last_mut()
+        .filter(|message| message.role == Role::Assistant);
+    match last {
+        Some(message) => match Arc::make_mut(message).content {
+            Content::Text(ref mut text) => text.push_str(prefill),
+            Content::Blocks(ref mut blocks) => match blocks.last_mut() {
+                Some(ContentBlock::Text { text, .. }) => text.push_str(prefill),
+                _ => blocks.push(ContentBlock::Text {
+                    text: prefill.to_string(),
+                    citations: None,
+                    cache_control: None,
+                }),
+            },
+        },
+        None => request.messages.push(Arc::new(Message {
+            role: Role::Assistant,
+            content: Content::Text(prefill.to_string()),
+        })),
+    }
+    request
+}
+
+fn started(message: &mut Option<MessageResponse>) -> Result<&mut MessageResponse, AnthropicError> {
+    message.as_mut().ok_or_else(|| {
+        AnthropicError::InvalidResponse("Stream event received before message_start".into())
+    })
+}
+
+fn sse_headers() -> HashMap<String, String> {
+    HashMap::from([
+        ("Content-Type".to_string(), "text/event-stream".to_string()),
+        ("Cache-Control".to_string(), "no-cache".to_string()),
+    ])
+}
+
+/// Answer the current HTTP server request with the stream's events as an SSE response
+pub fn send_sse_response(stream: &MessageStream) {
+    send_response(StatusCode::OK, Some(sse_headers()), stream.to_sse_body());
+}
+
+/// Answer the current HTTP server request with a single SSE `error` event
+///
+/// The event uses the same shape as Anthropic's stream errors, so clients relaying the
+/// API's events need no separate error handling. See [`SseEvent::error`].
+pub fn send_sse_error(error: &AnthropicError) {
+    let event = SseEvent::error(error);
+    send_response(
+        StatusCode::OK,
+        Some(sse_headers()),
+        event.to_sse().into_bytes(),
+    );
+}
+
+/// A tool use whose input is still streaming
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialToolUse {
+    pub id: String,
+    pub name: String,
+    /// Best-effort parse of the input so far; complete once the block has stopped
+    pub input: Value,
+    /// The input JSON received so far
+    pub raw: String,
+    /// Whether the block has stopped
+    pub complete: bool,
+}
+
+/// Follows tool use blocks through stream events, exposing partial arguments as they arrive
+///
+/// Feed it every event; UIs can render [`PartialToolUse::input`] to show what the agent
+/// is about to do before the call is finished. Each delta is parsed on its own and applied
+/// to the input in place, so following a long input costs time linear in its length.
+#[derive(Debug, Clone, Default)]
+pub struct ToolInputAccumulator {
+    tool_uses: BTreeMap<usize, PartialToolUse>,
+    scanners: BTreeMap<usize, Scanner>,
+}
+
+impl ToolInputAccumulator {
+    /// Start with no tool uses
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply an event, returning the tool use it updated, if any
+    pub fn push(&mut self, event: &StreamEvent) -> Option<&PartialToolUse> {
+        match event {
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block:
+                    ResponseContentBlock::ToolUse { id, name, input }
+                    | ResponseContentBlock::ServerToolUse { id, name, input },
+            } => {
+                let tool_use = PartialToolUse {
+                    id: id.clone(),
+                    name: name.clone(),
+                    input: input.clone(),
+                    raw: String::new(),
+                    complete: false,
+                };
+                self.tool_uses.insert(*index, tool_use);
+                self.scanners.insert(*index, Scanner::default());
+                self.tool_uses.get(index)
+            }
+            StreamEvent::ContentBlockDelta {
+                index,
+                delta: ContentDelta::InputJsonDelta { partial_json },
+            } => {
+                let tool_use = self.tool_uses.get_mut(index)?;
+                let start = tool_use.raw.len();
+                tool_use.raw.push_str(partial_json);
+                if let Some(scanner) = self.scanners.get_mut(index) {
+                    scanner.scan(&tool_use.raw, start, &mut tool_use.input);
+                }
+                Some(tool_use)
+            }
+            StreamEvent::ContentBlockStop { index } => {
+                let tool_use = self.tool_uses.get_mut(index)?;
+                tool_use.complete = true;
+                self.scanners.remove(index);
+                if let Ok(input) = serde_json::from_str(&tool_use.raw) {
+                    tool_use.input = input;
+                }
+                Some(tool_use)
+            }
+            _ => None,
+        }
+    }
+
+    /// Every tool use seen so far, in block order
+    pub fn tool_uses(&self) -> impl Iterator<Item = &PartialToolUse> {
+        self.tool_uses.values()
+    }
+}
@@ -0,0 +1,173 @@
+use crate::client::AnthropicClient;
+use crate::conversation::Conversation;
+use crate::error::AnthropicError;
+use crate::types::tools::{InputSchema, Tool, ToolChoice};
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+/// Name of the synthetic tool Claude is forced to call when producing structured output
+pub const RESPOND_JSON_TOOL: &str = "respond_json";
+/// Property that wraps non-object schemas, since tool inputs must be JSON objects
+const WRAPPED_VALUE_PROPERTY: &str = "value";
+const DEFAULT_STRUCTURED_MAX_TOKENS: u32 = 4096;
+const MAX_STRUCTURED_OUTPUT_RETRIES: u32 = 2;
+
+/// Build the `respond_json` tool whose input schema is derived from `T`
+///
+/// Subschemas are inlined because tool input schemas cannot carry shared definitions.
+/// Types that don't serialize as JSON objects are wrapped in a single `value` property.
+pub fn respond_json_tool<T: JsonSchema>() -> Tool {
+    let (properties, required) = match derive_schema::<T>() {
+        DerivedSchema::Object {
+            properties,
+            required,
+        } => (properties, required),
+        DerivedSchema::Wrapped(schema) => (
+            json!({ WRAPPED_VALUE_PROPERTY: schema }),
+            vec![WRAPPED_VALUE_PROPERTY.to_string()],
+        ),
+    };
+
+    Tool {
+        name: RESPOND_JSON_TOOL.to_string(),
+        description:
+            "Respond with JSON matching the input schema. Always answer by calling this tool."
+                .to_string(),
+        input_schema: InputSchema {
+            schema_type: "object".to_string(),
+            properties,
+            required: Some(required),
+        },
+        tool_type: None,
+        cache_control: None,
+        params: Default::default(),
+    }
+}
+
+/// Deserialize the `respond_json` tool input into `T`, unwrapping non-object schemas
+pub fn parse_structured<T: JsonSchema + DeserializeOwned>(input: &Value) -> Result<T, String> {
+    let value = match derive_schema::<T>() {
+        DerivedSchema::Object { .. } => input.clone(),
+        DerivedSchema::Wrapped(_) => input
+            .get(WRAPPED_VALUE_PROPERTY)
+            .cloned()
+            .ok_or_else(|| format!("Missing `{}` property", WRAPPED_VALUE_PROPERTY))?,
+    };
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+enum DerivedSchema {
+    Object {
+        properties: Value,
+        required: Vec<String>,
+    },
+    Wrapped(Value),
+}
+
+fn derive_schema<T: JsonSchema>() -> DerivedSchema {
+    let generator = SchemaSettings::draft07()
+        .with(|settings| settings.inline_subschemas = true)
+        .into_generator();
+    let mut schema = serde_json::to_value(generator.into_root_schema_for::<T>().schema)
+        .unwrap_or_else(|_| json!({}));
+
+    let is_object = schema.get("type") == Some(&json!("object"));
+    match schema.get_mut("properties").map(Value::take) {
+        Some(properties) if is_object => DerivedSchema::Object {
+            properties,
+            required: schema
+                .get("required")
+                .and_then(|r| serde_json::from_value(r.clone()).ok())
+                .unwrap_or_default(),
+        },
+        _ => DerivedSchema::Wrapped(schema),
+    }
+}
+
+impl Conversation {
+    /// Send the conversation and force Claude to answer with JSON deserializable into `T`
+    ///
+    /// Claude is required to call a synthetic `respond_json` tool whose schema is derived
+    /// from `T`. If the tool input fails to deserialize, the error is returned to Claude as
+    /// a tool result and the request is retried, up to a small fixed number of times.
+    /// Like [`send`](Self::send), it saves on success and rolls back on error.
+    pub async fn send_structured<T: JsonSchema + DeserializeOwned>(
+        &mut self,
+        client: &AnthropicClient,
+    ) -> Result<T, AnthropicError> {
+        self.take_duplicate_rejection()?;
+        let before = self.snapshot();
+        let result = self.structured_turn::<T>(client).await;
+        self.commit(before, result)
+    }
+
+    async fn structured_turn<T: JsonSchema + DeserializeOwned>(
+        &mut self,
+        client: &AnthropicClient,
+    ) -> Result<T, AnthropicError> {
+        let schema_tool = respond_json_tool::<T>();
+        let mut last_error = String::new();
+
+        for _ in 0..=MAX_STRUCTURED_OUTPUT_RETRIES {
+            let mut request = self.build_request_for(client);
+            request
+                .tools
+                .get_or_insert_with(Vec::new)
+                .push(schema_tool.clone());
+            request.tool_choice = Some(ToolChoice::tool(RESPOND_JSON_TOOL));
+
+            let response = client.send_message(request).await?;
+            let update = self.process_response(&response);
+
+            let Some(tool_use) = update
+                .tool_uses
+                .iter()
+                .find(|t| t.name == RESPOND_JSON_TOOL)
+            else {
+                return Err(AnthropicError::InvalidResponse(format!(
+                    "Expected a `{}` tool call in the response",
+                    RESPOND_JSON_TOOL
+                )));
+            };
+
+            match parse_structured::<T>(&tool_use.input) {
+                Ok(value) => {
+                    // Close the tool use so the history stays valid for later turns
+                    self.add_tool_result(tool_use.id.clone(), "Response accepted.", false)?;
+                    return Ok(value);
+                }
+                Err(error) => {
+                    self.add_tool_result(
+                        tool_use.id.clone(),
+                        format!(
+                            "The input did not match the schema: {}. Call `{}` again with corrected input.",
+                            error, RESPOND_JSON_TOOL
+                        ),
+                        true,
+                    )?;
+                    last_error = error;
+                }
+            }
+        }
+
+        Err(AnthropicError::Deserialization(format!(
+            "Structured output failed validation: {}",
+            last_error
+        )))
+    }
+}
+
+impl AnthropicClient {
+    /// Ask Claude a single question and deserialize the answer into `T`
+    pub async fn extract<T: JsonSchema + DeserializeOwned>(
+        &self,
+        model: impl Into<String>,
+        prompt: impl Into<String>,
+    ) -> Result<T, AnthropicError> {
+        let mut conversation = Conversation::new(model, DEFAULT_STRUCTURED_MAX_TOKENS);
+        conversation.add_user_message(prompt);
+        conversation.send_structured::<T>(self).await
+    }
+}
@@ -0,0 +1,343 @@
+use crate::client::AnthropicClient;
+use crate::conversation::Conversation;
+use crate::error::AnthropicError;
+use crate::types::messages::{
+    Content, ContentBlock, CreateMessageRequest, DocumentSource, ImageSourceData, Message, Role,
+    ToolResultContent,
+};
+use base64::Engine;
+use hyperware_process_lib::http::Method;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Rough characters-per-token ratio for English text with Claude's tokenizer
+pub(crate) const CHARS_PER_TOKEN: usize = 4;
+
+/// Estimate the number of tokens in a piece of text without calling the API
+///
+/// This is a heuristic (about four characters per token) meant for budgeting decisions,
+/// not for billing; use the token counting endpoint when exact numbers matter.
+pub fn estimate_text_tokens(text: &str) -> u32 {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN) as u32
+}
+
+/// Longest edge the API keeps; larger images are scaled down before tokenizing
+const MAX_IMAGE_EDGE: f64 = 1568.0;
+
+/// Most tokens a single image costs after the API's own downscaling
+const MAX_IMAGE_TOKENS: f64 = 1600.0;
+
+/// Pixels per token in Anthropic's image formula
+const PIXELS_PER_TOKEN: f64 = 750.0;
+
+/// Estimate the input tokens an image of the given size costs
+///
+/// Implements Anthropic's published formula, `width * height / 750`, after the same
+/// aspect-preserving downscaling the API applies to images over 1568 pixels on the long
+/// edge or about 1600 tokens.
+pub fn estimate_image_tokens(width: u32, height: u32) -> u32 {
+    if width == 0 || height == 0 {
+        return 0;
+    }
+    let (width, height) = (width as f64, height as f64);
+    let edge_scale = (MAX_IMAGE_EDGE / width.max(height)).min(1.0);
+    let pixels = width * height * edge_scale * edge_scale;
+    (pixels / PIXELS_PER_TOKEN).min(MAX_IMAGE_TOKENS).ceil() as u32
+}
+
+/// Estimate the input tokens of an encoded PNG, JPEG, GIF, or WebP image
+pub fn estimate_image_bytes_tokens(bytes: &[u8]) -> Option<u32> {
+    image_dimensions(bytes).map(|(width, height)| estimate_image_tokens(width, height))
+}
+
+/// Read the width and height from an encoded image's header without decoding it
+pub fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let le16 = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let le24 = |at: usize| {
+        let b = bytes.get(at..at + 3)?;
+        Some(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16)
+    };
+
+    match crate::types::messages::sniff_image_type(bytes)? {
+        "image/png" => {
+            let width = u32::from_be_bytes(bytes.get(16..20)?.try_into().ok()?);
+            let height = u32::from_be_bytes(bytes.get(20..24)?.try_into().ok()?);
+            Some((width, height))
+        }
+        "image/gif" => Some((le16(6)?, le16(8)?)),
+        "image/webp" => match bytes.get(12..16)? {
+            b"VP8 " => Some((le16(26)? & 0x3fff, le16(28)? & 0x3fff)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(bytes.get(21..25)?.try_into().ok()?);
+                Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+            }
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            _ => None,
+        },
+        "image/jpeg" => {
+            // Walk the marker segments until the start-of-frame header
+            let mut at = 2;
+            loop {
+                if *bytes.get(at)? != 0xFF {
+                    return None;
+                }
+                let marker = *bytes.get(at + 1)?;
+                match marker {
+                    0xFF => at += 1,
+                    0xD0..=0xD9 | 0x01 => at += 2,
+                    0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                        return Some((be16(at + 7)?, be16(at + 5)?));
+                    }
+                    _ => at += 2 + be16(at + 2)? as usize,
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Estimate the input tokens of a request without calling the API
+///
+/// Text, tool definitions, and tool inputs use the character heuristic; images use
+/// [`estimate_image_tokens`], falling back to the per-image maximum when their size can't
+/// be read (e.g. URL sources).
+pub fn estimate_request_tokens(request: &CreateMessageRequest) -> u32 {
+    let system = request.system.as_ref().map_or(0, json_tokens);
+    let tools = request.tools.as_ref().map_or(0, json_tokens);
+    let messages: u32 = request
+        .messages
+        .iter()
+        .map(|message| estimate_message_tokens(message))
+        .sum();
+    system + tools + messages
+}
+
+/// Estimate the input tokens one message contributes to a request
+pub fn estimate_message_tokens(message: &Message) -> u32 {
+    match &message.content {
+        Content::Text(text) => estimate_text_tokens(text),
+        Content::Blocks(blocks) => blocks.iter().map(estimate_block_tokens).sum(),
+    }
+}
+
+pub(crate) fn json_tokens(value: &impl Serialize) -> u32 {
+    serde_json::to_string(value).map_or(0, |json| estimate_text_tokens(&json))
+}
+
+fn estimate_block_tokens(block: &ContentBlock) -> u32 {
+    match block {
+        ContentBlock::Text { text, .. } => estimate_text_tokens(text),
+        ContentBlock::Image { source, .. } => estimate_source_tokens(&source.data),
+        ContentBlock::Document { source, .. } => match source {
+            DocumentSource::Text { data, .. } => estimate_text_tokens(data),
+            // PDFs are rendered page by page; their cost can't be known offline
+            _ => 0,
+        },
+        ContentBlock::ToolUse { name, input, .. }
+        | ContentBlock::ServerToolUse { name, input, .. }
+        | ContentBlock::McpToolUse { name, input, .. } => {
+            estimate_text_tokens(name) + estimate_text_tokens(&input.to_string())
+        }
+        ContentBlock::ToolResult { content, .. } => match content {
+            Some(ToolResultContent::Text(text)) => estimate_text_tokens(text),
+            Some(ToolResultContent::Blocks(blocks)) => {
+                blocks.iter().map(estimate_block_tokens).sum()
+            }
+            None => 0,
+        },
+        ContentBlock::WebSearchToolResult { content, .. }
+        | ContentBlock::WebFetchToolResult { content, .. }
+        | ContentBlock::CodeExecutionToolResult { content, .. }
+        | ContentBlock::BashCodeExecutionToolResult { content, .. }
+        | ContentBlock::TextEditorCodeExecutionToolResult { content, .. }
+        | ContentBlock::McpToolResult { content, .. } => json_tokens(content),
+        ContentBlock::Thinking { thinking, .. } => estimate_text_tokens(thinking),
+        ContentBlock::RedactedThinking { data } => estimate_text_tokens(data),
+        ContentBlock::Unknown(raw) => json_tokens(raw),
+    }
+}
+
+fn estimate_source_tokens(data: &ImageSourceData) -> u32 {
+    let estimate = match data {
+        ImageSourceData::Bytes { data, .. } => estimate_image_bytes_tokens(data.as_bytes()),
+        ImageSourceData::Base64 { data, .. } => base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .ok()
+            .and_then(|bytes| estimate_image_bytes_tokens(&bytes)),
+        ImageSourceData::Url { .. } | ImageSourceData::File { .. } => None,
+    };
+    estimate.unwrap_or(MAX_IMAGE_TOKENS as u32)
+}
+
+/// Counts the tokens of individual blocks for [`Conversation::token_breakdown`]
+pub trait TokenEstimator {
+    /// Tokens in a piece of text, such as the system prompt
+    fn text_tokens(&self, text: &str) -> u32;
+
+    /// Tokens one content block contributes
+    fn block_tokens(&self, block: &ContentBlock) -> u32;
+}
+
+/// The SDK's offline heuristics: four characters per token, and the image formula
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeuristicEstimator;
+
+impl TokenEstimator for HeuristicEstimator {
+    fn text_tokens(&self, text: &str) -> u32 {
+        estimate_text_tokens(text)
+    }
+
+    fn block_tokens(&self, block: &ContentBlock) -> u32 {
+        estimate_block_tokens(block)
+    }
+}
+
+/// Estimated input tokens of a conversation, split by message and block
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TokenBreakdown {
+    pub system: u32,
+    pub tools: u32,
+    pub messages: Vec<MessageTokens>,
+    pub total: u32,
+}
+
+/// Tokens of one message in a [`TokenBreakdown`]
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageTokens {
+    pub index: usize,
+    pub role: Role,
+    pub tokens: u32,
+    /// Tokens per block, as `(block kind, tokens)`; one `text` entry for plain text content
+    pub blocks: Vec<(&'static str, u32)>,
+}
+
+impl TokenBreakdown {
+    /// The `n` messages with the most tokens, largest first
+    pub fn largest_messages(&self, n: usize) -> Vec<&MessageTokens> {
+        let mut messages: Vec<&MessageTokens> = self.messages.iter().collect();
+        messages.sort_by_key(|message| std::cmp::Reverse(message.tokens));
+        messages.truncate(n);
+        messages
+    }
+
+    /// Scale every count so the total matches an exact count
+    fn calibrate(&mut self, exact: u32) {
+        if self.total == 0 {
+            self.total = exact;
+            return;
+        }
+        let factor = exact as f64 / self.total as f64;
+        let scale = |tokens: u32| (tokens as f64 * factor).round() as u32;
+        self.system = scale(self.system);
+        self.tools = scale(self.tools);
+        for message in &mut self.messages {
+            message.tokens = scale(message.tokens);
+            for (_, tokens) in &mut message.blocks {
+                *tokens = scale(*tokens);
+            }
+        }
+        self.total = exact;
+    }
+}
+
+impl Conversation {
+    /// Estimate the tokens each message and block adds to the next request
+    ///
+    /// Useful for showing what fills the context window and for tuning pruning. Pass
+    /// [`HeuristicEstimator`] for the SDK's offline estimate, or use
+    /// [`calibrated_token_breakdown`](Self::calibrated_token_breakdown) for exact totals.
+    pub fn token_breakdown(&self, estimator: &impl TokenEstimator) -> TokenBreakdown {
+        let request = self.build_request();
+        let system = request.system.as_ref().map_or(0, |system| {
+            estimator.text_tokens(&serde_json::to_string(system).unwrap_or_default())
+        });
+        let tools = request.tools.as_ref().map_or(0, |tools| {
+            estimator.text_tokens(&serde_json::to_string(tools).unwrap_or_default())
+        });
+        let messages: Vec<MessageTokens> = request
+            .messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| {
+                let blocks = match &message.content {
+                    Content::Text(text) => vec![("text", estimator.text_tokens(text))],
+                    Content::Blocks(blocks) => blocks
+                        .iter()
+                        .map(|block| (block.kind(), estimator.block_tokens(block)))
+                        .collect(),
+                };
+                MessageTokens {
+                    index,
+                    role: message.role,
+                    tokens: blocks.iter().map(|(_, tokens)| tokens).sum(),
+                    blocks,
+                }
+            })
+            .collect();
+        let total = system + tools + messages.iter().map(|m| m.tokens).sum::<u32>();
+        TokenBreakdown {
+            system,
+            tools,
+            messages,
+            total,
+        }
+    }
+
+    /// Like [`token_breakdown`](Self::token_breakdown), scaled to the API's exact count
+    ///
+    /// Makes one call to the token counting endpoint for the whole request; the split
+    /// between messages is still the heuristic's.
+    pub async fn calibrated_token_breakdown(
+        &self,
+        client: &AnthropicClient,
+    ) -> Result<TokenBreakdown, AnthropicError> {
+        let exact = client.count_tokens(&self.build_request_for(client)).await?;
+        let mut breakdown = self.token_breakdown(&HeuristicEstimator);
+        breakdown.calibrate(exact);
+        Ok(breakdown)
+    }
+}
+
+/// Fields of a message request the token counting endpoint doesn't accept
+const UNCOUNTED_FIELDS: &[&str] = &[
+    "max_tokens",
+    "metadata",
+    "stop_sequences",
+    "stream",
+    "temperature",
+    "top_k",
+    "top_p",
+];
+
+impl AnthropicClient {
+    /// Count a request's input tokens exactly with the token counting endpoint
+    pub async fn count_tokens(
+        &self,
+        request: &CreateMessageRequest,
+    ) -> Result<u32, AnthropicError> {
+        let mut body = serde_json::to_value(request)?;
+        if let Value::Object(ref mut fields) = body {
+            for field in UNCOUNTED_FIELDS {
+                fields.remove(*field);
+            }
+        }
+        let betas: Vec<&str> = request.betas.iter().map(String::as_str).collect();
+        let response = self
+            .send_raw_with_betas(
+                "/v1/messages/count_tokens",
+                Method::POST,
+                body,
+                &betas,
+                HashMap::new(),
+            )
+            .await?;
+        response.body["input_tokens"]
+            .as_u64()
+            .map(|tokens| tokens as u32)
+            .ok_or_else(|| {
+                AnthropicError::Deserialization("Token count response has no input_tokens".into())
+            })
+    }
+}
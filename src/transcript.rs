@@ -0,0 +1,218 @@
+use crate::error::AnthropicError;
+use crate::types::messages::{CreateMessageRequest, MessageResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// One request sent to the API and the response it produced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptExchange {
+    pub request: CreateMessageRequest,
+    pub response: MessageResponse,
+}
+
+/// A recorded sequence of API exchanges, stored as a JSON fixture for golden tests
+///
+/// Tool calls and tool results are captured as part of the responses and the requests
+/// that follow them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Transcript {
+    pub exchanges: Vec<TranscriptExchange>,
+}
+
+impl Transcript {
+    /// Create an empty transcript
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serialize the transcript as pretty-printed JSON, suitable for checking in
+    pub fn to_json(&self) -> Result<String, AnthropicError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Load a transcript from JSON
+    pub fn from_json(json: &str) -> Result<Self, AnthropicError> {
+        serde_json::from_str(json).map_err(|e| AnthropicError::Deserialization(e.to_string()))
+    }
+}
+
+/// Captures every exchange made by a client configured with
+/// [`AnthropicClient::with_recorder`](crate::AnthropicClient::with_recorder)
+///
+/// Clones share the same transcript, so keep one to read it back after the run.
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptRecorder {
+    transcript: Arc<Mutex<Transcript>>,
+}
+
+impl TranscriptRecorder {
+    /// Create a recorder with an empty transcript
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append an exchange
+    pub fn record(&self, request: &CreateMessageRequest, response: &MessageResponse) {
+        self.transcript
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .exchanges
+            .push(TranscriptExchange {
+                request: request.clone(),
+                response: response.clone(),
+            });
+    }
+
+    /// A copy of everything recorded so far
+    pub fn transcript(&self) -> Transcript {
+        self.transcript
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+/// Serves recorded responses in place of the API, checking each request against the recording
+///
+/// Install with [`AnthropicClient::with_replay`](crate::AnthropicClient::with_replay). A
+/// request that differs from the recorded one (outside the ignored fields) fails with
+/// [`AnthropicError::TranscriptMismatch`] naming the first differing path.
+#[derive(Debug, Clone)]
+pub struct TranscriptReplay {
+    transcript: Arc<Transcript>,
+    position: Arc<Mutex<usize>>,
+    ignored_fields: Vec<String>,
+    failures: Arc<Mutex<VecDeque<AnthropicError>>>,
+}
+
+impl TranscriptReplay {
+    /// Replay a transcript, ignoring the volatile `/metadata` field by default
+    pub fn new(transcript: Transcript) -> Self {
+        Self {
+            transcript: Arc::new(transcript),
+            position: Arc::new(Mutex::new(0)),
+            ignored_fields: vec!["/metadata".to_string()],
+            failures: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// Ignore a request field when comparing, given as a JSON pointer (e.g. `/temperature`)
+    pub fn with_ignored_field(mut self, pointer: impl Into<String>) -> Self {
+        self.ignored_fields.push(pointer.into());
+        self
+    }
+
+    /// Fail the next request with `error` before serving the recording, e.g. to exercise
+    /// retries
+    ///
+    /// Failures queue up in the order they are added and don't consume an exchange.
+    pub fn with_failure(self, error: AnthropicError) -> Self {
+        self.failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(error);
+        self
+    }
+
+    /// Check the request against the next recorded exchange and return its response
+    pub fn respond(
+        &self,
+        request: &CreateMessageRequest,
+    ) -> Result<MessageResponse, AnthropicError> {
+        let failure = self
+            .failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop_front();
+        if let Some(error) = failure {
+            return Err(error);
+        }
+        let mut position = self.position.lock().unwrap_or_else(|e| e.into_inner());
+        let exchange = self.transcript.exchanges.get(*position).ok_or_else(|| {
+            AnthropicError::TranscriptMismatch(format!(
+                "unexpected request #{}: the transcript only has {} exchanges",
+                *position + 1,
+                self.transcript.exchanges.len()
+            ))
+        })?;
+
+        let expected = self.normalize(&exchange.request)?;
+        let actual = self.normalize(request)?;
+        if let Some(difference) = first_difference(&expected, &actual, "") {
+            return Err(AnthropicError::TranscriptMismatch(format!(
+                "request #{} differs from the recording at {}",
+                *position + 1,
+                difference
+            )));
+        }
+
+        *position += 1;
+        Ok(exchange.response.clone())
+    }
+
+    /// Number of recorded exchanges not yet replayed
+    pub fn remaining(&self) -> usize {
+        let position = *self.position.lock().unwrap_or_else(|e| e.into_inner());
+        self.transcript.exchanges.len().saturating_sub(position)
+    }
+
+    /// Fail if any recorded exchange was never requested
+    pub fn finish(&self) -> Result<(), AnthropicError> {
+        match self.remaining() {
+            0 => Ok(()),
+            remaining => Err(AnthropicError::TranscriptMismatch(format!(
+                "{} recorded exchanges were never requested",
+                remaining
+            ))),
+        }
+    }
+
+    fn normalize(&self, request: &CreateMessageRequest) -> Result<Value, AnthropicError> {
+        let mut value = serde_json::to_value(request)?;
+        for pointer in &self.ignored_fields {
+            let (parent, key) = pointer.rsplit_once('/').unwrap_or(("", pointer));
+            let parent = if parent.is_empty() {
+                Some(&mut value)
+            } else {
+                value.pointer_mut(parent)
+            };
+            if let Some(Value::Object(object)) = parent {
+                object.remove(key);
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// Describe the first path at which two JSON values differ
+fn first_difference(expected: &Value, actual: &Value, path: &str) -> Option<String> {
+    match (expected, actual) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            keys.into_iter().find_map(|key| {
+                let path = format!("{}/{}", path, key);
+                match (a.get(key), b.get(key)) {
+                    (Some(x), Some(y)) => first_difference(x, y, &path),
+                    (Some(_), None) => Some(format!("{} (missing)", path)),
+                    (None, Some(_)) => Some(format!("{} (unexpected)", path)),
+                    (None, None) => None,
+                }
+            })
+        }
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .enumerate()
+            .find_map(|(i, (x, y))| first_difference(x, y, &format!("{}/{}", path, i)))
+            .or_else(|| {
+                (a.len() != b.len())
+                    .then(|| format!("{} (expected {} items, got {})", path, a.len(), b.len()))
+            }),
+        _ if expected == actual => None,
+        _ => Some(format!("{}: expected {}, got {}", path, expected, actual)),
+    }
+}
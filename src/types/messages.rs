@@ -1,9 +1,19 @@
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use crate::betas::{
+    EXTENDED_CACHE_TTL_BETA, FINE_GRAINED_TOOL_STREAMING_BETA, INTERLEAVED_THINKING_BETA,
+    TOKEN_EFFICIENT_TOOLS_BETA,
+};
+use crate::error::AnthropicError;
+use crate::models::{model_info, DEFAULT_MAX_TOKENS};
+use crate::tokens::estimate_request_tokens;
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
 
 // Core message types
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Role {
     User,
@@ -29,6 +39,9 @@ pub enum Content {
 pub enum ContentBlock {
     Text {
         text: String,
+        /// Sources Claude cited, echoed back from an earlier response
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        citations: Option<Vec<Value>>,
         #[serde(skip_serializing_if = "Option::is_none")]
         cache_control: Option<CacheControl>,
     },
@@ -37,6 +50,17 @@ pub enum ContentBlock {
         #[serde(skip_serializing_if = "Option::is_none")]
         cache_control: Option<CacheControl>,
     },
+    /// A PDF or plain-text document for Claude to read
+    Document {
+        source: DocumentSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        /// Context about the document that Claude can use but not cite
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        context: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
     ToolUse {
         id: String,
         name: String,
@@ -53,6 +77,280 @@ pub enum ContentBlock {
         #[serde(skip_serializing_if = "Option::is_none")]
         cache_control: Option<CacheControl>,
     },
+    /// A server tool call (e.g. web search) echoed back from an earlier response
+    ServerToolUse {
+        id: String,
+        name: String,
+        input: Value,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    /// Web search results echoed back from an earlier response
+    WebSearchToolResult {
+        tool_use_id: String,
+        content: Value,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    /// Web fetch results echoed back from an earlier response
+    WebFetchToolResult {
+        tool_use_id: String,
+        content: Value,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    /// Code execution output echoed back from an earlier response
+    CodeExecutionToolResult {
+        tool_use_id: String,
+        content: Value,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    /// Output of a shell command run by the code execution tool, echoed back
+    BashCodeExecutionToolResult {
+        tool_use_id: String,
+        content: Value,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    /// Result of a file edit made by the code execution tool, echoed back
+    TextEditorCodeExecutionToolResult {
+        tool_use_id: String,
+        content: Value,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    /// A call to a tool on an MCP server, echoed back from an earlier response
+    McpToolUse {
+        id: String,
+        name: String,
+        server_name: String,
+        input: Value,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    /// An MCP server's answer to a tool call, echoed back from an earlier response
+    McpToolResult {
+        tool_use_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        is_error: Option<bool>,
+        content: Value,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    /// Extended thinking echoed back unmodified, as the API requires during tool use
+    Thinking { thinking: String, signature: String },
+    /// Encrypted thinking echoed back unmodified
+    RedactedThinking { data: String },
+    /// A response block of a type this SDK doesn't know, sent back as the raw JSON
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+impl ContentBlock {
+    /// Convert a block from a response into one that can go back into the history
+    ///
+    /// Nothing is dropped: citations, thinking signatures, server tool results, and blocks
+    /// of unknown types are kept as the API returned them.
+    pub fn from_response(block: &ResponseContentBlock) -> Self {
+        match block {
+            ResponseContentBlock::Text { text, citations } => ContentBlock::Text {
+                text: text.clone(),
+                citations: citations.clone(),
+                cache_control: None,
+            },
+            ResponseContentBlock::ToolUse { id, name, input } => ContentBlock::ToolUse {
+                id: id.clone(),
+                name: name.clone(),
+                input: input.clone(),
+                cache_control: None,
+            },
+            ResponseContentBlock::ServerToolUse { id, name, input } => {
+                ContentBlock::ServerToolUse {
+                    id: id.clone(),
+                    name: name.clone(),
+                    input: input.clone(),
+                    cache_control: None,
+                }
+            }
+            ResponseContentBlock::WebSearchToolResult {
+                tool_use_id,
+                content,
+            } => ContentBlock::WebSearchToolResult {
+                tool_use_id: tool_use_id.clone(),
+                content: content.clone(),
+                cache_control: None,
+            },
+            ResponseContentBlock::WebFetchToolResult {
+                tool_use_id,
+                content,
+            } => ContentBlock::WebFetchToolResult {
+                tool_use_id: tool_use_id.clone(),
+                content: content.clone(),
+                cache_control: None,
+            },
+            ResponseContentBlock::CodeExecutionToolResult {
+                tool_use_id,
+                content,
+            } => ContentBlock::CodeExecutionToolResult {
+                tool_use_id: tool_use_id.clone(),
+                content: content.clone(),
+                cache_control: None,
+            },
+            ResponseContentBlock::BashCodeExecutionToolResult {
+                tool_use_id,
+                content,
+            } => ContentBlock::BashCodeExecutionToolResult {
+                tool_use_id: tool_use_id.clone(),
+                content: content.clone(),
+                cache_control: None,
+            },
+            ResponseContentBlock::TextEditorCodeExecutionToolResult {
+                tool_use_id,
+                content,
+            } => ContentBlock::TextEditorCodeExecutionToolResult {
+                tool_use_id: tool_use_id.clone(),
+                content: content.clone(),
+                cache_control: None,
+            },
+            ResponseContentBlock::McpToolUse {
+                id,
+                name,
+                server_name,
+                input,
+            } => ContentBlock::McpToolUse {
+                id: id.clone(),
+                name: name.clone(),
+                server_name: server_name.clone(),
+                input: input.clone(),
+                cache_control: None,
+            },
+            ResponseContentBlock::McpToolResult {
+                tool_use_id,
+                is_error,
+                content,
+            } => ContentBlock::McpToolResult {
+                tool_use_id: tool_use_id.clone(),
+                is_error: *is_error,
+                content: content.clone(),
+                cache_control: None,
+            },
+            ResponseContentBlock::Thinking {
+                thinking,
+                signature,
+            } => ContentBlock::Thinking {
+                thinking: thinking.clone(),
+                signature: signature.clone(),
+            },
+            ResponseContentBlock::RedactedThinking { data } => {
+                ContentBlock::RedactedThinking { data: data.clone() }
+            }
+            ResponseContentBlock::Unknown(raw) => ContentBlock::Unknown(raw.clone()),
+        }
+    }
+
+    /// Short human-readable name of the block type
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ContentBlock::Text { .. } => "text block",
+            ContentBlock::Image { .. } => "image",
+            ContentBlock::Document { .. } => "document",
+            ContentBlock::ToolUse { .. } => "tool use",
+            ContentBlock::ToolResult { .. } => "tool result",
+            ContentBlock::ServerToolUse { .. } => "server tool use",
+            ContentBlock::WebSearchToolResult { .. } => "web search result",
+            ContentBlock::WebFetchToolResult { .. } => "web fetch result",
+            ContentBlock::CodeExecutionToolResult { .. }
+            | ContentBlock::BashCodeExecutionToolResult { .. }
+            | ContentBlock::TextEditorCodeExecutionToolResult { .. } => "code execution result",
+            ContentBlock::McpToolUse { .. } => "MCP tool use",
+            ContentBlock::McpToolResult { .. } => "MCP tool result",
+            ContentBlock::Thinking { .. } => "thinking block",
+            ContentBlock::RedactedThinking { .. } => "redacted thinking block",
+            ContentBlock::Unknown(_) => "unknown block",
+        }
+    }
+
+    /// The block's cache breakpoint, if any; thinking and unknown blocks can't carry one
+    pub fn cache_control(&self) -> Option<&CacheControl> {
+        match self {
+            ContentBlock::Text { cache_control, .. }
+            | ContentBlock::Image { cache_control, .. }
+            | ContentBlock::Document { cache_control, .. }
+            | ContentBlock::ToolUse { cache_control, .. }
+            | ContentBlock::ToolResult { cache_control, .. }
+            | ContentBlock::ServerToolUse { cache_control, .. }
+            | ContentBlock::WebSearchToolResult { cache_control, .. }
+            | ContentBlock::WebFetchToolResult { cache_control, .. }
+            | ContentBlock::CodeExecutionToolResult { cache_control, .. }
+            | ContentBlock::BashCodeExecutionToolResult { cache_control, .. }
+            | ContentBlock::TextEditorCodeExecutionToolResult { cache_control, .. }
+            | ContentBlock::McpToolUse { cache_control, .. }
+            | ContentBlock::McpToolResult { cache_control, .. } => cache_control.as_ref(),
+            ContentBlock::Thinking { .. }
+            | ContentBlock::RedactedThinking { .. }
+            | ContentBlock::Unknown(_) => None,
+        }
+    }
+
+    /// An image uploaded through the Files API, referenced by its id
+    pub fn image_from_file_id(file_id: impl Into<String>) -> Result<Self, AnthropicError> {
+        Ok(ContentBlock::Image {
+            source: ImageSource::from_file_id(file_id)?,
+            cache_control: None,
+        })
+    }
+
+    /// A document uploaded through the Files API, referenced by its id
+    pub fn document_from_file_id(file_id: impl Into<String>) -> Result<Self, AnthropicError> {
+        let file_id = file_id.into();
+        validate_file_id(&file_id)?;
+        Ok(ContentBlock::Document {
+            source: DocumentSource::File { file_id },
+            title: None,
+            context: None,
+            cache_control: None,
+        })
+    }
+
+    /// Whether the block references a file uploaded through the Files API
+    pub fn uses_file(&self) -> bool {
+        match self {
+            ContentBlock::Image { source, .. } => {
+                matches!(source.data, ImageSourceData::File { .. })
+            }
+            ContentBlock::Document { source, .. } => {
+                matches!(source, DocumentSource::File { .. })
+            }
+            ContentBlock::ToolResult {
+                content: Some(ToolResultContent::Blocks(blocks)),
+                ..
+            } => blocks.iter().any(ContentBlock::uses_file),
+            _ => false,
+        }
+    }
+
+    /// Mutable access to the block's cache breakpoint, or `None` for thinking blocks
+    pub fn cache_control_mut(&mut self) -> Option<&mut Option<CacheControl>> {
+        match self {
+            ContentBlock::Text { cache_control, .. }
+            | ContentBlock::Image { cache_control, .. }
+            | ContentBlock::Document { cache_control, .. }
+            | ContentBlock::ToolUse { cache_control, .. }
+            | ContentBlock::ToolResult { cache_control, .. }
+            | ContentBlock::ServerToolUse { cache_control, .. }
+            | ContentBlock::WebSearchToolResult { cache_control, .. }
+            | ContentBlock::WebFetchToolResult { cache_control, .. }
+            | ContentBlock::CodeExecutionToolResult { cache_control, .. }
+            | ContentBlock::BashCodeExecutionToolResult { cache_control, .. }
+            | ContentBlock::TextEditorCodeExecutionToolResult { cache_control, .. }
+            | ContentBlock::McpToolUse { cache_control, .. }
+            | ContentBlock::McpToolResult { cache_control, .. } => Some(cache_control),
+            ContentBlock::Thinking { .. }
+            | ContentBlock::RedactedThinking { .. }
+            | ContentBlock::Unknown(_) => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -75,13 +373,234 @@ pub struct ImageSource {
 pub enum ImageSourceType {
     Base64,
     Url,
+    File,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ImageSourceData {
+    Base64 {
+        media_type: String,
+        data: String,
+    },
+    Url {
+        url: String,
+    },
+    /// Raw bytes encoded as base64 only when serialized; deserializes as `Base64`
+    Bytes {
+        media_type: String,
+        data: ImageBytes,
+    },
+    /// A file uploaded through the Files API
+    File {
+        file_id: String,
+    },
+}
+
+/// Where a [`ContentBlock::Document`] comes from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DocumentSource {
+    /// A base64-encoded PDF
     Base64 { media_type: String, data: String },
+    /// Plain text
+    Text { media_type: String, data: String },
+    /// A PDF at an http(s) URL
     Url { url: String },
+    /// A file uploaded through the Files API
+    File { file_id: String },
+}
+
+/// Check that `file_id` looks like an id returned by the Files API
+///
+/// Ids are `file_` followed by letters and digits; anything else would only be
+/// rejected by the API after the request was sent.
+pub fn validate_file_id(file_id: &str) -> Result<(), AnthropicError> {
+    let valid = file_id
+        .strip_prefix("file_")
+        .is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_alphanumeric()));
+    if valid {
+        Ok(())
+    } else {
+        Err(AnthropicError::InvalidRequest(format!(
+            "{:?} is not a Files API id; expected \"file_\" followed by letters and digits",
+            file_id
+        )))
+    }
+}
+
+/// Image media types accepted by the API
+pub const SUPPORTED_IMAGE_TYPES: [&str; 4] = ["image/jpeg", "image/png", "image/gif", "image/webp"];
+
+/// Largest image the API accepts, in raw (not base64-encoded) bytes
+pub const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// Detect the media type of an image from its magic bytes
+pub fn sniff_image_type(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
+
+impl ImageSource {
+    /// Create a base64 image source from raw bytes, deferring the encoding until it is sent
+    pub fn bytes(media_type: impl Into<String>, bytes: impl Into<Arc<[u8]>>) -> Self {
+        Self {
+            source_type: ImageSourceType::Base64,
+            data: ImageSourceData::Bytes {
+                media_type: media_type.into(),
+                data: ImageBytes::new(bytes),
+            },
+        }
+    }
+
+    /// Create a validated image source from raw bytes, detecting the media type
+    pub fn from_bytes(bytes: impl Into<Arc<[u8]>>) -> Result<Self, AnthropicError> {
+        let bytes = bytes.into();
+        let media_type = sniff_image_type(&bytes).ok_or_else(|| {
+            AnthropicError::InvalidImage(
+                "unrecognized format; expected PNG, JPEG, GIF, or WebP".to_string(),
+            )
+        })?;
+        let source = Self::bytes(media_type, bytes);
+        source.validate()?;
+        Ok(source)
+    }
+
+    /// Create a validated image source referencing an http(s) URL
+    pub fn from_url(url: impl Into<String>) -> Result<Self, AnthropicError> {
+        let source = Self {
+            source_type: ImageSourceType::Url,
+            data: ImageSourceData::Url { url: url.into() },
+        };
+        source.validate()?;
+        Ok(source)
+    }
+
+    /// Create a validated image source referencing a file uploaded through the Files API
+    pub fn from_file_id(file_id: impl Into<String>) -> Result<Self, AnthropicError> {
+        let source = Self {
+            source_type: ImageSourceType::File,
+            data: ImageSourceData::File {
+                file_id: file_id.into(),
+            },
+        };
+        source.validate()?;
+        Ok(source)
+    }
+
+    /// Check the media type, size, URL, and file id against what the API accepts
+    pub fn validate(&self) -> Result<(), AnthropicError> {
+        let (media_type, size, bytes) = match &self.data {
+            ImageSourceData::File { file_id } => return validate_file_id(file_id),
+            ImageSourceData::Url { url } => {
+                let parsed = url::Url::parse(url).map_err(|err| {
+                    AnthropicError::InvalidImage(format!("invalid URL {:?}: {}", url, err))
+                })?;
+                if !matches!(parsed.scheme(), "http" | "https") {
+                    return Err(AnthropicError::InvalidImage(format!(
+                        "URL scheme {:?} is not supported; use http or https",
+                        parsed.scheme()
+                    )));
+                }
+                return Ok(());
+            }
+            ImageSourceData::Base64 { media_type, data } => {
+                // Decoded size without decoding: 3 bytes per 4 characters, minus padding
+                let padding = data.bytes().rev().take_while(|&b| b == b'=').count();
+                (
+                    media_type,
+                    (data.len() / 4 * 3).saturating_sub(padding),
+                    None,
+                )
+            }
+            ImageSourceData::Bytes { media_type, data } => {
+                (media_type, data.as_bytes().len(), Some(data.as_bytes()))
+            }
+        };
+
+        if !SUPPORTED_IMAGE_TYPES.contains(&media_type.as_str()) {
+            return Err(AnthropicError::InvalidImage(format!(
+                "media type {:?} is not supported; expected one of {}",
+                media_type,
+                SUPPORTED_IMAGE_TYPES.join(", ")
+            )));
+        }
+        if size > MAX_IMAGE_BYTES {
+            return Err(AnthropicError::InvalidImage(format!(
+                "image is {} bytes, over the {} byte limit",
+                size, MAX_IMAGE_BYTES
+            )));
+        }
+        if let Some(detected) = bytes.and_then(sniff_image_type) {
+            if detected != media_type {
+                return Err(AnthropicError::InvalidImage(format!(
+                    "declared as {} but the data is {}",
+                    media_type, detected
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Image bytes that are base64-encoded at most once, the first time they are serialized
+///
+/// Clones share both the bytes and the encoded form, so forks, retries, and stored
+/// histories don't hold extra copies.
+#[derive(Clone)]
+pub struct ImageBytes {
+    bytes: Arc<[u8]>,
+    encoded: Arc<OnceLock<String>>,
+}
+
+impl ImageBytes {
+    /// Wrap raw image bytes
+    pub fn new(bytes: impl Into<Arc<[u8]>>) -> Self {
+        Self {
+            bytes: bytes.into(),
+            encoded: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// The raw bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The base64 encoding, computed on first use
+    pub fn base64(&self) -> &str {
+        self.encoded
+            .get_or_init(|| base64::engine::general_purpose::STANDARD.encode(&self.bytes))
+    }
+}
+
+impl fmt::Debug for ImageBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ImageBytes({} bytes)", self.bytes.len())
+    }
+}
+
+impl Serialize for ImageBytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.base64())
+    }
+}
+
+impl<'de> Deserialize<'de> for ImageBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(&encoded)
+            .map_err(serde::de::Error::custom)?;
+        let image = Self::new(bytes);
+        let _ = image.encoded.set(encoded);
+        Ok(image)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -118,11 +637,78 @@ impl CacheControl {
     }
 }
 
+/// How long a cached prompt prefix lives after it was last read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CacheTtl {
+    FiveMinutes,
+    /// Costs more to write, but survives gaps between turns; needs the extended TTL beta
+    OneHour,
+}
+
+impl CacheTtl {
+    /// A cache breakpoint with this TTL
+    pub fn cache_control(self) -> CacheControl {
+        match self {
+            CacheTtl::FiveMinutes => CacheControl::ephemeral_5m(),
+            CacheTtl::OneHour => CacheControl::ephemeral_1h(),
+        }
+    }
+}
+
+/// Most cache breakpoints a request may carry
+pub const MAX_CACHE_BREAKPOINTS: usize = 4;
+
+/// Put a breakpoint in an unmarked slot, returning whether one was added
+fn mark_last(slot: Option<&mut Option<CacheControl>>, cache_control: &CacheControl) -> bool {
+    match slot {
+        Some(slot) if slot.is_none() => {
+            *slot = Some(cache_control.clone());
+            true
+        }
+        _ => false,
+    }
+}
+
+fn has_cache_control(block: &ContentBlock) -> bool {
+    match block {
+        ContentBlock::ToolResult {
+            content: Some(ToolResultContent::Blocks(nested)),
+            ..
+        } if nested.iter().any(has_cache_control) => true,
+        _ => block.cache_control().is_some(),
+    }
+}
+
+fn retime_blocks(blocks: &mut [ContentBlock], retime: &mut impl FnMut(&mut Option<CacheControl>)) {
+    for block in blocks {
+        if let ContentBlock::ToolResult {
+            content: Some(ToolResultContent::Blocks(nested)),
+            ..
+        } = block
+        {
+            retime_blocks(nested, retime);
+        }
+        if let Some(slot) = block.cache_control_mut() {
+            retime(slot);
+        }
+    }
+}
+
+/// Maximum Messages API request body size in bytes
+pub const MAX_REQUEST_BYTES: usize = 32 * 1024 * 1024;
+
+/// Most custom stop sequences a request may set
+pub const MAX_STOP_SEQUENCES: usize = 16;
+
+/// Longest custom stop sequence, in characters
+pub const MAX_STOP_SEQUENCE_CHARS: usize = 256;
+
 // Request structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateMessageRequest {
     pub model: String,
-    pub messages: Vec<Message>,
+    /// Shared with the conversation that built the request, so building one is cheap
+    pub messages: Vec<Arc<Message>>,
     pub max_tokens: u32,
 
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -151,8 +737,126 @@ pub struct CreateMessageRequest {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_choice: Option<crate::types::tools::ToolChoice>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<ThinkingConfig>,
+
+    /// Remote MCP servers whose tools Claude may call, through the MCP connector
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mcp_servers: Option<Vec<McpServer>>,
+
+    /// Extra `anthropic-beta` values sent with this request, on top of the detected ones
+    #[serde(skip)]
+    pub betas: Vec<String>,
+
+    /// Transport settings that are sent as headers rather than in the body
+    #[serde(skip)]
+    pub options: RequestOptions,
 }
 
+/// A remote MCP server the API connects to on Claude's behalf
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct McpServer {
+    /// Always `url`, the only kind of server the connector supports
+    #[serde(rename = "type")]
+    pub server_type: String,
+    pub url: String,
+    /// Name the server's tool uses are reported under
+    pub name: String,
+    /// OAuth bearer token sent to the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorization_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_configuration: Option<McpToolConfiguration>,
+}
+
+/// Which of an MCP server's tools Claude may use
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct McpToolConfiguration {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// Only these tools are offered; all of them when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_tools: Option<Vec<String>>,
+}
+
+impl McpServer {
+    /// A server reachable at `url`, reported as `name`
+    pub fn new(name: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            server_type: "url".to_string(),
+            url: url.into(),
+            name: name.into(),
+            authorization_token: None,
+            tool_configuration: None,
+        }
+    }
+
+    /// Authenticate to the server with an OAuth bearer token
+    pub fn with_authorization_token(mut self, token: impl Into<String>) -> Self {
+        self.authorization_token = Some(token.into());
+        self
+    }
+
+    /// Offer only these of the server's tools
+    pub fn with_allowed_tools<S: Into<String>>(
+        mut self,
+        tools: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.tool_configuration
+            .get_or_insert_with(McpToolConfiguration::default)
+            .allowed_tools = Some(tools.into_iter().map(Into::into).collect());
+        self
+    }
+}
+
+/// Per-request settings sent outside the request body
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestOptions {
+    /// Sent as the `Idempotency-Key` header on every attempt, so proxies and gateways that
+    /// support idempotency don't create a second completion when a retry follows a
+    /// response lost to the network. Recorded in the request journal.
+    pub idempotency_key: Option<String>,
+}
+
+impl RequestOptions {
+    /// Options with nothing set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the idempotency key
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Headers these options add to the request
+    pub(crate) fn headers(&self) -> HashMap<String, String> {
+        self.idempotency_key
+            .iter()
+            .map(|key| (IDEMPOTENCY_KEY_HEADER.to_string(), key.clone()))
+            .collect()
+    }
+}
+
+/// Header carrying [`RequestOptions::idempotency_key`]
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Extended thinking settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ThinkingConfig {
+    /// Think before answering, using up to `budget_tokens` (at least 1024)
+    Enabled {
+        budget_tokens: u32,
+    },
+    Disabled,
+}
+
+/// Smallest thinking budget the API accepts
+pub const MIN_THINKING_BUDGET: u32 = 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum SystemPrompt {
@@ -198,6 +902,40 @@ pub struct MessageResponse {
     pub stop_reason: Option<StopReason>,
     pub stop_sequence: Option<String>,
     pub usage: Usage,
+    /// Response headers, present when the client captures them
+    #[serde(skip)]
+    pub headers: Option<HashMap<String, String>>,
+    /// Fields this SDK doesn't know about yet
+    #[serde(flatten, default, skip_serializing_if = "Map::is_empty")]
+    pub extra: Map<String, Value>,
+}
+
+impl MessageResponse {
+    /// Names of fields captured in `extra`, with usage fields prefixed by `usage.`
+    ///
+    /// Unrecognized content blocks and stop reasons are listed as `content.{type}` and
+    /// `stop_reason.{value}`.
+    pub fn unknown_fields(&self) -> Vec<String> {
+        let usage = self.usage.extra.keys().map(|key| format!("usage.{}", key));
+        let blocks = self.content.iter().filter_map(|block| match block {
+            ResponseContentBlock::Unknown(raw) => Some(format!(
+                "content.{}",
+                raw.get("type").and_then(Value::as_str).unwrap_or("?")
+            )),
+            _ => None,
+        });
+        let stop_reason = match self.stop_reason {
+            Some(StopReason::Other(ref reason)) => Some(format!("stop_reason.{}", reason)),
+            _ => None,
+        };
+        self.extra
+            .keys()
+            .cloned()
+            .chain(usage)
+            .chain(blocks)
+            .chain(stop_reason)
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -214,15 +952,76 @@ pub enum ResponseContentBlock {
         name: String,
         input: Value,
     },
+    /// A tool the API runs itself, such as web search
+    ServerToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    WebSearchToolResult {
+        tool_use_id: String,
+        content: Value,
+    },
+    WebFetchToolResult {
+        tool_use_id: String,
+        content: Value,
+    },
+    /// Output of the code execution tool
+    CodeExecutionToolResult {
+        tool_use_id: String,
+        content: Value,
+    },
+    /// Output of a shell command run by the code execution tool
+    BashCodeExecutionToolResult {
+        tool_use_id: String,
+        content: Value,
+    },
+    /// Result of a file edit made by the code execution tool
+    TextEditorCodeExecutionToolResult {
+        tool_use_id: String,
+        content: Value,
+    },
+    /// A call to a tool on one of the request's MCP servers, made by the API
+    McpToolUse {
+        id: String,
+        name: String,
+        server_name: String,
+        input: Value,
+    },
+    McpToolResult {
+        tool_use_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        is_error: Option<bool>,
+        content: Value,
+    },
+    /// Claude's extended thinking, signed so it can be sent back in later turns
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    /// Thinking the safety systems encrypted; still sent back like `Thinking`
+    RedactedThinking {
+        data: String,
+    },
+    /// A block type this SDK doesn't know yet, kept as the raw JSON
+    #[serde(untagged)]
+    Unknown(Value),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum StopReason {
     EndTurn,
     MaxTokens,
     StopSequence,
     ToolUse,
+    /// A long-running server tool paused the turn; send the conversation again to continue
+    PauseTurn,
+    /// Claude declined to continue for safety reasons
+    Refusal,
+    /// A stop reason this SDK doesn't know yet
+    #[serde(untagged)]
+    Other(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -233,13 +1032,221 @@ pub struct Usage {
     pub cache_creation_input_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cache_read_input_tokens: Option<u32>,
+    /// `cache_creation_input_tokens` split by cache TTL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_creation: Option<CacheCreationUsage>,
+    /// Requests made by server tools such as web search, billed separately from tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_tool_use: Option<ServerToolUsage>,
+    /// The tier that served the request, e.g. `standard`, `priority`, or `batch`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_tier: Option<String>,
+    /// Fields this SDK doesn't know about yet
+    #[serde(flatten, default, skip_serializing_if = "Map::is_empty")]
+    pub extra: Map<String, Value>,
 }
 
+/// Prompt cache writes split by TTL
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheCreationUsage {
+    #[serde(default)]
+    pub ephemeral_1h_input_tokens: u64,
+    #[serde(default)]
+    pub ephemeral_5m_input_tokens: u64,
+}
+
+/// Server tool requests made while generating a response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerToolUsage {
+    #[serde(default)]
+    pub web_search_requests: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub web_fetch_requests: Option<u32>,
+}
+
+/// Request fields left out of [`CreateMessageRequest::canonical_hash`] since they don't
+/// change what the model is asked
+pub const VOLATILE_REQUEST_FIELDS: &[&str] = &["metadata", "stream"];
+
 impl CreateMessageRequest {
+    /// A stable hash of what the request asks for, as 16 hex digits
+    ///
+    /// Object keys are sorted and [`VOLATILE_REQUEST_FIELDS`] are skipped, so equal requests
+    /// hash equally across processes and SDK builds. Useful as an app-level cache key.
+    pub fn canonical_hash(&self) -> Result<String, AnthropicError> {
+        let mut value = serde_json::to_value(self)?;
+        if let Value::Object(ref mut fields) = value {
+            for field in VOLATILE_REQUEST_FIELDS {
+                fields.remove(*field);
+            }
+        }
+        let mut canonical = String::new();
+        write_canonical(&value, &mut canonical);
+        Ok(format!("{:016x}", fnv1a(canonical.as_bytes())))
+    }
+
+    /// Fail if the serialized request is over `limit` bytes, naming its largest content block
+    pub fn check_size(&self, limit: usize) -> Result<(), AnthropicError> {
+        self.check_serialized_size(serde_json::to_vec(self)?.len(), limit)
+    }
+
+    pub(crate) fn check_serialized_size(
+        &self,
+        size: usize,
+        limit: usize,
+    ) -> Result<(), AnthropicError> {
+        if size <= limit {
+            return Ok(());
+        }
+
+        let largest = self
+            .messages
+            .iter()
+            .enumerate()
+            .filter_map(|(index, message)| match &message.content {
+                Content::Blocks(blocks) => Some(blocks.iter().map(move |block| (index, block))),
+                Content::Text(_) => None,
+            })
+            .flatten()
+            .map(|(index, block)| {
+                let bytes = serde_json::to_vec(block).map_or(0, |b| b.len());
+                (bytes, index, block.kind())
+            })
+            .max_by_key(|(bytes, _, _)| *bytes);
+
+        let hint = match largest {
+            Some((bytes, index, kind)) => format!(
+                "largest block is a {:.1} MB {} in message {}",
+                bytes as f64 / (1024.0 * 1024.0),
+                kind,
+                index
+            ),
+            None => "the request has no content blocks; check message text and tools".to_string(),
+        };
+        Err(AnthropicError::RequestTooLarge { size, limit, hint })
+    }
+
+    /// Set `max_tokens` to a sensible default for the model, never above its output ceiling
+    pub fn with_default_max_tokens(mut self) -> Self {
+        self.max_tokens = match model_info(&self.model) {
+            Some(info) => DEFAULT_MAX_TOKENS.min(info.max_output_tokens),
+            None => DEFAULT_MAX_TOKENS,
+        };
+        self
+    }
+
+    /// Fail if `max_tokens` is over the model's output ceiling; unknown models always pass
+    pub fn check_max_tokens(&self) -> Result<(), AnthropicError> {
+        match model_info(&self.model) {
+            Some(info) if self.max_tokens > info.max_output_tokens => {
+                Err(AnthropicError::MaxTokensExceeded {
+                    model: self.model.clone(),
+                    requested: self.max_tokens,
+                    limit: info.max_output_tokens,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fail if the estimated prompt plus `max_tokens` won't fit the model's context window
+    ///
+    /// Uses the offline estimator, so it catches clear overruns rather than exact ones.
+    /// Unknown models always pass.
+    pub fn check_context_window(&self) -> Result<(), AnthropicError> {
+        let Some(info) = model_info(&self.model) else {
+            return Ok(());
+        };
+        let needed = estimate_request_tokens(self).saturating_add(self.max_tokens);
+        if needed > info.context_window {
+            return Err(AnthropicError::ContextWindowExceeded {
+                needed,
+                available: info.context_window,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check the request locally before sending it
+    ///
+    /// Covers sampling ranges, `temperature` combined with `top_p` on models that reject
+    /// it, and stop sequence limits, failing with [`AnthropicError::InvalidRequest`].
+    pub fn validate(&self) -> Result<(), AnthropicError> {
+        self.sampling().validate()?;
+        if self.temperature.is_some() && self.top_p.is_some() {
+            if let Some(info) = model_info(&self.model).filter(|info| info.exclusive_sampling) {
+                return Err(AnthropicError::InvalidRequest(format!(
+                    "{} accepts temperature or top_p, not both",
+                    info.id
+                )));
+            }
+        }
+        self.check_thinking()?;
+        self.check_stop_sequences()
+    }
+
+    /// Fail if the thinking budget is out of range or sampling settings conflict with it
+    pub fn check_thinking(&self) -> Result<(), AnthropicError> {
+        let Some(ThinkingConfig::Enabled { budget_tokens }) = self.thinking else {
+            return Ok(());
+        };
+        if budget_tokens < MIN_THINKING_BUDGET {
+            return Err(AnthropicError::InvalidRequest(format!(
+                "thinking budget {} is under the {} token minimum",
+                budget_tokens, MIN_THINKING_BUDGET
+            )));
+        }
+        // Interleaved thinking may spend the budget across the whole turn
+        let interleaved = self
+            .betas
+            .iter()
+            .any(|beta| beta == INTERLEAVED_THINKING_BETA);
+        if budget_tokens >= self.max_tokens && !interleaved {
+            return Err(AnthropicError::InvalidRequest(format!(
+                "thinking budget {} must be less than max_tokens {}",
+                budget_tokens, self.max_tokens
+            )));
+        }
+        if self.temperature.is_some_and(|t| t != 1.0) || self.top_k.is_some() {
+            return Err(AnthropicError::InvalidRequest(
+                "thinking can't be combined with temperature or top_k".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fail if the stop sequences are over the count or length limits, or any is blank
+    pub fn check_stop_sequences(&self) -> Result<(), AnthropicError> {
+        let Some(ref sequences) = self.stop_sequences else {
+            return Ok(());
+        };
+        if sequences.len() > MAX_STOP_SEQUENCES {
+            return Err(AnthropicError::InvalidRequest(format!(
+                "{} stop sequences set, at most {} are allowed",
+                sequences.len(),
+                MAX_STOP_SEQUENCES
+            )));
+        }
+        for sequence in sequences {
+            if sequence.trim().is_empty() {
+                return Err(AnthropicError::InvalidRequest(
+                    "stop sequences must contain non-whitespace characters".to_string(),
+                ));
+            }
+            if sequence.chars().count() > MAX_STOP_SEQUENCE_CHARS {
+                return Err(AnthropicError::InvalidRequest(format!(
+                    "stop sequence {:?} is over {} characters",
+                    sequence, MAX_STOP_SEQUENCE_CHARS
+                )));
+            }
+        }
+        Ok(())
+    }
+
     pub fn new(model: impl Into<String>, messages: Vec<Message>, max_tokens: u32) -> Self {
         Self {
             model: model.into(),
-            messages,
+            messages: messages.into_iter().map(Arc::new).collect(),
             max_tokens,
             system: None,
             metadata: None,
@@ -250,6 +1257,10 @@ impl CreateMessageRequest {
             top_k: None,
             tools: None,
             tool_choice: None,
+            thinking: None,
+            mcp_servers: None,
+            betas: Vec::new(),
+            options: RequestOptions::default(),
         }
     }
 
@@ -258,6 +1269,190 @@ impl CreateMessageRequest {
         self
     }
 
+    /// Let Claude call the tools of a remote MCP server
+    pub fn with_mcp_server(mut self, server: McpServer) -> Self {
+        self.mcp_servers.get_or_insert_with(Vec::new).push(server);
+        self
+    }
+
+    /// Set the system prompt as either plain text or blocks
+    pub fn with_system_prompt(mut self, system: SystemPrompt) -> Self {
+        self.system = Some(system);
+        self
+    }
+
+    /// Replace the request metadata
+    pub fn with_metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Tag the request with an opaque end-user id, which the API uses for abuse detection
+    pub fn with_user_id(mut self, user_id: impl Into<String>) -> Self {
+        self.metadata
+            .get_or_insert_with(HashMap::new)
+            .insert("user_id".to_string(), user_id.into());
+        self
+    }
+
+    /// Enable extended thinking with a token budget
+    pub fn with_thinking(mut self, budget_tokens: u32) -> Self {
+        self.thinking = Some(ThinkingConfig::Enabled { budget_tokens });
+        self
+    }
+
+    /// Enable extended thinking between tool calls, adding the interleaved-thinking beta
+    pub fn with_interleaved_thinking(self, budget_tokens: u32) -> Self {
+        self.with_thinking(budget_tokens)
+            .with_beta(INTERLEAVED_THINKING_BETA)
+    }
+
+    /// Stream tool inputs as Claude writes them rather than in validated chunks
+    ///
+    /// Deltas arrive sooner, but a tool input cut off by `max_tokens` may not be valid
+    /// JSON. Use [`ToolInputAccumulator`](crate::streaming::ToolInputAccumulator) to read
+    /// partial arguments while streaming.
+    pub fn with_fine_grained_tool_streaming(self) -> Self {
+        self.with_beta(FINE_GRAINED_TOOL_STREAMING_BETA)
+    }
+
+    /// Have Claude write tool calls in fewer output tokens
+    ///
+    /// Adds the token-efficient tools beta when it can apply: to Claude 3.7 Sonnet requests
+    /// with tools, unless parallel tool use is disabled, which the beta doesn't support.
+    /// Claude 4 and later models are already token-efficient, so nothing changes for them.
+    /// Call it after setting the tools and tool choice.
+    pub fn with_token_efficient_tools(self) -> Self {
+        let applies = self.model.starts_with("claude-3-7-sonnet")
+            && self.tools.as_ref().is_some_and(|tools| !tools.is_empty())
+            && !self
+                .tool_choice
+                .as_ref()
+                .is_some_and(|choice| choice.is_parallel_disabled());
+        match applies {
+            true => self.with_beta(TOKEN_EFFICIENT_TOOLS_BETA),
+            false => self,
+        }
+    }
+
+    /// Send an extra `anthropic-beta` value with this request
+    pub fn with_beta(mut self, beta: impl Into<String>) -> Self {
+        let beta = beta.into();
+        if !self.betas.contains(&beta) {
+            self.betas.push(beta);
+        }
+        self
+    }
+
+    /// Cache the request's prefix with the given TTL
+    ///
+    /// A breakpoint goes after the system prompt (or the tools, without one) and another on
+    /// the last message, as far as [`MAX_CACHE_BREAKPOINTS`] allows. Breakpoints already in
+    /// the request are given the same TTL, since the API requires longer TTLs to come
+    /// before shorter ones. One-hour caching adds its beta.
+    pub fn with_cache_ttl(mut self, ttl: CacheTtl) -> Self {
+        let cache_control = ttl.cache_control();
+        let mut breakpoints = self.retime_cache_controls(&cache_control);
+
+        if breakpoints < MAX_CACHE_BREAKPOINTS {
+            let marked = match &mut self.system {
+                Some(SystemPrompt::Text(text)) => {
+                    let block = SystemPromptBlock::text(std::mem::take(text))
+                        .with_cache_control(cache_control.clone());
+                    self.system = Some(SystemPrompt::Blocks(vec![block]));
+                    true
+                }
+                Some(SystemPrompt::Blocks(blocks)) => mark_last(
+                    blocks.last_mut().map(|b| &mut b.cache_control),
+                    &cache_control,
+                ),
+                None => mark_last(
+                    self.tools
+                        .iter_mut()
+                        .flatten()
+                        .last()
+                        .map(|t| &mut t.cache_control),
+                    &cache_control,
+                ),
+            };
+            if marked {
+                breakpoints += 1;
+            }
+        }
+
+        if breakpoints < MAX_CACHE_BREAKPOINTS {
+            if let Some(last) = self.messages.last_mut() {
+                let message = Arc::make_mut(last);
+                if let Content::Text(text) = &mut message.content {
+                    message.content = Content::Blocks(vec![ContentBlock::Text {
+                        text: std::mem::take(text),
+                        cache_control: None,
+                        citations: None,
+                    }]);
+                }
+                if let Content::Blocks(blocks) = &mut message.content {
+                    let last_cacheable =
+                        blocks.iter_mut().rev().find_map(|b| b.cache_control_mut());
+                    mark_last(last_cacheable, &cache_control);
+                }
+            }
+        }
+
+        match ttl {
+            CacheTtl::OneHour => self.with_beta(EXTENDED_CACHE_TTL_BETA),
+            CacheTtl::FiveMinutes => self,
+        }
+    }
+
+    /// Give every existing cache breakpoint `cache_control`, returning how many there are
+    fn retime_cache_controls(&mut self, cache_control: &CacheControl) -> usize {
+        let mut count = 0;
+        let mut retime = |slot: &mut Option<CacheControl>| {
+            if slot.is_some() {
+                *slot = Some(cache_control.clone());
+                count += 1;
+            }
+        };
+        for tool in self.tools.iter_mut().flatten() {
+            retime(&mut tool.cache_control);
+        }
+        if let Some(SystemPrompt::Blocks(blocks)) = &mut self.system {
+            for block in blocks {
+                retime(&mut block.cache_control);
+            }
+        }
+        for message in &mut self.messages {
+            let marked = match &message.content {
+                Content::Blocks(blocks) => blocks.iter().any(has_cache_control),
+                Content::Text(_) => false,
+            };
+            if marked {
+                if let Content::Blocks(blocks) = &mut Arc::make_mut(message).content {
+                    retime_blocks(blocks, &mut retime);
+                }
+            }
+        }
+        count
+    }
+
+    /// Set the transport options, such as an idempotency key
+    pub fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Send an `Idempotency-Key` header with this request and each of its retries
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.options.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Set the `stream` flag; the client's send and stream methods override it
+    pub fn with_stream(mut self, stream: bool) -> Self {
+        self.stream = Some(stream);
+        self
+    }
+
     pub fn with_system_blocks(mut self, blocks: Vec<SystemPromptBlock>) -> Self {
         self.system = Some(SystemPrompt::Blocks(blocks));
         self
@@ -273,8 +1468,73 @@ impl CreateMessageRequest {
         self
     }
 
+    /// Set the sampling temperature, between 0 and 1; checked by [`validate`](Self::validate)
     pub fn with_temperature(mut self, temperature: f32) -> Self {
         self.temperature = Some(temperature);
         self
     }
+
+    /// Set nucleus sampling, between 0 and 1; newer models reject this alongside
+    /// `temperature`
+    ///
+    /// The range is checked by [`validate`](Self::validate) before sending.
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    /// Sample only from the `top_k` most likely tokens; must be at least 1
+    ///
+    /// Checked by [`validate`](Self::validate) before sending; thinking rejects it.
+    pub fn with_top_k(mut self, top_k: u32) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    /// Stop generating when Claude outputs any of these sequences
+    pub fn with_stop_sequences<S: Into<String>>(
+        mut self,
+        sequences: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.stop_sequences = Some(sequences.into_iter().map(Into::into).collect());
+        self
+    }
+}
+
+/// Write JSON with object keys sorted, whatever order the map keeps them in
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(fields) => {
+            let mut keys: Vec<&String> = fields.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&fields[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// 64-bit FNV-1a, stable across processes and compiler versions
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
 }
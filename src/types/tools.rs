@@ -1,20 +1,28 @@
 use crate::types::messages::CacheControl;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
+use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tool {
     pub name: String,
+    /// Left out of the request when empty, as built-in tools require
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub description: String,
+    /// Left out of the request when empty, as built-in tools require
+    #[serde(default, skip_serializing_if = "InputSchema::is_empty")]
     pub input_schema: InputSchema,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "type")]
     pub tool_type: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cache_control: Option<CacheControl>,
+    /// Other fields of the definition, e.g. a built-in tool's `display_width_px`
+    #[serde(flatten)]
+    pub params: Map<String, Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct InputSchema {
     #[serde(rename = "type")]
     pub schema_type: String,
@@ -23,6 +31,13 @@ pub struct InputSchema {
     pub required: Option<Vec<String>>,
 }
 
+impl InputSchema {
+    /// Whether this is the placeholder schema of a tool that has none
+    pub fn is_empty(&self) -> bool {
+        self.schema_type.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
@@ -43,6 +58,90 @@ pub enum ToolChoice {
     None,
 }
 
+/// Versioned identifiers for Anthropic-defined tools, serialized as the API's `type` strings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BuiltinToolType {
+    #[serde(rename = "bash_20241022")]
+    Bash20241022,
+    #[serde(rename = "bash_20250124")]
+    Bash20250124,
+    #[serde(rename = "text_editor_20241022")]
+    TextEditor20241022,
+    #[serde(rename = "text_editor_20250124")]
+    TextEditor20250124,
+    #[serde(rename = "text_editor_20250429")]
+    TextEditor20250429,
+    #[serde(rename = "text_editor_20250728")]
+    TextEditor20250728,
+    #[serde(rename = "computer_20241022")]
+    Computer20241022,
+    #[serde(rename = "computer_20250124")]
+    Computer20250124,
+    #[serde(rename = "web_search_20250305")]
+    WebSearch20250305,
+    #[serde(rename = "web_fetch_20250910")]
+    WebFetch20250910,
+    #[serde(rename = "code_execution_20250522")]
+    CodeExecution20250522,
+    #[serde(rename = "code_execution_20250825")]
+    CodeExecution20250825,
+    #[serde(rename = "memory_20250818")]
+    Memory20250818,
+}
+
+impl BuiltinToolType {
+    /// The versioned `type` string sent to the API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BuiltinToolType::Bash20241022 => "bash_20241022",
+            BuiltinToolType::Bash20250124 => "bash_20250124",
+            BuiltinToolType::TextEditor20241022 => "text_editor_20241022",
+            BuiltinToolType::TextEditor20250124 => "text_editor_20250124",
+            BuiltinToolType::TextEditor20250429 => "text_editor_20250429",
+            BuiltinToolType::TextEditor20250728 => "text_editor_20250728",
+            BuiltinToolType::Computer20241022 => "computer_20241022",
+            BuiltinToolType::Computer20250124 => "computer_20250124",
+            BuiltinToolType::WebSearch20250305 => "web_search_20250305",
+            BuiltinToolType::WebFetch20250910 => "web_fetch_20250910",
+            BuiltinToolType::CodeExecution20250522 => "code_execution_20250522",
+            BuiltinToolType::CodeExecution20250825 => "code_execution_20250825",
+            BuiltinToolType::Memory20250818 => "memory_20250818",
+        }
+    }
+
+    /// The tool name the API expects to be paired with this type
+    pub fn tool_name(&self) -> &'static str {
+        match self {
+            BuiltinToolType::Bash20241022 | BuiltinToolType::Bash20250124 => "bash",
+            BuiltinToolType::TextEditor20241022 | BuiltinToolType::TextEditor20250124 => {
+                "str_replace_editor"
+            }
+            BuiltinToolType::TextEditor20250429 | BuiltinToolType::TextEditor20250728 => {
+                "str_replace_based_edit_tool"
+            }
+            BuiltinToolType::Computer20241022 | BuiltinToolType::Computer20250124 => "computer",
+            BuiltinToolType::WebSearch20250305 => "web_search",
+            BuiltinToolType::WebFetch20250910 => "web_fetch",
+            BuiltinToolType::CodeExecution20250522 | BuiltinToolType::CodeExecution20250825 => {
+                "code_execution"
+            }
+            BuiltinToolType::Memory20250818 => "memory",
+        }
+    }
+}
+
+impl fmt::Display for BuiltinToolType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<BuiltinToolType> for String {
+    fn from(tool_type: BuiltinToolType) -> Self {
+        tool_type.as_str().to_string()
+    }
+}
+
 impl Tool {
     pub fn new(
         name: impl Into<String>,
@@ -61,19 +160,109 @@ impl Tool {
             },
             tool_type,
             cache_control: None,
+            params: Map::new(),
         }
     }
 
+    /// An Anthropic-defined tool, named as its type requires and sent without a
+    /// description or schema
+    ///
+    /// Add the tool's settings with [`with_param`](Self::with_param), e.g.
+    /// `display_width_px` for computer use or `max_uses` for web search.
+    pub fn builtin(tool_type: BuiltinToolType) -> Self {
+        Self {
+            name: tool_type.tool_name().to_string(),
+            description: String::new(),
+            input_schema: InputSchema::default(),
+            tool_type: Some(tool_type.into()),
+            cache_control: None,
+            params: Map::new(),
+        }
+    }
+
+    /// Set another field of the definition
+    pub fn with_param(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.params.insert(key.into(), value.into());
+        self
+    }
+
     pub fn with_cache_control(mut self, cache_control: CacheControl) -> Self {
         self.cache_control = Some(cache_control);
         self
     }
 }
 
-impl Default for ToolChoice {
-    fn default() -> Self {
+impl ToolChoice {
+    /// Let Claude decide whether to use a tool
+    pub fn auto() -> Self {
         ToolChoice::Auto {
             disable_parallel_tool_use: None,
         }
     }
+
+    /// Require Claude to use one of the available tools
+    pub fn any() -> Self {
+        ToolChoice::Any {
+            disable_parallel_tool_use: None,
+        }
+    }
+
+    /// Require Claude to use the named tool
+    pub fn tool(name: impl Into<String>) -> Self {
+        ToolChoice::Tool {
+            name: name.into(),
+            disable_parallel_tool_use: None,
+        }
+    }
+
+    /// Prevent Claude from using any tools
+    pub fn none() -> Self {
+        ToolChoice::None
+    }
+
+    /// Limit Claude to at most one tool use per response (no effect on `None`)
+    pub fn with_parallel_disabled(self) -> Self {
+        self.with_parallel_tool_use(false)
+    }
+
+    /// Allow or forbid several tool uses in one response (no effect on `None`)
+    pub fn with_parallel_tool_use(mut self, parallel: bool) -> Self {
+        match &mut self {
+            ToolChoice::Auto {
+                disable_parallel_tool_use,
+            }
+            | ToolChoice::Any {
+                disable_parallel_tool_use,
+            }
+            | ToolChoice::Tool {
+                disable_parallel_tool_use,
+                ..
+            } => *disable_parallel_tool_use = Some(!parallel),
+            ToolChoice::None => {}
+        }
+        self
+    }
+
+    /// Whether the choice limits Claude to one tool use per response
+    pub fn is_parallel_disabled(&self) -> bool {
+        match self {
+            ToolChoice::Auto {
+                disable_parallel_tool_use,
+            }
+            | ToolChoice::Any {
+                disable_parallel_tool_use,
+            }
+            | ToolChoice::Tool {
+                disable_parallel_tool_use,
+                ..
+            } => *disable_parallel_tool_use == Some(true),
+            ToolChoice::None => false,
+        }
+    }
+}
+
+impl Default for ToolChoice {
+    fn default() -> Self {
+        ToolChoice::auto()
+    }
 }
@@ -0,0 +1,89 @@
+use crate::conversation::ConversationUpdate;
+use std::fmt;
+use std::sync::Arc;
+
+type ValidatorFn = dyn Fn(&ConversationUpdate) -> Result<(), String> + Send + Sync;
+
+/// A check applied to Claude's text responses in [`Conversation::send`](crate::Conversation::send)
+///
+/// When a validator rejects a response, the conversation sends a corrective follow-up that
+/// includes the rejection reason and asks Claude to try again.
+#[derive(Clone)]
+pub struct ResponseValidator {
+    name: String,
+    check: Arc<ValidatorFn>,
+}
+
+impl ResponseValidator {
+    /// Create a validator from a closure returning the rejection reason on failure
+    pub fn new<F>(name: impl Into<String>, check: F) -> Self
+    where
+        F: Fn(&ConversationUpdate) -> Result<(), String> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            check: Arc::new(check),
+        }
+    }
+
+    /// Require the response text to be valid JSON (a surrounding code fence is allowed)
+    pub fn json() -> Self {
+        Self::new("json", |update| {
+            let text = update.text();
+            serde_json::from_str::<serde_json::Value>(strip_code_fence(&text))
+                .map(|_| ())
+                .map_err(|e| format!("the response must be valid JSON ({})", e))
+        })
+    }
+
+    /// Require the response text to contain a fenced code block
+    pub fn code_block() -> Self {
+        Self::new("code_block", |update| {
+            if update.text().matches("```").count() >= 2 {
+                Ok(())
+            } else {
+                Err("the response must contain a fenced code block".to_string())
+            }
+        })
+    }
+
+    /// Require the response text to contain the given substring
+    pub fn contains(needle: impl Into<String>) -> Self {
+        let needle = needle.into();
+        Self::new("contains", move |update| {
+            if update.text().contains(&needle) {
+                Ok(())
+            } else {
+                Err(format!("the response must contain \"{}\"", needle))
+            }
+        })
+    }
+
+    /// The validator's name, used in diagnostics
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Run the check against a response
+    pub fn validate(&self, update: &ConversationUpdate) -> Result<(), String> {
+        (self.check)(update)
+    }
+}
+
+impl fmt::Debug for ResponseValidator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseValidator")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+pub(crate) fn strip_code_fence(text: &str) -> &str {
+    let trimmed = text.trim();
+    let Some(rest) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    // Skip the optional language tag on the opening fence
+    let body = rest.split_once('\n').map_or("", |(_, body)| body);
+    body.strip_suffix("```").unwrap_or(body).trim()
+}
@@ -0,0 +1,92 @@
+use crate::types::messages::{Content, ContentBlock, CreateMessageRequest};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A known value of the `anthropic-version` header, oldest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ApiVersion {
+    /// The initial release, predating the Messages API's streaming event format
+    #[serde(rename = "2023-01-01")]
+    V2023_01_01,
+    /// Named SSE streaming events; required by every Messages API feature
+    #[serde(rename = "2023-06-01")]
+    V2023_06_01,
+}
+
+impl ApiVersion {
+    /// The version the client sends by default
+    pub const LATEST: ApiVersion = ApiVersion::V2023_06_01;
+
+    /// The header value, e.g. `2023-06-01`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiVersion::V2023_01_01 => "2023-01-01",
+            ApiVersion::V2023_06_01 => "2023-06-01",
+        }
+    }
+
+    /// Look up a header value, returning `None` for versions this SDK doesn't know
+    pub fn parse(version: &str) -> Option<Self> {
+        match version {
+            "2023-01-01" => Some(ApiVersion::V2023_01_01),
+            "2023-06-01" => Some(ApiVersion::V2023_06_01),
+            _ => None,
+        }
+    }
+
+    /// Features used by `request` that need a newer version than this one
+    pub fn unsupported_features(&self, request: &CreateMessageRequest) -> Vec<&'static str> {
+        required_versions(request)
+            .into_iter()
+            .filter(|(_, required)| required > self)
+            .map(|(feature, _)| feature)
+            .collect()
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<ApiVersion> for String {
+    fn from(version: ApiVersion) -> Self {
+        version.as_str().to_string()
+    }
+}
+
+/// Features a request uses, each with the oldest version that supports it
+fn required_versions(request: &CreateMessageRequest) -> Vec<(&'static str, ApiVersion)> {
+    let mut features = Vec::new();
+    if request.stream == Some(true) {
+        features.push(("streaming", ApiVersion::V2023_06_01));
+    }
+    if request
+        .tools
+        .as_ref()
+        .is_some_and(|tools| !tools.is_empty())
+    {
+        features.push(("tool use", ApiVersion::V2023_06_01));
+    }
+    if request.thinking.is_some() {
+        features.push(("extended thinking", ApiVersion::V2023_06_01));
+    }
+    // Every block type beyond plain text arrived with the Messages API
+    let blocks = request
+        .messages
+        .iter()
+        .filter_map(|message| match message.content {
+            Content::Blocks(ref blocks) => Some(blocks),
+            Content::Text(_) => None,
+        });
+    for block in blocks.flatten() {
+        let feature = block.kind();
+        if !matches!(block, ContentBlock::Text { .. })
+            && !features.iter().any(|(existing, _)| *existing == feature)
+        {
+            features.push((feature, ApiVersion::V2023_06_01));
+        }
+    }
+    features
+}
@@ -0,0 +1,360 @@
+use crate::clock::{Clock, ClockHandle};
+use crate::error::AnthropicError;
+use crate::storage::ConversationStore;
+use crate::types::messages::{
+    ContentBlock, CreateMessageRequest, ImageSource, Message, MessageResponse,
+};
+use hyperware_process_lib::vfs;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Timeout in seconds for VFS reads and writes
+const VFS_TIMEOUT: u64 = 5;
+
+/// Strings longer than this are cut in debug dumps unless configured otherwise
+const DEFAULT_MAX_STRING_CHARS: usize = 2000;
+
+/// Header name fragments whose values are never written to a debug dump
+const SENSITIVE_HEADERS: &[&str] = &["key", "token", "auth", "secret", "cookie"];
+
+impl ContentBlock {
+    /// Build an image block from a file in the Hyperware VFS, e.g. a user upload
+    ///
+    /// The media type is detected from the file contents, and the image is validated
+    /// against the API's size and format limits.
+    pub fn image_from_vfs(path: &str) -> Result<Self, AnthropicError> {
+        let bytes = vfs::open_file(path, false, Some(VFS_TIMEOUT))
+            .and_then(|file| file.read())
+            .map_err(|e| AnthropicError::Storage(format!("Failed to read `{}`: {}", path, e)))?;
+        Ok(ContentBlock::Image {
+            source: ImageSource::from_bytes(bytes)?,
+            cache_control: None,
+        })
+    }
+}
+
+/// Writes each request with its response or error as a JSON file in a VFS directory
+///
+/// Files are named `{unix_ms}-{sequence}.json`. The API key, credential-like headers and
+/// MCP server tokens are redacted, and long strings such as base64 images are truncated.
+/// Write failures never fail the request; they are reported on the warning channel as
+/// [`ClientWarning::DebugDumpFailed`](crate::ClientWarning::DebugDumpFailed).
+#[derive(Debug, Clone)]
+pub struct VfsDebugDump {
+    dir: String,
+    max_string_chars: usize,
+    sequence: Arc<AtomicU64>,
+}
+
+impl VfsDebugDump {
+    /// Dump into `dir`, e.g. `/my-app:publisher.os/debug`, creating it if needed
+    pub fn new(dir: impl Into<String>) -> Self {
+        Self {
+            dir: dir.into().trim_end_matches('/').to_string(),
+            max_string_chars: DEFAULT_MAX_STRING_CHARS,
+            sequence: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Truncate strings longer than this many characters (default 2000)
+    pub fn with_max_string_chars(mut self, max_string_chars: usize) -> Self {
+        self.max_string_chars = max_string_chars;
+        self
+    }
+
+    /// Build the JSON written for one exchange
+    pub fn render(
+        &self,
+        api_key: &str,
+        headers: &HashMap<String, String>,
+        request: &CreateMessageRequest,
+        outcome: Result<&MessageResponse, &AnthropicError>,
+        latency: Duration,
+    ) -> Value {
+        let headers: HashMap<&str, &str> = headers
+            .iter()
+            .map(|(name, value)| {
+                let lower = name.to_ascii_lowercase();
+                if SENSITIVE_HEADERS.iter().any(|s| lower.contains(s)) {
+                    (name.as_str(), "[redacted]")
+                } else {
+                    (name.as_str(), value.as_str())
+                }
+            })
+            .collect();
+        let key_suffix: String = api_key
+            .chars()
+            .rev()
+            .take(4)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+
+        let mut request = json!(request);
+        if let Some(servers) = request["mcp_servers"].as_array_mut() {
+            for server in servers
+                .iter_mut()
+                .filter(|s| s.get("authorization_token").is_some())
+            {
+                server["authorization_token"] = json!("[redacted]");
+            }
+        }
+        truncate_strings(&mut request, self.max_string_chars);
+        let mut dump = json!({
+            "latency_ms": latency.as_millis() as u64,
+            "api_key": format!("[redacted, ends in {}]", key_suffix),
+            "headers": headers,
+            "request": request,
+        });
+        match outcome {
+            Ok(response) => {
+                let mut response = json!(response);
+                truncate_strings(&mut response, self.max_string_chars);
+                dump["response"] = response;
+            }
+            Err(error) => dump["error"] = json!({ "message": error.to_string(), "detail": error }),
+        }
+        dump
+    }
+
+    /// Write a dump, returning the path and the reason if that fails
+    pub(crate) fn write(
+        &self,
+        api_key: &str,
+        headers: &HashMap<String, String>,
+        request: &CreateMessageRequest,
+        outcome: Result<&MessageResponse, &AnthropicError>,
+        latency: Duration,
+    ) -> Result<(), (String, String)> {
+        let dump = self.render(api_key, headers, request, outcome, latency);
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let path = format!("{}/{}-{:04}.json", self.dir, now_ms, sequence);
+
+        let written = serde_json::to_vec_pretty(&dump)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| {
+                vfs::open_dir(&self.dir, true, Some(VFS_TIMEOUT))
+                    .and_then(|_| vfs::create_file(&path, Some(VFS_TIMEOUT)))
+                    .and_then(|file| file.write(&bytes))
+                    .map_err(|e| e.to_string())
+            });
+        written.map_err(|e| (path, e))
+    }
+}
+
+/// Stores each conversation as a JSON Lines file in a VFS directory, one message per line
+///
+/// New messages are appended to the file instead of rewriting it, so saving a long, growing
+/// history only costs what was added. A last line cut short by a crash mid-append is
+/// skipped when loading, and the next save rewrites the file instead of appending after it.
+/// The time of each save is kept next to the history in `{id}.saved_at`, for
+/// [`ExpiryPolicy`](crate::ExpiryPolicy).
+#[derive(Debug, Clone)]
+pub struct VfsStore {
+    dir: String,
+    clock: ClockHandle,
+}
+
+impl VfsStore {
+    /// Store files in `dir`, e.g. `/my-app:publisher.os/conversations`, creating it if needed
+    pub fn new(dir: impl Into<String>) -> Self {
+        Self {
+            dir: dir.into().trim_end_matches('/').to_string(),
+            clock: ClockHandle::default(),
+        }
+    }
+
+    /// Time saves with `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = ClockHandle::new(clock);
+        self
+    }
+
+    /// Parse the contents of a history file
+    ///
+    /// A last line that isn't a message, e.g. because a crash cut it short, is skipped.
+    /// Also returns whether the file ends in a complete line, which appending requires.
+    pub fn parse_lines(bytes: &[u8]) -> Result<(Vec<Arc<Message>>, bool), AnthropicError> {
+        let text = String::from_utf8_lossy(bytes);
+        let lines: Vec<&str> = text.lines().filter(|line| !line.is_empty()).collect();
+        let mut messages = Vec::with_capacity(lines.len());
+        let mut clean = ends_cleanly(bytes);
+        for (index, line) in lines.iter().enumerate() {
+            match serde_json::from_str::<Message>(line) {
+                Ok(message) => messages.push(Arc::new(message)),
+                Err(_) if index + 1 == lines.len() => clean = false,
+                Err(e) => {
+                    return Err(AnthropicError::Storage(format!(
+                        "Line {} is not a message: {}",
+                        index + 1,
+                        e
+                    )))
+                }
+            }
+        }
+        Ok((messages, clean))
+    }
+
+    fn path(&self, id: &str) -> Result<String, AnthropicError> {
+        if id.is_empty() || id.contains('/') || id.starts_with('.') {
+            return Err(AnthropicError::Storage(format!(
+                "`{}` can't be used as a file name",
+                id
+            )));
+        }
+        Ok(format!("{}/{}.jsonl", self.dir, id))
+    }
+
+    fn saved_at_path(&self, id: &str) -> Result<String, AnthropicError> {
+        let path = self.path(id)?;
+        Ok(format!("{}.saved_at", path.trim_end_matches(".jsonl")))
+    }
+
+    /// Record that a history was saved just now
+    fn touch(&self, id: &str) -> Result<(), AnthropicError> {
+        let path = self.saved_at_path(id)?;
+        vfs::create_file(&path, Some(VFS_TIMEOUT))
+            .and_then(|file| file.write(self.clock.now_ms().to_string().as_bytes()))
+            .map_err(|e| AnthropicError::Storage(format!("Failed to write `{}`: {}", path, e)))
+    }
+
+    /// Whether an open history file is empty or ends in a newline
+    fn ends_in_newline(file: &mut vfs::File) -> Result<bool, vfs::VfsError> {
+        if file.metadata()?.len == 0 {
+            return Ok(true);
+        }
+        file.seek(vfs::SeekFrom::End(-1))?;
+        let mut last = [0u8; 1];
+        file.read_at(&mut last)?;
+        Ok(ends_cleanly(&last))
+    }
+}
+
+impl ConversationStore for VfsStore {
+    fn load(&self, id: &str) -> Result<Option<Vec<Arc<Message>>>, AnthropicError> {
+        let path = self.path(id)?;
+        let Ok(file) = vfs::open_file(&path, false, Some(VFS_TIMEOUT)) else {
+            return Ok(None);
+        };
+        let bytes = file
+            .read()
+            .map_err(|e| AnthropicError::Storage(format!("Failed to read `{}`: {}", path, e)))?;
+        let (messages, _) = Self::parse_lines(&bytes).map_err(|e| match e {
+            AnthropicError::Storage(message) => {
+                AnthropicError::Storage(format!("`{}`: {}", path, message))
+            }
+            e => e,
+        })?;
+        Ok(Some(messages))
+    }
+
+    fn save(&self, id: &str, messages: &[Arc<Message>]) -> Result<(), AnthropicError> {
+        let path = self.path(id)?;
+        let bytes = to_lines(messages)?;
+        vfs::open_dir(&self.dir, true, Some(VFS_TIMEOUT))
+            .and_then(|_| vfs::create_file(&path, Some(VFS_TIMEOUT)))
+            .and_then(|file| file.write(&bytes))
+            .map_err(|e| AnthropicError::Storage(format!("Failed to write `{}`: {}", path, e)))?;
+        self.touch(id)
+    }
+
+    /// Appends unless the file ends in a torn line, in which case it returns `false` so the
+    /// caller rewrites the file
+    fn append(&self, id: &str, messages: &[Arc<Message>]) -> Result<bool, AnthropicError> {
+        let path = self.path(id)?;
+        let Ok(mut file) = vfs::open_file(&path, false, Some(VFS_TIMEOUT)) else {
+            return Ok(false);
+        };
+        let clean = Self::ends_in_newline(&mut file)
+            .map_err(|e| AnthropicError::Storage(format!("Failed to read `{}`: {}", path, e)))?;
+        if !clean {
+            return Ok(false);
+        }
+        file.append(&to_lines(messages)?).map_err(|e| {
+            AnthropicError::Storage(format!("Failed to append to `{}`: {}", path, e))
+        })?;
+        self.touch(id)?;
+        Ok(true)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), AnthropicError> {
+        for path in [self.path(id)?, self.saved_at_path(id)?] {
+            if vfs::open_file(&path, false, Some(VFS_TIMEOUT)).is_err() {
+                continue;
+            }
+            vfs::remove_file(&path, Some(VFS_TIMEOUT)).map_err(|e| {
+                AnthropicError::Storage(format!("Failed to delete `{}`: {}", path, e))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, AnthropicError> {
+        let entries = vfs::open_dir(&self.dir, true, Some(VFS_TIMEOUT))
+            .and_then(|dir| dir.read())
+            .map_err(|e| {
+                AnthropicError::Storage(format!("Failed to list `{}`: {}", self.dir, e))
+            })?;
+        Ok(entries
+            .iter()
+            .filter_map(|entry| {
+                let name = entry.path.rsplit('/').next()?;
+                name.strip_suffix(".jsonl").map(str::to_string)
+            })
+            .collect())
+    }
+
+    /// Read from `{id}.saved_at`; histories written before it existed never expire
+    fn last_saved(&self, id: &str) -> Result<Option<u64>, AnthropicError> {
+        let path = self.saved_at_path(id)?;
+        let Ok(file) = vfs::open_file(&path, false, Some(VFS_TIMEOUT)) else {
+            return Ok(None);
+        };
+        let bytes = file
+            .read()
+            .map_err(|e| AnthropicError::Storage(format!("Failed to read `{}`: {}", path, e)))?;
+        Ok(String::from_utf8_lossy(&bytes).trim().parse().ok())
+    }
+}
+
+/// Whether file contents are empty or end in a newline
+fn ends_cleanly(bytes: &[u8]) -> bool {
+    matches!(bytes.last(), None | Some(b'\n'))
+}
+
+/// Serialize messages as JSON Lines, each line ending in a newline
+fn to_lines(messages: &[Arc<Message>]) -> Result<Vec<u8>, AnthropicError> {
+    let mut bytes = Vec::new();
+    for message in messages {
+        serde_json::to_writer(&mut bytes, message)?;
+        bytes.push(b'\n');
+    }
+    Ok(bytes)
+}
+
+/// Cut every string in a JSON value to at most `max_chars` characters
+fn truncate_strings(value: &mut Value, max_chars: usize) {
+    match value {
+        Value::String(text) => {
+            if let Some((end, _)) = text.char_indices().nth(max_chars) {
+                let dropped = text[end..].chars().count();
+                text.truncate(end);
+                text.push_str(&format!("…[{} chars truncated]", dropped));
+            }
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| truncate_strings(item, max_chars)),
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|item| truncate_strings(item, max_chars)),
+        _ => {}
+    }
+}
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A non-fatal condition reported through
+/// [`AnthropicClient::with_warning_callback`](crate::AnthropicClient::with_warning_callback)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClientWarning {
+    /// The API signalled that the requested model is deprecated or sunsetting
+    Deprecation(DeprecationNotice),
+    /// The configured `anthropic-version` is not one this SDK knows
+    UnknownApiVersion(String),
+    /// A request uses a feature the configured `anthropic-version` predates
+    UnsupportedFeature {
+        feature: String,
+        api_version: String,
+    },
+    /// Less than a tenth of a rate limit is left, as reported by the response headers
+    ApproachingRateLimit {
+        /// Which limit, e.g. `requests`, `tokens`, `input-tokens`, or `output-tokens`
+        limit: String,
+        remaining: u64,
+        total: u64,
+    },
+    /// A request's `max_tokens` is over the model's ceiling and was sent anyway, under
+    /// [`MaxTokensPolicy::Warn`](crate::MaxTokensPolicy::Warn)
+    MaxTokensExceeded {
+        model: String,
+        requested: u32,
+        limit: u32,
+    },
+    /// A response stopped at its `max_tokens` before Claude finished
+    Truncated { model: String, max_tokens: u32 },
+    /// A response had fields this SDK doesn't know, kept in its `extra` maps
+    UnknownFields(Vec<String>),
+    /// A journaled request finished but its entry could not be marked complete, so it will
+    /// be reported as in flight
+    JournalNotCompleted { entry_id: String, error: String },
+    /// A debug dump could not be written to the VFS
+    DebugDumpFailed { path: String, error: String },
+    /// The outcome of [`send_message_notify`](crate::AnthropicClient::send_message_notify)
+    /// could not be delivered
+    NotificationFailed {
+        correlation_id: String,
+        error: String,
+    },
+}
+
+/// Fraction of a rate limit left below which [`ClientWarning::ApproachingRateLimit`] is sent
+pub const RATE_LIMIT_WARNING_FRACTION: f64 = 0.1;
+
+impl ClientWarning {
+    /// Read the `anthropic-ratelimit-*` headers, returning a warning for each limit that
+    /// is nearly used up
+    pub fn rate_limits_from_headers(headers: &HashMap<String, String>) -> Vec<Self> {
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .and_then(|(_, value)| value.trim().parse::<u64>().ok())
+        };
+        ["requests", "tokens", "input-tokens", "output-tokens"]
+            .into_iter()
+            .filter_map(|limit| {
+                let total = header(&format!("anthropic-ratelimit-{}-limit", limit))?;
+                let remaining = header(&format!("anthropic-ratelimit-{}-remaining", limit))?;
+                ((remaining as f64) < total as f64 * RATE_LIMIT_WARNING_FRACTION).then(|| {
+                    ClientWarning::ApproachingRateLimit {
+                        limit: limit.to_string(),
+                        remaining,
+                        total,
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+/// Deprecation metadata the API returned for a model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeprecationNotice {
+    /// The model named in the request
+    pub model: String,
+    /// Value of the `Deprecation` header (or another deprecation notice), if present
+    pub deprecation: Option<String>,
+    /// Value of the `Sunset` header, usually the retirement date, if present
+    pub sunset: Option<String>,
+}
+
+impl DeprecationNotice {
+    /// Read deprecation headers from a response, returning `None` if there are none
+    ///
+    /// Understands the standard `Deprecation` and `Sunset` headers, plus any other header
+    /// (including `Warning`) that mentions a deprecation.
+    pub fn from_headers(model: &str, headers: &HashMap<String, String>) -> Option<Self> {
+        let mut deprecation = None;
+        let mut sunset = None;
+        for (name, value) in headers {
+            let name = name.to_ascii_lowercase();
+            if name == "sunset" {
+                sunset = Some(value.clone());
+            } else if name == "deprecation"
+                || (deprecation.is_none()
+                    && (name.contains("deprecat")
+                        || (name == "warning" && value.to_ascii_lowercase().contains("deprecat"))))
+            {
+                deprecation = Some(value.clone());
+            }
+        }
+
+        if deprecation.is_none() && sunset.is_none() {
+            return None;
+        }
+        Some(Self {
+            model: model.to_string(),
+            deprecation,
+            sunset,
+        })
+    }
+}
@@ -0,0 +1,221 @@
+use crate::conversation::Conversation;
+use crate::tokens::{estimate_message_tokens, estimate_text_tokens, json_tokens};
+use crate::types::messages::{Content, ContentBlock, Role, SystemPrompt};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+/// Shortest text, in characters, worth reporting when it is repeated
+pub const MIN_REPEATED_CHARS: usize = 200;
+
+/// Assistant turns after which a tool that was never called counts as stale
+pub const STALE_TOOL_TURNS: usize = 4;
+
+/// Smallest prefix the API will cache, in tokens (Haiku models need 2048)
+pub const MIN_CACHEABLE_TOKENS: u32 = 1024;
+
+/// Share of the input price a cache read saves
+const CACHE_READ_SAVING: f64 = 0.9;
+
+/// Waste found in a conversation by [`Conversation::analyze_waste`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Waste {
+    /// A user message repeats the system prompt
+    DuplicatedSystemPrompt { message: usize, tokens: u32 },
+    /// The same paragraph appears in several user messages
+    RepeatedText {
+        preview: String,
+        messages: Vec<usize>,
+        tokens: u32,
+    },
+    /// A tool is sent with every request but Claude has never called it
+    StaleTool { name: String, tokens: u32 },
+    /// A large prefix that stays the same between turns is sent without a cache breakpoint
+    UncachedPrefix { tokens: u32 },
+}
+
+impl Waste {
+    /// Estimated input tokens saved on each request by fixing this
+    pub fn savings(&self) -> u32 {
+        match self {
+            Waste::DuplicatedSystemPrompt { tokens, .. } | Waste::StaleTool { tokens, .. } => {
+                *tokens
+            }
+            Waste::RepeatedText {
+                messages, tokens, ..
+            } => tokens * (messages.len() as u32).saturating_sub(1),
+            Waste::UncachedPrefix { tokens } => (*tokens as f64 * CACHE_READ_SAVING) as u32,
+        }
+    }
+
+    /// What to change to remove this waste
+    pub fn suggestion(&self) -> &'static str {
+        match self {
+            Waste::DuplicatedSystemPrompt { .. } => {
+                "Remove the system prompt from user messages; it is already sent once"
+            }
+            Waste::RepeatedText { .. } => {
+                "Keep boilerplate in the system prompt instead of repeating it each turn"
+            }
+            Waste::StaleTool { .. } => {
+                "Drop the tool with with_tools, or only register it when it is needed"
+            }
+            Waste::UncachedPrefix { .. } => {
+                "Call with_cache_ttl or add a cache breakpoint so the prefix is read from cache"
+            }
+        }
+    }
+}
+
+/// Everything [`Conversation::analyze_waste`] found, largest savings first
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WasteReport {
+    /// Estimated input tokens of the next request
+    pub total_tokens: u32,
+    pub findings: Vec<Waste>,
+}
+
+impl WasteReport {
+    /// Estimated input tokens saved on each request by fixing every finding
+    pub fn savings(&self) -> u32 {
+        self.findings.iter().map(Waste::savings).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+impl Conversation {
+    /// Scan the next request for tokens that are sent without need
+    ///
+    /// Reports user messages that repeat the system prompt, boilerplate repeated across
+    /// turns, tools never called after [`STALE_TOOL_TURNS`] turns, and large prefixes
+    /// sent without a cache breakpoint. Token counts use the offline heuristic.
+    pub fn analyze_waste(&self) -> WasteReport {
+        let request = self.build_request();
+        let system_text = match &request.system {
+            Some(SystemPrompt::Text(text)) => text.clone(),
+            Some(SystemPrompt::Blocks(blocks)) => blocks
+                .iter()
+                .map(|block| block.text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            None => String::new(),
+        };
+        let system_text = system_text.trim();
+        let mut findings = Vec::new();
+
+        // The user messages each long paragraph appears in, counting each message once
+        let mut paragraphs: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (index, message) in request.messages.iter().enumerate() {
+            if message.role != Role::User {
+                continue;
+            }
+            let texts: Vec<&str> = match &message.content {
+                Content::Text(text) => vec![text.as_str()],
+                Content::Blocks(blocks) => blocks
+                    .iter()
+                    .filter_map(|block| match block {
+                        ContentBlock::Text { text, .. } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect(),
+            };
+            let mut seen = HashSet::new();
+            for text in texts {
+                if system_text.len() >= MIN_REPEATED_CHARS && text.contains(system_text) {
+                    findings.push(Waste::DuplicatedSystemPrompt {
+                        message: index,
+                        tokens: estimate_text_tokens(system_text),
+                    });
+                }
+                for paragraph in text.split("\n\n").map(str::trim) {
+                    if paragraph.len() >= MIN_REPEATED_CHARS
+                        && !system_text.contains(paragraph)
+                        && seen.insert(paragraph)
+                    {
+                        paragraphs.entry(paragraph).or_default().push(index);
+                    }
+                }
+            }
+        }
+        findings.extend(
+            paragraphs
+                .into_iter()
+                .filter(|(_, messages)| messages.len() > 1)
+                .map(|(paragraph, messages)| Waste::RepeatedText {
+                    preview: paragraph.chars().take(60).collect(),
+                    messages,
+                    tokens: estimate_text_tokens(paragraph),
+                }),
+        );
+
+        let assistant_turns = request
+            .messages
+            .iter()
+            .filter(|message| message.role == Role::Assistant)
+            .count();
+        if assistant_turns >= STALE_TOOL_TURNS {
+            let called: HashSet<&str> = request
+                .messages
+                .iter()
+                .filter_map(|message| match &message.content {
+                    Content::Blocks(blocks) => Some(blocks),
+                    Content::Text(_) => None,
+                })
+                .flatten()
+                .filter_map(|block| match block {
+                    ContentBlock::ToolUse { name, .. }
+                    | ContentBlock::ServerToolUse { name, .. } => Some(name.as_str()),
+                    _ => None,
+                })
+                .collect();
+            findings.extend(
+                request
+                    .tools
+                    .iter()
+                    .flatten()
+                    .filter(|tool| !called.contains(tool.name.as_str()))
+                    .map(|tool| Waste::StaleTool {
+                        name: tool.name.clone(),
+                        tokens: json_tokens(tool),
+                    }),
+            );
+        }
+
+        let system_tokens = request.system.as_ref().map_or(0, json_tokens);
+        let tool_tokens = request.tools.as_ref().map_or(0, json_tokens);
+        let message_tokens: Vec<u32> = request
+            .messages
+            .iter()
+            .map(|message| estimate_message_tokens(message))
+            .collect();
+        // Everything but the newest message is resent unchanged next turn
+        let prefix = system_tokens + tool_tokens + message_tokens.iter().rev().skip(1).sum::<u32>();
+        let cached = request.system.as_ref().is_some_and(|system| match system {
+            SystemPrompt::Blocks(blocks) => blocks.iter().any(|b| b.cache_control.is_some()),
+            SystemPrompt::Text(_) => false,
+        }) || request
+            .tools
+            .iter()
+            .flatten()
+            .any(|tool| tool.cache_control.is_some())
+            || request
+                .messages
+                .iter()
+                .any(|message| match &message.content {
+                    Content::Blocks(blocks) => blocks.iter().any(|b| b.cache_control().is_some()),
+                    Content::Text(_) => false,
+                });
+        if !cached && prefix >= MIN_CACHEABLE_TOKENS {
+            findings.push(Waste::UncachedPrefix { tokens: prefix });
+        }
+
+        findings.sort_by_key(|waste| std::cmp::Reverse(waste.savings()));
+        WasteReport {
+            total_tokens: system_tokens + tool_tokens + message_tokens.iter().sum::<u32>(),
+            findings,
+        }
+    }
+}
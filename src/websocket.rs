@@ -0,0 +1,199 @@
+use crate::conversation::ConversationUpdate;
+use crate::error::AnthropicError;
+use crate::streaming::{ContentDelta, StreamEvent};
+use crate::types::messages::{ResponseContentBlock, StopReason, Usage};
+use hyperware_process_lib::http::server::{send_ws_push, WsMessageType};
+use hyperware_process_lib::LazyLoadBlob;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// JSON frame pushed to a frontend over a Hyperware WebSocket channel
+///
+/// Frames are internally tagged by `type`, e.g.
+/// `{"type":"text_delta","conversation_id":"c1","text":"Hel"}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsFrame {
+    /// A piece of assistant text to append to the message being displayed
+    TextDelta {
+        conversation_id: String,
+        text: String,
+    },
+    /// A streamed tool call started; its input follows as `tool_input_delta` frames
+    ToolUseStart {
+        conversation_id: String,
+        index: usize,
+        id: String,
+        name: String,
+    },
+    /// A piece of the JSON input of the tool call started at `index`
+    ToolInputDelta {
+        conversation_id: String,
+        index: usize,
+        partial_json: String,
+    },
+    /// The streamed response ended
+    MessageStop { conversation_id: String },
+    /// Claude requested a tool call
+    ToolUse {
+        conversation_id: String,
+        id: String,
+        name: String,
+        input: Value,
+    },
+    /// A response finished; `text` is the complete text of the response
+    Update {
+        conversation_id: String,
+        text: String,
+        stop_reason: Option<StopReason>,
+        usage: Usage,
+    },
+    /// The request failed; the fields are those of its [`ErrorPayload`](crate::ErrorPayload)
+    Error {
+        conversation_id: String,
+        code: String,
+        message: String,
+        retryable: bool,
+    },
+}
+
+/// Pushes [`WsFrame`]s for one conversation to one WebSocket channel
+#[derive(Debug, Clone)]
+pub struct WsForwarder {
+    channel_id: u32,
+    conversation_id: String,
+}
+
+impl WsForwarder {
+    /// Forward frames for `conversation_id` to the WebSocket channel `channel_id`
+    pub fn new(channel_id: u32, conversation_id: impl Into<String>) -> Self {
+        Self {
+            channel_id,
+            conversation_id: conversation_id.into(),
+        }
+    }
+
+    /// Push a single frame
+    pub fn send(&self, frame: &WsFrame) -> Result<(), AnthropicError> {
+        let bytes = serde_json::to_vec(frame)?;
+        send_ws_push(
+            self.channel_id,
+            WsMessageType::Text,
+            LazyLoadBlob {
+                mime: Some("application/json".to_string()),
+                bytes,
+            },
+        );
+        Ok(())
+    }
+
+    /// Push a text delta
+    pub fn send_text_delta(&self, text: impl Into<String>) -> Result<(), AnthropicError> {
+        self.send(&WsFrame::TextDelta {
+            conversation_id: self.conversation_id.clone(),
+            text: text.into(),
+        })
+    }
+
+    /// Push an error frame
+    pub fn send_error(&self, error: &AnthropicError) -> Result<(), AnthropicError> {
+        self.send(&self.frame_for_error(error))
+    }
+
+    /// The frame [`WsForwarder::send_error`] pushes, built from the error's
+    /// [`ErrorPayload`](crate::ErrorPayload) so internal details stay out of the frontend
+    pub fn frame_for_error(&self, error: &AnthropicError) -> WsFrame {
+        let payload = error.to_payload();
+        WsFrame::Error {
+            conversation_id: self.conversation_id.clone(),
+            code: payload.code,
+            message: payload.message,
+            retryable: payload.retryable,
+        }
+    }
+
+    /// Push the frame for one streaming event, if the frontend needs one
+    pub fn forward_event(&self, event: &StreamEvent) -> Result<(), AnthropicError> {
+        match self.frame_for_event(event) {
+            Some(frame) => self.send(&frame),
+            None => Ok(()),
+        }
+    }
+
+    /// The frame [`WsForwarder::forward_event`] pushes for `event`
+    ///
+    /// Text deltas, tool-use starts with their partial input, errors, and the end of the
+    /// message are forwarded; thinking, pings, and bookkeeping events are not.
+    pub fn frame_for_event(&self, event: &StreamEvent) -> Option<WsFrame> {
+        let conversation_id = self.conversation_id.clone();
+        match event {
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block:
+                    ResponseContentBlock::ToolUse { id, name, .. }
+                    | ResponseContentBlock::ServerToolUse { id, name, .. }
+                    | ResponseContentBlock::McpToolUse { id, name, .. },
+            } => Some(WsFrame::ToolUseStart {
+                conversation_id,
+                index: *index,
+                id: id.clone(),
+                name: name.clone(),
+            }),
+            StreamEvent::ContentBlockDelta {
+                delta: ContentDelta::TextDelta { text },
+                ..
+            } => Some(WsFrame::TextDelta {
+                conversation_id,
+                text: text.clone(),
+            }),
+            StreamEvent::ContentBlockDelta {
+                index,
+                delta: ContentDelta::InputJsonDelta { partial_json },
+            } => Some(WsFrame::ToolInputDelta {
+                conversation_id,
+                index: *index,
+                partial_json: partial_json.clone(),
+            }),
+            StreamEvent::MessageStop => Some(WsFrame::MessageStop { conversation_id }),
+            StreamEvent::Error { error } => Some(self.frame_for_error(&AnthropicError::ApiError {
+                error_type: error.error_type.clone(),
+                message: error.message.clone(),
+            })),
+            _ => None,
+        }
+    }
+
+    /// Push the frames describing a completed response
+    pub fn forward_update(&self, update: &ConversationUpdate) -> Result<(), AnthropicError> {
+        for frame in self.frames_for_update(update) {
+            self.send(&frame)?;
+        }
+        Ok(())
+    }
+
+    /// The frames [`WsForwarder::forward_update`] pushes: the text as a delta, one frame per
+    /// tool use, then the final update
+    pub fn frames_for_update(&self, update: &ConversationUpdate) -> Vec<WsFrame> {
+        let text = update.text();
+        let mut frames = Vec::new();
+        if !text.is_empty() {
+            frames.push(WsFrame::TextDelta {
+                conversation_id: self.conversation_id.clone(),
+                text: text.clone(),
+            });
+        }
+        frames.extend(update.tool_uses.iter().map(|tool_use| WsFrame::ToolUse {
+            conversation_id: self.conversation_id.clone(),
+            id: tool_use.id.clone(),
+            name: tool_use.name.clone(),
+            input: tool_use.input.clone(),
+        }));
+        frames.push(WsFrame::Update {
+            conversation_id: self.conversation_id.clone(),
+            text,
+            stop_reason: update.stop_reason.clone(),
+            usage: update.usage.clone(),
+        });
+        frames
+    }
+}
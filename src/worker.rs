@@ -0,0 +1,171 @@
+use crate::agent::{Agent, AgentStopReason};
+use crate::client::AnthropicClient;
+use crate::conversation::{Conversation, DuplicatePolicy};
+use crate::error::AnthropicError;
+use crate::registry::ToolRegistry;
+use crate::types::messages::Message;
+use hyperware_process_lib::{Address, Request};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+const DEFAULT_WORKER_MAX_ITERATIONS: u32 = 10;
+
+/// Everything a worker process needs to continue a conversation through a full tool loop
+///
+/// Tools are not included: handlers can't be serialized, so the worker supplies its own
+/// [`ToolRegistry`] when it runs the job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolLoopState {
+    pub model: String,
+    pub max_tokens: u32,
+    pub system: Option<String>,
+    /// History before `input`
+    pub messages: Vec<Arc<Message>>,
+    /// The user message that starts the run
+    pub input: String,
+    pub max_iterations: u32,
+    pub token_budget: Option<u32>,
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+    /// The conversation's duplicate guard, applied to `input`
+    #[serde(default)]
+    pub duplicate_policy: Option<DuplicatePolicy>,
+}
+
+impl ToolLoopState {
+    /// Capture a conversation's model, system prompt, and history, to be continued with `input`
+    pub fn from_conversation(conversation: &Conversation, input: impl Into<String>) -> Self {
+        Self {
+            model: conversation.model().to_string(),
+            max_tokens: conversation.max_tokens(),
+            system: conversation.resolved_system(),
+            messages: conversation.messages().to_vec(),
+            input: input.into(),
+            max_iterations: DEFAULT_WORKER_MAX_ITERATIONS,
+            token_budget: None,
+            max_output_tokens: None,
+            duplicate_policy: conversation.duplicate_policy(),
+        }
+    }
+
+    /// Set the maximum number of tool round-trips
+    pub fn with_max_iterations(mut self, max_iterations: u32) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Stop once this many input plus output tokens have been used
+    pub fn with_token_budget(mut self, token_budget: u32) -> Self {
+        self.token_budget = Some(token_budget);
+        self
+    }
+
+    /// Stop once the run has generated this many output tokens
+    pub fn with_max_output_tokens(mut self, max_output_tokens: u32) -> Self {
+        self.max_output_tokens = Some(max_output_tokens);
+        self
+    }
+
+    /// Send the state to a worker process; its [`WorkerResult`] is delivered to `reply_to`
+    ///
+    /// The worker is an ordinary child process spawned by the parent (with the capabilities
+    /// it needs for networking and its tools) that passes incoming [`WorkerJob`]s to
+    /// [`run_worker_job`]. Returning immediately keeps the parent free to handle messages.
+    pub fn dispatch(
+        self,
+        worker: Address,
+        reply_to: Address,
+        correlation_id: impl Into<String>,
+    ) -> Result<(), AnthropicError> {
+        let job = WorkerJob {
+            correlation_id: correlation_id.into(),
+            reply_to,
+            state: self,
+        };
+        Request::to(worker)
+            .body(serde_json::to_vec(&job)?)
+            .send()
+            .map_err(|e| AnthropicError::Gateway(e.to_string()))
+    }
+}
+
+/// Message sent from a parent process to a worker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerJob {
+    pub correlation_id: String,
+    pub reply_to: Address,
+    pub state: ToolLoopState,
+}
+
+/// Final transcript of a worker run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerOutput {
+    /// The complete history after the run, including `input` and all tool exchanges
+    pub messages: Vec<Arc<Message>>,
+    pub final_text: String,
+    pub stop_reason: AgentStopReason,
+    pub tokens_used: u32,
+}
+
+/// Message sent from a worker back to the parent when a job finishes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerResult {
+    pub correlation_id: String,
+    pub result: Result<WorkerOutput, AnthropicError>,
+}
+
+/// Run a job in a worker process and send the [`WorkerResult`] to the job's `reply_to`
+pub async fn run_worker_job(
+    client: AnthropicClient,
+    registry: ToolRegistry,
+    job: WorkerJob,
+) -> Result<(), AnthropicError> {
+    let WorkerJob {
+        correlation_id,
+        reply_to,
+        state,
+    } = job;
+
+    let result = run_tool_loop(client, registry, state).await;
+    let body = serde_json::to_vec(&WorkerResult {
+        correlation_id,
+        result,
+    })?;
+    Request::to(reply_to)
+        .body(body)
+        .send()
+        .map_err(|e| AnthropicError::Gateway(e.to_string()))
+}
+
+async fn run_tool_loop(
+    client: AnthropicClient,
+    registry: ToolRegistry,
+    state: ToolLoopState,
+) -> Result<WorkerOutput, AnthropicError> {
+    let mut conversation = Conversation::new(state.model, state.max_tokens);
+    if let Some(system) = state.system {
+        conversation = conversation.with_system(system);
+    }
+    if let Some(policy) = state.duplicate_policy {
+        conversation = conversation.with_duplicate_guard(policy);
+    }
+    *conversation.messages_mut() = state.messages;
+
+    let mut agent = Agent::with_conversation(client, conversation)
+        .with_registry(registry)
+        .with_max_iterations(state.max_iterations);
+    if let Some(token_budget) = state.token_budget {
+        agent = agent.with_token_budget(token_budget);
+    }
+    if let Some(max_output_tokens) = state.max_output_tokens {
+        agent = agent.with_max_output_tokens(max_output_tokens);
+    }
+
+    let run = agent.run(state.input).await?;
+    Ok(WorkerOutput {
+        messages: agent.conversation().messages().to_vec(),
+        final_text: run.final_text(),
+        stop_reason: run.stop_reason,
+        tokens_used: agent.tokens_used(),
+    })
+}
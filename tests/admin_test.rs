@@ -0,0 +1,138 @@
+#[cfg(test)]
+mod tests {
+    use hyperware_anthropic_sdk::{
+        ApiKey, BucketWidth, CostGroupBy, CostReportParams, CostResult, ListPage, ListParams,
+        Report, UsageGroupBy, UsageReportParams, UsageResult, Workspace,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_admin_list_pages_deserialize() {
+        let page: ListPage<Workspace> = serde_json::from_value(json!({
+            "data": [{
+                "id": "wrkspc_01",
+                "type": "workspace",
+                "name": "Hyperware apps",
+                "created_at": "2025-01-01T00:00:00Z",
+                "archived_at": null,
+                "display_color": "#6C5BB9"
+            }],
+            "has_more": true,
+            "first_id": "wrkspc_01",
+            "last_id": "wrkspc_01"
+        }))
+        .unwrap();
+        assert_eq!(page.data[0].name, "Hyperware apps");
+
+        let next = ListParams::new().with_limit(20).after(&page);
+        assert_eq!(next.after_id.as_deref(), Some("wrkspc_01"));
+
+        let key: ApiKey = serde_json::from_value(json!({
+            "id": "apikey_01",
+            "type": "api_key",
+            "name": "node key",
+            "workspace_id": "wrkspc_01",
+            "created_at": "2025-01-01T00:00:00Z",
+            "created_by": {"id": "user_01", "type": "user"},
+            "partial_key_hint": "sk-ant-api03-R2D...igAA",
+            "status": "active"
+        }))
+        .unwrap();
+        assert_eq!(key.status, "active");
+    }
+
+    #[test]
+    fn test_report_params_query() {
+        let usage = UsageReportParams::new("2025-08-01T00:00:00Z")
+            .with_ending_at("2025-08-02T00:00:00Z")
+            .with_bucket_width(BucketWidth::Hour)
+            .with_group_by(UsageGroupBy::Model)
+            .with_group_by(UsageGroupBy::WorkspaceId)
+            .with_model("claude-sonnet-4-5")
+            .with_api_key("apikey_01")
+            .with_limit(24);
+        assert_eq!(
+            usage.query(),
+            vec![
+                ("starting_at", "2025-08-01T00:00:00Z".to_string()),
+                ("ending_at", "2025-08-02T00:00:00Z".to_string()),
+                ("bucket_width", "1h".to_string()),
+                ("group_by[]", "model".to_string()),
+                ("group_by[]", "workspace_id".to_string()),
+                ("models[]", "claude-sonnet-4-5".to_string()),
+                ("api_key_ids[]", "apikey_01".to_string()),
+                ("limit", "24".to_string()),
+            ]
+        );
+
+        // The next page continues from the previous report's cursor
+        let report: Report<CostResult> = serde_json::from_value(json!({
+            "data": [], "has_more": true, "next_page": "page_2"
+        }))
+        .unwrap();
+        let cost = CostReportParams::new("2025-08-01T00:00:00Z")
+            .with_group_by(CostGroupBy::Description)
+            .with_limit(7)
+            .after(&report);
+        assert_eq!(
+            cost.query(),
+            vec![
+                ("starting_at", "2025-08-01T00:00:00Z".to_string()),
+                ("group_by[]", "description".to_string()),
+                ("limit", "7".to_string()),
+                ("page", "page_2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_usage_and_cost_reports_deserialize() {
+        let usage: Report<UsageResult> = serde_json::from_value(json!({
+            "data": [{
+                "starting_at": "2025-08-01T00:00:00Z",
+                "ending_at": "2025-08-02T00:00:00Z",
+                "results": [{
+                    "uncached_input_tokens": 1000,
+                    "cache_creation": {
+                        "ephemeral_1h_input_tokens": 50,
+                        "ephemeral_5m_input_tokens": 200
+                    },
+                    "cache_read_input_tokens": 750,
+                    "output_tokens": 400,
+                    "server_tool_use": {"web_search_requests": 2},
+                    "api_key_id": null,
+                    "workspace_id": "wrkspc_01",
+                    "model": "claude-sonnet-4-5-20250929",
+                    "service_tier": null,
+                    "context_window": null
+                }]
+            }],
+            "has_more": false,
+            "next_page": null
+        }))
+        .unwrap();
+        let result = &usage.data[0].results[0];
+        assert_eq!(result.total_input_tokens(), 2000);
+        assert_eq!(result.workspace_id.as_deref(), Some("wrkspc_01"));
+
+        let cost: Report<CostResult> = serde_json::from_value(json!({
+            "data": [{
+                "starting_at": "2025-08-01T00:00:00Z",
+                "ending_at": "2025-08-02T00:00:00Z",
+                "results": [{
+                    "currency": "USD",
+                    "amount": "123.45",
+                    "workspace_id": null,
+                    "description": "Claude Sonnet 4.5 Usage - Input Tokens",
+                    "cost_type": "tokens",
+                    "token_type": "uncached_input_tokens"
+                }]
+            }],
+            "has_more": true,
+            "next_page": "page_02"
+        }))
+        .unwrap();
+        assert_eq!(cost.data[0].results[0].amount_cents(), Some(123.45));
+        assert_eq!(cost.next_page.as_deref(), Some("page_02"));
+    }
+}
@@ -0,0 +1,52 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::text_response;
+    use hyperware_anthropic_sdk::batches::parse_batch_results;
+
+    use hyperware_anthropic_sdk::{AnthropicError, BatchResultItem, Conversation};
+    use serde_json::json;
+
+    #[test]
+    fn test_batch_round_trip() {
+        let mut conversation = Conversation::new("claude-test", 100);
+        conversation.add_user_message("Summarize the report");
+
+        let item = conversation.build_batch_item("report-1");
+        assert_eq!(item.custom_id, "report-1");
+        let params = serde_json::to_value(&item.params).unwrap();
+        assert!(params.get("stream").is_none());
+        assert_eq!(params["messages"][0]["content"], "Summarize the report");
+
+        let results = parse_batch_results(&format!(
+            "{}\n{}\n",
+            json!({"custom_id": "report-1", "result": {"type": "succeeded", "message": text_response("Done")}}),
+            json!({"custom_id": "report-2", "result": {"type": "expired"}}),
+        ))
+        .unwrap();
+        let mut results = results.into_iter();
+
+        let update = conversation
+            .apply_batch_result(results.next().unwrap())
+            .unwrap();
+        assert_eq!(update.text(), "Done");
+        assert_eq!(conversation.messages().len(), 2);
+
+        let error = conversation
+            .apply_batch_result(results.next().unwrap())
+            .unwrap_err();
+        assert!(error.to_string().contains("report-2"));
+        assert_eq!(conversation.messages().len(), 2);
+
+        let errored: BatchResultItem = serde_json::from_value(json!({
+            "custom_id": "report-3",
+            "result": {"type": "errored", "error": {"type": "error", "error": {"type": "invalid_request_error", "message": "bad"}}}
+        }))
+        .unwrap();
+        assert!(matches!(
+            errored.into_response(),
+            Err(AnthropicError::ApiError { ref error_type, .. }) if error_type == "invalid_request_error"
+        ));
+    }
+}
@@ -0,0 +1,509 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{block_on, response, text_response};
+    use hyperware_anthropic_sdk::{
+        model_info, AnthropicClient, AnthropicError, ClientWarning, Content, ContentBlock,
+        Conversation, CreateMessageRequest, GuardrailAction, Guardrails, ImageSource,
+        MaxTokensPolicy, MessageResponse, MockClock, ResponseContentBlock, SamplingParams,
+        StopReason, SystemPrompt, Transcript, TranscriptRecorder, TranscriptReplay,
+    };
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_guardrails_replace_and_block() {
+        let guardrails = Guardrails::new()
+            .with_outgoing_filter("redact_email", |text| {
+                if text.contains('@') {
+                    GuardrailAction::Replace("[redacted]".to_string())
+                } else {
+                    GuardrailAction::Allow
+                }
+            })
+            .with_incoming_filter("no_secrets", |text| {
+                if text.contains("SECRET") {
+                    GuardrailAction::Block("leaked secret".to_string())
+                } else {
+                    GuardrailAction::Allow
+                }
+            });
+
+        let mut conversation = Conversation::new("claude-test", 100);
+        conversation.add_user_message("Email me at ada@example.com");
+        let mut request = conversation.build_request();
+        guardrails.apply_to_request(&mut request).unwrap();
+        assert!(matches!(request.messages[0].content, Content::Text(ref t) if t == "[redacted]"));
+        // The stored history keeps the original text
+        assert!(
+            matches!(conversation.messages()[0].content, Content::Text(ref t) if t.contains('@'))
+        );
+
+        let mut leaked = text_response("The SECRET is 42");
+        assert!(matches!(
+            guardrails.apply_to_response(&mut leaked),
+            Err(AnthropicError::PolicyViolation { ref filter, .. }) if filter == "no_secrets"
+        ));
+    }
+
+    #[test]
+    fn test_transcript_replay_detects_prompt_changes() {
+        let mut conversation = Conversation::new("claude-test", 100).with_system("Be brief.");
+        conversation.add_user_message("Hi");
+
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&conversation.build_request(), &text_response("Hello!"));
+        let fixture = recorder.transcript().to_json().unwrap();
+
+        let replay = TranscriptReplay::new(Transcript::from_json(&fixture).unwrap());
+        let response = replay.respond(&conversation.build_request()).unwrap();
+        assert_eq!(response.id, "msg_test");
+        assert!(replay.finish().is_ok());
+
+        let changed = Conversation::new("claude-test", 100).with_system("Be verbose.");
+        let replay = TranscriptReplay::new(Transcript::from_json(&fixture).unwrap());
+        assert!(matches!(
+            replay.respond(&changed.build_request()),
+            Err(AnthropicError::TranscriptMismatch(ref m)) if m.contains("/messages")
+        ));
+    }
+
+    #[test]
+    fn test_request_size_precheck_names_largest_block() {
+        let mut conversation = Conversation::new("claude-test", 100);
+        conversation.add_user_message("Describe these");
+        conversation.add_user_blocks(vec![
+            ContentBlock::Text {
+                text: "small".to_string(),
+                citations: None,
+                cache_control: None,
+            },
+            ContentBlock::Image {
+                source: ImageSource::bytes("image/png", vec![0u8; 300_000]),
+                cache_control: None,
+            },
+        ]);
+        let request = conversation.build_request();
+
+        assert!(request.check_size(1_000_000).is_ok());
+        match request.check_size(100_000) {
+            Err(AnthropicError::RequestTooLarge { size, limit, hint }) => {
+                assert!(size > 400_000);
+                assert_eq!(limit, 100_000);
+                assert_eq!(hint, "largest block is a 0.4 MB image in message 1");
+            }
+            other => panic!("Expected RequestTooLarge, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_send_many_preserves_input_order() {
+        let client = AnthropicClient::new("test_key");
+        let requests: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|item| client.create_simple_message("claude-test", *item, 10))
+            .collect();
+
+        let recorder = TranscriptRecorder::new();
+        for (request, label) in requests.iter().zip(["A", "B", "C"]) {
+            recorder.record(request, &text_response(label));
+        }
+
+        let client = client.with_replay(TranscriptReplay::new(recorder.transcript()));
+        let results = block_on(client.send_many(requests, 2));
+        let texts: Vec<String> = results
+            .into_iter()
+            .map(|result| {
+                let mut conversation = Conversation::new("claude-test", 10);
+                conversation.process_response(&result.unwrap()).text()
+            })
+            .collect();
+        assert_eq!(texts, vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    fn test_blocking_client_sends_without_an_executor() {
+        let client = AnthropicClient::new("test_key");
+        let recorder = TranscriptRecorder::new();
+        recorder.record(
+            &client.create_simple_message("claude-test", "ping", 10),
+            &text_response("pong"),
+        );
+
+        // Retries back off on the client's clock and complete without an executor
+        let clock = MockClock::new(0);
+        let client = client
+            .with_clock(clock.clone())
+            .with_replay(
+                TranscriptReplay::new(recorder.transcript())
+                    .with_failure(AnthropicError::RateLimit),
+            )
+            .blocking();
+        assert_eq!(
+            client
+                .send_simple_message("claude-test", "ping", 10)
+                .unwrap(),
+            "pong"
+        );
+        assert_eq!(clock.sleeps().len(), 1);
+    }
+
+    #[test]
+    fn test_client_clones_share_state() {
+        fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+        assert_shareable::<AnthropicClient>();
+
+        let client = AnthropicClient::new("test_key");
+        let recorder = TranscriptRecorder::new();
+        recorder.record(
+            &client.create_simple_message("claude-test", "one", 10),
+            &text_response("1"),
+        );
+        recorder.record(
+            &client.create_simple_message("claude-test", "two", 10),
+            &text_response("2"),
+        );
+
+        // Both clones advance the same replay position
+        let first = client.with_replay(TranscriptReplay::new(recorder.transcript()));
+        let second = first.clone().with_timeout(5);
+        assert_eq!(
+            block_on(first.send_simple_message("claude-test", "one", 10)).unwrap(),
+            "1"
+        );
+        assert_eq!(
+            block_on(second.send_simple_message("claude-test", "two", 10)).unwrap(),
+            "2"
+        );
+    }
+
+    #[test]
+    fn test_max_tokens_checked_against_model_ceiling() {
+        assert_eq!(
+            model_info("claude-opus-4-1-20250805").map(|info| info.id),
+            Some("claude-opus-4-1")
+        );
+        assert_eq!(
+            model_info("claude-3-5-haiku-latest").map(|info| info.max_output_tokens),
+            Some(8192)
+        );
+        assert!(model_info("claude-opus-40").is_none());
+
+        let client = AnthropicClient::new("test_key");
+        let request = client.create_simple_message("claude-3-haiku-20240307", "hi", 64_000);
+        assert!(matches!(
+            request.check_max_tokens(),
+            Err(AnthropicError::MaxTokensExceeded { limit: 4096, .. })
+        ));
+        assert_eq!(request.clone().with_default_max_tokens().max_tokens, 4096);
+
+        // Clamping rewrites the request before it is sent
+        let recorder = TranscriptRecorder::new();
+        recorder.record(
+            &client.create_simple_message("claude-3-haiku-20240307", "hi", 4096),
+            &text_response("hello"),
+        );
+        let clamping = client
+            .clone()
+            .with_max_tokens_policy(MaxTokensPolicy::Clamp)
+            .with_replay(TranscriptReplay::new(recorder.transcript()));
+        assert!(block_on(clamping.send_message(request.clone())).is_ok());
+
+        // Warning sends the request unchanged and reports it through the callback
+        let unchanged = TranscriptRecorder::new();
+        unchanged.record(&request, &text_response("hello"));
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let seen = warnings.clone();
+        let warning = client
+            .clone()
+            .with_max_tokens_policy(MaxTokensPolicy::Warn)
+            .with_warning_callback(move |warning| seen.lock().unwrap().push(warning.clone()))
+            .with_replay(TranscriptReplay::new(unchanged.transcript()));
+        assert!(block_on(warning.send_message(request.clone())).is_ok());
+        assert_eq!(
+            *warnings.lock().unwrap(),
+            vec![ClientWarning::MaxTokensExceeded {
+                model: "claude-3-haiku-20240307".to_string(),
+                requested: 64_000,
+                limit: 4096,
+            }]
+        );
+
+        let rejecting = client.with_max_tokens_policy(MaxTokensPolicy::Reject);
+        assert!(matches!(
+            block_on(rejecting.send_message(request)),
+            Err(AnthropicError::MaxTokensExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_context_window_preflight() {
+        let client = AnthropicClient::new("test_key").with_context_window_check();
+        let small = client.create_simple_message("claude-sonnet-4-20250514", "hello", 1024);
+        assert!(small.check_context_window().is_ok());
+
+        // ~190k estimated prompt tokens plus 20k output can't fit in 200k
+        let huge = client.create_simple_message(
+            "claude-sonnet-4-20250514",
+            "word ".repeat(152_000),
+            20_000,
+        );
+        assert!(matches!(
+            block_on(client.send_message(huge)),
+            Err(AnthropicError::ContextWindowExceeded {
+                needed: 210_000,
+                available: 200_000
+            })
+        ));
+
+        // Images count by their pixel size, not their base64 length
+        let mut conversation = Conversation::new("claude-sonnet-4-20250514", 1024);
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        png.extend([0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        png.extend(750u32.to_be_bytes());
+        png.extend(100u32.to_be_bytes());
+        png.resize(4_000_000, 0);
+        conversation.add_user_image_message("What is this?", png, "image/png");
+        assert_eq!(
+            hyperware_anthropic_sdk::tokens::estimate_request_tokens(&conversation.build_request()),
+            100 + 4
+        );
+    }
+
+    #[test]
+    fn test_sampling_ranges_validated_locally() {
+        let client = AnthropicClient::new("test_key");
+        let hot = client
+            .create_simple_message("claude-3-5-haiku-latest", "hi", 100)
+            .with_temperature(1.5);
+        assert!(matches!(
+            block_on(client.send_message(hot)),
+            Err(AnthropicError::InvalidRequest(_))
+        ));
+
+        let both = client
+            .create_simple_message("claude-sonnet-4-5", "hi", 100)
+            .with_temperature(0.5)
+            .with_top_p(0.9);
+        assert!(both.validate().is_err());
+        // Older models still accept the combination
+        let mut older = both.clone();
+        older.model = "claude-3-5-haiku-latest".to_string();
+        assert!(older.validate().is_ok());
+
+        // The builders leave range checks to validate, as with_temperature does
+        let wide = client
+            .create_simple_message("claude-3-5-haiku-latest", "hi", 100)
+            .with_top_p(1.5);
+        assert!(matches!(
+            wide.validate(),
+            Err(AnthropicError::InvalidRequest(ref message)) if message.contains("top_p")
+        ));
+        let narrow = client
+            .create_simple_message("claude-3-5-haiku-latest", "hi", 100)
+            .with_top_k(0);
+        assert!(matches!(
+            block_on(client.send_message(narrow)),
+            Err(AnthropicError::InvalidRequest(ref message)) if message.contains("top_k")
+        ));
+
+        assert!(SamplingParams {
+            temperature: None,
+            top_p: Some(-0.1),
+            top_k: Some(40),
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_request_builders_cover_every_field() {
+        let request = CreateMessageRequest::new("claude-test", Vec::new(), 100)
+            .with_system_prompt(SystemPrompt::Text("Be brief".to_string()))
+            .with_user_id("user-1234")
+            .with_stream(true)
+            .with_top_k(40)
+            .with_stop_sequences(["END"]);
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["system"], "Be brief");
+        assert_eq!(body["metadata"], json!({"user_id": "user-1234"}));
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["top_k"], 40);
+        assert_eq!(body["stop_sequences"], json!(["END"]));
+    }
+
+    #[test]
+    fn test_usage_callback_runs_after_each_success() {
+        let client = AnthropicClient::new("test_key");
+        let request = client.create_simple_message("claude-test", "hi", 100);
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&request, &text_response("hello"));
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let client = client
+            .with_replay(TranscriptReplay::new(recorder.transcript()))
+            .with_usage_callback(move |usage, model, _latency| {
+                sink.lock().unwrap().push((
+                    model.to_string(),
+                    usage.input_tokens,
+                    usage.output_tokens,
+                ));
+            });
+        block_on(client.send_message(request)).unwrap();
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![("claude-test".to_string(), 10, 5)]
+        );
+    }
+
+    #[test]
+    fn test_strict_and_tolerant_deserialization() {
+        let mut drifted = serde_json::to_value(text_response("hello")).unwrap();
+        drifted["container"] = json!({"id": "c_1"});
+        // Documented usage fields are typed, so only genuinely new ones count as drift
+        drifted["usage"]["service_tier"] = json!("standard");
+        drifted["usage"]["cache_creation"] =
+            json!({"ephemeral_5m_input_tokens": 0, "ephemeral_1h_input_tokens": 8});
+        drifted["usage"]["server_tool_use"] = json!({"web_search_requests": 1});
+        drifted["usage"]["inference_region"] = json!("us");
+        let drifted: MessageResponse = serde_json::from_value(drifted).unwrap();
+        assert_eq!(drifted.extra["container"], json!({"id": "c_1"}));
+        assert_eq!(
+            drifted.unknown_fields(),
+            vec![
+                "container".to_string(),
+                "usage.inference_region".to_string()
+            ]
+        );
+        assert!(text_response("hello").unknown_fields().is_empty());
+
+        let client = AnthropicClient::new("test_key");
+        let request = client.create_simple_message("claude-test", "hi", 100);
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&request, &drifted);
+
+        let tolerant = client
+            .clone()
+            .with_replay(TranscriptReplay::new(recorder.transcript()));
+        let response = block_on(tolerant.send_message(request.clone())).unwrap();
+        assert_eq!(response.usage.service_tier.as_deref(), Some("standard"));
+        assert_eq!(
+            response
+                .usage
+                .cache_creation
+                .as_ref()
+                .map(|c| c.ephemeral_1h_input_tokens),
+            Some(8)
+        );
+        assert_eq!(
+            response
+                .usage
+                .server_tool_use
+                .as_ref()
+                .map(|s| s.web_search_requests),
+            Some(1)
+        );
+        assert_eq!(response.usage.extra["inference_region"], "us");
+
+        let strict = client
+            .with_replay(TranscriptReplay::new(recorder.transcript()))
+            .with_strict_deserialization(true);
+        let error = block_on(strict.send_message(request)).unwrap_err();
+        assert!(matches!(error, AnthropicError::Deserialization(ref message)
+            if message.contains("container, usage.inference_region")));
+    }
+
+    #[test]
+    fn test_unknown_block_types_are_kept_when_tolerant() {
+        let drifted = response(
+            json!([
+                {"type": "text", "text": "hello"},
+                {"type": "made_up_block", "payload": {"x": 1}}
+            ]),
+            "made_up_reason",
+        );
+        assert!(matches!(
+            drifted.content[1],
+            ResponseContentBlock::Unknown(ref raw) if raw["payload"]["x"] == 1
+        ));
+        assert_eq!(
+            drifted.stop_reason,
+            Some(StopReason::Other("made_up_reason".to_string()))
+        );
+        assert_eq!(
+            serde_json::to_value(&drifted.content).unwrap()[1],
+            json!({"type": "made_up_block", "payload": {"x": 1}})
+        );
+
+        let client = AnthropicClient::new("test_key");
+        let request = client.create_simple_message("claude-test", "hi", 100);
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&request, &drifted);
+
+        let tolerant = client
+            .clone()
+            .with_replay(TranscriptReplay::new(recorder.transcript()));
+        let response = block_on(tolerant.send_message(request.clone())).unwrap();
+        assert_eq!(response.content.len(), 2);
+
+        let strict = client
+            .with_replay(TranscriptReplay::new(recorder.transcript()))
+            .with_strict_deserialization(true);
+        let error = block_on(strict.send_message(request)).unwrap_err();
+        assert!(matches!(error, AnthropicError::Deserialization(ref message)
+            if message.contains("content.made_up_block, stop_reason.made_up_reason")));
+    }
+
+    #[cfg(feature = "vfs")]
+    #[test]
+    fn test_vfs_debug_dump_redacts_and_truncates() {
+        use hyperware_anthropic_sdk::{McpServer, VfsDebugDump};
+        use std::collections::HashMap;
+
+        let client = AnthropicClient::new("sk-ant-secret-abcd");
+        let request = client
+            .create_simple_message("claude-test", "x".repeat(50), 100)
+            .with_mcp_server(
+                McpServer::new("docs", "https://mcp.example.com")
+                    .with_authorization_token("hunter3"),
+            );
+        let headers = HashMap::from([
+            ("X-Custom-Token".to_string(), "hunter2".to_string()),
+            ("X-Trace".to_string(), "trace-1".to_string()),
+        ]);
+        let dump = VfsDebugDump::new("/app:pub.os/debug/").with_max_string_chars(10);
+        let rendered = dump.render(
+            "sk-ant-secret-abcd",
+            &headers,
+            &request,
+            Ok(&text_response("hello")),
+            std::time::Duration::from_millis(12),
+        );
+        let text = rendered.to_string();
+        assert!(!text.contains("secret") && !text.contains("hunter2"));
+        assert!(!text.contains("hunter3"));
+        assert_eq!(
+            rendered["request"]["mcp_servers"][0]["authorization_token"],
+            "[redacted]"
+        );
+        assert_eq!(rendered["api_key"], "[redacted, ends in abcd]");
+        assert_eq!(rendered["headers"]["X-Trace"], "trace-1");
+        assert_eq!(
+            rendered["request"]["messages"][0]["content"],
+            "xxxxxxxxxx…[40 chars truncated]"
+        );
+        assert_eq!(rendered["latency_ms"], 12);
+
+        let failed = dump.render(
+            "key",
+            &HashMap::new(),
+            &request,
+            Err(&AnthropicError::RateLimit),
+            std::time::Duration::ZERO,
+        );
+        assert_eq!(failed["error"]["message"], "Rate limit exceeded");
+        assert!(failed.get("response").is_none());
+    }
+}
@@ -0,0 +1,45 @@
+//! Fixtures shared by the integration tests
+#![allow(dead_code, unused_imports)]
+
+pub use futures::executor::block_on;
+use hyperware_anthropic_sdk::{
+    AnthropicClient, MessageResponse, TranscriptRecorder, TranscriptReplay,
+};
+use serde_json::{json, Value};
+
+/// A response from `claude-test` with the given content blocks
+pub fn response(content: Value, stop_reason: &str) -> MessageResponse {
+    serde_json::from_value(json!({
+        "id": "msg_test",
+        "type": "message",
+        "role": "assistant",
+        "content": content,
+        "model": "claude-test",
+        "stop_reason": stop_reason,
+        "stop_sequence": null,
+        "usage": {"input_tokens": 10, "output_tokens": 5}
+    }))
+    .unwrap()
+}
+
+/// A finished response holding one text block
+pub fn text_response(text: &str) -> MessageResponse {
+    response(json!([{"type": "text", "text": text}]), "end_turn")
+}
+
+/// A client that answers with the exchanges recorded so far, in order
+pub fn replayed_client(recorder: &TranscriptRecorder) -> AnthropicClient {
+    AnthropicClient::new("test_key").with_replay(TranscriptReplay::new(recorder.transcript()))
+}
+
+/// Like [`replayed_client`], not comparing the request fields at `ignored` (JSON pointers)
+pub fn replayed_client_ignoring(
+    recorder: &TranscriptRecorder,
+    ignored: &[&str],
+) -> AnthropicClient {
+    let replay = ignored.iter().fold(
+        TranscriptReplay::new(recorder.transcript()),
+        |replay, pointer| replay.with_ignored_field(*pointer),
+    );
+    AnthropicClient::new("test_key").with_replay(replay)
+}
@@ -0,0 +1,847 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{block_on, replayed_client, response, text_response};
+    use hyperware_anthropic_sdk::tokens::{HeuristicEstimator, TokenEstimator};
+    use hyperware_anthropic_sdk::{
+        messages, Agent, AnthropicClient, AnthropicError, Content, ContentBlock, Conversation,
+        ConversationBuilder, ConversationStore, DuplicatePolicy, GenerationPreset, HistoryRepair,
+        InMemoryStore, Message, MessageResponse, MockClock, RefusalPolicy, ResponseContentBlock,
+        ResponseValidator, Role, StopReason, ToolLoopState, ToolResult, TranscriptRecorder,
+        TranscriptReplay, UpdateOutcome,
+    };
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_json_validator() {
+        let mut conversation = Conversation::new("claude-test", 100);
+        let validator = ResponseValidator::json();
+
+        let update = conversation.process_response(&text_response("```json\n{\"ok\": true}\n```"));
+        assert!(validator.validate(&update).is_ok());
+
+        let update = conversation.process_response(&text_response("Sure! Here it is."));
+        assert!(validator.validate(&update).is_err());
+    }
+
+    #[test]
+    fn test_code_block_and_custom_validators() {
+        let mut conversation = Conversation::new("claude-test", 100);
+        let update = conversation.process_response(&text_response("```rust\nfn main() {}\n```"));
+
+        assert!(ResponseValidator::code_block().validate(&update).is_ok());
+        assert!(ResponseValidator::contains("fn main")
+            .validate(&update)
+            .is_ok());
+
+        let short = ResponseValidator::new("short", |update| {
+            if update.text().len() < 10 {
+                Ok(())
+            } else {
+                Err("too long".to_string())
+            }
+        });
+        assert_eq!(short.validate(&update), Err("too long".to_string()));
+    }
+
+    #[test]
+    fn test_build_request_shares_history() {
+        let mut conversation = Conversation::new("claude-test", 100);
+        conversation.add_user_message("x".repeat(10_000));
+        conversation.add_assistant_message("ok");
+
+        let first = conversation.build_request();
+        let second = conversation.build_request();
+        assert!(Arc::ptr_eq(&first.messages[0], &conversation.messages()[0]));
+        assert!(Arc::ptr_eq(&first.messages[1], &second.messages[1]));
+    }
+
+    #[test]
+    fn test_paused_turn_auto_resumes_in_place() {
+        let mut paused = response(
+            json!([
+                {"type": "text", "text": "Let me search."},
+                {"type": "server_tool_use", "id": "srv_1", "name": "web_search", "input": {"query": "rust"}},
+                {"type": "web_search_tool_result", "tool_use_id": "srv_1", "content": []}
+            ]),
+            "pause_turn",
+        );
+        paused.usage.cache_creation_input_tokens = Some(20);
+        paused.usage.cache_read_input_tokens = Some(100);
+        let mut finished = text_response("Rust is a language.");
+        finished.usage.cache_read_input_tokens = Some(50);
+
+        let mut expected = Conversation::new("claude-test", 100);
+        expected.add_user_message("What is Rust?");
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&expected.build_request(), &paused);
+        assert!(expected.process_response(&paused).is_paused());
+        recorder.record(&expected.build_request(), &finished);
+
+        let client = replayed_client(&recorder);
+        let mut conversation = Conversation::new("claude-test", 100).with_auto_resume(1);
+        let update = block_on(conversation.send_user_message(&client, "What is Rust?")).unwrap();
+
+        assert_eq!(update.text(), "Let me search.\nRust is a language.");
+        assert!(!update.is_paused() && !conversation.is_paused());
+        // Usage covers both responses, cached tokens included
+        assert_eq!(update.usage.input_tokens, 20);
+        assert_eq!(update.usage.cache_creation_input_tokens, Some(20));
+        assert_eq!(update.usage.cache_read_input_tokens, Some(150));
+        // The continuation joins the paused assistant message
+        assert_eq!(conversation.messages().len(), 2);
+        match &conversation.messages()[1].content {
+            Content::Blocks(blocks) => assert_eq!(blocks.len(), 4),
+            Content::Text(_) => panic!("Expected assistant blocks"),
+        }
+    }
+
+    #[test]
+    fn test_current_date_follows_client_clock() {
+        let template =
+            Conversation::new("claude-test", 100).with_system("Today is {current_date}.");
+        let mut expected = template
+            .fork()
+            .with_clock(MockClock::new(1_738_324_800_000));
+        expected.add_user_message("What day is it?");
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&expected.build_request(), &text_response("Friday."));
+
+        // Without a clock of its own, the conversation is dated by the client that sends it
+        let client = replayed_client(&recorder).with_clock(MockClock::new(1_738_324_800_000));
+        let mut conversation = template.fork();
+        let update = block_on(conversation.send_user_message(&client, "What day is it?")).unwrap();
+        assert_eq!(update.text(), "Friday.");
+    }
+
+    #[test]
+    fn test_refusal_policies() {
+        let refusal = response(
+            json!([{"type": "text", "text": "I can't help."}]),
+            "refusal",
+        );
+        let mut expected = Conversation::new("claude-test", 100);
+        expected.add_user_message("bad prompt");
+        let refused_request = expected.build_request();
+        let mut rephrased = Conversation::new("claude-test", 100);
+        rephrased.add_user_message("good prompt");
+
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&refused_request, &refusal);
+        recorder.record(&rephrased.build_request(), &text_response("Sure."));
+        let client = replayed_client(&recorder);
+
+        let mut conversation = Conversation::new("claude-test", 100)
+            .with_refusal_policy(RefusalPolicy::retry_with("good prompt"));
+        let update = block_on(conversation.send_user_message(&client, "bad prompt")).unwrap();
+        assert_eq!(update.text(), "Sure.");
+        assert_eq!(conversation.messages().len(), 2);
+
+        // Surfaced refusals are flagged and kept out of the history
+        let mut conversation = Conversation::new("claude-test", 100);
+        conversation.add_user_message("bad prompt");
+        let update = conversation.process_response(&refusal);
+        assert!(update.is_refusal());
+        assert_eq!(conversation.messages().len(), 1);
+
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&refused_request, &refusal);
+        let client = replayed_client(&recorder);
+        let mut conversation =
+            Conversation::new("claude-test", 100).with_refusal_policy(RefusalPolicy::abort());
+        assert!(matches!(
+            block_on(conversation.send_user_message(&client, "bad prompt")),
+            Err(AnthropicError::Refused(_))
+        ));
+
+        // A refused tool-result turn can't be rephrased without losing the results
+        let mut expected = Conversation::new("claude-test", 100);
+        expected.add_user_message("Check the weather");
+        expected.process_response(&response(
+            json!([{"type": "tool_use", "id": "toolu_1", "name": "weather", "input": {}}]),
+            "tool_use",
+        ));
+        expected
+            .add_tool_result("toolu_1".to_string(), "Sunny", false)
+            .unwrap();
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&expected.build_request(), &refusal);
+        let client = replayed_client(&recorder);
+        let mut conversation = expected
+            .fork()
+            .with_refusal_policy(RefusalPolicy::retry_with("good prompt"));
+        *conversation.messages_mut() = expected.messages().to_vec();
+        assert!(matches!(
+            block_on(conversation.send(&client)),
+            Err(AnthropicError::InvalidRequest(ref message)) if message.contains("tool result")
+        ));
+        assert_eq!(conversation.messages().len(), 3);
+    }
+
+    #[test]
+    fn test_update_outcomes() {
+        let mut conversation = Conversation::new("claude-test", 100);
+        let outcome = |conversation: &mut Conversation, response: &MessageResponse| {
+            conversation.process_response(response).outcome()
+        };
+
+        assert_eq!(
+            outcome(&mut conversation, &text_response("done")),
+            UpdateOutcome::Completed
+        );
+        let tool_call = json!([{"type": "tool_use", "id": "t1", "name": "lookup", "input": {}}]);
+        assert_eq!(
+            outcome(&mut conversation, &response(tool_call, "tool_use")),
+            UpdateOutcome::NeedsToolResults
+        );
+        assert_eq!(
+            outcome(
+                &mut conversation,
+                &response(json!([{"type": "text", "text": "cut"}]), "max_tokens")
+            ),
+            UpdateOutcome::Truncated
+        );
+        assert_eq!(
+            outcome(&mut conversation, &response(json!([]), "refusal")),
+            UpdateOutcome::Refused
+        );
+        assert_eq!(
+            outcome(&mut conversation, &response(json!([]), "pause_turn")),
+            UpdateOutcome::Paused
+        );
+
+        let mut stopped = text_response("partial");
+        stopped.stop_reason = Some(StopReason::StopSequence);
+        stopped.stop_sequence = Some("</answer>".to_string());
+        assert_eq!(
+            outcome(&mut conversation, &stopped),
+            UpdateOutcome::HitStopSequence("</answer>".to_string())
+        );
+    }
+
+    #[test]
+    fn test_stop_sequences_validated_and_surfaced() {
+        let client = AnthropicClient::new("test_key");
+        let blank = client
+            .create_simple_message("claude-test", "hi", 100)
+            .with_stop_sequences(["</answer>", "  "]);
+        assert!(matches!(
+            block_on(client.send_message(blank)),
+            Err(AnthropicError::InvalidRequest(_))
+        ));
+        let too_many = client
+            .create_simple_message("claude-test", "hi", 100)
+            .with_stop_sequences((0..=messages::MAX_STOP_SEQUENCES).map(|i| format!("<{}>", i)));
+        assert!(too_many.check_stop_sequences().is_err());
+
+        let mut conversation =
+            Conversation::new("claude-test", 100).with_stop_sequences(["</answer>"]);
+        conversation.add_user_message("Answer in tags");
+        assert_eq!(
+            conversation.build_request().stop_sequences,
+            Some(vec!["</answer>".to_string()])
+        );
+
+        let mut stopped = response(
+            json!([{"type": "text", "text": "<answer>42"}]),
+            "stop_sequence",
+        );
+        stopped.stop_sequence = Some("</answer>".to_string());
+        let update = conversation.process_response(&stopped);
+        assert_eq!(update.matched_stop_sequence(), Some("</answer>"));
+        assert_eq!(
+            conversation
+                .process_response(&text_response("done"))
+                .matched_stop_sequence(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_conversation_builder_defaults_and_storage() {
+        assert!(matches!(
+            Conversation::builder().build(),
+            Err(AnthropicError::InvalidRequest(_))
+        ));
+
+        let client = AnthropicClient::new("test_key").with_default_model("claude-3-5-haiku-latest");
+        let store = Arc::new(InMemoryStore::new());
+        let mut conversation = Conversation::builder()
+            .with_client(&client)
+            .with_system("Be brief")
+            .with_preset(GenerationPreset::Deterministic)
+            .with_storage(store.clone(), "chat-1")
+            .build()
+            .unwrap();
+        let request = conversation.build_request();
+        assert_eq!(request.model, "claude-3-5-haiku-latest");
+        assert_eq!(request.max_tokens, 4096);
+        assert_eq!(request.temperature, Some(0.0));
+
+        conversation.add_user_message("hi");
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&conversation.build_request(), &text_response("hello"));
+        let client = client.with_replay(TranscriptReplay::new(recorder.transcript()));
+        block_on(conversation.send(&client)).unwrap();
+        assert_eq!(
+            store.load("chat-1").unwrap().map(|history| history.len()),
+            Some(2)
+        );
+
+        // A new builder with the same store picks the history back up
+        let resumed = ConversationBuilder::new()
+            .with_model("claude-sonnet-4-5")
+            .with_storage(store, "chat-1")
+            .build()
+            .unwrap();
+        assert_eq!(resumed.messages().len(), 2);
+        assert_eq!(resumed.build_request().max_tokens, 4096);
+    }
+
+    #[test]
+    fn test_bound_conversation_sends_without_client_argument() {
+        let mut conversation = Conversation::new("claude-test", 100);
+        conversation.add_user_message("hi");
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&conversation.build_request(), &text_response("hello"));
+        conversation.process_response(&text_response("hello"));
+        conversation.add_user_message("again");
+        recorder.record(&conversation.build_request(), &text_response("hello again"));
+
+        let client = replayed_client(&recorder);
+        let mut bound = Conversation::new("claude-test", 100).bind(client);
+        assert_eq!(block_on(bound.say("hi")).unwrap().text(), "hello");
+        assert_eq!(block_on(bound.say("again")).unwrap().text(), "hello again");
+        assert_eq!(bound.messages().len(), 4);
+        assert_eq!(bound.into_inner().messages().len(), 4);
+    }
+
+    #[test]
+    fn test_debug_dump_renders_history() {
+        let mut conversation = Conversation::new("claude-test", 100).with_system("Be brief");
+        conversation.add_user_message("What's the weather\nin Paris?");
+        conversation.process_response(&response(
+            json!([
+                {"type": "text", "text": "Checking."},
+                {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {"city": "Paris"}}
+            ]),
+            "tool_use",
+        ));
+
+        let dump = conversation.debug_dump(false);
+        assert!(dump.starts_with("claude-test · 2 messages"));
+        assert!(dump.contains("system ~2 \"Be brief\""));
+        assert!(dump.contains("[0] user"));
+        assert!(dump.contains("What's the weather⏎in Paris?"));
+        assert!(dump.contains("tool_use get_weather toolu_1"));
+        assert!(dump.contains("pending get_weather"));
+        assert!(!dump.contains('\x1b'));
+        assert!(conversation.debug_dump(true).contains("\x1b[36muser"));
+    }
+
+    #[test]
+    fn test_workbench_export() {
+        let mut conversation = Conversation::new("claude-test", 100)
+            .with_system("Be brief")
+            .with_temperature(0.2);
+        conversation.add_user_message("Plan a trip");
+        conversation.process_response(&text_response("Where to?"));
+        conversation.add_user_message("Lisbon");
+
+        let export: Value =
+            serde_json::from_str(&conversation.to_workbench_json().unwrap()).unwrap();
+        assert_eq!(export["system"], "Be brief");
+        assert_eq!(export["messages"].as_array().unwrap().len(), 3);
+        assert_eq!(export["messages"][1]["role"], "assistant");
+        assert_eq!(export["params"]["model"], "claude-test");
+        assert_eq!(export["params"]["max_tokens"], 100);
+        assert!(export["params"].get("messages").is_none());
+        assert!(export["params"].get("stream").is_none());
+        assert!(!export.to_string().contains("cache_control"));
+    }
+
+    #[test]
+    fn test_conversation_diff() {
+        let mut base = Conversation::new("claude-test", 100);
+        base.add_user_message("Plan a trip");
+        base.process_response(&text_response("Where to?"));
+
+        let mut left = base.clone();
+        let mut right = base.clone();
+        assert_eq!(left.diff(&right), None);
+
+        left.add_user_message("Lisbon");
+        right.add_user_message("Oslo");
+        right.process_response(&text_response("Bring a coat."));
+
+        let diff = left.diff(&right).unwrap();
+        assert_eq!(diff.index, 2);
+        assert_eq!(diff.left.as_ref().unwrap().role, Role::User);
+        assert_eq!(diff.right.as_ref().unwrap().summary, "\"Oslo\"");
+        assert_eq!(
+            diff.to_string(),
+            "conversations diverge at message [2]\n- user \"Lisbon\"\n+ user \"Oslo\"\n"
+        );
+
+        left.messages_mut().pop();
+        left.add_user_message("Oslo");
+        let diff = left.diff(&right).unwrap();
+        assert_eq!(diff.index, 3);
+        assert!(diff.left.is_none());
+        assert_eq!(diff.right.unwrap().summary, "text \"Bring a coat.\"");
+    }
+
+    #[test]
+    fn test_merge_accepts_regenerated_branch() {
+        let mut parent = Conversation::new("claude-test", 100);
+        parent.add_user_message("Write a haiku");
+        parent.process_response(&text_response("First draft"));
+
+        // Regenerate the last response on a branch, then accept it
+        let mut branch = parent.clone();
+        branch.messages_mut().pop();
+        branch.process_response(&text_response("Second draft"));
+        branch.add_user_message("Shorter please");
+
+        assert_eq!(parent.merge(&branch).unwrap(), 2);
+        assert_eq!(parent.diff(&branch), None);
+        assert_eq!(parent.merge(&branch).unwrap(), 0);
+
+        // A pending tool use the branch neither answers nor keeps is a conflict
+        parent.process_response(&response(
+            json!([{"type": "tool_use", "id": "t1", "name": "count", "input": {}}]),
+            "tool_use",
+        ));
+        let mut branch = parent.clone();
+        branch.messages_mut().pop();
+        branch.process_response(&text_response("Here it is"));
+        let error = parent.merge(&branch).unwrap_err();
+        assert!(matches!(
+            error,
+            AnthropicError::MergeConflict { ref tool_use_ids } if tool_use_ids == &["t1"]
+        ));
+        assert_eq!(parent.messages().len(), 4);
+
+        // Answering it on the branch resolves the conflict
+        let mut branch = parent.clone();
+        branch
+            .add_tool_results(vec![ToolResult::success("t1", "17 syllables")])
+            .unwrap();
+        assert_eq!(parent.merge(&branch).unwrap(), 1);
+        assert!(!parent.has_pending_tool_uses());
+    }
+
+    #[test]
+    fn test_duplicate_guard() {
+        let mut conversation =
+            Conversation::new("claude-test", 100).with_duplicate_guard(DuplicatePolicy::Skip);
+        conversation.add_user_message("Book a table");
+        conversation.add_user_message("Book a table");
+        assert_eq!(conversation.messages().len(), 1);
+        conversation.process_response(&text_response("For how many?"));
+        conversation.add_user_message("Two");
+        conversation.add_user_message("Two");
+        assert_eq!(conversation.messages().len(), 3);
+
+        let mut conversation =
+            Conversation::new("claude-test", 100).with_duplicate_guard(DuplicatePolicy::Reject);
+        conversation.add_user_message("Book a table");
+        conversation.add_user_message("Book a table");
+        assert_eq!(conversation.messages().len(), 1);
+        let client = AnthropicClient::new("test_key");
+        let error = block_on(conversation.send(&client)).unwrap_err();
+        assert!(matches!(error, AnthropicError::DuplicateMessage));
+        assert!(matches!(
+            conversation.try_add_user_message("Book a table"),
+            Err(AnthropicError::DuplicateMessage)
+        ));
+        assert!(
+            ToolLoopState::from_conversation(&conversation, "Book a table")
+                .duplicate_policy
+                .is_some()
+        );
+        let mut agent = Agent::with_conversation(client, conversation.clone());
+        assert!(matches!(
+            block_on(agent.run("Book a table")),
+            Err(AnthropicError::DuplicateMessage)
+        ));
+
+        // A different message supersedes an earlier rejected repeat
+        conversation.add_user_message("Book a table");
+        conversation.add_user_message("For four");
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&conversation.build_request(), &text_response("Booked"));
+        let update = block_on(conversation.send(&replayed_client(&recorder))).unwrap();
+        assert_eq!(update.text(), "Booked");
+
+        // Without a guard, repeats are kept
+        let mut conversation = Conversation::new("claude-test", 100);
+        conversation.add_user_message("Again");
+        conversation.add_user_message("Again");
+        assert_eq!(conversation.messages().len(), 2);
+    }
+
+    #[test]
+    fn test_send_expect_text() {
+        let mut conversation = Conversation::new("claude-test", 100);
+        conversation.add_user_message("Hi");
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&conversation.build_request(), &text_response("Hello"));
+        let mut after_text = conversation.clone();
+        after_text.process_response(&text_response("Hello"));
+        after_text.add_user_message("Look it up");
+        let tool_turn = response(
+            json!([{"type": "tool_use", "id": "t1", "name": "search", "input": {}}]),
+            "tool_use",
+        );
+        recorder.record(&after_text.build_request(), &tool_turn);
+        let mut after_tools = after_text.clone();
+        after_tools.process_response(&tool_turn);
+        after_tools
+            .add_tool_results(vec![ToolResult::success("t1", "found")])
+            .unwrap();
+        recorder.record(
+            &after_tools.build_request(),
+            &response(json!([{"type": "text", "text": "It was"}]), "max_tokens"),
+        );
+
+        let client = replayed_client(&recorder);
+        let run =
+            |conversation: &mut Conversation| block_on(conversation.send_expect_text(&client));
+        assert_eq!(run(&mut conversation).unwrap(), "Hello");
+
+        conversation.add_user_message("Look it up");
+        let error = run(&mut conversation).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Expected a plain text response, but Claude requested tools: search"
+        );
+
+        conversation
+            .add_tool_results(vec![ToolResult::success("t1", "found")])
+            .unwrap();
+        assert!(matches!(
+            run(&mut conversation),
+            Err(AnthropicError::NotText(ref problem)) if problem.contains("max_tokens")
+        ));
+    }
+
+    #[test]
+    fn test_token_breakdown() {
+        let mut conversation = Conversation::new("claude-test", 100).with_system("Be brief.");
+        conversation.add_user_message("x".repeat(400));
+        conversation.process_response(&response(
+            json!([
+                {"type": "text", "text": "Checking"},
+                {"type": "tool_use", "id": "t1", "name": "search", "input": {}}
+            ]),
+            "tool_use",
+        ));
+
+        let breakdown = conversation.token_breakdown(&HeuristicEstimator);
+        assert_eq!(breakdown.messages.len(), 2);
+        assert_eq!(breakdown.messages[0].tokens, 100);
+        assert_eq!(breakdown.messages[0].blocks, vec![("text", 100)]);
+        assert_eq!(breakdown.messages[1].role, Role::Assistant);
+        assert_eq!(breakdown.messages[1].blocks[1].0, "tool use");
+        assert_eq!(breakdown.largest_messages(1)[0].index, 0);
+        assert_eq!(
+            breakdown.total,
+            breakdown.system + breakdown.messages.iter().map(|m| m.tokens).sum::<u32>()
+        );
+
+        // Any estimator can be plugged in, e.g. one token per block
+        struct PerBlock;
+        impl TokenEstimator for PerBlock {
+            fn text_tokens(&self, _text: &str) -> u32 {
+                1
+            }
+            fn block_tokens(&self, _block: &ContentBlock) -> u32 {
+                1
+            }
+        }
+        assert_eq!(conversation.token_breakdown(&PerBlock).total, 4);
+    }
+
+    #[test]
+    fn test_content_block_from_response_is_lossless() {
+        let blocks: Vec<ResponseContentBlock> = serde_json::from_value(json!([
+            {"type": "thinking", "thinking": "Check the doc", "signature": "sig"},
+            {"type": "text", "text": "It's blue.", "citations": [{"type": "char_location", "cited_text": "blue"}]},
+            {"type": "tool_use", "id": "toolu_1", "name": "lookup", "input": {"q": "sky"}},
+            {"type": "made_up_block", "payload": {"x": 1}}
+        ]))
+        .unwrap();
+        for block in &blocks {
+            let converted = serde_json::to_value(ContentBlock::from_response(block)).unwrap();
+            assert_eq!(converted, serde_json::to_value(block).unwrap());
+        }
+
+        // process_response uses the same mapping, so citations reach the history
+        let mut conversation = Conversation::new("claude-test", 100);
+        conversation.add_user_message("What color is the sky?");
+        conversation.process_response(&response(
+            serde_json::to_value(&blocks[1..2]).unwrap(),
+            "end_turn",
+        ));
+        let history = serde_json::to_value(conversation.messages()).unwrap();
+        assert_eq!(
+            history[1]["content"][0]["citations"][0]["cited_text"],
+            "blue"
+        );
+
+        // Blocks of unknown types pass through the history and back unchanged
+        conversation.add_user_message("And then?");
+        conversation.process_response(&response(
+            serde_json::to_value(&blocks[3..]).unwrap(),
+            "end_turn",
+        ));
+        let history = serde_json::to_value(conversation.messages()).unwrap();
+        let restored: Vec<Message> = serde_json::from_value(history.clone()).unwrap();
+        match &restored[3].content {
+            Content::Blocks(blocks) => assert!(matches!(blocks[0], ContentBlock::Unknown(_))),
+            Content::Text(_) => panic!("Expected assistant blocks"),
+        }
+        assert_eq!(serde_json::to_value(&restored).unwrap(), history);
+        assert_eq!(
+            history[3]["content"][0],
+            json!({"type": "made_up_block", "payload": {"x": 1}})
+        );
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let store = Arc::new(InMemoryStore::new());
+        let mut conversation = ConversationBuilder::new()
+            .with_model("claude-test")
+            .with_storage(store.clone(), "chat-1")
+            .build()
+            .unwrap();
+        conversation.add_user_message("Book a table");
+        let snapshot = conversation.snapshot();
+
+        // A speculative turn that ends waiting on a tool
+        conversation.process_response(&response(
+            json!([{"type": "tool_use", "id": "toolu_1", "name": "book", "input": {}}]),
+            "tool_use",
+        ));
+        conversation.add_user_message("Actually, never mind");
+        assert_eq!(conversation.pending_tool_uses().len(), 1);
+
+        conversation.restore(snapshot);
+        assert_eq!(conversation.messages().len(), 1);
+        assert!(conversation.pending_tool_uses().is_empty());
+        assert!(conversation.turn_usage().is_empty());
+        assert!(store.load("chat-1").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_failed_send_rolls_back() {
+        let mut conversation = Conversation::new("claude-test", 100);
+        let empty = AnthropicClient::new("test_key").with_replay(TranscriptReplay::new(
+            TranscriptRecorder::new().transcript(),
+        ));
+
+        // A failed send_user_message leaves no trace, so it can be retried as-is
+        assert!(block_on(conversation.send_user_message(&empty, "Hi")).is_err());
+        assert!(conversation.messages().is_empty());
+
+        // A message added beforehand stays, unanswered, for a later send to retry
+        conversation.add_user_message("Hi");
+        assert!(block_on(conversation.send(&empty)).is_err());
+        assert_eq!(conversation.messages().len(), 1);
+        assert!(conversation.has_unanswered_message());
+
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&conversation.build_request(), &text_response("Hello"));
+        let client = replayed_client(&recorder);
+        block_on(conversation.send(&client)).unwrap();
+        assert_eq!(conversation.messages().len(), 2);
+        assert!(!conversation.has_unanswered_message());
+    }
+
+    #[test]
+    fn test_failed_save_rolls_back_send() {
+        struct FailingStore;
+        impl ConversationStore for FailingStore {
+            fn load(&self, _id: &str) -> Result<Option<Vec<Arc<Message>>>, AnthropicError> {
+                Ok(None)
+            }
+            fn save(&self, _id: &str, _messages: &[Arc<Message>]) -> Result<(), AnthropicError> {
+                Err(AnthropicError::Storage("disk full".to_string()))
+            }
+            fn delete(&self, _id: &str) -> Result<(), AnthropicError> {
+                Ok(())
+            }
+            fn list(&self) -> Result<Vec<String>, AnthropicError> {
+                Ok(Vec::new())
+            }
+        }
+
+        let mut conversation = Conversation::builder()
+            .with_model("claude-test")
+            .with_max_tokens(100)
+            .with_storage(FailingStore, "chat-1")
+            .build()
+            .unwrap();
+        conversation.add_user_message("Hi");
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&conversation.build_request(), &text_response("Hello"));
+        let client = replayed_client(&recorder);
+
+        // The reply is dropped with the failed save, so a retry doesn't repeat the turn
+        let error = block_on(conversation.send(&client)).unwrap_err();
+        assert!(matches!(error, AnthropicError::Storage(_)));
+        assert_eq!(conversation.messages().len(), 1);
+        assert!(conversation.has_unanswered_message());
+        assert!(conversation.turn_usage().is_empty());
+    }
+
+    #[test]
+    fn test_from_messages_repairs_imported_history() {
+        let text = |role: Role, text: &str| Message {
+            role,
+            content: Content::Text(text.to_string()),
+        };
+        let blocks = |role: Role, blocks: serde_json::Value| Message {
+            role,
+            content: Content::Blocks(serde_json::from_value(blocks).unwrap()),
+        };
+        let messages = vec![
+            text(Role::User, "Hi"),
+            text(Role::User, "  "),
+            text(Role::User, "Check the weather"),
+            blocks(
+                Role::Assistant,
+                json!([
+                    {"type": "text", "text": "Checking"},
+                    {"type": "tool_use", "id": "t1", "name": "weather", "input": {}},
+                    {"type": "tool_use", "id": "t2", "name": "weather", "input": {}}
+                ]),
+            ),
+            blocks(
+                Role::User,
+                json!([
+                    {"type": "tool_result", "tool_use_id": "t1", "content": "Sunny"},
+                    {"type": "tool_result", "tool_use_id": "t9", "content": "Stale"}
+                ]),
+            ),
+            text(Role::Assistant, "It's sunny"),
+            text(Role::User, "And tomorrow?"),
+            blocks(
+                Role::Assistant,
+                json!([{"type": "tool_use", "id": "t3", "name": "weather", "input": {}}]),
+            ),
+        ];
+
+        let mut conversation = Conversation::new("claude-test", 100);
+        let repairs = conversation.import_messages(messages.clone());
+        assert_eq!(
+            repairs,
+            vec![
+                HistoryRepair::DroppedEmpty { role: Role::User },
+                HistoryRepair::MergedConsecutive { role: Role::User },
+                HistoryRepair::AnsweredOrphanedUse {
+                    tool_use_id: "t2".to_string()
+                },
+                HistoryRepair::DroppedOrphanedResult {
+                    tool_use_id: "t9".to_string()
+                },
+            ]
+        );
+
+        let request = serde_json::to_value(conversation.build_request()).unwrap();
+        let results = &request["messages"][2]["content"];
+        assert_eq!(results.as_array().unwrap().len(), 2);
+        assert_eq!(results[0]["tool_use_id"], "t2");
+        assert_eq!(results[0]["is_error"], true);
+        assert_eq!(results[1]["tool_use_id"], "t1");
+
+        let imported = Conversation::from_messages("claude-test", 100, messages);
+        assert_eq!(imported.messages().len(), 6);
+        assert_eq!(imported.pending_tool_uses().len(), 1);
+        assert_eq!(imported.pending_tool_uses()[0].id, "t3");
+    }
+
+    #[test]
+    fn test_conversation_stats() {
+        let mut conversation = Conversation::new("claude-sonnet-4-5", 100);
+        assert_eq!(conversation.stats().average_tokens_per_turn(), 0.0);
+
+        conversation.add_user_message("Weather in Paris and Rome?");
+        let mut tool_turn = response(
+            json!([
+                {"type": "tool_use", "id": "t1", "name": "weather", "input": {"city": "Paris"}},
+                {"type": "tool_use", "id": "t2", "name": "weather", "input": {"city": "Rome"}}
+            ]),
+            "tool_use",
+        );
+        tool_turn.usage.cache_creation_input_tokens = Some(1_000_000);
+        conversation.process_response(&tool_turn);
+        conversation
+            .add_tool_results(vec![
+                ToolResult::success("t1", "Sunny"),
+                ToolResult::success("t2", "Rainy"),
+            ])
+            .unwrap();
+        let mut answer = text_response("Sunny in Paris, rainy in Rome.");
+        answer.usage.cache_read_input_tokens = Some(1_000_000);
+        conversation.process_response(&answer);
+
+        let stats = conversation.stats();
+        assert_eq!(stats.turns, 2);
+        assert_eq!(stats.user_messages, 2);
+        assert_eq!(stats.assistant_messages, 2);
+        assert_eq!(stats.tool_calls["weather"], 2);
+        assert_eq!(stats.input_tokens, 20);
+        assert_eq!(stats.output_tokens, 10);
+        assert_eq!(stats.total_tokens(), 2_000_030);
+        assert_eq!(stats.average_tokens_per_turn(), 1_000_015.0);
+        // $3/MTok input: 1M written at 1.25x and 1M read at 0.1x, plus a few base tokens
+        let cost = stats.estimated_cost_usd.unwrap();
+        assert!((cost - 4.05).abs() < 0.001, "{}", cost);
+        let savings = stats.cache_savings_usd.unwrap();
+        assert!((savings - 1.95).abs() < 1e-9, "{}", savings);
+
+        assert!(Conversation::new("claude-test", 100)
+            .stats()
+            .estimated_cost_usd
+            .is_none());
+    }
+
+    #[test]
+    fn test_interleaved_thinking_preserves_blocks_across_tool_turns() {
+        let mut conversation =
+            Conversation::new("claude-sonnet-4-5", 4096).with_interleaved_thinking(8000);
+        conversation.add_user_message("What's the weather in Paris?");
+        let request = conversation.build_request();
+        assert_eq!(
+            serde_json::to_value(&request).unwrap()["thinking"],
+            json!({"type": "enabled", "budget_tokens": 8000})
+        );
+        assert_eq!(request.betas, vec!["interleaved-thinking-2025-05-14"]);
+        // The interleaved budget may exceed max_tokens
+        assert!(request.validate().is_ok());
+        assert!(request.clone().with_thinking(512).validate().is_err());
+
+        conversation.process_response(&response(
+            json!([
+                {"type": "thinking", "thinking": "I should check the weather.", "signature": "sig_1"},
+                {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {"city": "Paris"}}
+            ]),
+            "tool_use",
+        ));
+        conversation
+            .add_tool_results(vec![ToolResult::success("toolu_1", "22C")])
+            .unwrap();
+
+        let body = serde_json::to_value(conversation.build_request()).unwrap();
+        assert_eq!(
+            body["messages"][1]["content"][0],
+            json!({"type": "thinking", "thinking": "I should check the weather.", "signature": "sig_1"})
+        );
+        assert_eq!(body["messages"][1]["content"][1]["type"], "tool_use");
+    }
+}
@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use hyperware_anthropic_sdk::{Assertion, EvalCase, EvalCaseResult, EvalReport};
+    use serde_json::json;
+
+    #[test]
+    fn test_text_assertions() {
+        let response = "The capital of France is Paris.";
+        assert_eq!(
+            Assertion::contains("Paris").check_text(response),
+            Some(Ok(()))
+        );
+        assert!(matches!(
+            Assertion::not_contains("Paris").check_text(response),
+            Some(Err(_))
+        ));
+        assert_eq!(
+            Assertion::regex(r"(?i)capital of \w+ is").check_text(response),
+            Some(Ok(()))
+        );
+        assert!(matches!(
+            Assertion::regex("(unclosed").check_text(response),
+            Some(Err(ref e)) if e.starts_with("invalid regex")
+        ));
+        assert_eq!(Assertion::graded("Is polite").check_text(response), None);
+    }
+
+    #[test]
+    fn test_json_schema_assertion() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "tags"],
+            "properties": {
+                "name": {"type": "string"},
+                "tags": {"type": "array", "items": {"enum": ["a", "b"]}}
+            }
+        });
+        let assertion = Assertion::json_schema(schema);
+
+        assert_eq!(
+            assertion.check_text("```json\n{\"name\": \"x\", \"tags\": [\"a\"]}\n```"),
+            Some(Ok(()))
+        );
+        assert!(matches!(
+            assertion.check_text("{\"name\": \"x\", \"tags\": [\"c\"]}"),
+            Some(Err(ref e)) if e.starts_with("$.tags[0]")
+        ));
+        assert!(matches!(
+            assertion.check_text("{\"name\": 1, \"tags\": []}"),
+            Some(Err(ref e)) if e.starts_with("$.name")
+        ));
+    }
+
+    #[test]
+    fn test_case_fixture_and_report() {
+        let case: EvalCase = serde_json::from_value(json!({
+            "name": "weather",
+            "turns": ["What's the weather in Paris?"],
+            "tool_results": {"get_weather": "Sunny, 22C"},
+            "assertions": [{"type": "contains", "value": "22"}]
+        }))
+        .unwrap();
+        assert_eq!(case.turns.len(), 1);
+        assert!(matches!(case.assertions[0], Assertion::Contains { .. }));
+
+        let report = EvalReport {
+            results: vec![
+                EvalCaseResult {
+                    name: "weather".to_string(),
+                    response: "It is 22C".to_string(),
+                    failures: Vec::new(),
+                    error: None,
+                },
+                EvalCaseResult {
+                    name: "refusal".to_string(),
+                    response: String::new(),
+                    failures: vec!["expected the response to contain \"sorry\"".to_string()],
+                    error: None,
+                },
+            ],
+        };
+        assert_eq!(report.passed(), 1);
+        assert_eq!(report.pass_rate(), 0.5);
+        assert!(report.summary().contains("✗ refusal"));
+    }
+}
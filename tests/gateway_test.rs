@@ -0,0 +1,42 @@
+#[cfg(test)]
+mod tests {
+    use hyperware_anthropic_sdk::{
+        AnthropicError, CompletionNotification, Content, CreateMessageRequest, GatewayRequest,
+        GatewayResponse, Message, Role,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_gateway_messages_roundtrip() {
+        let request = GatewayRequest::SendMessage(CreateMessageRequest::new(
+            "claude-test",
+            vec![Message {
+                role: Role::User,
+                content: Content::Text("Hi".to_string()),
+            }],
+            100,
+        ));
+        let bytes = serde_json::to_vec(&request).unwrap();
+        let GatewayRequest::SendMessage(decoded) = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(decoded.model, "claude-test");
+
+        let response = GatewayResponse::SendMessage(Err(AnthropicError::RateLimit));
+        let bytes = serde_json::to_vec(&response).unwrap();
+        let GatewayResponse::SendMessage(result) = serde_json::from_slice(&bytes).unwrap();
+        assert!(matches!(result, Err(AnthropicError::RateLimit)));
+    }
+
+    #[test]
+    fn test_completion_notification_format() {
+        let notification: CompletionNotification = serde_json::from_value(json!({
+            "correlation_id": "digest-42",
+            "result": {"Err": "RateLimit"}
+        }))
+        .unwrap();
+        assert_eq!(notification.correlation_id, "digest-42");
+        assert!(matches!(
+            notification.result,
+            Err(AnthropicError::RateLimit)
+        ));
+    }
+}
@@ -0,0 +1,181 @@
+#[cfg(test)]
+mod tests {
+
+    use hyperware_anthropic_sdk::{
+        sniff_image_type, AnthropicError, ContentBlock, Conversation, ImageSource, ImageSourceData,
+        MAX_IMAGE_BYTES,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_lazy_image_bytes_serialize_as_base64() {
+        let block = ContentBlock::Image {
+            source: ImageSource::bytes("image/png", vec![0x89, b'P', b'N', b'G']),
+            cache_control: None,
+        };
+        let copy = block.clone();
+        assert_eq!(
+            serde_json::to_value(&block).unwrap(),
+            json!({
+                "type": "image",
+                "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw=="}
+            })
+        );
+        // The clone shares the cached encoding
+        match copy {
+            ContentBlock::Image {
+                source:
+                    ImageSource {
+                        data: ImageSourceData::Bytes { data, .. },
+                        ..
+                    },
+                ..
+            } => assert_eq!(data.base64(), "iVBORw=="),
+            _ => panic!("Expected lazily encoded image bytes"),
+        }
+    }
+
+    #[test]
+    fn test_image_message_puts_image_before_prompt() {
+        let mut conversation = Conversation::new("claude-sonnet-4-20250514", 512);
+        conversation.add_user_image_message("What's in this image?", vec![1, 2, 3], "image/png");
+        let request = serde_json::to_value(conversation.build_request()).unwrap();
+        assert_eq!(
+            request["messages"][0]["content"],
+            json!([
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "AQID"}},
+                {"type": "text", "text": "What's in this image?"}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_image_source_sniffs_and_validates() {
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0];
+        let source = ImageSource::from_bytes(png.to_vec()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&source).unwrap()["media_type"],
+            json!("image/png")
+        );
+        assert_eq!(
+            sniff_image_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+
+        assert!(matches!(
+            ImageSource::from_bytes(b"%PDF-1.7".to_vec()),
+            Err(AnthropicError::InvalidImage(_))
+        ));
+        // A valid PNG header, so only the size can be at fault
+        let mut oversized = png.to_vec();
+        oversized.resize(MAX_IMAGE_BYTES + 1, 0);
+        match ImageSource::from_bytes(oversized) {
+            Err(AnthropicError::InvalidImage(message)) => {
+                assert!(message.contains("byte limit"), "{}", message)
+            }
+            other => panic!("expected a size error, got {:?}", other),
+        }
+        // Declared type must match the data
+        assert!(ImageSource::bytes("image/gif", png.to_vec())
+            .validate()
+            .is_err());
+
+        assert!(ImageSource::from_url("https://example.com/cat.jpg").is_ok());
+        assert!(ImageSource::from_url("file:///etc/passwd").is_err());
+        assert!(ImageSource::from_url("not a url").is_err());
+    }
+
+    #[test]
+    fn test_file_id_blocks_reference_uploads() {
+        use hyperware_anthropic_sdk::betas::{required_betas, FILES_API_BETA};
+
+        let mut conversation = Conversation::new("claude-sonnet-4-20250514", 512);
+        conversation.add_user_message("No files yet");
+        assert!(!required_betas(&conversation.build_request()).contains(&FILES_API_BETA));
+
+        conversation.add_user_blocks(vec![
+            ContentBlock::image_from_file_id("file_011CNha8iCJcU1wXNR6q4V8w").unwrap(),
+            ContentBlock::document_from_file_id("file_011CPMxVD3fHLUhvTqtsQA5w").unwrap(),
+        ]);
+        let request = conversation.build_request();
+        assert!(required_betas(&request).contains(&FILES_API_BETA));
+        let json = serde_json::to_value(&request).unwrap();
+        let content = json["messages"][1]["content"].as_array().unwrap();
+        assert_eq!(
+            content[0],
+            json!({"type": "image", "source": {"type": "file", "file_id": "file_011CNha8iCJcU1wXNR6q4V8w"}})
+        );
+        assert_eq!(
+            content[1],
+            json!({"type": "document", "source": {"type": "file", "file_id": "file_011CPMxVD3fHLUhvTqtsQA5w"}})
+        );
+
+        // File sources survive a round trip through stored history
+        let block: ContentBlock = serde_json::from_value(content[0].clone()).unwrap();
+        assert!(block.uses_file());
+
+        for bad in ["", "file_", "011CNha8", "file_abc def", "file_../etc"] {
+            assert!(matches!(
+                ContentBlock::image_from_file_id(bad),
+                Err(AnthropicError::InvalidRequest(_))
+            ));
+            assert!(ContentBlock::document_from_file_id(bad).is_err());
+        }
+    }
+
+    #[cfg(feature = "image-processing")]
+    #[test]
+    fn test_image_preprocessor_downscales_large_images() {
+        use hyperware_anthropic_sdk::ImagePreprocessor;
+
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::RgbImage::from_pixel(400, 200, image::Rgb([200, 30, 30]))
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+
+        let (bytes, media_type) = ImagePreprocessor::new()
+            .with_max_dimension(100)
+            .process(&png)
+            .unwrap();
+        assert_eq!(media_type, "image/jpeg");
+        let resized = image::load_from_memory(&bytes).unwrap();
+        assert_eq!((resized.width(), resized.height()), (100, 50));
+
+        // Images within the limits pass through untouched
+        let (bytes, media_type) = ImagePreprocessor::new().process(&png).unwrap();
+        assert_eq!((bytes, media_type), (png, "image/png"));
+    }
+
+    #[test]
+    fn test_image_token_estimates() {
+        use hyperware_anthropic_sdk::tokens::{
+            estimate_image_bytes_tokens, estimate_image_tokens, image_dimensions,
+        };
+
+        // 1000x1000 / 750, rounded up
+        assert_eq!(estimate_image_tokens(1000, 1000), 1334);
+        assert_eq!(estimate_image_tokens(200, 200), 54);
+        // Oversized images are capped after the API's downscaling
+        assert_eq!(estimate_image_tokens(4000, 3000), 1600);
+        assert_eq!(estimate_image_tokens(0, 100), 0);
+
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        png.extend([0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        png.extend(640u32.to_be_bytes());
+        png.extend(480u32.to_be_bytes());
+        assert_eq!(image_dimensions(&png), Some((640, 480)));
+        assert_eq!(estimate_image_bytes_tokens(&png), Some(410));
+
+        // JPEG with an APP0 segment before the baseline frame header
+        let jpeg = [
+            0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xC0, 0x00, 0x11, 0x08, 0x01,
+            0x2C, 0x01, 0x90,
+        ];
+        assert_eq!(image_dimensions(&jpeg), Some((400, 300)));
+
+        let gif = *b"GIF89a\x20\x00\x10\x00";
+        assert_eq!(image_dimensions(&gif), Some((32, 16)));
+        assert_eq!(image_dimensions(b"not an image"), None);
+    }
+}
@@ -0,0 +1,207 @@
+#[cfg(test)]
+mod tests {
+
+    use hyperware_anthropic_sdk::{
+        CacheTtl, ChunkOrder, Content, ContentBlock, ContextPack, Conversation, FewShot,
+        FewShotMode, GenerationPreset, MockClock, PromptFragment, Role, Router, SystemPrompt,
+        SystemPromptBuilder,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_few_shot_token_budget() {
+        // Each example is roughly 25 estimated tokens
+        let few_shot = FewShot::new()
+            .with_example("a".repeat(60), "b".repeat(40))
+            .with_example("c".repeat(60), "d".repeat(40))
+            .with_example("e".repeat(60), "f".repeat(40))
+            .with_token_budget(60);
+        assert_eq!(few_shot.selected().len(), 2);
+
+        let messages = few_shot.to_messages();
+        assert_eq!(messages.len(), 4);
+        assert!(matches!(messages[0].role, Role::User));
+        assert!(matches!(messages[1].role, Role::Assistant));
+    }
+
+    #[test]
+    fn test_few_shot_injection_modes() {
+        let few_shot = FewShot::new().with_example("2 + 2", "4");
+
+        let conversation = Conversation::new("claude-test", 100)
+            .with_system("You are a calculator.")
+            .with_few_shot(&few_shot, FewShotMode::System);
+        let system = conversation.system().unwrap();
+        assert!(system.starts_with("You are a calculator."));
+        assert!(system.contains("<input>\n2 + 2\n</input>"));
+        assert!(conversation.messages().is_empty());
+
+        let conversation =
+            Conversation::new("claude-test", 100).with_few_shot(&few_shot, FewShotMode::Messages);
+        assert_eq!(conversation.messages().len(), 2);
+        assert!(matches!(conversation.messages()[1].content, Content::Text(ref t) if t == "4"));
+    }
+
+    #[test]
+    fn test_generation_preset() {
+        let request = Conversation::new("claude-test", 100)
+            .with_temperature(0.3)
+            .with_preset(GenerationPreset::Deterministic)
+            .build_request();
+        assert_eq!(request.temperature, Some(0.0));
+        assert_eq!(request.top_k, Some(1));
+        assert_eq!(request.top_p, None);
+
+        let request = Conversation::new("claude-test", 100)
+            .with_preset(GenerationPreset::Creative)
+            .build_request();
+        assert_eq!(request.temperature, Some(1.0));
+        assert_eq!(request.top_k, None);
+    }
+
+    #[test]
+    fn test_system_prompt_builder_places_cache_breakpoint() {
+        let builder = SystemPromptBuilder::new()
+            .with(PromptFragment::Persona {
+                name: "Ada".to_string(),
+                role: "a support agent for Acme".to_string(),
+            })
+            .with(PromptFragment::Concise)
+            .with_dynamic(PromptFragment::Custom(
+                "The user is on the free plan.".to_string(),
+            ));
+        let blocks = match builder.build() {
+            SystemPrompt::Blocks(blocks) => blocks,
+            other => panic!("expected blocks, got {:?}", other),
+        };
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].text, "You are Ada, a support agent for Acme.");
+        assert!(blocks[0].cache_control.is_none());
+        assert!(blocks[1].cache_control.is_some());
+        assert!(blocks[2].cache_control.is_none());
+
+        let text = builder.without_cache().build_text();
+        assert!(text.starts_with("You are Ada"));
+        assert!(text.ends_with("free plan."));
+        assert_eq!(text.matches("\n\n").count(), 2);
+    }
+
+    #[test]
+    fn test_router_classification_request() {
+        let router = Router::new("claude-haiku")
+            .route("billing", "Questions about invoices and payments", ())
+            .route("support", "Technical problems", ());
+
+        let request = router.classification_request("My invoice is wrong");
+        let tool = &request.tools.as_ref().unwrap()[0];
+        assert_eq!(
+            tool.input_schema.properties["route"]["enum"],
+            json!(["billing", "support"])
+        );
+        assert_eq!(
+            serde_json::to_value(request.tool_choice).unwrap(),
+            json!({"type": "tool", "name": tool.name})
+        );
+    }
+
+    #[test]
+    fn test_context_pack_order_budget_and_cache() {
+        let pack = ContextPack::new()
+            .with_scored_chunk("low.md", "x".repeat(200), 0.1)
+            .with_scored_chunk("high.md", "y".repeat(200), 0.9)
+            .with_scored_chunk("mid.md", "z".repeat(200), 0.5)
+            .with_order(ChunkOrder::ByScore)
+            .with_token_budget(160);
+
+        let sources: Vec<&str> = pack.selected().iter().map(|c| c.source.as_str()).collect();
+        assert_eq!(sources, vec!["high.md", "mid.md"]);
+
+        let blocks = pack.build();
+        assert_eq!(blocks.len(), 2);
+        match (&blocks[0], &blocks[1]) {
+            (
+                ContentBlock::Text {
+                    text,
+                    cache_control: None,
+                    ..
+                },
+                ContentBlock::Text {
+                    cache_control: Some(_),
+                    ..
+                },
+            ) => assert!(text.contains("<source>high.md</source>")),
+            _ => panic!("Expected the cache breakpoint on the last document"),
+        }
+    }
+
+    #[test]
+    fn test_system_prompt_variables() {
+        let mut conversation = Conversation::new("claude-test", 100)
+            .with_system("Date: {current_date}. User: {user_name}. Reply as {\"ok\": true} {unset}")
+            .with_variable("user_name", "Ada");
+        conversation.add_user_message("Hi");
+
+        let system = conversation.resolved_system().unwrap();
+        let date = &system["Date: ".len().."Date: ".len() + 10];
+        assert!(date.starts_with("20") && date.as_bytes()[4] == b'-' && date.as_bytes()[7] == b'-');
+        assert!(system.ends_with(". User: Ada. Reply as {\"ok\": true} {unset}"));
+
+        conversation
+            .set_variable("current_date", "2025-01-31")
+            .set_variable("user_name", "Grace");
+        let request = serde_json::to_value(conversation.build_request()).unwrap();
+        assert_eq!(
+            request["system"],
+            "Date: 2025-01-31. User: Grace. Reply as {\"ok\": true} {unset}"
+        );
+        assert!(conversation.system().unwrap().contains("{user_name}"));
+
+        // The date comes from the conversation's clock
+        let clock = MockClock::new(1_738_324_800_000);
+        let conversation = Conversation::new("claude-test", 100)
+            .with_system("Date: {current_date}")
+            .with_clock(clock.clone());
+        assert_eq!(conversation.resolved_system().unwrap(), "Date: 2025-01-31");
+        clock.advance(std::time::Duration::from_secs(12 * 60 * 60));
+        assert_eq!(conversation.resolved_system().unwrap(), "Date: 2025-02-01");
+    }
+
+    #[test]
+    fn test_cache_ttl_places_breakpoints() {
+        let mut conversation = Conversation::new("claude-test", 100)
+            .with_system("You are terse.")
+            .with_cache_ttl(CacheTtl::OneHour);
+        let mut blocks = ContextPack::new().with_chunk("notes.md", "Notes").build();
+        blocks.push(ContentBlock::Text {
+            text: "Summarize the notes".to_string(),
+            cache_control: None,
+            citations: None,
+        });
+        conversation.add_user_blocks(blocks);
+        conversation.add_assistant_message("They say hello.");
+        conversation.add_user_message("Thanks");
+
+        let request = conversation.build_request();
+        assert!(request
+            .betas
+            .contains(&"extended-cache-ttl-2025-04-11".to_string()));
+        let request = serde_json::to_value(request).unwrap();
+        let one_hour = json!({"type": "ephemeral", "ttl": "1h"});
+        assert_eq!(request["system"][0]["cache_control"], one_hour);
+        // The context pack's own breakpoint is retimed so TTLs never decrease
+        assert_eq!(
+            request["messages"][0]["content"][0]["cache_control"],
+            one_hour
+        );
+        assert_eq!(request["messages"][2]["content"][0]["text"], "Thanks");
+        assert_eq!(
+            request["messages"][2]["content"][0]["cache_control"],
+            one_hour
+        );
+
+        let five_minutes = Conversation::new("claude-test", 100)
+            .with_cache_ttl(CacheTtl::FiveMinutes)
+            .build_request();
+        assert!(five_minutes.betas.is_empty());
+    }
+}
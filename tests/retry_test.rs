@@ -1,7 +1,19 @@
+mod common;
+
 #[cfg(test)]
 mod tests {
-    use hyperware_anthropic_sdk::{AnthropicClient, AnthropicError};
+    use crate::common::{block_on, text_response};
+    use hyperware_anthropic_sdk::error::HttpClientError;
+    use hyperware_anthropic_sdk::{
+        AnthropicClient, AnthropicError, ApiVersion, ClientWarning, Clock, CredentialStatus,
+        DeprecationNotice, ErrorPayload, InMemoryJournal, JournalEntry, MessageResponse, MockClock,
+        RateLimitCoordinator, RateLimits, RequestJournal, RequestOptions, RetryEvent,
+        SharedRateLimiter, TranscriptRecorder, TranscriptReplay, TransportErrorKind,
+    };
+    use serde_json::json;
     use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
     #[test]
     fn test_retry_configuration() {
@@ -69,6 +81,121 @@ mod tests {
         // Client should be created with all configurations
     }
 
+    #[test]
+    fn test_protected_headers_are_rejected_unless_allowed() {
+        let recorder = TranscriptRecorder::new();
+        let client = AnthropicClient::new("test_key");
+        let request = client.create_simple_message("claude-test", "hi", 100);
+        let response: MessageResponse = serde_json::from_value(json!({
+            "id": "msg_1", "type": "message", "role": "assistant",
+            "content": [{"type": "text", "text": "hello"}],
+            "model": "claude-test", "stop_reason": "end_turn", "stop_sequence": null,
+            "usage": {"input_tokens": 1, "output_tokens": 1}
+        }))
+        .unwrap();
+        for _ in 0..3 {
+            recorder.record(&request, &response);
+        }
+        let client = client
+            .with_replay(TranscriptReplay::new(recorder.transcript()))
+            .with_header("X-Api-Key", "stray-key")
+            .with_header("X-Trace", "trace-1");
+
+        let error = block_on(client.send_message(request.clone())).unwrap_err();
+        assert!(
+            matches!(error, AnthropicError::InvalidRequest(ref message) if message.contains("X-Api-Key"))
+        );
+
+        let allowed = client.clone().allow_protected_headers();
+        block_on(allowed.send_message(request.clone())).unwrap();
+        let removed = client.remove_header("x-api-key");
+        block_on(removed.send_message(request.clone())).unwrap();
+
+        let injected = AnthropicClient::new("test_key").with_header("X-Trace", "a\r\nx-api-key: b");
+        assert!(matches!(
+            block_on(injected.send_message(request.clone())),
+            Err(AnthropicError::InvalidRequest(_))
+        ));
+        let cleared = removed.with_header("Bad Name", "x").clear_headers();
+        block_on(cleared.send_message(request)).unwrap();
+    }
+
+    #[test]
+    fn test_credential_status_from_error() {
+        let api_error = |error_type: &str| AnthropicError::ApiError {
+            error_type: error_type.to_string(),
+            message: "denied".to_string(),
+        };
+        assert_eq!(
+            CredentialStatus::from_error(&api_error("authentication_error")),
+            Some(CredentialStatus::InvalidKey)
+        );
+        assert_eq!(
+            CredentialStatus::from_error(&api_error("permission_error")),
+            Some(CredentialStatus::InsufficientPermissions(
+                "denied".to_string()
+            ))
+        );
+        assert!(CredentialStatus::from_error(&AnthropicError::RateLimit)
+            .unwrap()
+            .is_valid());
+        assert_eq!(
+            CredentialStatus::from_error(&AnthropicError::HttpClient {
+                kind: TransportErrorKind::Timeout,
+                message: "timed out".to_string(),
+            }),
+            Some(CredentialStatus::NetworkFailure("timed out".to_string()))
+        );
+        assert_eq!(CredentialStatus::from_error(&api_error("api_error")), None);
+
+        let missing = AnthropicClient::new("");
+        assert_eq!(
+            block_on(missing.verify_credentials()).unwrap(),
+            CredentialStatus::InvalidKey
+        );
+    }
+
+    #[test]
+    fn test_retry_observer_sees_each_retry() {
+        let client = AnthropicClient::new("test_key");
+        let request = client.create_simple_message("claude-test", "hi", 100);
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&request, &text_response("hello"));
+
+        // Retries are reported to the observer instead of being printed
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let clock = MockClock::new(0);
+        let client = client
+            .with_max_retries(3)
+            .with_clock(clock.clone())
+            .with_retry_observer(move |event: &RetryEvent| sink.lock().unwrap().push(event.clone()))
+            .with_replay(
+                TranscriptReplay::new(recorder.transcript())
+                    .with_failure(AnthropicError::RateLimit)
+                    .with_failure(AnthropicError::ApiError {
+                        error_type: "overloaded_error".to_string(),
+                        message: "Overloaded".to_string(),
+                    }),
+            );
+        assert!(block_on(client.send_message(request)).is_ok());
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events.iter().map(|e| e.attempt).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(events[0].error_class, AnthropicError::RateLimit.code());
+        assert_eq!(events[1].error_class, "overloaded_error");
+        assert!(events.iter().all(|e| e.max_retries == 3));
+        // The reported delays are the ones actually waited
+        assert_eq!(
+            events.iter().map(|e| e.delay_ms).collect::<Vec<_>>(),
+            clock.sleeps()
+        );
+    }
+
     #[test]
     fn test_oauth_configuration() {
         // Test OAuth authentication mode
@@ -90,4 +217,435 @@ mod tests {
 
         // Client should be created with OAuth and custom headers
     }
+
+    #[tokio::test]
+    async fn test_mock_clock_waits_out_rate_limit_instantly() {
+        let clock = MockClock::new(1_000);
+        let limiter = SharedRateLimiter::local(RateLimits {
+            requests_per_minute: Some(1),
+            tokens_per_minute: None,
+        })
+        .unwrap();
+
+        limiter.acquire_with(10, &clock).await.unwrap();
+        assert!(clock.sleeps().is_empty());
+        // The second request waits out the rest of the window on the mock clock
+        clock.advance(Duration::from_secs(15));
+        limiter.acquire_with(10, &clock).await.unwrap();
+        assert_eq!(clock.sleeps(), vec![45_000]);
+        assert_eq!(clock.now_ms(), 61_000);
+
+        let _client = AnthropicClient::new("test_key")
+            .with_rate_limiter(limiter)
+            .with_clock(clock.clone());
+    }
+
+    #[test]
+    fn test_send_message_backs_off_on_mock_clock() {
+        let client = AnthropicClient::new("test_key");
+        let request = client.create_simple_message("claude-test", "hi", 100);
+        let recorder = TranscriptRecorder::new();
+        recorder.record(&request, &text_response("hello"));
+
+        let clock = MockClock::new(1_000);
+        let replay = TranscriptReplay::new(recorder.transcript())
+            .with_failure(AnthropicError::RateLimit)
+            .with_failure(AnthropicError::RateLimit);
+        let client = client.with_clock(clock.clone()).with_replay(replay.clone());
+        assert!(block_on(client.send_message(request)).is_ok());
+        assert_eq!(replay.remaining(), 0);
+
+        // Exponential backoff with up to a second of jitter, waited on the mock clock
+        let sleeps = clock.sleeps();
+        assert_eq!(sleeps.len(), 2);
+        assert!((1_000..2_000).contains(&sleeps[0]), "{:?}", sleeps);
+        assert!((2_000..3_000).contains(&sleeps[1]), "{:?}", sleeps);
+        assert_eq!(clock.now_ms(), 1_000 + sleeps.iter().sum::<u64>());
+    }
+
+    #[test]
+    fn test_rate_limit_coordinator_window() {
+        let mut coordinator = RateLimitCoordinator::new(RateLimits {
+            requests_per_minute: Some(2),
+            tokens_per_minute: Some(1000),
+        })
+        .unwrap();
+
+        assert_eq!(coordinator.try_acquire(100, 0), Ok(()));
+        assert_eq!(coordinator.try_acquire(100, 10_000), Ok(()));
+        // Third request in the window must wait until the first grant expires
+        assert_eq!(coordinator.try_acquire(100, 20_000), Err(40_000));
+        assert_eq!(coordinator.try_acquire(100, 60_000), Ok(()));
+        // Token limit: 100 + 100 already in the window
+        assert_eq!(coordinator.try_acquire(900, 65_000), Err(5_000));
+
+        // Oversized requests still go through once the window is empty
+        let mut coordinator = RateLimitCoordinator::new(RateLimits {
+            requests_per_minute: None,
+            tokens_per_minute: Some(10),
+        })
+        .unwrap();
+        assert_eq!(coordinator.try_acquire(50, 0), Ok(()));
+
+        // Large token counts add up without overflowing the window sum
+        let mut coordinator = RateLimitCoordinator::new(RateLimits {
+            requests_per_minute: Some(10),
+            tokens_per_minute: None,
+        })
+        .unwrap();
+        for now in 0..3 {
+            assert_eq!(coordinator.try_acquire(u32::MAX, now), Ok(()));
+        }
+
+        // Zero limits would never grant anything, so they are rejected up front
+        for limits in [
+            RateLimits {
+                requests_per_minute: Some(0),
+                tokens_per_minute: None,
+            },
+            RateLimits {
+                requests_per_minute: None,
+                tokens_per_minute: Some(0),
+            },
+        ] {
+            assert!(matches!(
+                SharedRateLimiter::local(limits),
+                Err(AnthropicError::InvalidRequest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_request_journal_tracks_in_flight() {
+        let client = AnthropicClient::new("test_key");
+        let request = client.create_simple_message("claude-test", "Hello", 100);
+
+        let first = JournalEntry::new(&request, Some("chat-1".to_string())).unwrap();
+        let second = JournalEntry::new(&request, None).unwrap();
+        assert_eq!(first.request_hash, second.request_hash);
+        assert_ne!(first.id, second.id);
+
+        let journal = InMemoryJournal::new();
+        journal.begin(&first).unwrap();
+        journal.begin(&second).unwrap();
+        journal.complete(&second.id).unwrap();
+        assert_eq!(journal.in_flight().unwrap(), vec![first]);
+    }
+
+    #[test]
+    fn test_idempotency_key_is_journaled_not_serialized() {
+        let client = AnthropicClient::new("test_key");
+        let request = client
+            .create_simple_message("claude-test", "Hello", 100)
+            .with_options(RequestOptions::new().with_idempotency_key("turn-42"));
+        assert_eq!(request.options.idempotency_key.as_deref(), Some("turn-42"));
+        assert!(!serde_json::to_string(&request).unwrap().contains("turn-42"));
+
+        let entry = JournalEntry::new(&request, None).unwrap();
+        assert_eq!(entry.idempotency_key.as_deref(), Some("turn-42"));
+        let plain =
+            JournalEntry::new(&request.clone().with_options(RequestOptions::new()), None).unwrap();
+        assert_eq!(plain.request_hash, entry.request_hash);
+        assert_eq!(plain.idempotency_key, None);
+
+        // Entries journaled before keys existed still load
+        let legacy: JournalEntry = serde_json::from_value(json!({
+            "id": "a", "request_hash": "b", "conversation_id": null, "started_at": 1
+        }))
+        .unwrap();
+        assert_eq!(legacy.idempotency_key, None);
+    }
+
+    #[test]
+    fn test_warnings_for_truncation_unknown_fields_and_rate_limits() {
+        let client = AnthropicClient::new("test_key");
+        let request = client.create_simple_message("claude-test", "Write an essay", 5);
+        let recorder = TranscriptRecorder::new();
+        let response: MessageResponse = serde_json::from_value(json!({
+            "id": "msg_1", "type": "message", "role": "assistant",
+            "content": [{"type": "text", "text": "Once upon"}],
+            "model": "claude-test", "stop_reason": "max_tokens", "stop_sequence": null,
+            "usage": {"input_tokens": 3, "output_tokens": 5},
+            "container": {"id": "c_1"}
+        }))
+        .unwrap();
+        recorder.record(&request, &response);
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let sink = warnings.clone();
+        let client = client
+            .with_replay(TranscriptReplay::new(recorder.transcript()))
+            .with_warning_callback(move |warning| sink.lock().unwrap().push(warning.clone()));
+        block_on(client.send_message(request)).unwrap();
+        assert_eq!(
+            *warnings.lock().unwrap(),
+            vec![
+                ClientWarning::Truncated {
+                    model: "claude-test".to_string(),
+                    max_tokens: 5,
+                },
+                ClientWarning::UnknownFields(vec!["container".to_string()]),
+            ]
+        );
+
+        let headers = HashMap::from([
+            (
+                "anthropic-ratelimit-requests-limit".to_string(),
+                "50".to_string(),
+            ),
+            (
+                "anthropic-ratelimit-requests-remaining".to_string(),
+                "3".to_string(),
+            ),
+            (
+                "anthropic-ratelimit-tokens-limit".to_string(),
+                "1000".to_string(),
+            ),
+            (
+                "anthropic-ratelimit-tokens-remaining".to_string(),
+                "900".to_string(),
+            ),
+        ]);
+        assert_eq!(
+            ClientWarning::rate_limits_from_headers(&headers),
+            vec![ClientWarning::ApproachingRateLimit {
+                limit: "requests".to_string(),
+                remaining: 3,
+                total: 50,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_spend_is_attributed_per_label() {
+        let client = AnthropicClient::new("sk-ant-test-abcd");
+        let request = client.create_simple_message("claude-3-5-haiku-latest", "hi", 100);
+        let recorder = TranscriptRecorder::new();
+        let response: MessageResponse = serde_json::from_value(json!({
+            "id": "msg_1", "type": "message", "role": "assistant",
+            "content": [{"type": "text", "text": "hello"}],
+            "model": "claude-3-5-haiku-latest", "stop_reason": "end_turn", "stop_sequence": null,
+            "usage": {"input_tokens": 1000, "output_tokens": 200}
+        }))
+        .unwrap();
+        for _ in 0..3 {
+            recorder.record(&request, &response);
+        }
+        let client = client.with_replay(TranscriptReplay::new(recorder.transcript()));
+        let tenant_a = client.clone().with_spend_label("tenant-a");
+        block_on(tenant_a.send_message(request.clone())).unwrap();
+        block_on(tenant_a.send_message(request.clone())).unwrap();
+        block_on(client.send_message(request)).unwrap();
+
+        // Clones share stats, so every label shows up on the original client
+        let spend = client.stats().spend;
+        assert_eq!(spend.len(), 2);
+        assert_eq!(spend["tenant-a"].requests, 2);
+        assert_eq!(spend["tenant-a"].input_tokens, 2000);
+        assert!(spend["tenant-a"].estimated_cost_usd > 0.0);
+        assert_eq!(spend["key-...abcd"].output_tokens, 200);
+    }
+
+    #[test]
+    fn test_canonical_request_hash() {
+        let client = AnthropicClient::new("test_key");
+        let request = client.create_simple_message("claude-test", "Hello", 100);
+        let hash = request.canonical_hash().unwrap();
+        assert_eq!(hash.len(), 16);
+
+        // Volatile fields don't change the hash; content does
+        let mut tagged = request.clone();
+        tagged.metadata = Some(HashMap::from([("user_id".to_string(), "u-1".to_string())]));
+        tagged.stream = Some(true);
+        assert_eq!(tagged.canonical_hash().unwrap(), hash);
+        let other = client.create_simple_message("claude-test", "Hello!", 100);
+        assert_ne!(other.canonical_hash().unwrap(), hash);
+
+        assert_eq!(JournalEntry::new(&tagged, None).unwrap().request_hash, hash);
+    }
+
+    #[test]
+    fn test_deprecation_notice_from_headers() {
+        let mut headers = HashMap::new();
+        headers.insert("content-type".to_string(), "application/json".to_string());
+        assert!(DeprecationNotice::from_headers("claude-3-opus-20240229", &headers).is_none());
+
+        headers.insert("Sunset".to_string(), "2026-01-05".to_string());
+        headers.insert(
+            "warning".to_string(),
+            "299 - \"claude-3-opus-20240229 is deprecated\"".to_string(),
+        );
+        let notice = DeprecationNotice::from_headers("claude-3-opus-20240229", &headers).unwrap();
+        assert_eq!(notice.sunset.as_deref(), Some("2026-01-05"));
+        assert!(notice.deprecation.unwrap().contains("deprecated"));
+
+        let stats = AnthropicClient::new("test_key").stats();
+        assert_eq!((stats.requests, stats.failures), (0, 0));
+        assert!(stats.deprecations.is_empty());
+    }
+
+    #[test]
+    fn test_errors_with_headers_expose_inner_error() {
+        let mut headers = HashMap::new();
+        headers.insert("x-gateway-region".to_string(), "eu".to_string());
+        let error = AnthropicError::WithHeaders {
+            error: Box::new(AnthropicError::ApiError {
+                error_type: "overloaded_error".to_string(),
+                message: "busy".to_string(),
+            }),
+            headers,
+        };
+
+        assert_eq!(error.to_string(), "API error: overloaded_error: busy");
+        assert!(matches!(error.inner(), AnthropicError::ApiError { .. }));
+        assert_eq!(error.headers().unwrap()["x-gateway-region"], "eu");
+        assert!(AnthropicError::RateLimit.headers().is_none());
+    }
+
+    #[test]
+    fn test_user_safe_error_payloads() {
+        let overloaded = AnthropicError::WithHeaders {
+            error: Box::new(AnthropicError::ApiError {
+                error_type: "overloaded_error".to_string(),
+                message: "Overloaded".to_string(),
+            }),
+            headers: HashMap::new(),
+        };
+        assert!(overloaded.is_retryable());
+        assert_eq!(overloaded.http_status(), 529);
+        let payload = ErrorPayload::from(&overloaded);
+        assert_eq!(payload.code, "overloaded_error");
+        assert!(payload.retryable);
+
+        let leaky = AnthropicError::ApiError {
+            error_type: "authentication_error".to_string(),
+            message: "invalid x-api-key: sk-ant-api03-abc_DEF-123 was rejected".to_string(),
+        };
+        let payload = leaky.to_payload();
+        assert_eq!(
+            payload.message,
+            "invalid x-api-key: [redacted] was rejected"
+        );
+        assert!(!payload.retryable);
+
+        let internal = AnthropicError::InvalidResponse("API returned status 500: <html>".into());
+        let payload: ErrorPayload = internal.into();
+        assert_eq!(payload.code, "invalid_response");
+        assert!(!payload.message.contains("html"));
+
+        let too_long = AnthropicError::ContextWindowExceeded {
+            needed: 300_000,
+            available: 200_000,
+        };
+        assert_eq!(too_long.http_status(), 400);
+        assert_eq!(too_long.to_payload().code, "invalid_request_error");
+    }
+
+    #[test]
+    fn test_transport_errors_are_classified() {
+        let offline = AnthropicError::from(HttpClientError::ExecuteRequestFailed(
+            "error sending request: dns error: failed to lookup address information".to_string(),
+        ));
+        assert!(matches!(
+            offline,
+            AnthropicError::HttpClient {
+                kind: TransportErrorKind::Dns,
+                ..
+            }
+        ));
+        assert!(offline.is_retryable());
+        assert_eq!(offline.http_status(), 502);
+        assert!(offline.to_payload().message.contains("this node"));
+
+        let timeout = AnthropicError::from(HttpClientError::ExecuteRequestFailed(
+            "operation timed out".to_string(),
+        ));
+        assert!(timeout.is_retryable());
+        assert_eq!(timeout.http_status(), 504);
+        assert!(!timeout.to_payload().message.contains("this node"));
+
+        let bad_url = AnthropicError::from(HttpClientError::BadUrl {
+            url: "not a url".to_string(),
+        });
+        assert!(matches!(
+            bad_url,
+            AnthropicError::HttpClient {
+                kind: TransportErrorKind::InvalidRequest,
+                ..
+            }
+        ));
+        assert!(!bad_url.is_retryable());
+        // A bad URL is a configuration problem, not an offline node
+        assert!(!TransportErrorKind::InvalidRequest.is_local());
+        assert_eq!(
+            bad_url.to_payload().message,
+            "The request to the AI service could not be sent."
+        );
+
+        assert_eq!(
+            TransportErrorKind::classify("invalid peer certificate: UnknownIssuer"),
+            TransportErrorKind::Tls
+        );
+        assert_eq!(
+            TransportErrorKind::classify("tcp connect error: Connection refused (os error 111)"),
+            TransportErrorKind::ConnectionRefused
+        );
+        assert_eq!(
+            TransportErrorKind::classify("connection reset by peer"),
+            TransportErrorKind::ConnectionReset
+        );
+        assert!(TransportErrorKind::ConnectionReset.may_have_reached_api());
+        assert!(!TransportErrorKind::Dns.may_have_reached_api());
+    }
+
+    #[test]
+    fn test_api_version_compatibility_warnings() {
+        assert_eq!(ApiVersion::LATEST.as_str(), "2023-06-01");
+        assert_eq!(
+            ApiVersion::parse("2023-01-01"),
+            Some(ApiVersion::V2023_01_01)
+        );
+        assert!(ApiVersion::V2023_01_01 < ApiVersion::V2023_06_01);
+
+        let client = AnthropicClient::new("test_key");
+        let request = client
+            .create_simple_message("claude-test", "hi", 4096)
+            .with_thinking(2048);
+        assert!(ApiVersion::LATEST.unsupported_features(&request).is_empty());
+        assert_eq!(
+            ApiVersion::V2023_01_01.unsupported_features(&request),
+            vec!["extended thinking"]
+        );
+
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let sink = warnings.clone();
+        let recorder = TranscriptRecorder::new();
+        let response: MessageResponse = serde_json::from_value(json!({
+            "id": "msg_1", "type": "message", "role": "assistant",
+            "content": [{"type": "text", "text": "hello"}],
+            "model": "claude-test", "stop_reason": "end_turn", "stop_sequence": null,
+            "usage": {"input_tokens": 1, "output_tokens": 1}
+        }))
+        .unwrap();
+        recorder.record(&request, &response);
+        recorder.record(&request, &response);
+        let client = client
+            .with_replay(TranscriptReplay::new(recorder.transcript()))
+            .with_warning_callback(move |warning| sink.lock().unwrap().push(warning.clone()));
+
+        let old = client.clone().with_api_version(ApiVersion::V2023_01_01);
+        block_on(old.send_message(request.clone())).unwrap();
+        let unknown = client.with_api_version("2099-01-01");
+        block_on(unknown.send_message(request)).unwrap();
+
+        assert_eq!(
+            *warnings.lock().unwrap(),
+            vec![
+                ClientWarning::UnsupportedFeature {
+                    feature: "extended thinking".to_string(),
+                    api_version: "2023-01-01".to_string(),
+                },
+                ClientWarning::UnknownApiVersion("2099-01-01".to_string()),
+            ]
+        );
+    }
 }
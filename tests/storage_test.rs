@@ -0,0 +1,350 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{block_on, replayed_client, text_response};
+    use hyperware_anthropic_sdk::{
+        AnthropicClient, AnthropicError, Clock, Content, Conversation, ConversationBuilder,
+        ConversationStore, ExpiringStore, ExpiryPolicy, ExpiryReport, InMemoryScheduleStore,
+        InMemoryStore, Message, MockClock, Role, ScheduleStore, Scheduler, TranscriptRecorder,
+        TranscriptReplay,
+    };
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn test_scheduler_due_jobs_and_persistence() {
+        // Pending jobs are restored from process state; arming timers needs a running process
+        let mut scheduler: Scheduler = serde_json::from_value(json!({
+            "jobs": [
+                {"id": "job-0", "conversation_id": "team", "prompt": "Post the digest", "fire_at": 1000},
+                {"id": "job-1", "conversation_id": "ada", "prompt": "Remind me", "fire_at": 5000},
+                {"id": "job-2", "conversation_id": "ada", "prompt": "Cancelled", "fire_at": 2000}
+            ],
+            "next_id": 3
+        }))
+        .unwrap();
+        assert!(scheduler.cancel("job-2").unwrap());
+        assert!(!scheduler.cancel("job-2").unwrap());
+
+        let due = scheduler.take_due(3_000).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, "job-0");
+        assert_eq!(scheduler.pending()[0].id, "job-1");
+        assert!(Scheduler::is_scheduler_timer(b"anthropic-scheduler:job-1"));
+        assert!(!Scheduler::is_scheduler_timer(b"other"));
+    }
+
+    #[test]
+    fn test_scheduler_runs_due_prompts_by_its_clock() {
+        let template = Conversation::new("claude-test", 100);
+        let mut expected = template.fork();
+        expected.add_user_message("Post the digest");
+        let recorder = TranscriptRecorder::new();
+        recorder.record(
+            &expected.build_request(),
+            &text_response("Nothing new today"),
+        );
+        let client = replayed_client(&recorder);
+
+        let clock = MockClock::new(0);
+        let scheduler: Scheduler = serde_json::from_value(json!({
+            "jobs": [
+                {"id": "job-0", "conversation_id": "team", "prompt": "Post the digest", "fire_at": 60_000},
+                {"id": "job-1", "conversation_id": "team", "prompt": "Remind me", "fire_at": 3_600_000}
+            ],
+            "next_id": 2
+        }))
+        .unwrap();
+        let mut scheduler = scheduler.with_clock(clock.clone());
+        let store = InMemoryStore::new();
+
+        assert!(block_on(scheduler.run_due(&client, &store, &template))
+            .unwrap()
+            .is_empty());
+        clock.advance(Duration::from_secs(60));
+        let results = block_on(scheduler.run_due(&client, &store, &template)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0.id, "job-0");
+        assert_eq!(results[0].1.as_ref().unwrap().text(), "Nothing new today");
+        assert_eq!(store.load("team").unwrap().unwrap().len(), 2);
+
+        // The other prompt stays pending until its time comes
+        assert_eq!(scheduler.take_due(clock.now_ms()).unwrap(), vec![]);
+        clock.advance(Duration::from_secs(60 * 60));
+        assert_eq!(scheduler.take_due(clock.now_ms()).unwrap()[0].id, "job-1");
+    }
+
+    #[test]
+    fn test_scheduler_persists_changes_and_requeues_failed_sends() {
+        let template = Conversation::new("claude-test", 100);
+        let mut expected = template.fork();
+        expected.add_user_message("Post the digest");
+        let recorder = TranscriptRecorder::new();
+        recorder.record(
+            &expected.build_request(),
+            &text_response("Nothing new today"),
+        );
+        // The first send is rate limited and not retried by the client
+        let client = AnthropicClient::new("test_key")
+            .with_max_retries(0)
+            .with_replay(
+                TranscriptReplay::new(recorder.transcript())
+                    .with_failure(AnthropicError::RateLimit),
+            );
+
+        let saved = Arc::new(InMemoryScheduleStore::new());
+        saved
+            .save(
+                &serde_json::from_value(json!({
+                    "jobs": [
+                        {"id": "job-0", "conversation_id": "team", "prompt": "Post the digest", "fire_at": 0},
+                        {"id": "job-1", "conversation_id": "team", "prompt": "Cancelled", "fire_at": 0}
+                    ],
+                    "next_id": 2
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        let clock = MockClock::new(1_000);
+        let mut scheduler = Scheduler::load(saved.clone())
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_retry_delay(Duration::from_secs(30));
+        let saved_ids = || -> Vec<String> {
+            let restored = saved.load().unwrap().unwrap();
+            restored
+                .pending()
+                .iter()
+                .map(|job| job.id.clone())
+                .collect()
+        };
+
+        assert!(scheduler.cancel("job-1").unwrap());
+        assert_eq!(saved_ids(), vec!["job-0"]);
+
+        // A retryable failure is reported and the prompt stays saved for a later attempt
+        let store = InMemoryStore::new();
+        let results = block_on(scheduler.run_due(&client, &store, &template)).unwrap();
+        assert!(matches!(results[0].1, Err(AnthropicError::RateLimit)));
+        assert_eq!(saved_ids(), vec!["job-0"]);
+        assert_eq!(scheduler.pending()[0].fire_at, 31_000);
+        assert!(block_on(scheduler.run_due(&client, &store, &template))
+            .unwrap()
+            .is_empty());
+
+        clock.advance(Duration::from_secs(30));
+        let results = block_on(scheduler.run_due(&client, &store, &template)).unwrap();
+        assert_eq!(results[0].1.as_ref().unwrap().text(), "Nothing new today");
+        assert!(saved_ids().is_empty());
+        assert!(scheduler.pending().is_empty());
+    }
+
+    #[test]
+    fn test_store_timestamps_follow_mock_clock() {
+        const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+        let clock = MockClock::new(1_700_000_000_000);
+        let active = InMemoryStore::new().with_clock(clock.clone());
+        let archive = InMemoryStore::new().with_clock(clock.clone());
+        let history = vec![Arc::new(Message {
+            role: Role::User,
+            content: Content::Text("hi".to_string()),
+        })];
+        active.save("chat-1", &history).unwrap();
+        assert_eq!(active.last_saved("chat-1").unwrap(), Some(clock.now_ms()));
+
+        let policy = ExpiryPolicy::new(30 * DAY);
+        clock.advance(29 * DAY);
+        let report = policy.enforce(&active, &archive, clock.now_ms()).unwrap();
+        assert!(report.archived.is_empty());
+        clock.advance(DAY);
+        let report = policy.enforce(&active, &archive, clock.now_ms()).unwrap();
+        assert_eq!(report.archived, vec!["chat-1"]);
+        assert_eq!(archive.last_saved("chat-1").unwrap(), Some(clock.now_ms()));
+    }
+
+    #[test]
+    fn test_expiring_store_enforces_policy_on_save() {
+        const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+        let clock = MockClock::new(1_700_000_000_000);
+        let active = Arc::new(InMemoryStore::new().with_clock(clock.clone()));
+        let archive = Arc::new(InMemoryStore::new().with_clock(clock.clone()));
+        let store =
+            ExpiringStore::new(active.clone(), archive.clone(), ExpiryPolicy::new(30 * DAY))
+                .with_check_interval(2 * DAY)
+                .with_clock(clock.clone());
+        let history = vec![Arc::new(Message {
+            role: Role::User,
+            content: Content::Text("hi".to_string()),
+        })];
+        store.save("chat-1", &history).unwrap();
+
+        clock.advance(29 * DAY);
+        store.save("chat-2", &history).unwrap();
+        assert!(archive.list().unwrap().is_empty());
+
+        // chat-1 has expired, but saves within the check interval don't run the policy
+        clock.advance(DAY);
+        store.save("chat-2", &history).unwrap();
+        assert!(archive.list().unwrap().is_empty());
+        clock.advance(DAY);
+        store.save("chat-2", &history).unwrap();
+        assert_eq!(archive.list().unwrap(), vec!["chat-1"]);
+        assert_eq!(active.list().unwrap(), vec!["chat-2"]);
+        let mut ids = store.list().unwrap();
+        ids.sort();
+        assert_eq!(ids, vec!["chat-1", "chat-2"]);
+
+        // Loading an archived conversation makes it active again
+        assert_eq!(store.load("chat-1").unwrap().unwrap().len(), 1);
+        assert!(archive.list().unwrap().is_empty());
+        assert_eq!(active.last_saved("chat-1").unwrap(), Some(clock.now_ms()));
+
+        clock.advance(31 * DAY);
+        let report = store.enforce().unwrap();
+        assert_eq!(report.archived.len(), 2);
+        assert!(!store.append("chat-2", &history).unwrap());
+    }
+
+    #[cfg(feature = "vfs")]
+    #[test]
+    fn test_vfs_store_recovers_from_torn_line() {
+        use hyperware_anthropic_sdk::VfsStore;
+
+        let line = |text: &str| {
+            serde_json::to_string(&Message {
+                role: Role::User,
+                content: Content::Text(text.to_string()),
+            })
+            .unwrap()
+        };
+        let complete = format!("{}\n{}\n", line("one"), line("two"));
+        let (messages, clean) = VfsStore::parse_lines(complete.as_bytes()).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(clean);
+        let (messages, clean) = VfsStore::parse_lines(b"").unwrap();
+        assert!(messages.is_empty() && clean);
+
+        // A crash mid-append leaves part of a line, which is dropped and forces a rewrite
+        let third = line("three");
+        let torn = format!("{}{}", complete, &third[..third.len() / 2]);
+        let (messages, clean) = VfsStore::parse_lines(torn.as_bytes()).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(!clean);
+
+        // A whole message without its newline is kept, but still can't be appended after
+        let unterminated = format!("{}{}", complete, third);
+        let (messages, clean) = VfsStore::parse_lines(unterminated.as_bytes()).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert!(!clean);
+
+        let corrupt = format!("{{\n{}", complete);
+        let error = VfsStore::parse_lines(corrupt.as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("Line 1 is not a message"));
+    }
+
+    #[test]
+    fn test_expiry_policy_archives_then_deletes() {
+        const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+        let active = InMemoryStore::new();
+        let archive = InMemoryStore::new();
+        let history = vec![Arc::new(Message {
+            role: Role::User,
+            content: Content::Text("hi".to_string()),
+        })];
+        active.save("chat-1", &history).unwrap();
+        active.save("keep", &history).unwrap();
+        let now = active.last_saved("chat-1").unwrap().unwrap();
+
+        let offered = Arc::new(Mutex::new(Vec::new()));
+        let seen = offered.clone();
+        let policy = ExpiryPolicy::new(Duration::from_secs(30 * 24 * 60 * 60))
+            .with_delete_after(Duration::from_secs(90 * 24 * 60 * 60))
+            .with_before_delete(move |id, messages| {
+                seen.lock().unwrap().push((id.to_string(), messages.len()));
+                id != "keep"
+            });
+
+        let report = policy.enforce(&active, &archive, now + DAY_MS).unwrap();
+        assert_eq!(report, ExpiryReport::default());
+
+        let mut report = policy
+            .enforce(&active, &archive, now + 31 * DAY_MS)
+            .unwrap();
+        report.archived.sort();
+        assert_eq!(report.archived, vec!["chat-1", "keep"]);
+        assert!(active.list().unwrap().is_empty());
+        assert_eq!(archive.load("chat-1").unwrap().unwrap().len(), 1);
+
+        // Archiving resets the clock, so deletion counts from the move
+        let archived_at = archive.last_saved("chat-1").unwrap().unwrap();
+        let report = policy
+            .enforce(&active, &archive, archived_at + 60 * DAY_MS)
+            .unwrap();
+        assert!(report.deleted.is_empty());
+        let report = policy
+            .enforce(&active, &archive, archived_at + 91 * DAY_MS)
+            .unwrap();
+        assert_eq!(report.deleted, vec!["chat-1"]);
+        assert_eq!(archive.list().unwrap(), vec!["keep"]);
+        assert_eq!(offered.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_saves_append_only_new_messages() {
+        #[derive(Default)]
+        struct LoggingStore {
+            inner: InMemoryStore,
+            calls: Mutex<Vec<&'static str>>,
+        }
+        impl ConversationStore for LoggingStore {
+            fn load(&self, id: &str) -> Result<Option<Vec<Arc<Message>>>, AnthropicError> {
+                self.inner.load(id)
+            }
+            fn save(&self, id: &str, messages: &[Arc<Message>]) -> Result<(), AnthropicError> {
+                self.calls.lock().unwrap().push("save");
+                self.inner.save(id, messages)
+            }
+            fn append(&self, id: &str, messages: &[Arc<Message>]) -> Result<bool, AnthropicError> {
+                self.calls.lock().unwrap().push("append");
+                self.inner.append(id, messages)
+            }
+            fn delete(&self, id: &str) -> Result<(), AnthropicError> {
+                self.inner.delete(id)
+            }
+            fn list(&self) -> Result<Vec<String>, AnthropicError> {
+                self.inner.list()
+            }
+        }
+
+        let store = Arc::new(LoggingStore::default());
+        let mut conversation = ConversationBuilder::new()
+            .with_model("claude-test")
+            .with_storage(store.clone(), "chat-1")
+            .build()
+            .unwrap();
+        conversation.add_user_message("hi");
+        conversation.save().unwrap();
+        let snapshot = conversation.snapshot();
+        conversation.process_response(&text_response("hello"));
+        conversation.save().unwrap();
+        conversation.save().unwrap();
+        assert_eq!(*store.calls.lock().unwrap(), vec!["save", "append"]);
+
+        // A history removed behind the conversation's back is written out in full again
+        store.delete("chat-1").unwrap();
+        conversation.add_user_message("more");
+        conversation.save().unwrap();
+        assert_eq!(store.load("chat-1").unwrap().unwrap().len(), 3);
+
+        // So is one that no longer extends what was saved
+        conversation.restore(snapshot);
+        conversation.save().unwrap();
+        assert_eq!(
+            *store.calls.lock().unwrap(),
+            vec!["save", "append", "append", "save", "save"]
+        );
+        assert_eq!(store.load("chat-1").unwrap().unwrap().len(), 1);
+    }
+}
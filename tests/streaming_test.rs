@@ -0,0 +1,432 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{block_on, replayed_client_ignoring, response};
+    use hyperware_anthropic_sdk::{
+        AnthropicClient, AnthropicError, Content, ContentDelta, Conversation, CreateMessageRequest,
+        GuardrailAction, Guardrails, Message, MessageStream, PartialJson, ResponseContentBlock,
+        Role, SseEvent, StopReason, StreamEvent, ToolInputAccumulator, TranscriptRecorder,
+        WsForwarder,
+    };
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_streamed_requests_are_replayed_recorded_and_filtered() {
+        let request = CreateMessageRequest::new(
+            "claude-test",
+            vec![Message {
+                role: Role::User,
+                content: Content::Text("What's the code?".to_string()),
+            }],
+            100,
+        );
+        let fixture = TranscriptRecorder::new();
+        fixture.record(
+            &request,
+            &response(
+                json!([
+                    {"type": "text", "text": "The code is secret."},
+                    {"type": "tool_use", "id": "toolu_1", "name": "log", "input": {"level": "info"}}
+                ]),
+                "tool_use",
+            ),
+        );
+
+        let recording = TranscriptRecorder::new();
+        let guardrails = Guardrails::new().with_incoming_filter("redact", |text| {
+            match text.contains("secret") {
+                true => GuardrailAction::Replace(text.replace("secret", "[redacted]")),
+                false => GuardrailAction::Allow,
+            }
+        });
+        let client = replayed_client_ignoring(&fixture, &["/stream"])
+            .with_recorder(recording.clone())
+            .with_guardrails(guardrails);
+        let stream = block_on(client.stream_message(request)).unwrap();
+
+        // Guardrails rewrite the events themselves, not just the accumulated response
+        assert!(stream.events().unwrap().iter().any(|event| matches!(
+            event,
+            StreamEvent::ContentBlockDelta { delta: ContentDelta::TextDelta { text }, .. }
+                if text == "The code is [redacted]."
+        )));
+        let streamed = stream.into_response().unwrap();
+        assert_eq!(streamed.stop_reason, Some(StopReason::ToolUse));
+        assert!(matches!(
+            &streamed.content[1],
+            ResponseContentBlock::ToolUse { input, .. } if input == &json!({"level": "info"})
+        ));
+
+        let recorded = recording.transcript();
+        assert_eq!(recorded.exchanges.len(), 1);
+        assert_eq!(recorded.exchanges[0].request.stream, Some(true));
+    }
+
+    #[test]
+    fn test_ws_frames_for_update() {
+        let mut conversation = Conversation::new("claude-test", 100);
+        let update = conversation.process_response(&response(
+            json!([
+                {"type": "text", "text": "Let me check."},
+                {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {"city": "Paris"}}
+            ]),
+            "tool_use",
+        ));
+
+        let frames = WsForwarder::new(7, "c1").frames_for_update(&update);
+        let frames: Vec<Value> = frames
+            .iter()
+            .map(|f| serde_json::to_value(f).unwrap())
+            .collect();
+        assert_eq!(
+            frames[0],
+            json!({"type": "text_delta", "conversation_id": "c1", "text": "Let me check."})
+        );
+        assert_eq!(frames[1]["type"], "tool_use");
+        assert_eq!(frames[2]["type"], "update");
+        assert_eq!(frames[2]["stop_reason"], "tool_use");
+    }
+
+    #[test]
+    fn test_ws_frames_for_stream_events() {
+        let body = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"type\":\"message\",\"role\":\"assistant\",\"content\":[],\"model\":\"claude-test\",\"stop_reason\":null,\"stop_sequence\":null,\"usage\":{\"input_tokens\":12,\"output_tokens\":1}}}\n\n",
+            "event: content_block_start\n",
+            "data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hel\"}}\n\n",
+            "event: ping\n",
+            "data: {\"type\":\"ping\"}\n\n",
+            "event: content_block_stop\n",
+            "data: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
+            "event: content_block_start\n",
+            "data: {\"type\":\"content_block_start\",\"index\":1,\"content_block\":{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"get_weather\",\"input\":{}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"city\\\": \\\"Pa\"}}\n\n",
+            "event: content_block_stop\n",
+            "data: {\"type\":\"content_block_stop\",\"index\":1}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"tool_use\",\"stop_sequence\":null},\"usage\":{\"output_tokens\":20}}\n\n",
+            "event: message_stop\n",
+            "data: {\"type\":\"message_stop\"}\n\n",
+        );
+
+        let forwarder = WsForwarder::new(7, "c1");
+        let frames: Vec<Value> = MessageStream::from_sse(body)
+            .events()
+            .unwrap()
+            .iter()
+            .filter_map(|event| forwarder.frame_for_event(event))
+            .map(|frame| serde_json::to_value(frame).unwrap())
+            .collect();
+        assert_eq!(
+            frames,
+            vec![
+                json!({"type": "text_delta", "conversation_id": "c1", "text": "Hel"}),
+                json!({"type": "tool_use_start", "conversation_id": "c1", "index": 1, "id": "toolu_1", "name": "get_weather"}),
+                json!({"type": "tool_input_delta", "conversation_id": "c1", "index": 1, "partial_json": "{\"city\": \"Pa"}),
+                json!({"type": "message_stop", "conversation_id": "c1"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_frames_use_error_payload() {
+        let forwarder = WsForwarder::new(7, "c1");
+        let storage = AnthropicError::Storage("/app:pub.os/chats/c1.jsonl: disk full".to_string());
+        assert_eq!(
+            serde_json::to_value(forwarder.frame_for_error(&storage)).unwrap(),
+            json!({
+                "type": "error",
+                "conversation_id": "c1",
+                "code": "storage_error",
+                "message": "The conversation could not be saved.",
+                "retryable": false
+            })
+        );
+
+        let stream = MessageStream::from_sse(concat!(
+            "event: error\n",
+            "data: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n",
+        ));
+        let frame = forwarder
+            .frame_for_event(&stream.events().unwrap()[0])
+            .unwrap();
+        let frame = serde_json::to_value(frame).unwrap();
+        assert_eq!(frame["message"], "Overloaded");
+        assert_eq!(frame["retryable"], true);
+
+        // SSE errors keep Anthropic's shape but carry the user-safe message
+        let event = SseEvent::error(&AnthropicError::Deserialization(
+            "unexpected body from sk-ant-secret".to_string(),
+        ));
+        let data: Value = serde_json::from_str(&event.data).unwrap();
+        assert_eq!(event.event.as_deref(), Some("error"));
+        assert_eq!(
+            data,
+            json!({
+                "type": "error",
+                "error": {
+                    "type": "api_error",
+                    "message": "The AI service returned an unexpected response."
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_stream_accumulation_and_sse_roundtrip() {
+        let body = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"type\":\"message\",\"role\":\"assistant\",\"content\":[],\"model\":\"claude-test\",\"stop_reason\":null,\"stop_sequence\":null,\"usage\":{\"input_tokens\":12,\"output_tokens\":1}}}\n\n",
+            ": keep-alive comment\n\n",
+            "event: content_block_start\n",
+            "data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hel\"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"lo\"}}\n\n",
+            "event: content_block_stop\n",
+            "data: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
+            "event: content_block_start\n",
+            "data: {\"type\":\"content_block_start\",\"index\":1,\"content_block\":{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"get_weather\",\"input\":{}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"city\\\": \\\"Pa\"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"ris\\\"}\"}}\n\n",
+            "event: content_block_stop\n",
+            "data: {\"type\":\"content_block_stop\",\"index\":1}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"tool_use\",\"stop_sequence\":null},\"usage\":{\"output_tokens\":20}}\n\n",
+            "event: message_stop\n",
+            "data: {\"type\":\"message_stop\"}\n\n",
+        );
+
+        let stream = MessageStream::from_sse(body);
+        assert_eq!(stream.raw_events().len(), 11);
+
+        let message = stream.into_response().unwrap();
+        assert_eq!(message.usage.input_tokens, 12);
+        assert_eq!(message.usage.output_tokens, 20);
+        let mut conversation = Conversation::new("claude-test", 100);
+        let update = conversation.process_response(&message);
+        assert_eq!(update.text(), "Hello");
+        assert_eq!(update.tool_uses[0].input, json!({"city": "Paris"}));
+
+        let relayed = MessageStream::from_sse(&String::from_utf8(stream.to_sse_body()).unwrap());
+        assert_eq!(relayed.raw_events(), stream.raw_events());
+    }
+
+    #[test]
+    fn test_interrupted_stream_resumes_from_prefill() {
+        let start = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"type\":\"message\",\"role\":\"assistant\",\"content\":[],\"model\":\"claude-test\",\"stop_reason\":null,\"stop_sequence\":null,\"usage\":{\"input_tokens\":12,\"output_tokens\":1}}}\n\n",
+            "event: content_block_start\n",
+            "data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+        );
+        let broken = format!(
+            "{}{}",
+            start,
+            concat!(
+                "event: content_block_delta\n",
+                "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Once upon\"}}\n\n",
+                "event: content_block_delta\n",
+                "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\" a \"}}\n\n",
+                "event: content_block_delta\n",
+                "data: {\"type\":\"content_block_del",
+            )
+        );
+        let stream = MessageStream::from_sse(&broken);
+        assert!(stream.is_interrupted());
+        assert_eq!(stream.resume_prefill().as_deref(), Some("Once upon a"));
+
+        let continuation = format!(
+            "{}{}",
+            start,
+            concat!(
+                "event: content_block_delta\n",
+                "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\" time.\"}}\n\n",
+                "event: content_block_stop\n",
+                "data: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
+                "event: message_delta\n",
+                "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\",\"stop_sequence\":null},\"usage\":{\"output_tokens\":7}}\n\n",
+                "event: message_stop\n",
+                "data: {\"type\":\"message_stop\"}\n\n",
+            )
+        );
+        let resumed = stream.resume_with(&MessageStream::from_sse(&continuation));
+        assert!(!resumed.is_interrupted());
+        let message = resumed.into_response().unwrap();
+        assert_eq!(message.content.len(), 1);
+        let mut conversation = Conversation::new("claude-test", 100);
+        assert_eq!(
+            conversation.process_response(&message).text(),
+            "Once upon a time."
+        );
+        assert_eq!(message.stop_reason, Some(StopReason::EndTurn));
+        assert_eq!(message.usage.output_tokens, 7);
+
+        // Only transient errors are worth resuming, and tool input can't be prefilled
+        let overloaded = format!(
+            "{}event: error\ndata: {{\"type\":\"error\",\"error\":{{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}}}\n\n",
+            start
+        );
+        assert!(MessageStream::from_sse(&overloaded).is_interrupted());
+        let invalid = overloaded.replace("overloaded_error", "invalid_request_error");
+        assert!(!MessageStream::from_sse(&invalid).is_interrupted());
+        let tool = start.replace(
+            "{\"type\":\"text\",\"text\":\"\"}",
+            "{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"f\",\"input\":{}}",
+        );
+        assert_eq!(MessageStream::from_sse(&tool).resume_prefill(), None);
+    }
+
+    #[test]
+    fn test_streamed_thinking_is_accumulated() {
+        let body = [
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"type\":\"message\",\"role\":\"assistant\",\"content\":[],\"model\":\"claude-test\",\"stop_reason\":null,\"stop_sequence\":null,\"usage\":{\"input_tokens\":5,\"output_tokens\":1}}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"thinking\",\"thinking\":\"\",\"signature\":\"\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"thinking_delta\",\"thinking\":\"Let me \"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"thinking_delta\",\"thinking\":\"think.\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"signature_delta\",\"signature\":\"sig_2\"}}\n\n",
+            "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
+        ]
+        .concat();
+        let response = MessageStream::from_sse(&body).into_response().unwrap();
+        assert!(matches!(
+            response.content[0],
+            ResponseContentBlock::Thinking { ref thinking, ref signature }
+                if thinking == "Let me think." && signature == "sig_2"
+        ));
+    }
+
+    #[test]
+    fn test_fine_grained_tool_streaming_partial_inputs() {
+        let client = AnthropicClient::new("test_key");
+        let request = client
+            .create_simple_message("claude-test", "hi", 100)
+            .with_fine_grained_tool_streaming();
+        assert_eq!(
+            request.betas,
+            vec!["fine-grained-tool-streaming-2025-05-14"]
+        );
+
+        let events: Vec<StreamEvent> = [
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "write_file", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"path\": \"notes.md\", \"con"}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "tent\": \"# Plan\\n- fi"}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "rst\", \"tags\": [1, 2"}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "]}"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+        ]
+        .into_iter()
+        .map(|event| serde_json::from_value(event).unwrap())
+        .collect();
+
+        let mut accumulator = ToolInputAccumulator::new();
+        let inputs: Vec<Value> = events
+            .iter()
+            .filter_map(|event| {
+                accumulator
+                    .push(event)
+                    .map(|tool_use| tool_use.input.clone())
+            })
+            .collect();
+        assert_eq!(
+            inputs,
+            vec![
+                json!({}),
+                json!({"path": "notes.md"}),
+                json!({"path": "notes.md", "content": "# Plan\n- fi"}),
+                json!({"path": "notes.md", "content": "# Plan\n- first", "tags": [1, 2]}),
+                json!({"path": "notes.md", "content": "# Plan\n- first", "tags": [1, 2]}),
+                json!({"path": "notes.md", "content": "# Plan\n- first", "tags": [1, 2]}),
+            ]
+        );
+        let tool_use = accumulator.tool_uses().next().unwrap();
+        assert!(tool_use.complete);
+        assert_eq!(tool_use.name, "write_file");
+        assert_eq!(
+            tool_use.raw,
+            "{\"path\": \"notes.md\", \"content\": \"# Plan\\n- first\", \"tags\": [1, 2]}"
+        );
+        assert_eq!(accumulator.clone().tool_uses().next(), Some(tool_use));
+    }
+
+    #[test]
+    fn test_partial_json_best_valid_prefix() {
+        let mut partial = PartialJson::new();
+        assert_eq!(partial.value(), None);
+        let steps = [
+            ("{\"qu", json!({})),
+            ("ery\": \"rust \\u00e", json!({"query": "rust "})),
+            ("9\", \"limit\": 1", json!({"query": "rust é", "limit": 1})),
+            (
+                "0, \"filters\": [tr",
+                json!({"query": "rust é", "limit": 10, "filters": []}),
+            ),
+            (
+                "ue, nul",
+                json!({"query": "rust é", "limit": 10, "filters": [true]}),
+            ),
+            (
+                "l]}",
+                json!({"query": "rust é", "limit": 10, "filters": [true, null]}),
+            ),
+        ];
+        for (fragment, expected) in steps {
+            partial.push(fragment);
+            assert_eq!(partial.value(), Some(expected), "after {:?}", partial.raw());
+        }
+        assert!(partial.is_complete());
+        assert_eq!(
+            hyperware_anthropic_sdk::partial_json::parse_partial("[1, {\"a\": \"b"),
+            Some(json!([1, {"a": "b"}]))
+        );
+    }
+
+    #[test]
+    fn test_partial_json_char_by_char_and_invalid_input() {
+        // Fed one character at a time, every escape and nesting level still comes out whole
+        let text =
+            r#"{"emoji": "\ud83e\udd80 \"crab\"", "nested": [[], {"a": [1.5e3, false]}], "n": -7}"#;
+        let mut partial = PartialJson::new();
+        for c in text.chars() {
+            partial.push(&c.to_string());
+        }
+        assert!(partial.is_complete());
+        assert_eq!(
+            partial.value(),
+            Some(serde_json::from_str::<Value>(text).unwrap())
+        );
+        // Half a surrogate pair isn't shown until the other half arrives
+        assert_eq!(
+            hyperware_anthropic_sdk::partial_json::parse_partial(r#"["\ud83e"#),
+            Some(json!([""]))
+        );
+
+        // Text that can't be JSON keeps the last complete reading
+        let mut partial = PartialJson::new();
+        partial.push(r#"{"a": 1, "b": tru"#);
+        assert_eq!(partial.value(), Some(json!({"a": 1})));
+        partial.push("x, \"c\": 2}");
+        assert_eq!(partial.value(), Some(json!({"a": 1})));
+        assert!(!partial.is_complete());
+
+        let mut partial = PartialJson::new();
+        partial.push("{} {}");
+        assert!(!partial.is_complete());
+        assert_eq!(partial.value(), Some(json!({})));
+        assert_eq!(
+            hyperware_anthropic_sdk::partial_json::parse_partial("-1"),
+            Some(json!(-1))
+        );
+        assert_eq!(
+            hyperware_anthropic_sdk::partial_json::parse_partial("-"),
+            None
+        );
+    }
+}
@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod tests {
+    use hyperware_anthropic_sdk::structured::{parse_structured, respond_json_tool};
+    use schemars::JsonSchema;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, JsonSchema, PartialEq)]
+    struct Address {
+        city: String,
+        country: String,
+    }
+
+    #[derive(Debug, Deserialize, JsonSchema, PartialEq)]
+    struct Person {
+        name: String,
+        age: Option<u32>,
+        address: Address,
+    }
+
+    #[test]
+    fn test_object_schema_is_inlined() {
+        let tool = respond_json_tool::<Person>();
+        assert_eq!(tool.name, "respond_json");
+        assert_eq!(
+            tool.input_schema.required,
+            Some(vec!["address".to_string(), "name".to_string()])
+        );
+        // Nested types are inlined rather than referenced
+        assert_eq!(
+            tool.input_schema.properties["address"]["properties"]["city"]["type"],
+            json!("string")
+        );
+    }
+
+    #[test]
+    fn test_parse_structured_object() {
+        let input = json!({
+            "name": "Ada",
+            "address": {"city": "London", "country": "UK"}
+        });
+        let person: Person = parse_structured(&input).unwrap();
+        assert_eq!(person.address.city, "London");
+        assert_eq!(person.age, None);
+
+        // Validation errors are surfaced so they can be sent back to Claude
+        assert!(parse_structured::<Person>(&json!({"name": "Ada"})).is_err());
+    }
+
+    #[test]
+    fn test_non_object_schema_is_wrapped() {
+        let tool = respond_json_tool::<Vec<String>>();
+        assert_eq!(tool.input_schema.required, Some(vec!["value".to_string()]));
+
+        let tags: Vec<String> = parse_structured(&json!({"value": ["a", "b"]})).unwrap();
+        assert_eq!(tags, vec!["a", "b"]);
+    }
+}